@@ -0,0 +1,168 @@
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use ferrox::gateway::{self, GatewayConfig, WaitStrategy, ingest};
+use ferrox::order::{Order, Side};
+use ferrox::protocol::{
+    self, EngineCommand, MSG_ORDER_ACK, NEW_ORDER_SIZE, ORDER_ACK_SIZE, encode_new_order,
+};
+use ferrox::ring::ring_buffer;
+use std::io::Write;
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const ORDERS: usize = 10_000;
+
+fn encoded_orders() -> Vec<u8> {
+    let mut buf = vec![0u8; ORDERS * NEW_ORDER_SIZE];
+    for (i, chunk) in buf.chunks_exact_mut(NEW_ORDER_SIZE).enumerate() {
+        let id = i as u64 + 1;
+        let side = if id.is_multiple_of(2) {
+            Side::Bid
+        } else {
+            Side::Ask
+        };
+        let order = Order::try_new(id, id % 100, side, 10_000 + (id as i64 % 500), 100, 0).unwrap();
+        encode_new_order(chunk, &order).unwrap();
+    }
+    buf
+}
+
+/// One client writes every order in a single `write_all`; the gateway reads
+/// them off the socket into the ring.
+fn bench_ingest(c: &mut Criterion) {
+    let payload = encoded_orders();
+    let config = GatewayConfig::default();
+    let (mut producer, mut consumer) = ring_buffer::<EngineCommand>(16_384);
+
+    let mut group = c.benchmark_group("gateway");
+    group.throughput(Throughput::Elements(ORDERS as u64));
+    group.bench_function("ingest_10k_orders_one_write", |b| {
+        b.iter(|| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let payload = payload.clone();
+            let client = thread::spawn(move || {
+                TcpStream::connect(addr)
+                    .unwrap()
+                    .write_all(&payload)
+                    .unwrap();
+            });
+            let (stream, _) = listener.accept().unwrap();
+            ingest(stream, &mut producer, &config).unwrap();
+            client.join().unwrap();
+            let mut count = 0;
+            while consumer.pop().is_ok() {
+                count += 1;
+            }
+            assert_eq!(count, ORDERS);
+        });
+    });
+    group.finish();
+}
+
+/// Sends order `id` and waits for its ack on the feed.
+fn round_trip(stream: &mut TcpStream, feed: &UdpSocket, id: u64) -> Duration {
+    // Alternate sides at one price so each pair trades and the book stays empty.
+    let side = if id.is_multiple_of(2) {
+        Side::Ask
+    } else {
+        Side::Bid
+    };
+    let mut buf = [0u8; NEW_ORDER_SIZE];
+    encode_new_order(
+        &mut buf,
+        &Order::try_new(id, 1, side, 10_000, 1, 0).unwrap(),
+    )
+    .unwrap();
+    let mut msg = [0u8; 128];
+
+    let start = Instant::now();
+    stream.write_all(&buf).unwrap();
+    loop {
+        let n = feed.recv(&mut msg).unwrap();
+        if n == ORDER_ACK_SIZE
+            && msg[0] == MSG_ORDER_ACK
+            && protocol::decode_order_ack(&msg[..n]).unwrap().order_id == id
+        {
+            return start.elapsed();
+        }
+    }
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    sorted[((sorted.len() - 1) as f64 * p) as usize]
+}
+
+/// Command-to-ack latency through a full gateway, one order in flight at a
+/// time, under each matching-thread wait strategy. Prints p50/p99 alongside
+/// criterion's mean, since the strategies differ most in the tail.
+fn bench_wait_strategies(c: &mut Criterion) {
+    let park = Duration::from_micros(50);
+    let strategies = [
+        ("yield", WaitStrategy::Yield),
+        ("spin", WaitStrategy::Spin),
+        ("park_50us", WaitStrategy::Park { timeout: park }),
+        (
+            "spin_10k_then_park_50us",
+            WaitStrategy::SpinThenPark {
+                spins: 10_000,
+                timeout: park,
+            },
+        ),
+    ];
+
+    let mut group = c.benchmark_group("gateway_latency");
+    group.sample_size(20);
+    for (name, wait_strategy) in strategies {
+        let feed = UdpSocket::bind("127.0.0.1:0").unwrap();
+        feed.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        // Pick a free port for the gateway to listen on.
+        let listen_addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let config = GatewayConfig {
+            listen_addr,
+            multicast_addr: feed.local_addr().unwrap(),
+            wait_strategy,
+            ..GatewayConfig::default()
+        };
+        let server = thread::spawn(move || gateway::run(config));
+        let mut stream = loop {
+            match TcpStream::connect(listen_addr) {
+                Ok(stream) => break stream,
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        stream.set_nodelay(true).unwrap();
+
+        let mut next_id = 1;
+        let mut samples = Vec::new();
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                let mut total = Duration::ZERO;
+                for _ in 0..iters {
+                    let latency = round_trip(&mut stream, &feed, next_id);
+                    next_id += 1;
+                    samples.push(latency);
+                    total += latency;
+                }
+                total
+            });
+        });
+
+        drop(stream);
+        server.join().unwrap().unwrap();
+        samples.sort_unstable();
+        println!(
+            "gateway_latency/{name}: p50 {:?} p99 {:?} over {} orders",
+            percentile(&samples, 0.50),
+            percentile(&samples, 0.99),
+            samples.len()
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_ingest, bench_wait_strategies);
+criterion_main!(benches);
@@ -1,9 +1,11 @@
-use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::time::{Duration, Instant};
+
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
 use ferrox::matching::MatchingEngine;
 use ferrox::order::{Order, Side};
 
 fn make_order(id: u64, side: Side, price: i64, qty: u64) -> Order {
-    Order::new(id, id, side, price, qty, id).unwrap()
+    Order::try_new(id, id, side, price, qty, id).unwrap()
 }
 
 fn engine(cap: u32) -> MatchingEngine {
@@ -57,6 +59,30 @@ fn bench_match(c: &mut Criterion) {
         );
     });
 
+    group.bench_function("full_fill_1k_streamed", |b| {
+        b.iter_batched(
+            || {
+                let mut e = engine(2_048);
+                for i in 1..=1_000u64 {
+                    e.add_order(make_order(i, Side::Ask, 100, 10)).unwrap();
+                }
+                e
+            },
+            |mut engine| {
+                let mut filled = 0;
+                for i in 1..=1_000u64 {
+                    engine
+                        .add_order_with(make_order(1_000 + i, Side::Bid, 100, 10), |fill| {
+                            filled += fill.quantity
+                        })
+                        .unwrap();
+                }
+                filled
+            },
+            BatchSize::LargeInput,
+        );
+    });
+
     group.bench_function("multi_level_sweep", |b| {
         b.iter_batched(
             || {
@@ -79,6 +105,54 @@ fn bench_match(c: &mut Criterion) {
         );
     });
 
+    // Same sweep, reusing one fills buffer across iterations.
+    let mut fills = Vec::new();
+    group.bench_function("multi_level_sweep_into", |b| {
+        b.iter_batched(
+            || {
+                let mut e = engine(2_048);
+                for i in 0..100u64 {
+                    for j in 0..10u64 {
+                        let id = i * 10 + j + 1;
+                        e.add_order(make_order(id, Side::Ask, 100 + i as i64, 10))
+                            .unwrap();
+                    }
+                }
+                e
+            },
+            |mut engine| {
+                engine
+                    .add_order_into(make_order(5_000, Side::Bid, 199, 5_000), &mut fills)
+                    .unwrap();
+            },
+            BatchSize::LargeInput,
+        );
+    });
+
+    // 100k resting makers: 6.4 MB of nodes by default, 3.2 MB with
+    // `packed-nodes`, so the sweep runs out of L2 either way.
+    group.bench_function("deep_sweep_100k", |b| {
+        b.iter_batched(
+            || {
+                let mut e = engine(131_072);
+                for i in 0..1_000u64 {
+                    for j in 0..100u64 {
+                        let id = i * 100 + j + 1;
+                        e.add_order(make_order(id, Side::Ask, 1_000 + i as i64, 1))
+                            .unwrap();
+                    }
+                }
+                e
+            },
+            |mut engine| {
+                engine
+                    .add_order(make_order(200_000, Side::Bid, 1_999, 100_000))
+                    .unwrap();
+            },
+            BatchSize::LargeInput,
+        );
+    });
+
     group.finish();
 }
 
@@ -145,6 +219,57 @@ fn bench_cancel(c: &mut Criterion) {
     group.finish();
 }
 
+/// Worst cases for a deep book, reported per cancel so the sizes compare
+/// directly: emptying the best level each time, and unlinking one order
+/// from a level holding 10k.
+fn bench_deep_cancel(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deep_cancel");
+
+    for &n in &[1_000u64, 10_000] {
+        group.throughput(Throughput::Elements(n));
+        group.bench_with_input(BenchmarkId::new("best_level_first", n), &n, |b, &n| {
+            b.iter_batched(
+                || {
+                    let mut e = engine(n as u32 + 16);
+                    for i in 0..n {
+                        e.add_order(make_order(i + 1, Side::Bid, 1000 + i as i64, 10))
+                            .unwrap();
+                    }
+                    e
+                },
+                |mut engine| {
+                    for i in (0..n).rev() {
+                        engine.cancel_order(i + 1).unwrap();
+                    }
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+
+    group.throughput(Throughput::Elements(1));
+    for (name, id) in [("head", 1), ("middle", 5_000), ("tail", 10_000)] {
+        group.bench_function(BenchmarkId::new("one_of_10k_in_level", name), |b| {
+            b.iter_batched(
+                || {
+                    let mut e = engine(10_016);
+                    for i in 1..=10_000u64 {
+                        e.add_order(make_order(i, Side::Bid, 100, 10)).unwrap();
+                    }
+                    e
+                },
+                |mut engine| {
+                    engine.cancel_order(id).unwrap();
+                    engine
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+
+    group.finish();
+}
+
 fn bench_mixed(c: &mut Criterion) {
     c.bench_function("mixed_workload_10k", |b| {
         b.iter_batched(
@@ -157,7 +282,11 @@ fn bench_mixed(c: &mut Criterion) {
                     let action = i % 10;
                     match action {
                         0..=5 => {
-                            let side = if i % 2 == 0 { Side::Bid } else { Side::Ask };
+                            let side = if i.is_multiple_of(2) {
+                                Side::Bid
+                            } else {
+                                Side::Ask
+                            };
                             let price = if side == Side::Bid { 100 } else { 200 };
                             let _ = engine.add_order(make_order(next_id, side, price, 10));
                             resting_ids.push(next_id);
@@ -180,11 +309,65 @@ fn bench_mixed(c: &mut Criterion) {
     });
 }
 
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    sorted[((sorted.len() - 1) as f64 * p) as usize]
+}
+
+/// Per-order latency of the first 1,000 orders into a fresh default-capacity
+/// engine, with and without `warmup` beforehand (not timed). Every fourth
+/// order crosses and fills. Prints p50/p99/p99.9 over all samples, since
+/// the first-order cost is a tail effect the mean hides.
+fn bench_first_orders(c: &mut Criterion) {
+    const ORDERS: u64 = 1_000;
+    let mut group = c.benchmark_group("first_orders");
+    group.sample_size(20);
+    group.throughput(Throughput::Elements(ORDERS));
+    for (name, warm) in [("cold", false), ("warm", true)] {
+        let mut samples = Vec::new();
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                let mut total = Duration::ZERO;
+                for _ in 0..iters {
+                    let mut engine = MatchingEngine::new();
+                    if warm {
+                        engine.warmup();
+                    }
+                    for id in 1..=ORDERS {
+                        let order = if id % 4 == 0 {
+                            make_order(id, Side::Ask, 1000, 5)
+                        } else {
+                            make_order(id, Side::Bid, 1000 - (id % 50) as i64, 10)
+                        };
+                        let start = Instant::now();
+                        let result = engine.add_order(order);
+                        let latency = start.elapsed();
+                        std::hint::black_box(result).unwrap();
+                        samples.push(latency);
+                        total += latency;
+                    }
+                }
+                total
+            });
+        });
+        samples.sort_unstable();
+        println!(
+            "first_orders/{name}: p50 {:?} p99 {:?} p99.9 {:?} over {} orders",
+            percentile(&samples, 0.50),
+            percentile(&samples, 0.99),
+            percentile(&samples, 0.999),
+            samples.len()
+        );
+    }
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_insert,
     bench_match,
     bench_cancel,
-    bench_mixed
+    bench_deep_cancel,
+    bench_mixed,
+    bench_first_orders
 );
 criterion_main!(benches);
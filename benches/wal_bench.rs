@@ -1,17 +1,29 @@
 use criterion::{Criterion, criterion_group, criterion_main};
+use ferrox::checksum::{Checksum, Crc32, Crc32c};
 use ferrox::matching::MatchingEngine;
 use ferrox::order::{Order, Side};
 
-use ferrox::protocol::{EngineCommand, NEW_ORDER_SIZE, encode_cancel_order, encode_new_order};
+use ferrox::protocol::{
+    EngineCommand, MAX_COMMAND_SIZE, NEW_ORDER_SIZE, encode_cancel_all, encode_cancel_order,
+    encode_cancel_replace, encode_new_order,
+};
 
 fn make_order(id: u64) -> Order {
     Order {
-        id,
-        trader_id: 1,
-        side: if id % 2 == 0 { Side::Bid } else { Side::Ask },
-        price: 10000 + (id % 100) as i64,
-        quantity: 100,
+        id: id.into(),
+        trader_id: 1.into(),
+        symbol_id: 0,
+        side: if id.is_multiple_of(2) {
+            Side::Bid
+        } else {
+            Side::Ask
+        },
+        price: (10000 + (id % 100) as i64).into(),
+        quantity: 100.into(),
         timestamp: id,
+        expiry: None,
+        reduce_only: false,
+        post_only: false,
     }
 }
 
@@ -41,10 +53,32 @@ fn bench_wal_encode_crc_throughput(c: &mut Criterion) {
     });
 }
 
+/// A WAL append's checksum work, encode plus CRC, with one backend.
+fn bench_encode_checksum<C: Checksum>(c: &mut Criterion, name: &str) {
+    let mut buf = [0u8; NEW_ORDER_SIZE];
+    let orders: Vec<Order> = (1..=10_000).map(make_order).collect();
+
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            for order in &orders {
+                encode_new_order(&mut buf, order).unwrap();
+                let mut crc = C::default();
+                crc.update(&buf[..NEW_ORDER_SIZE]);
+                crc.finalize();
+            }
+        })
+    });
+}
+
+fn bench_checksum_backends(c: &mut Criterion) {
+    bench_encode_checksum::<Crc32>(c, "wal/encode+crc32_10k");
+    bench_encode_checksum::<Crc32c>(c, "wal/encode+crc32c_10k");
+}
+
 fn bench_snapshot_capture(c: &mut Criterion) {
     let mut engine = MatchingEngine::with_capacity(20_000);
     for i in 1..=10_000u64 {
-        let order = Order::new(i, i, Side::Bid, 10000 - (i as i64 % 5000), 100, i).unwrap();
+        let order = Order::try_new(i, i, Side::Bid, 10000 - (i as i64 % 5000), 100, i).unwrap();
         engine.add_order(order).unwrap();
     }
 
@@ -56,7 +90,7 @@ fn bench_snapshot_capture(c: &mut Criterion) {
 fn bench_snapshot_serialize(c: &mut Criterion) {
     let mut engine = MatchingEngine::with_capacity(20_000);
     for i in 1..=10_000u64 {
-        let order = Order::new(i, i, Side::Bid, 10000 - (i as i64 % 5000), 100, i).unwrap();
+        let order = Order::try_new(i, i, Side::Bid, 10000 - (i as i64 % 5000), 100, i).unwrap();
         engine.add_order(order).unwrap();
     }
     let orders = engine.book().all_resting_orders();
@@ -69,7 +103,7 @@ fn bench_snapshot_serialize(c: &mut Criterion) {
 fn bench_snapshot_deserialize(c: &mut Criterion) {
     let mut engine = MatchingEngine::with_capacity(20_000);
     for i in 1..=10_000u64 {
-        let order = Order::new(i, i, Side::Bid, 10000 - (i as i64 % 5000), 100, i).unwrap();
+        let order = Order::try_new(i, i, Side::Bid, 10000 - (i as i64 % 5000), 100, i).unwrap();
         engine.add_order(order).unwrap();
     }
     let orders = engine.book().all_resting_orders();
@@ -85,7 +119,7 @@ fn bench_snapshot_deserialize(c: &mut Criterion) {
 fn bench_restore_from_orders(c: &mut Criterion) {
     let mut engine = MatchingEngine::with_capacity(20_000);
     for i in 1..=10_000u64 {
-        let order = Order::new(i, i, Side::Bid, 10000 - (i as i64 % 5000), 100, i).unwrap();
+        let order = Order::try_new(i, i, Side::Bid, 10000 - (i as i64 % 5000), 100, i).unwrap();
         engine.add_order(order).unwrap();
     }
     let orders = engine.book().all_resting_orders();
@@ -111,7 +145,7 @@ fn bench_mixed_wal_encode(c: &mut Criterion) {
         })
         .collect();
 
-    let mut buf = [0u8; NEW_ORDER_SIZE];
+    let mut buf = [0u8; MAX_COMMAND_SIZE];
 
     c.bench_function("wal/mixed_encode+crc_10k", |b| {
         b.iter(|| {
@@ -125,6 +159,19 @@ fn bench_mixed_wal_encode(c: &mut Criterion) {
                         let n = encode_cancel_order(&mut buf, *order_id).unwrap();
                         crc32fast::hash(&buf[..n]);
                     }
+                    EngineCommand::CancelReplace { old_id, new_order } => {
+                        let n = encode_cancel_replace(&mut buf, *old_id, new_order).unwrap();
+                        crc32fast::hash(&buf[..n]);
+                    }
+                    EngineCommand::CancelAll { trader_id } => {
+                        let n = encode_cancel_all(&mut buf, *trader_id).unwrap();
+                        crc32fast::hash(&buf[..n]);
+                    }
+                    EngineCommand::ReduceOrder { .. }
+                    | EngineCommand::Halt { .. }
+                    | EngineCommand::Resume
+                    | EngineCommand::RequestSnapshot
+                    | EngineCommand::AdminSnapshot => {}
                 }
             }
         })
@@ -135,6 +182,7 @@ criterion_group!(
     benches,
     bench_wal_encode_new_order,
     bench_wal_encode_crc_throughput,
+    bench_checksum_backends,
     bench_mixed_wal_encode,
     bench_snapshot_capture,
     bench_snapshot_serialize,
@@ -1,15 +1,30 @@
 use std::net::{Ipv4Addr, UdpSocket};
 
-use ferrox::protocol::{self, EXECUTION_REPORT_SIZE};
+use ferrox::protocol::{
+    self, EXECUTION_REPORT_SIZE, MSG_AGG_TRADE, MSG_BOOK_UPDATE, MSG_CANCEL_REPORT,
+    MSG_LEVEL_DELTA, MSG_ORDER_ACK, MSG_ORDER_REJECT, PROTOCOL_VERSION, ProtocolError,
+};
 
 fn main() {
+    // Optional local address of the interface to join on; the OS picks one
+    // otherwise.
+    let interface = match std::env::args().nth(1) {
+        Some(arg) => arg.parse::<Ipv4Addr>().unwrap_or_else(|_| {
+            eprintln!("usage: subscriber [interface-ipv4]");
+            std::process::exit(2);
+        }),
+        None => Ipv4Addr::UNSPECIFIED,
+    };
+
     let socket = UdpSocket::bind("0.0.0.0:9001").expect("failed to bind UDP socket");
 
     socket
-        .join_multicast_v4(&Ipv4Addr::new(239, 1, 1, 1), &Ipv4Addr::UNSPECIFIED)
+        .join_multicast_v4(&Ipv4Addr::new(239, 1, 1, 1), &interface)
         .expect("failed to join multicast group");
 
-    eprintln!("subscriber: listening for execution reports on 239.1.1.1:9001");
+    eprintln!(
+        "subscriber: listening for execution reports on 239.1.1.1:9001 via {interface} (protocol v{PROTOCOL_VERSION})"
+    );
 
     let mut buf = [0u8; EXECUTION_REPORT_SIZE];
     let mut expected_seq: u32 = 1;
@@ -23,36 +38,112 @@ fn main() {
             }
         };
 
-        if n < EXECUTION_REPORT_SIZE {
-            eprintln!("subscriber: short packet ({n} bytes) from {src}");
-            continue;
-        }
+        // All feed messages share one sequence. Decoders check the length.
+        let msg = &buf[..n];
+        let decoded = if msg.first() == Some(&MSG_ORDER_ACK) {
+            protocol::decode_order_ack(msg).map(|a| {
+                let line = format!(
+                    "v{} seq={} ACK order={} status={:?} best={} ts={}",
+                    buf[1], a.seq_num, a.order_id, a.status, a.became_best, a.timestamp,
+                );
+                (a.seq_num, line)
+            })
+        } else if msg.first() == Some(&MSG_AGG_TRADE) {
+            protocol::decode_agg_trade(msg).map(|t| {
+                let line = format!(
+                    "v{} seq={} TRADE taker={} price={} qty={} fills={} ts={}",
+                    buf[1],
+                    t.seq_num,
+                    t.taker_order_id,
+                    t.price,
+                    t.quantity,
+                    t.fill_count,
+                    t.timestamp,
+                );
+                (t.seq_num, line)
+            })
+        } else if msg.first() == Some(&MSG_CANCEL_REPORT) {
+            protocol::decode_cancel_report(msg).map(|c| {
+                let line = format!(
+                    "v{} seq={} CANCEL order={} qty={} ts={}",
+                    buf[1], c.seq_num, c.order_id, c.quantity, c.timestamp,
+                );
+                (c.seq_num, line)
+            })
+        } else if msg.first() == Some(&MSG_ORDER_REJECT) {
+            protocol::decode_order_reject(msg).map(|r| {
+                let line = format!(
+                    "v{} seq={} REJECT order={} reason={} ts={}",
+                    buf[1], r.seq_num, r.order_id, r.reason, r.timestamp,
+                );
+                (r.seq_num, line)
+            })
+        } else if msg.first() == Some(&MSG_LEVEL_DELTA) {
+            protocol::decode_level_delta(msg).map(|d| {
+                let line = format!(
+                    "v{} seq={} LEVEL {:?} {}@{} orders={} ts={}",
+                    buf[1], d.seq_num, d.side, d.quantity, d.price, d.order_count, d.timestamp,
+                );
+                (d.seq_num, line)
+            })
+        } else if msg.first() == Some(&MSG_BOOK_UPDATE) {
+            protocol::decode_book_update(msg).map(|u| {
+                let side = |level: Option<(i64, u64)>| match level {
+                    Some((price, qty)) => format!("{qty}@{price}"),
+                    None => "-".to_string(),
+                };
+                let line = format!(
+                    "v{} seq={} bid={} ask={} ts={}",
+                    buf[1],
+                    u.seq_num,
+                    side(u.best_bid),
+                    side(u.best_ask),
+                    u.timestamp,
+                );
+                (u.seq_num, line)
+            })
+        } else {
+            protocol::decode_execution_report(msg).map(|r| {
+                let line = format!(
+                    "v{} seq={} taker={}#{} maker={}#{} price={} qty={} ts={}",
+                    buf[1],
+                    r.seq_num,
+                    r.taker_order_id,
+                    r.taker_seq,
+                    r.maker_order_id,
+                    r.maker_seq,
+                    r.price,
+                    r.quantity,
+                    r.timestamp,
+                );
+                (r.seq_num, line)
+            })
+        };
 
-        let report = match protocol::decode_execution_report(&buf) {
-            Ok(r) => r,
+        let (seq_num, line) = match decoded {
+            Ok(d) => d,
+            Err(ProtocolError::VersionMismatch { got, .. }) => {
+                eprintln!("subscriber: skipping v{got} message from {src}");
+                continue;
+            }
+            Err(ProtocolError::BufferTooShort) => {
+                eprintln!("subscriber: short packet ({n} bytes) from {src}");
+                continue;
+            }
             Err(e) => {
                 eprintln!("subscriber: decode error: {e}");
                 continue;
             }
         };
 
-        if report.seq_num != expected_seq {
-            let gap = report.seq_num.wrapping_sub(expected_seq);
+        if seq_num != expected_seq {
+            let gap = seq_num.wrapping_sub(expected_seq);
             eprintln!(
-                "subscriber: GAP detected — expected seq {expected_seq}, got {}, missing {gap} report(s)",
-                report.seq_num
+                "subscriber: GAP detected — expected seq {expected_seq}, got {seq_num}, missing {gap} message(s)"
             );
         }
-        expected_seq = report.seq_num.wrapping_add(1);
-
-        println!(
-            "seq={} taker={} maker={} price={} qty={} ts={}",
-            report.seq_num,
-            report.taker_order_id,
-            report.maker_order_id,
-            report.price,
-            report.quantity,
-            report.timestamp,
-        );
+        expected_seq = seq_num.wrapping_add(1);
+
+        println!("{line}");
     }
 }
@@ -0,0 +1,77 @@
+use std::process::ExitCode;
+
+use ferrox::order::Order;
+use ferrox::protocol::EngineCommand;
+use ferrox::wal::WalReader;
+
+fn describe(order: &Order) -> String {
+    let mut line = format!(
+        "id={} trader={} symbol={} side={:?} price={} qty={} ts={}",
+        order.id,
+        order.trader_id,
+        order.symbol_id,
+        order.side,
+        order.price,
+        order.quantity,
+        order.timestamp,
+    );
+    if let Some(expiry) = order.expiry {
+        line.push_str(&format!(" expiry={expiry}"));
+    }
+    if order.reduce_only {
+        line.push_str(" reduce-only");
+    }
+    if order.post_only {
+        line.push_str(" post-only");
+    }
+    line
+}
+
+fn main() -> ExitCode {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("usage: ferrox-walcat <data/wal.bin or a rotated data/wal.bin.<n> segment>");
+        return ExitCode::FAILURE;
+    };
+
+    let reader = match WalReader::open(&path) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("walcat: failed to open {path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut count = 0u64;
+    for result in reader.iter() {
+        let (record, cmd) = match result {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("walcat: stopped after {count} record(s): {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        count += 1;
+
+        match cmd {
+            EngineCommand::NewOrder(order) => println!("{record} NEW {}", describe(&order)),
+            EngineCommand::CancelOrder { order_id } => println!("{record} CANCEL id={order_id}"),
+            EngineCommand::CancelReplace { old_id, new_order } => {
+                println!("{record} REPLACE old={old_id} {}", describe(&new_order))
+            }
+            EngineCommand::CancelAll { trader_id } => {
+                println!("{record} CANCEL_ALL trader={trader_id}")
+            }
+            EngineCommand::ReduceOrder {
+                order_id,
+                reduce_by,
+            } => println!("{record} REDUCE id={order_id} by={reduce_by}"),
+            EngineCommand::Halt { policy } => println!("{record} HALT policy={policy:?}"),
+            EngineCommand::Resume => println!("{record} RESUME"),
+            EngineCommand::RequestSnapshot => println!("{record} SNAPSHOT REQUEST"),
+            EngineCommand::AdminSnapshot => println!("{record} ADMIN SNAPSHOT"),
+        }
+    }
+
+    eprintln!("walcat: {count} record(s)");
+    ExitCode::SUCCESS
+}
@@ -1,3 +1,5 @@
+use std::num::NonZeroU64;
+
 use crate::order::{Order, Side};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -9,18 +11,73 @@ pub(crate) const ARENA_NULL: u32 = u32::MAX;
 
 const DEFAULT_CAPACITY: u32 = 1_048_576;
 
-#[derive(Clone)]
+/// Order fields the matching loop doesn't read: a maker's price is its
+/// level's and its side the opposite of the taker's.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct NodeCold {
+    pub(crate) price: i64,
+    pub(crate) timestamp: u64,
+    pub(crate) expiry: u64,
+    pub(crate) symbol_id: u32,
+    pub(crate) side: Side,
+    pub(crate) reduce_only: bool,
+    pub(crate) post_only: bool,
+}
+
+impl NodeCold {
+    fn zeroed() -> Self {
+        Self {
+            price: 0,
+            timestamp: 0,
+            expiry: 0,
+            symbol_id: 0,
+            side: Side::Bid,
+            reduce_only: false,
+            post_only: false,
+        }
+    }
+
+    fn from_order(order: &Order) -> Self {
+        Self {
+            price: order.price.0,
+            timestamp: order.timestamp,
+            expiry: order.expiry.map_or(0, NonZeroU64::get),
+            symbol_id: order.symbol_id,
+            side: order.side,
+            reduce_only: order.reduce_only,
+            post_only: order.post_only,
+        }
+    }
+
+    pub(crate) fn expiry(&self) -> Option<NonZeroU64> {
+        NonZeroU64::new(self.expiry)
+    }
+}
+
+/// One cache line per order, cold fields inline.
+#[cfg(not(feature = "packed-nodes"))]
+#[derive(Debug, Clone)]
 #[repr(C, align(64))]
 pub(crate) struct OrderNode {
     pub(crate) id: u64,
     pub(crate) trader_id: u64,
-    pub(crate) price: i64,
     pub(crate) quantity: u64,
-    pub(crate) timestamp: u64,
     pub(crate) prev: u32,
     pub(crate) next: u32,
-    pub(crate) side: Side,
-    _pad: [u8; 15],
+    cold: NodeCold,
+}
+
+/// Two orders per cache line; the cold fields live in `Arena::cold` at the
+/// same index.
+#[cfg(feature = "packed-nodes")]
+#[derive(Debug, Clone)]
+#[repr(C, align(32))]
+pub(crate) struct OrderNode {
+    pub(crate) id: u64,
+    pub(crate) trader_id: u64,
+    pub(crate) quantity: u64,
+    pub(crate) prev: u32,
+    pub(crate) next: u32,
 }
 
 impl OrderNode {
@@ -28,57 +85,27 @@ impl OrderNode {
         Self {
             id: 0,
             trader_id: 0,
-            price: 0,
             quantity: 0,
-            timestamp: 0,
             prev: ARENA_NULL,
             next: ARENA_NULL,
-            side: Side::Bid,
-            _pad: [0u8; 15],
+            #[cfg(not(feature = "packed-nodes"))]
+            cold: NodeCold::zeroed(),
         }
     }
 
-    pub(crate) fn from_order(order: &Order) -> Self {
+    fn from_order(order: &Order) -> Self {
         Self {
-            id: order.id,
-            trader_id: order.trader_id,
-            price: order.price,
-            quantity: order.quantity,
-            timestamp: order.timestamp,
+            id: order.id.0,
+            trader_id: order.trader_id.0,
+            quantity: order.quantity.0,
             prev: ARENA_NULL,
             next: ARENA_NULL,
-            side: order.side,
-            _pad: [0u8; 15],
-        }
-    }
-
-    pub(crate) fn to_order(&self) -> Order {
-        Order {
-            id: self.id,
-            trader_id: self.trader_id,
-            side: self.side,
-            price: self.price,
-            quantity: self.quantity,
-            timestamp: self.timestamp,
+            #[cfg(not(feature = "packed-nodes"))]
+            cold: NodeCold::from_order(order),
         }
     }
 }
 
-impl std::fmt::Debug for OrderNode {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("OrderNode")
-            .field("id", &self.id)
-            .field("trader_id", &self.trader_id)
-            .field("price", &self.price)
-            .field("quantity", &self.quantity)
-            .field("timestamp", &self.timestamp)
-            .field("prev", &self.prev)
-            .field("next", &self.next)
-            .field("side", &self.side)
-            .finish()
-    }
-}
-
 #[derive(Debug, Clone)]
 pub(crate) struct PriceLevel {
     pub(crate) head: u32,
@@ -101,25 +128,44 @@ impl PriceLevel {
 #[derive(Debug)]
 pub(crate) struct Arena {
     storage: Vec<OrderNode>,
+    #[cfg(feature = "packed-nodes")]
+    cold: Vec<NodeCold>,
+    /// Engine sequence number per slot. A column of its own: the node is a
+    /// full cache line already, and only fills and snapshots read it.
+    seqs: Vec<u64>,
     free_head: u32,
     count: u32,
     capacity: u32,
+    growable: bool,
 }
 
 impl Arena {
+    /// Pre-allocates `capacity` slots and doubles the backing storage when
+    /// they run out. Growth reallocates the `Vec` on the alloc path, but
+    /// indices stay valid since nodes are only ever addressed by position.
     pub(crate) fn new(capacity: u32) -> Self {
-        let mut storage = Vec::with_capacity(capacity as usize);
-        for i in 0..capacity {
-            let mut node = OrderNode::zeroed();
-            node.next = if i + 1 < capacity { i + 1 } else { ARENA_NULL };
-            storage.push(node);
-        }
-        Self {
-            storage,
-            free_head: if capacity > 0 { 0 } else { ARENA_NULL },
+        Self::build(capacity, true)
+    }
+
+    /// Hard limit of `capacity` slots: `alloc` returns `ArenaError::Full`
+    /// instead of reallocating.
+    pub(crate) fn with_fixed_capacity(capacity: u32) -> Self {
+        Self::build(capacity, false)
+    }
+
+    fn build(capacity: u32, growable: bool) -> Self {
+        let mut arena = Self {
+            storage: Vec::with_capacity(capacity as usize),
+            #[cfg(feature = "packed-nodes")]
+            cold: Vec::with_capacity(capacity as usize),
+            seqs: Vec::with_capacity(capacity as usize),
+            free_head: ARENA_NULL,
             count: 0,
-            capacity,
-        }
+            capacity: 0,
+            growable,
+        };
+        arena.extend_free_list(capacity);
+        arena
     }
 
     pub(crate) fn default_capacity() -> u32 {
@@ -130,18 +176,40 @@ impl Arena {
         self.count
     }
 
-    pub(crate) fn alloc(&mut self, order: &Order) -> Result<u32, ArenaError> {
+    pub(crate) fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    pub(crate) fn alloc(&mut self, order: &Order, seq: u64) -> Result<u32, ArenaError> {
         if self.free_head == ARENA_NULL {
-            return Err(ArenaError::Full);
+            self.grow()?;
         }
 
         let index = self.free_head;
         self.free_head = self.storage[index as usize].next;
         self.storage[index as usize] = OrderNode::from_order(order);
+        #[cfg(feature = "packed-nodes")]
+        {
+            self.cold[index as usize] = NodeCold::from_order(order);
+        }
+        self.seqs[index as usize] = seq;
         self.count += 1;
         Ok(index)
     }
 
+    /// Frees every slot, relinking the free list in index order as on
+    /// creation. Keeps the storage, including any growth.
+    pub(crate) fn clear(&mut self) {
+        for (i, node) in self.storage.iter_mut().enumerate() {
+            node.next = i as u32 + 1;
+        }
+        if let Some(last) = self.storage.last_mut() {
+            last.next = ARENA_NULL;
+        }
+        self.free_head = if self.capacity > 0 { 0 } else { ARENA_NULL };
+        self.count = 0;
+    }
+
     pub(crate) fn dealloc(&mut self, index: u32) {
         debug_assert!(index < self.capacity);
         self.storage[index as usize].next = self.free_head;
@@ -149,6 +217,38 @@ impl Arena {
         self.count -= 1;
     }
 
+    fn grow(&mut self) -> Result<(), ArenaError> {
+        // ARENA_NULL is reserved as the list terminator, so it can never be an index.
+        if !self.growable || self.capacity == ARENA_NULL {
+            return Err(ArenaError::Full);
+        }
+        let new_capacity = self.capacity.saturating_mul(2).clamp(1, ARENA_NULL);
+        self.extend_free_list(new_capacity);
+        Ok(())
+    }
+
+    /// Appends slots `capacity..new_capacity` and threads them onto the free list.
+    fn extend_free_list(&mut self, new_capacity: u32) {
+        let old_capacity = self.capacity;
+        self.storage.reserve((new_capacity - old_capacity) as usize);
+        for i in old_capacity..new_capacity {
+            let mut node = OrderNode::zeroed();
+            node.next = if i + 1 < new_capacity {
+                i + 1
+            } else {
+                self.free_head
+            };
+            self.storage.push(node);
+        }
+        #[cfg(feature = "packed-nodes")]
+        self.cold.resize(new_capacity as usize, NodeCold::zeroed());
+        self.seqs.resize(new_capacity as usize, 0);
+        if new_capacity > old_capacity {
+            self.free_head = old_capacity;
+        }
+        self.capacity = new_capacity;
+    }
+
     pub(crate) fn get(&self, index: u32) -> &OrderNode {
         &self.storage[index as usize]
     }
@@ -157,6 +257,37 @@ impl Arena {
         &mut self.storage[index as usize]
     }
 
+    #[cfg(not(feature = "packed-nodes"))]
+    pub(crate) fn cold(&self, index: u32) -> &NodeCold {
+        &self.storage[index as usize].cold
+    }
+
+    #[cfg(feature = "packed-nodes")]
+    pub(crate) fn cold(&self, index: u32) -> &NodeCold {
+        &self.cold[index as usize]
+    }
+
+    pub(crate) fn seq(&self, index: u32) -> u64 {
+        self.seqs[index as usize]
+    }
+
+    pub(crate) fn to_order(&self, index: u32) -> Order {
+        let node = self.get(index);
+        let cold = self.cold(index);
+        Order {
+            id: node.id.into(),
+            trader_id: node.trader_id.into(),
+            symbol_id: cold.symbol_id,
+            side: cold.side,
+            price: cold.price.into(),
+            quantity: node.quantity.into(),
+            timestamp: cold.timestamp,
+            expiry: cold.expiry(),
+            reduce_only: cold.reduce_only,
+            post_only: cold.post_only,
+        }
+    }
+
     pub(crate) fn push_back(&mut self, level: &mut PriceLevel, index: u32) {
         let quantity = self.storage[index as usize].quantity;
 
@@ -175,6 +306,37 @@ impl Arena {
         level.qty += quantity;
     }
 
+    /// Links `index` into `level` behind every node whose seq is not above
+    /// its own, so a queue stays in seq order however its nodes are linked in
+    /// and equal seqs keep arrival order. The new order has the highest seq
+    /// in the usual case, which only looks at the tail.
+    pub(crate) fn insert_by_seq(&mut self, level: &mut PriceLevel, index: u32) {
+        let seq = self.seqs[index as usize];
+        let mut after = level.tail;
+        while after != ARENA_NULL && self.seqs[after as usize] > seq {
+            after = self.storage[after as usize].prev;
+        }
+        if after == level.tail {
+            self.push_back(level, index);
+            return;
+        }
+
+        // `after` isn't the tail, so something follows the new node.
+        let next = match after {
+            ARENA_NULL => level.head,
+            after => self.storage[after as usize].next,
+        };
+        self.storage[index as usize].prev = after;
+        self.storage[index as usize].next = next;
+        self.storage[next as usize].prev = index;
+        match after {
+            ARENA_NULL => level.head = index,
+            after => self.storage[after as usize].next = index,
+        }
+        level.count += 1;
+        level.qty += self.storage[index as usize].quantity;
+    }
+
     pub(crate) fn pop_front(&mut self, level: &mut PriceLevel) -> Option<u32> {
         if level.head == ARENA_NULL {
             return None;
@@ -224,30 +386,59 @@ mod tests {
     use super::*;
 
     fn make_order(id: u64, price: i64, qty: u64) -> Order {
-        Order::new(id, id, Side::Bid, price, qty, id).unwrap()
+        Order::try_new(id, id, Side::Bid, price, qty, id).unwrap()
     }
 
     #[test]
+    #[cfg(not(feature = "packed-nodes"))]
     fn ordernode_size_and_alignment() {
         assert_eq!(std::mem::size_of::<OrderNode>(), 64);
         assert_eq!(std::mem::align_of::<OrderNode>(), 64);
     }
 
+    #[test]
+    #[cfg(feature = "packed-nodes")]
+    fn packed_ordernode_size_and_alignment() {
+        assert_eq!(std::mem::size_of::<OrderNode>(), 32);
+        assert_eq!(std::mem::align_of::<OrderNode>(), 32);
+    }
+
+    #[test]
+    fn clear_frees_every_slot_in_place() {
+        let mut arena = Arena::with_fixed_capacity(3);
+        for id in 1..=3 {
+            arena.alloc(&make_order(id, 100, 1), id).unwrap();
+        }
+        let storage = arena.storage.as_ptr();
+
+        arena.clear();
+        assert_eq!(arena.count(), 0);
+        let indices: Vec<_> = (1..=3)
+            .map(|id| arena.alloc(&make_order(id, 100, 1), id).unwrap())
+            .collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+        assert!(arena.alloc(&make_order(4, 100, 1), 4).is_err());
+        assert_eq!(arena.storage.as_ptr(), storage);
+    }
+
     #[test]
     fn ordernode_roundtrip() {
-        let order = Order::new(1, 2, Side::Ask, 100, 50, 999).unwrap();
-        let node = OrderNode::from_order(&order);
-        let back = node.to_order();
-        assert_eq!(back, order);
+        let order = Order::try_new(1, 2, Side::Ask, 100, 50, 999)
+            .unwrap()
+            .with_expiry(5_000)
+            .with_post_only(true);
+        let mut arena = Arena::new(1);
+        let index = arena.alloc(&order, 0).unwrap();
+        assert_eq!(arena.to_order(index), order);
     }
 
     #[test]
     fn arena_alloc_dealloc_cycle() {
         let mut arena = Arena::new(4);
-        let i0 = arena.alloc(&make_order(1, 100, 10)).unwrap();
-        let i1 = arena.alloc(&make_order(2, 101, 20)).unwrap();
-        let i2 = arena.alloc(&make_order(3, 102, 30)).unwrap();
-        let i3 = arena.alloc(&make_order(4, 103, 40)).unwrap();
+        let i0 = arena.alloc(&make_order(1, 100, 10), 0).unwrap();
+        let i1 = arena.alloc(&make_order(2, 101, 20), 0).unwrap();
+        let i2 = arena.alloc(&make_order(3, 102, 30), 0).unwrap();
+        let i3 = arena.alloc(&make_order(4, 103, 40), 0).unwrap();
 
         assert_eq!(arena.count(), 4);
         assert_eq!(i0, 0);
@@ -259,8 +450,8 @@ mod tests {
         arena.dealloc(i3);
         assert_eq!(arena.count(), 2);
 
-        let i4 = arena.alloc(&make_order(5, 104, 50)).unwrap();
-        let i5 = arena.alloc(&make_order(6, 105, 60)).unwrap();
+        let i4 = arena.alloc(&make_order(5, 104, 50), 0).unwrap();
+        let i5 = arena.alloc(&make_order(6, 105, 60), 0).unwrap();
         assert_eq!(arena.count(), 4);
         assert_eq!(i4, 3);
         assert_eq!(i5, 1);
@@ -268,32 +459,90 @@ mod tests {
 
     #[test]
     fn arena_full() {
-        let mut arena = Arena::new(2);
-        arena.alloc(&make_order(1, 100, 10)).unwrap();
-        arena.alloc(&make_order(2, 101, 20)).unwrap();
+        let mut arena = Arena::with_fixed_capacity(2);
+        arena.alloc(&make_order(1, 100, 10), 0).unwrap();
+        arena.alloc(&make_order(2, 101, 20), 0).unwrap();
         assert_eq!(
-            arena.alloc(&make_order(3, 102, 30)).unwrap_err(),
+            arena.alloc(&make_order(3, 102, 30), 0).unwrap_err(),
             ArenaError::Full
         );
     }
 
     #[test]
     fn arena_zero_capacity() {
-        let mut arena = Arena::new(0);
+        let mut arena = Arena::with_fixed_capacity(0);
         assert_eq!(
-            arena.alloc(&make_order(1, 100, 10)).unwrap_err(),
+            arena.alloc(&make_order(1, 100, 10), 0).unwrap_err(),
             ArenaError::Full
         );
     }
 
+    #[test]
+    fn arena_grows_when_exhausted() {
+        let mut arena = Arena::new(2);
+        let i0 = arena.alloc(&make_order(1, 100, 10), 0).unwrap();
+        let i1 = arena.alloc(&make_order(2, 101, 20), 0).unwrap();
+        let i2 = arena.alloc(&make_order(3, 102, 30), 0).unwrap();
+
+        assert_eq!(arena.capacity(), 4);
+        assert_eq!(arena.count(), 3);
+        assert_eq!((i0, i1, i2), (0, 1, 2));
+        // Pre-growth indices still resolve to the same orders
+        assert_eq!(arena.get(i0).id, 1);
+        assert_eq!(arena.get(i1).id, 2);
+
+        arena.alloc(&make_order(4, 103, 40), 0).unwrap();
+        arena.alloc(&make_order(5, 104, 50), 0).unwrap();
+        assert_eq!(arena.capacity(), 8);
+    }
+
+    #[test]
+    fn arena_grows_from_zero() {
+        let mut arena = Arena::new(0);
+        assert_eq!(arena.alloc(&make_order(1, 100, 10), 0).unwrap(), 0);
+        assert_eq!(arena.capacity(), 1);
+        assert_eq!(arena.alloc(&make_order(2, 100, 10), 0).unwrap(), 1);
+        assert_eq!(arena.capacity(), 2);
+    }
+
+    #[test]
+    fn growth_keeps_linked_levels_intact() {
+        let mut arena = Arena::new(2);
+        let mut level = PriceLevel::new();
+        for id in 1..=10 {
+            let idx = arena.alloc(&make_order(id, 100, id), 0).unwrap();
+            arena.push_back(&mut level, idx);
+        }
+
+        let mut ids = Vec::new();
+        let mut cur = level.head;
+        while cur != ARENA_NULL {
+            ids.push(arena.get(cur).id);
+            cur = arena.get(cur).next;
+        }
+        assert_eq!(ids, (1..=10).collect::<Vec<u64>>());
+        assert_eq!(level.qty, 55);
+    }
+
+    #[test]
+    fn growth_after_dealloc_reuses_free_slots_first() {
+        let mut arena = Arena::new(2);
+        let i0 = arena.alloc(&make_order(1, 100, 10), 0).unwrap();
+        arena.alloc(&make_order(2, 100, 10), 0).unwrap();
+        arena.dealloc(i0);
+
+        assert_eq!(arena.alloc(&make_order(3, 100, 10), 0).unwrap(), i0);
+        assert_eq!(arena.capacity(), 2);
+    }
+
     #[test]
     fn push_back_builds_list() {
         let mut arena = Arena::new(8);
         let mut level = PriceLevel::new();
 
-        let i0 = arena.alloc(&make_order(1, 100, 10)).unwrap();
-        let i1 = arena.alloc(&make_order(2, 100, 20)).unwrap();
-        let i2 = arena.alloc(&make_order(3, 100, 30)).unwrap();
+        let i0 = arena.alloc(&make_order(1, 100, 10), 0).unwrap();
+        let i1 = arena.alloc(&make_order(2, 100, 20), 0).unwrap();
+        let i2 = arena.alloc(&make_order(3, 100, 30), 0).unwrap();
 
         arena.push_back(&mut level, i0);
         arena.push_back(&mut level, i1);
@@ -317,9 +566,9 @@ mod tests {
         let mut arena = Arena::new(8);
         let mut level = PriceLevel::new();
 
-        let i0 = arena.alloc(&make_order(1, 100, 10)).unwrap();
-        let i1 = arena.alloc(&make_order(2, 100, 20)).unwrap();
-        let i2 = arena.alloc(&make_order(3, 100, 30)).unwrap();
+        let i0 = arena.alloc(&make_order(1, 100, 10), 0).unwrap();
+        let i1 = arena.alloc(&make_order(2, 100, 20), 0).unwrap();
+        let i2 = arena.alloc(&make_order(3, 100, 30), 0).unwrap();
 
         arena.push_back(&mut level, i0);
         arena.push_back(&mut level, i1);
@@ -352,9 +601,9 @@ mod tests {
         let mut arena = Arena::new(8);
         let mut level = PriceLevel::new();
 
-        let i0 = arena.alloc(&make_order(1, 100, 10)).unwrap();
-        let i1 = arena.alloc(&make_order(2, 100, 20)).unwrap();
-        let i2 = arena.alloc(&make_order(3, 100, 30)).unwrap();
+        let i0 = arena.alloc(&make_order(1, 100, 10), 0).unwrap();
+        let i1 = arena.alloc(&make_order(2, 100, 20), 0).unwrap();
+        let i2 = arena.alloc(&make_order(3, 100, 30), 0).unwrap();
         arena.push_back(&mut level, i0);
         arena.push_back(&mut level, i1);
         arena.push_back(&mut level, i2);
@@ -372,9 +621,9 @@ mod tests {
         let mut arena = Arena::new(8);
         let mut level = PriceLevel::new();
 
-        let i0 = arena.alloc(&make_order(1, 100, 10)).unwrap();
-        let i1 = arena.alloc(&make_order(2, 100, 20)).unwrap();
-        let i2 = arena.alloc(&make_order(3, 100, 30)).unwrap();
+        let i0 = arena.alloc(&make_order(1, 100, 10), 0).unwrap();
+        let i1 = arena.alloc(&make_order(2, 100, 20), 0).unwrap();
+        let i2 = arena.alloc(&make_order(3, 100, 30), 0).unwrap();
         arena.push_back(&mut level, i0);
         arena.push_back(&mut level, i1);
         arena.push_back(&mut level, i2);
@@ -392,9 +641,9 @@ mod tests {
         let mut arena = Arena::new(8);
         let mut level = PriceLevel::new();
 
-        let i0 = arena.alloc(&make_order(1, 100, 10)).unwrap();
-        let i1 = arena.alloc(&make_order(2, 100, 20)).unwrap();
-        let i2 = arena.alloc(&make_order(3, 100, 30)).unwrap();
+        let i0 = arena.alloc(&make_order(1, 100, 10), 0).unwrap();
+        let i1 = arena.alloc(&make_order(2, 100, 20), 0).unwrap();
+        let i2 = arena.alloc(&make_order(3, 100, 30), 0).unwrap();
         arena.push_back(&mut level, i0);
         arena.push_back(&mut level, i1);
         arena.push_back(&mut level, i2);
@@ -413,7 +662,7 @@ mod tests {
         let mut arena = Arena::new(8);
         let mut level = PriceLevel::new();
 
-        let i0 = arena.alloc(&make_order(1, 100, 10)).unwrap();
+        let i0 = arena.alloc(&make_order(1, 100, 10), 0).unwrap();
         arena.push_back(&mut level, i0);
 
         arena.remove(&mut level, i0);
@@ -431,7 +680,7 @@ mod tests {
         let indices: Vec<u32> = ids
             .iter()
             .map(|&id| {
-                let idx = arena.alloc(&make_order(id, 100, id)).unwrap();
+                let idx = arena.alloc(&make_order(id, 100, id), 0).unwrap();
                 arena.push_back(&mut level, idx);
                 idx
             })
@@ -462,9 +711,9 @@ mod tests {
         let mut arena = Arena::new(3);
         let mut level = PriceLevel::new();
 
-        let i0 = arena.alloc(&make_order(1, 100, 10)).unwrap();
-        let i1 = arena.alloc(&make_order(2, 100, 20)).unwrap();
-        let i2 = arena.alloc(&make_order(3, 100, 30)).unwrap();
+        let i0 = arena.alloc(&make_order(1, 100, 10), 0).unwrap();
+        let i1 = arena.alloc(&make_order(2, 100, 20), 0).unwrap();
+        let i2 = arena.alloc(&make_order(3, 100, 30), 0).unwrap();
         arena.push_back(&mut level, i0);
         arena.push_back(&mut level, i1);
         arena.push_back(&mut level, i2);
@@ -472,7 +721,7 @@ mod tests {
         arena.remove(&mut level, i1);
         arena.dealloc(i1);
 
-        let i3 = arena.alloc(&make_order(4, 100, 40)).unwrap();
+        let i3 = arena.alloc(&make_order(4, 100, 40), 0).unwrap();
         assert_eq!(i3, i1);
         assert_eq!(arena.get(i3).id, 4);
         assert_eq!(arena.count(), 3);
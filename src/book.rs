@@ -1,4 +1,7 @@
 use std::collections::{BTreeMap, HashMap};
+use std::num::NonZeroU64;
+
+use serde::{Deserialize, Serialize};
 
 use crate::arena::{ARENA_NULL, Arena, ArenaError, OrderNode, PriceLevel};
 use crate::order::{Order, Side};
@@ -8,22 +11,90 @@ pub enum BookError {
     DuplicateOrderId(u64),
     OrderNotFound(u64),
     PriceLevelNotFound(i64),
-    FillExceedsQuantity { available: u64, requested: u64 },
+    FillExceedsQuantity {
+        available: u64,
+        requested: u64,
+    },
     ArenaFull,
+    /// A restored order's side or price differs from the level it was listed under.
+    LevelMismatch {
+        order_id: u64,
+    },
+    /// Resting the order would take a level's or a side's total quantity
+    /// past `u64::MAX`.
+    QuantityOverflow,
+    /// The best bid is at or above the best ask.
+    Crossed {
+        bid: i64,
+        ask: i64,
+    },
+}
+
+impl std::fmt::Display for BookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicateOrderId(id) => write!(f, "order {id} is already resting"),
+            Self::OrderNotFound(id) => write!(f, "order {id} not found"),
+            Self::PriceLevelNotFound(price) => write!(f, "no price level at {price}"),
+            Self::FillExceedsQuantity {
+                available,
+                requested,
+            } => write!(f, "fill of {requested} exceeds the {available} available"),
+            Self::ArenaFull => write!(f, "order arena is full"),
+            Self::LevelMismatch { order_id } => {
+                write!(f, "order {order_id} listed under the wrong level")
+            }
+            Self::QuantityOverflow => write!(f, "resting quantity would overflow"),
+            Self::Crossed { bid, ask } => write!(f, "book crossed: bid {bid} >= ask {ask}"),
+        }
+    }
 }
 
+impl std::error::Error for BookError {}
+
 impl From<ArenaError> for BookError {
     fn from(_: ArenaError) -> Self {
         Self::ArenaFull
     }
 }
 
+/// Aggregate view of one price level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelView {
+    pub price: i64,
+    pub quantity: u64,
+    pub order_count: u32,
+}
+
+/// One price level's resting orders with their engine sequence numbers,
+/// head of the queue first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct LevelQueue {
+    pub(crate) side: Side,
+    pub(crate) price: i64,
+    pub(crate) orders: Vec<(Order, u64)>,
+}
+
+/// One row of an order-by-order dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RankedOrder {
+    pub order: Order,
+    /// Zero-based position in the level's FIFO queue; 0 matches next.
+    pub queue_rank: u32,
+    /// Engine sequence number the order was inserted with. Queues are kept
+    /// in `seq` order, so rank follows it.
+    pub seq: u64,
+}
+
 #[derive(Debug)]
 pub struct OrderBook {
     bids: BTreeMap<i64, PriceLevel>,
     asks: BTreeMap<i64, PriceLevel>,
     best_bid: Option<i64>,
     best_ask: Option<i64>,
+    /// Running sums of `PriceLevel::qty` per side.
+    bid_qty: u64,
+    ask_qty: u64,
     order_index: HashMap<u64, u32>,
     arena: Arena,
 }
@@ -33,17 +104,65 @@ impl OrderBook {
         Self::with_capacity(Arena::default_capacity())
     }
 
+    /// Pre-allocates `arena_capacity` order slots; the arena doubles when full.
     pub fn with_capacity(arena_capacity: u32) -> Self {
+        Self::with_arena(Arena::new(arena_capacity))
+    }
+
+    /// Sizes the arena and the order index together for `expected_resting`
+    /// orders times `growth_factor` (at least 1), so a burst past the
+    /// expected count neither doubles the arena nor rehashes the index.
+    /// Price levels are `BTreeMap` nodes allocated per level; there is no
+    /// level capacity to set.
+    pub fn with_capacity_hint(expected_resting: u32, growth_factor: f64) -> Self {
+        let slots = (f64::from(expected_resting) * growth_factor.max(1.0)).ceil();
+        Self::with_capacity(slots.min(f64::from(ARENA_NULL - 1)) as u32)
+    }
+
+    /// Never reallocates: inserts beyond `arena_capacity` fail with `BookError::ArenaFull`.
+    pub fn with_fixed_capacity(arena_capacity: u32) -> Self {
+        Self::with_arena(Arena::with_fixed_capacity(arena_capacity))
+    }
+
+    fn with_arena(arena: Arena) -> Self {
         Self {
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
             best_bid: None,
             best_ask: None,
-            order_index: HashMap::with_capacity(arena_capacity as usize),
-            arena: Arena::new(arena_capacity),
+            bid_qty: 0,
+            ask_qty: 0,
+            order_index: HashMap::with_capacity(arena.capacity() as usize),
+            arena,
         }
     }
 
+    /// Removes every order, leaving the book as new without reallocating the
+    /// arena or the order index.
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+        self.best_bid = None;
+        self.best_ask = None;
+        self.bid_qty = 0;
+        self.ask_qty = 0;
+        self.order_index.clear();
+        self.arena.clear();
+    }
+
+    /// Fills the empty order index to its capacity and clears it, so every
+    /// bucket page is faulted in before the first real insert. No-op unless
+    /// the book is empty.
+    pub(crate) fn prefault_index(&mut self) {
+        if !self.order_index.is_empty() {
+            return;
+        }
+        for id in 0..self.order_index.capacity() as u64 {
+            self.order_index.insert(id, ARENA_NULL);
+        }
+        self.order_index.clear();
+    }
+
     pub fn best_bid(&self) -> Option<i64> {
         self.best_bid
     }
@@ -52,41 +171,236 @@ impl OrderBook {
         self.best_ask
     }
 
+    /// `best_ask - best_bid` in ticks, saturating. `None` if either side is empty.
+    pub fn spread(&self) -> Option<i64> {
+        Some(self.best_ask?.saturating_sub(self.best_bid?))
+    }
+
+    /// Midpoint of the best bid and ask, rounded down (towards negative
+    /// infinity) to a whole tick. `None` if either side is empty.
+    pub fn mid(&self) -> Option<i64> {
+        let sum = self.best_bid? as i128 + self.best_ask? as i128;
+        Some(sum.div_euclid(2) as i64)
+    }
+
+    /// `(bid - ask) / (bid + ask)` over the quantity in the best `levels`
+    /// levels of each side: 1 is all bids, -1 all asks. `None` if either side
+    /// is empty or `levels` is 0. O(levels).
+    pub fn imbalance(&self, levels: usize) -> Option<f64> {
+        let depth = |side| -> u128 {
+            self.iter_levels(side)
+                .take(levels)
+                .map(|level| u128::from(level.quantity))
+                .sum()
+        };
+        let (bid, ask) = (depth(Side::Bid), depth(Side::Ask));
+        if bid == 0 || ask == 0 {
+            return None;
+        }
+        Some((bid as f64 - ask as f64) / (bid + ask) as f64)
+    }
+
+    /// Size-weighted mid of the top of book,
+    /// `(bid_px * ask_qty + ask_px * bid_qty) / (bid_qty + ask_qty)`: leans
+    /// towards the side with less quantity, the one more likely to be taken
+    /// out next. `None` if either side is empty.
+    pub fn microprice(&self) -> Option<f64> {
+        let best = |side| self.iter_levels(side).next();
+        let (bid, ask) = (best(Side::Bid)?, best(Side::Ask)?);
+        let (bid_qty, ask_qty) = (bid.quantity as f64, ask.quantity as f64);
+        Some((bid.price as f64 * ask_qty + ask.price as f64 * bid_qty) / (bid_qty + ask_qty))
+    }
+
+    /// Quantity resting on `side` at `limit_price` or better for a taker:
+    /// asks at or below it, bids at or above it. What an order of unlimited
+    /// size limited at `limit_price` would sweep, 0 if nothing qualifies.
+    /// O(levels walked).
+    pub fn cumulative_quantity_to(&self, side: Side, limit_price: i64) -> u64 {
+        self.iter_levels(side)
+            .take_while(|level| match side {
+                Side::Bid => level.price >= limit_price,
+                Side::Ask => level.price <= limit_price,
+            })
+            // Bounded by the side total, which can't overflow.
+            .map(|level| level.quantity)
+            .sum()
+    }
+
+    /// The individual orders at the best `levels` levels of `side`, best
+    /// level first and each walked head to tail, so in matching order. The
+    /// L3 counterpart of `iter_levels(side).take(levels)`. Allocates the
+    /// returned `Vec`; O(levels + orders returned).
+    pub fn top_orders(&self, side: Side, levels: usize) -> Vec<Order> {
+        let top = || self.levels(side).take(levels).map(|(_, level)| level);
+        let mut orders = Vec::with_capacity(top().map(|l| l.count as usize).sum());
+        for level in top() {
+            let mut idx = level.head;
+            while idx != ARENA_NULL {
+                orders.push(self.arena.to_order(idx));
+                idx = self.arena.get(idx).next;
+            }
+        }
+        orders
+    }
+
+    /// Total quantity resting on the bid side, O(1).
+    pub fn total_bid_quantity(&self) -> u64 {
+        self.bid_qty
+    }
+
+    /// Total quantity resting on the ask side, O(1).
+    pub fn total_ask_quantity(&self) -> u64 {
+        self.ask_qty
+    }
+
+    fn total_quantity(&self, side: Side) -> u64 {
+        match side {
+            Side::Bid => self.bid_qty,
+            Side::Ask => self.ask_qty,
+        }
+    }
+
+    /// `(quantity, order_count)` resting at `price` on `side`, or `None` if
+    /// there is no level there. O(log levels).
+    pub fn level_depth(&self, side: Side, price: i64) -> Option<(u64, u32)> {
+        let level = match side {
+            Side::Bid => self.bids.get(&price),
+            Side::Ask => self.asks.get(&price),
+        }?;
+        Some((level.qty, level.count))
+    }
+
+    /// Zero-based place of a resting order in its level's queue; 0 matches
+    /// next. O(orders ahead of it).
+    pub fn queue_position(&self, order_id: u64) -> Option<u32> {
+        self.queue_ahead(order_id).map(|(count, _)| count)
+    }
+
+    /// `(order_count, quantity)` queued ahead of a resting order at its
+    /// price, not counting the order itself. Walks the level from its head.
+    pub fn queue_ahead(&self, order_id: u64) -> Option<(u32, u64)> {
+        let &index = self.order_index.get(&order_id)?;
+        let target = self.arena.cold(index);
+        let level = match target.side {
+            Side::Bid => self.bids.get(&target.price),
+            Side::Ask => self.asks.get(&target.price),
+        }?;
+
+        let (mut count, mut quantity) = (0, 0);
+        let mut idx = level.head;
+        while idx != index {
+            debug_assert_ne!(idx, ARENA_NULL, "order missing from its level");
+            let node = self.arena.get(idx);
+            count += 1;
+            quantity += node.quantity;
+            idx = node.next;
+        }
+        Some((count, quantity))
+    }
+
     pub fn order_count(&self) -> usize {
         self.order_index.len()
     }
 
-    pub(crate) fn insert_order(&mut self, order: Order) -> Result<(), BookError> {
-        if self.order_index.contains_key(&order.id) {
-            return Err(BookError::DuplicateOrderId(order.id));
+    pub fn contains_order(&self, order_id: u64) -> bool {
+        self.order_index.contains_key(&order_id)
+    }
+
+    pub(crate) fn get_order(&self, order_id: u64) -> Option<Order> {
+        let &index = self.order_index.get(&order_id)?;
+        Some(self.arena.to_order(index))
+    }
+
+    /// Engine sequence number the order was inserted with.
+    pub(crate) fn order_seq(&self, order_id: u64) -> Option<u64> {
+        let &index = self.order_index.get(&order_id)?;
+        Some(self.arena.seq(index))
+    }
+
+    /// Overwrites a resting order's quantity in place, keeping its queue
+    /// position. Returns the order as it was before the change.
+    pub(crate) fn set_order_quantity(
+        &mut self,
+        order_id: u64,
+        quantity: u64,
+    ) -> Result<Order, BookError> {
+        let &index = self
+            .order_index
+            .get(&order_id)
+            .ok_or(BookError::OrderNotFound(order_id))?;
+        let before = self.arena.to_order(index);
+        let total = match before.side {
+            Side::Bid => &mut self.bid_qty,
+            Side::Ask => &mut self.ask_qty,
+        };
+        // The side total bounds every level's, so checking it covers both.
+        *total = (*total - before.quantity.0)
+            .checked_add(quantity)
+            .ok_or(BookError::QuantityOverflow)?;
+        self.arena.get_mut(index).quantity = quantity;
+
+        let level = match before.side {
+            Side::Bid => self.bids.get_mut(&before.price.0),
+            Side::Ask => self.asks.get_mut(&before.price.0),
+        }
+        .ok_or(BookError::PriceLevelNotFound(before.price.0))?;
+        level.qty = level.qty - before.quantity.0 + quantity;
+
+        self.debug_check_invariants();
+        Ok(before)
+    }
+
+    /// Queues the order at its level by `seq`, behind every order whose seq
+    /// is not above it, rather than simply at the tail.
+    pub(crate) fn insert_order(&mut self, order: Order, seq: u64) -> Result<(), BookError> {
+        if self.order_index.contains_key(&order.id.0) {
+            return Err(BookError::DuplicateOrderId(order.id.0));
         }
 
         let side = order.side;
-        let price = order.price;
-        let id = order.id;
+        let price = order.price.0;
+        let id = order.id.0;
+        // As in `set_order_quantity`, the side total bounds the level's.
+        let new_total = self
+            .total_quantity(side)
+            .checked_add(order.quantity.0)
+            .ok_or(BookError::QuantityOverflow)?;
 
         let Self {
             bids,
             asks,
+            bid_qty,
+            ask_qty,
             arena,
             order_index,
             ..
         } = self;
 
-        let index = arena.alloc(&order)?;
+        let index = arena.alloc(&order, seq)?;
+        // Grow the index with the arena, once per doubling, rather than
+        // letting it rehash on its own schedule.
+        let slots = arena.capacity() as usize;
+        if order_index.capacity() < slots {
+            order_index.reserve(slots - order_index.len());
+        }
 
-        let levels = match side {
-            Side::Bid => bids,
-            Side::Ask => asks,
+        let (levels, total) = match side {
+            Side::Bid => (bids, bid_qty),
+            Side::Ask => (asks, ask_qty),
         };
+        *total = new_total;
+        let new_level = !levels.contains_key(&price);
         let level = levels.entry(price).or_insert_with(PriceLevel::new);
-        arena.push_back(level, index);
+        arena.insert_by_seq(level, index);
 
         order_index.insert(id, index);
 
-        self.update_best_after_insert(side, price);
+        if new_level {
+            self.refresh_best(side);
+        }
 
         debug_assert_eq!(self.arena.count() as usize, self.order_index.len());
+        self.debug_check_invariants();
         Ok(())
     }
 
@@ -96,17 +410,18 @@ impl OrderBook {
             asks,
             arena,
             order_index,
-            best_bid,
-            best_ask,
+            bid_qty,
+            ask_qty,
+            ..
         } = self;
 
         let index = order_index
             .remove(&order_id)
             .ok_or(BookError::OrderNotFound(order_id))?;
 
-        let order = arena.get(index).to_order();
+        let order = arena.to_order(index);
         let side = order.side;
-        let price = order.price;
+        let price = order.price.0;
 
         let level_empty = {
             let level = match side {
@@ -119,21 +434,23 @@ impl OrderBook {
             arena.dealloc(index);
             level.count == 0
         };
+        match side {
+            Side::Bid => *bid_qty -= order.quantity.0,
+            Side::Ask => *ask_qty -= order.quantity.0,
+        }
 
         if level_empty {
             match side {
-                Side::Bid => {
-                    bids.remove(&price);
-                    *best_bid = bids.keys().next_back().copied();
-                }
-                Side::Ask => {
-                    asks.remove(&price);
-                    *best_ask = asks.keys().next().copied();
-                }
-            }
+                Side::Bid => bids.remove(&price),
+                Side::Ask => asks.remove(&price),
+            };
         }
 
         debug_assert_eq!(arena.count() as usize, order_index.len());
+        if level_empty {
+            self.refresh_best(side);
+        }
+        self.debug_check_invariants();
         Ok(order)
     }
 
@@ -149,6 +466,32 @@ impl OrderBook {
         Some(self.arena.get(level.head))
     }
 
+    /// Expiry of the order at the front of a level.
+    pub(crate) fn front_expiry(&self, side: Side, price: i64) -> Option<NonZeroU64> {
+        let levels = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        let level = levels.get(&price)?;
+        if level.head == ARENA_NULL {
+            return None;
+        }
+        self.arena.cold(level.head).expiry()
+    }
+
+    /// `peek_front` plus the order's sequence number, in one level lookup.
+    pub(crate) fn peek_front_seq(&self, side: Side, price: i64) -> Option<(&OrderNode, u64)> {
+        let levels = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        let level = levels.get(&price)?;
+        if level.head == ARENA_NULL {
+            return None;
+        }
+        Some((self.arena.get(level.head), self.arena.seq(level.head)))
+    }
+
     pub(crate) fn reduce_front_quantity(
         &mut self,
         side: Side,
@@ -160,8 +503,9 @@ impl OrderBook {
             asks,
             arena,
             order_index,
-            best_bid,
-            best_ask,
+            bid_qty,
+            ask_qty,
+            ..
         } = self;
 
         let (remaining, level_empty) = {
@@ -187,6 +531,10 @@ impl OrderBook {
 
             front.quantity -= fill_qty;
             level.qty -= fill_qty;
+            match side {
+                Side::Bid => *bid_qty -= fill_qty,
+                Side::Ask => *ask_qty -= fill_qty,
+            }
             let remaining = front.quantity;
 
             if remaining == 0 {
@@ -202,54 +550,176 @@ impl OrderBook {
 
         if level_empty {
             match side {
-                Side::Bid => {
-                    bids.remove(&price);
-                    *best_bid = bids.keys().next_back().copied();
-                }
-                Side::Ask => {
-                    asks.remove(&price);
-                    *best_ask = asks.keys().next().copied();
-                }
-            }
+                Side::Bid => bids.remove(&price),
+                Side::Ask => asks.remove(&price),
+            };
         }
 
         debug_assert_eq!(arena.count() as usize, order_index.len());
+        if level_empty {
+            self.refresh_best(side);
+        }
+        self.debug_check_invariants();
         Ok(remaining)
     }
 
-    /// Asks ascending price, then bids descending price; FIFO within each level.
+    /// Levels in priority order: bids high-to-low, asks low-to-high. Lazy and
+    /// allocation-free; the borrow keeps the book from changing underneath, so
+    /// the levels seen are a consistent view of the book at one instant.
+    pub fn iter_levels(&self, side: Side) -> impl Iterator<Item = LevelView> + '_ {
+        self.levels(side).map(|(&price, level)| LevelView {
+            price,
+            quantity: level.qty,
+            order_count: level.count,
+        })
+    }
+
+    /// Resting orders on one side in matching order with their level's price:
+    /// levels by priority, each walked head to tail.
+    pub(crate) fn iter_queue(&self, side: Side) -> impl Iterator<Item = (i64, &OrderNode)> + '_ {
+        self.levels(side).flat_map(move |(&price, level)| {
+            let head = (level.head != ARENA_NULL).then(|| self.arena.get(level.head));
+            std::iter::successors(head, move |node| {
+                (node.next != ARENA_NULL).then(|| self.arena.get(node.next))
+            })
+            .map(move |node| (price, node))
+        })
+    }
+
+    fn levels(&self, side: Side) -> impl Iterator<Item = (&i64, &PriceLevel)> + '_ {
+        let (bids, asks) = match side {
+            Side::Bid => (Some(self.bids.iter().rev()), None),
+            Side::Ask => (None, Some(self.asks.iter())),
+        };
+        bids.into_iter().flatten().chain(asks.into_iter().flatten())
+    }
+
+    /// Asks ascending price, then bids descending price; FIFO within each
+    /// level, which is engine seq order. The order depends only on the
+    /// book's contents and queue positions, never on arena slots or hash
+    /// state, so equal books list equally; snapshots and `state_hash` rely
+    /// on it.
     pub fn all_resting_orders(&self) -> Vec<Order> {
         let mut orders = Vec::with_capacity(self.order_index.len());
+        self.walk_queues(|order, _, _| orders.push(order));
+        orders
+    }
 
-        for level in self.asks.values() {
+    /// Full L3 dump in the same order as `all_resting_orders` (asks ascending,
+    /// then bids descending, each level walked head to tail), with each
+    /// order's rank in its level's queue and its engine seq.
+    pub fn all_resting_orders_ordered(&self) -> Vec<RankedOrder> {
+        let mut orders = Vec::with_capacity(self.order_index.len());
+        self.walk_queues(|order, queue_rank, seq| {
+            orders.push(RankedOrder {
+                order,
+                queue_rank,
+                seq,
+            })
+        });
+        orders
+    }
+
+    /// 64-bit FNV-1a over every resting order in `all_resting_orders` order:
+    /// side, price, id, trader, symbol, quantity, timestamp, expiry, flags and
+    /// engine seq. Two books holding the same orders in the same queue positions hash
+    /// equal regardless of arena slots or how they got there, so a standby
+    /// replaying the primary's WAL can compare hashes to detect divergence.
+    /// Stable across runs and builds.
+    pub fn state_hash(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let mut hash = FNV_OFFSET;
+        let mut feed = |word: u64| {
+            for byte in word.to_le_bytes() {
+                hash = (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+            }
+        };
+        for level in self.asks.values().chain(self.bids.values().rev()) {
             let mut idx = level.head;
             while idx != ARENA_NULL {
-                let node = self.arena.get(idx);
-                orders.push(node.to_order());
-                idx = node.next;
+                let order = self.arena.to_order(idx);
+                let flags = u64::from(order.reduce_only) | u64::from(order.post_only) << 1;
+                feed(order.side as u64);
+                feed(order.price.0 as u64);
+                feed(order.id.0);
+                feed(order.trader_id.0);
+                feed(u64::from(order.symbol_id));
+                feed(order.quantity.0);
+                feed(order.timestamp);
+                feed(order.expiry.map_or(0, NonZeroU64::get));
+                feed(flags);
+                feed(self.arena.seq(idx));
+                idx = self.arena.get(idx).next;
             }
         }
+        hash
+    }
+
+    /// Every level with its queue, in the same order as `all_resting_orders`.
+    pub(crate) fn level_queues(&self) -> Vec<LevelQueue> {
+        let asks = self
+            .asks
+            .iter()
+            .map(|(&price, level)| (Side::Ask, price, level));
+        let bids = self
+            .bids
+            .iter()
+            .rev()
+            .map(|(&price, level)| (Side::Bid, price, level));
+        asks.chain(bids)
+            .map(|(side, price, level)| {
+                let mut orders = Vec::with_capacity(level.count as usize);
+                let mut idx = level.head;
+                while idx != ARENA_NULL {
+                    orders.push((self.arena.to_order(idx), self.arena.seq(idx)));
+                    idx = self.arena.get(idx).next;
+                }
+                LevelQueue {
+                    side,
+                    price,
+                    orders,
+                }
+            })
+            .collect()
+    }
 
-        for level in self.bids.values().rev() {
+    fn walk_queues(&self, mut visit: impl FnMut(Order, u32, u64)) {
+        for level in self.asks.values().chain(self.bids.values().rev()) {
             let mut idx = level.head;
+            let mut rank = 0;
             while idx != ARENA_NULL {
-                let node = self.arena.get(idx);
-                orders.push(node.to_order());
-                idx = node.next;
+                visit(self.arena.to_order(idx), rank, self.arena.seq(idx));
+                rank += 1;
+                idx = self.arena.get(idx).next;
             }
         }
+    }
 
-        orders
+    /// Fails if the book is crossed. Matching never rests an order that
+    /// could trade, so a crossed book means two resting orders could match
+    /// each other. Cheap: reads only the cached best prices.
+    pub fn verify(&self) -> Result<(), BookError> {
+        match (self.best_bid, self.best_ask) {
+            (Some(bid), Some(ask)) if bid >= ask => Err(BookError::Crossed { bid, ask }),
+            _ => Ok(()),
+        }
+    }
+
+    fn debug_check_invariants(&self) {
+        debug_assert_eq!(self.bid_qty, self.bids.values().map(|l| l.qty).sum::<u64>());
+        debug_assert_eq!(self.ask_qty, self.asks.values().map(|l| l.qty).sum::<u64>());
+        debug_assert_eq!(self.best_bid, self.bids.keys().next_back().copied());
+        debug_assert_eq!(self.best_ask, self.asks.keys().next().copied());
     }
 
-    fn update_best_after_insert(&mut self, side: Side, price: i64) {
+    /// The only writer of `best_bid`/`best_ask`: re-reads the side's extreme
+    /// key from its map. Called whenever a level is added or removed.
+    fn refresh_best(&mut self, side: Side) {
         match side {
-            Side::Bid => {
-                self.best_bid = Some(self.best_bid.map_or(price, |b| b.max(price)));
-            }
-            Side::Ask => {
-                self.best_ask = Some(self.best_ask.map_or(price, |a| a.min(price)));
-            }
+            Side::Bid => self.best_bid = self.bids.keys().next_back().copied(),
+            Side::Ask => self.best_ask = self.asks.keys().next().copied(),
         }
     }
 }
@@ -266,39 +736,285 @@ mod tests {
     use crate::order::Order;
 
     fn bid(id: u64, price: i64, qty: u64, ts: u64) -> Order {
-        Order::new(id, 1, Side::Bid, price, qty, ts).unwrap()
+        Order::try_new(id, 1, Side::Bid, price, qty, ts).unwrap()
     }
 
     fn ask(id: u64, price: i64, qty: u64, ts: u64) -> Order {
-        Order::new(id, 1, Side::Ask, price, qty, ts).unwrap()
+        Order::try_new(id, 1, Side::Ask, price, qty, ts).unwrap()
+    }
+
+    #[test]
+    fn verify_flags_a_crossed_book() {
+        let mut book = OrderBook::with_capacity(16);
+        book.insert_order(bid(1, 100, 10, 1), 1).unwrap();
+        book.insert_order(ask(2, 101, 10, 2), 2).unwrap();
+        assert_eq!(book.verify(), Ok(()));
+
+        // The book itself doesn't match, so inserting directly can cross it.
+        book.insert_order(ask(3, 100, 10, 3), 3).unwrap();
+        assert_eq!(
+            book.verify(),
+            Err(BookError::Crossed { bid: 100, ask: 100 })
+        );
+    }
+
+    #[test]
+    fn queue_order_follows_seq() {
+        let mut book = OrderBook::with_capacity(16);
+        book.insert_order(bid(1, 100, 10, 1), 1).unwrap();
+        book.insert_order(bid(3, 100, 10, 3), 3).unwrap();
+        book.insert_order(bid(4, 100, 10, 4), 4).unwrap();
+        // Linked in late with an older seq, e.g. from a delta: goes ahead of
+        // the newer orders, not behind them.
+        book.insert_order(bid(2, 100, 10, 2), 2).unwrap();
+
+        // A refreshed slice comes back with a fresh seq and queues behind
+        // everything already resting.
+        let refreshed = book.cancel_order(1).unwrap();
+        book.insert_order(refreshed, 5).unwrap();
+
+        let dump: Vec<(u64, u32, u64)> = book
+            .all_resting_orders_ordered()
+            .iter()
+            .map(|r| (r.order.id.0, r.queue_rank, r.seq))
+            .collect();
+        assert_eq!(dump, [(2, 0, 2), (3, 1, 3), (4, 2, 4), (1, 3, 5)]);
+        assert_eq!(book.peek_front(Side::Bid, 100).unwrap().id, 2);
+        assert_eq!(book.queue_ahead(1), Some((3, 30)));
+        assert_eq!(book.level_depth(Side::Bid, 100), Some((40, 4)));
+
+        // Head insert into a one-order level.
+        book.insert_order(ask(6, 105, 1, 6), 9).unwrap();
+        book.insert_order(ask(7, 105, 1, 7), 8).unwrap();
+        assert_eq!(book.peek_front(Side::Ask, 105).unwrap().id, 7);
+        book.cancel_order(7).unwrap();
+        assert_eq!(book.peek_front(Side::Ask, 105).unwrap().id, 6);
+    }
+
+    #[test]
+    fn state_hash_depends_only_on_logical_state() {
+        let mut a = OrderBook::new();
+        a.insert_order(bid(1, 100, 10, 1), 1).unwrap();
+        a.insert_order(bid(2, 100, 5, 2), 2).unwrap();
+        a.insert_order(ask(3, 105, 7, 3), 3).unwrap();
+
+        // Same orders, arrived with others in between, so different slots.
+        let mut b = OrderBook::new();
+        b.insert_order(ask(9, 110, 1, 0), 9).unwrap();
+        b.insert_order(bid(1, 100, 10, 1), 1).unwrap();
+        b.insert_order(ask(8, 104, 1, 0), 8).unwrap();
+        b.insert_order(bid(2, 100, 5, 2), 2).unwrap();
+        b.insert_order(ask(3, 105, 7, 3), 3).unwrap();
+        b.cancel_order(9).unwrap();
+        b.cancel_order(8).unwrap();
+        assert_eq!(a.state_hash(), b.state_hash());
+        assert_ne!(a.state_hash(), OrderBook::new().state_hash());
+
+        let mut smaller = OrderBook::new();
+        smaller.insert_order(bid(1, 100, 9, 1), 1).unwrap();
+        smaller.insert_order(bid(2, 100, 5, 2), 2).unwrap();
+        smaller.insert_order(ask(3, 105, 7, 3), 3).unwrap();
+        assert_ne!(a.state_hash(), smaller.state_hash());
+
+        // Queues follow seq, so swapping queue positions means swapping seqs.
+        let mut reordered = OrderBook::new();
+        reordered.insert_order(bid(2, 100, 5, 2), 1).unwrap();
+        reordered.insert_order(bid(1, 100, 10, 1), 2).unwrap();
+        reordered.insert_order(ask(3, 105, 7, 3), 3).unwrap();
+        assert_ne!(a.state_hash(), reordered.state_hash());
+
+        let mut other_symbol = OrderBook::new();
+        other_symbol.insert_order(bid(1, 100, 10, 1), 1).unwrap();
+        other_symbol
+            .insert_order(bid(2, 100, 5, 2).with_symbol(7), 2)
+            .unwrap();
+        other_symbol.insert_order(ask(3, 105, 7, 3), 3).unwrap();
+        assert_ne!(a.state_hash(), other_symbol.state_hash());
     }
 
     #[test]
     fn insert_and_best_prices() {
         let mut book = OrderBook::new();
-        book.insert_order(bid(1, 100, 10, 1)).unwrap();
-        book.insert_order(bid(2, 102, 10, 2)).unwrap();
-        book.insert_order(ask(3, 105, 10, 3)).unwrap();
-        book.insert_order(ask(4, 103, 10, 4)).unwrap();
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        book.insert_order(bid(2, 102, 10, 2), 0).unwrap();
+        book.insert_order(ask(3, 105, 10, 3), 0).unwrap();
+        book.insert_order(ask(4, 103, 10, 4), 0).unwrap();
 
         assert_eq!(book.best_bid(), Some(102));
         assert_eq!(book.best_ask(), Some(103));
         assert_eq!(book.order_count(), 4);
     }
 
+    #[test]
+    fn clear_leaves_full_capacity_reusable() {
+        let mut book = OrderBook::with_fixed_capacity(4);
+        for id in 1..=2 {
+            book.insert_order(bid(id, 100 - id as i64, 10, id), id)
+                .unwrap();
+            book.insert_order(ask(id + 2, 100 + id as i64, 10, id), id)
+                .unwrap();
+        }
+        assert!(matches!(
+            book.insert_order(bid(5, 90, 10, 5), 5),
+            Err(BookError::ArenaFull)
+        ));
+
+        book.clear();
+        assert_eq!(book.order_count(), 0);
+        assert_eq!((book.best_bid(), book.best_ask()), (None, None));
+        assert_eq!(book.total_bid_quantity() + book.total_ask_quantity(), 0);
+        assert!(!book.contains_order(1));
+        assert_eq!(book.state_hash(), OrderBook::new().state_hash());
+
+        for id in 1..=4 {
+            book.insert_order(bid(id, 100, 10, id), id).unwrap();
+        }
+        assert_eq!(book.level_depth(Side::Bid, 100), Some((40, 4)));
+    }
+
+    #[test]
+    fn spread_and_mid() {
+        let mut book = OrderBook::new();
+        assert_eq!(book.spread(), None);
+        assert_eq!(book.mid(), None);
+
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        assert_eq!(book.spread(), None);
+        assert_eq!(book.mid(), None);
+
+        book.insert_order(ask(2, 104, 10, 2), 0).unwrap();
+        assert_eq!(book.spread(), Some(4));
+        assert_eq!(book.mid(), Some(102));
+
+        book.insert_order(ask(3, 101, 10, 3), 0).unwrap();
+        assert_eq!(book.spread(), Some(1));
+        assert_eq!(book.mid(), Some(100));
+
+        book.cancel_order(1).unwrap();
+        assert_eq!(book.spread(), None);
+        assert_eq!(book.mid(), None);
+    }
+
+    #[test]
+    fn imbalance_over_top_levels() {
+        let mut book = OrderBook::new();
+        book.insert_order(bid(1, 100, 30, 1), 0).unwrap();
+        assert_eq!(book.imbalance(1), None);
+
+        book.insert_order(bid(2, 99, 50, 2), 0).unwrap();
+        book.insert_order(ask(3, 101, 10, 3), 0).unwrap();
+        book.insert_order(ask(4, 102, 10, 4), 0).unwrap();
+        book.insert_order(ask(5, 110, 1000, 5), 0).unwrap();
+
+        // (30 - 10) / 40
+        assert_eq!(book.imbalance(1), Some(0.5));
+        // (80 - 20) / 100
+        assert_eq!(book.imbalance(2), Some(0.6));
+        // The deep ask swamps the bids once it is in range.
+        assert_eq!(book.imbalance(3), Some((80.0 - 1020.0) / 1100.0));
+        assert_eq!(book.imbalance(0), None);
+    }
+
+    #[test]
+    fn microprice_leans_towards_the_thinner_side() {
+        let mut book = OrderBook::new();
+        book.insert_order(bid(1, 100, 30, 1), 0).unwrap();
+        assert_eq!(book.microprice(), None);
+
+        // Only the best level counts, so the 99 bid doesn't move it.
+        book.insert_order(bid(2, 99, 500, 2), 0).unwrap();
+        book.insert_order(ask(3, 104, 10, 3), 0).unwrap();
+        // (100 * 10 + 104 * 30) / 40
+        assert_eq!(book.microprice(), Some(103.0));
+
+        book.insert_order(ask(4, 104, 20, 4), 0).unwrap();
+        assert_eq!(book.microprice(), Some(102.0));
+    }
+
+    #[test]
+    fn top_orders_walks_best_levels_in_fifo_order() {
+        let mut book = OrderBook::new();
+        assert!(book.top_orders(Side::Bid, 2).is_empty());
+        let orders = [
+            (1, 7, 100, 10),
+            (2, 8, 100, 4),
+            (3, 7, 100, 6),
+            (4, 8, 99, 5),
+            (5, 7, 98, 1),
+        ];
+        for (id, trader, price, qty) in orders {
+            let order = Order::try_new(id, trader, Side::Bid, price, qty, id).unwrap();
+            book.insert_order(order, id).unwrap();
+        }
+
+        let top: Vec<_> = book
+            .top_orders(Side::Bid, 1)
+            .iter()
+            .map(|o| (o.id.0, o.trader_id.0, o.quantity.0))
+            .collect();
+        assert_eq!(top, vec![(1, 7, 10), (2, 8, 4), (3, 7, 6)]);
+
+        let ids: Vec<_> = book
+            .top_orders(Side::Bid, 2)
+            .iter()
+            .map(|o| o.id.0)
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+        assert_eq!(book.top_orders(Side::Bid, 10).len(), 5);
+        assert!(book.top_orders(Side::Ask, 1).is_empty());
+    }
+
+    #[test]
+    fn cumulative_quantity_stops_at_the_limit() {
+        let mut book = OrderBook::new();
+        assert_eq!(book.cumulative_quantity_to(Side::Ask, i64::MAX), 0);
+        for (id, price, qty) in [(1, 101, 10), (2, 102, 20), (3, 102, 5), (4, 105, 40)] {
+            book.insert_order(ask(id, price, qty, id), 0).unwrap();
+        }
+        for (id, price, qty) in [(5, 99, 7), (6, 97, 3)] {
+            book.insert_order(bid(id, price, qty, id), 0).unwrap();
+        }
+
+        assert_eq!(book.cumulative_quantity_to(Side::Ask, 100), 0);
+        assert_eq!(book.cumulative_quantity_to(Side::Ask, 101), 10);
+        // Covers two of the three ask levels, both orders at 102 included.
+        assert_eq!(book.cumulative_quantity_to(Side::Ask, 104), 35);
+        assert_eq!(book.cumulative_quantity_to(Side::Ask, 105), 75);
+
+        assert_eq!(book.cumulative_quantity_to(Side::Bid, 100), 0);
+        assert_eq!(book.cumulative_quantity_to(Side::Bid, 98), 7);
+        assert_eq!(
+            book.cumulative_quantity_to(Side::Bid, i64::MIN),
+            book.total_bid_quantity()
+        );
+    }
+
+    #[test]
+    fn mid_rounds_down_for_negative_prices() {
+        let mut book = OrderBook::new();
+        book.insert_order(bid(1, -3, 10, 1), 0).unwrap();
+        book.insert_order(ask(2, -2, 10, 2), 0).unwrap();
+        assert_eq!(book.mid(), Some(-3));
+
+        let mut book = OrderBook::new();
+        book.insert_order(bid(1, i64::MAX - 1, 10, 1), 0).unwrap();
+        book.insert_order(ask(2, i64::MAX, 10, 2), 0).unwrap();
+        assert_eq!(book.mid(), Some(i64::MAX - 1));
+    }
+
     #[test]
     fn duplicate_id_rejected() {
         let mut book = OrderBook::new();
-        book.insert_order(bid(1, 100, 10, 1)).unwrap();
-        let err = book.insert_order(ask(1, 105, 5, 2)).unwrap_err();
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        let err = book.insert_order(ask(1, 105, 5, 2), 0).unwrap_err();
         assert_eq!(err, BookError::DuplicateOrderId(1));
     }
 
     #[test]
     fn cancel_order_updates_best() {
         let mut book = OrderBook::new();
-        book.insert_order(bid(1, 100, 10, 1)).unwrap();
-        book.insert_order(bid(2, 102, 10, 2)).unwrap();
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        book.insert_order(bid(2, 102, 10, 2), 0).unwrap();
 
         let cancelled = book.cancel_order(2).unwrap();
         assert_eq!(cancelled.id, 2);
@@ -309,7 +1025,7 @@ mod tests {
     #[test]
     fn cancel_last_order_clears_best() {
         let mut book = OrderBook::new();
-        book.insert_order(ask(1, 105, 10, 1)).unwrap();
+        book.insert_order(ask(1, 105, 10, 1), 0).unwrap();
         book.cancel_order(1).unwrap();
 
         assert_eq!(book.best_ask(), None);
@@ -326,9 +1042,9 @@ mod tests {
     #[test]
     fn fifo_ordering_within_level() {
         let mut book = OrderBook::new();
-        book.insert_order(bid(1, 100, 10, 1)).unwrap();
-        book.insert_order(bid(2, 100, 20, 2)).unwrap();
-        book.insert_order(bid(3, 100, 30, 3)).unwrap();
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        book.insert_order(bid(2, 100, 20, 2), 0).unwrap();
+        book.insert_order(bid(3, 100, 30, 3), 0).unwrap();
 
         let front = book.peek_front(Side::Bid, 100).unwrap();
         assert_eq!(front.id, 1);
@@ -337,7 +1053,7 @@ mod tests {
     #[test]
     fn reduce_front_partial() {
         let mut book = OrderBook::new();
-        book.insert_order(ask(1, 105, 100, 1)).unwrap();
+        book.insert_order(ask(1, 105, 100, 1), 0).unwrap();
 
         let remaining = book.reduce_front_quantity(Side::Ask, 105, 40).unwrap();
         assert_eq!(remaining, 60);
@@ -350,8 +1066,8 @@ mod tests {
     #[test]
     fn reduce_front_full_removes_order() {
         let mut book = OrderBook::new();
-        book.insert_order(ask(1, 105, 100, 1)).unwrap();
-        book.insert_order(ask(2, 105, 50, 2)).unwrap();
+        book.insert_order(ask(1, 105, 100, 1), 0).unwrap();
+        book.insert_order(ask(2, 105, 50, 2), 0).unwrap();
 
         let remaining = book.reduce_front_quantity(Side::Ask, 105, 100).unwrap();
         assert_eq!(remaining, 0);
@@ -364,8 +1080,8 @@ mod tests {
     #[test]
     fn reduce_front_removes_empty_level() {
         let mut book = OrderBook::new();
-        book.insert_order(ask(1, 105, 100, 1)).unwrap();
-        book.insert_order(ask(2, 110, 50, 2)).unwrap();
+        book.insert_order(ask(1, 105, 100, 1), 0).unwrap();
+        book.insert_order(ask(2, 110, 50, 2), 0).unwrap();
 
         book.reduce_front_quantity(Side::Ask, 105, 100).unwrap();
         assert_eq!(book.best_ask(), Some(110));
@@ -374,7 +1090,7 @@ mod tests {
     #[test]
     fn fill_exceeds_quantity_error() {
         let mut book = OrderBook::new();
-        book.insert_order(bid(1, 100, 10, 1)).unwrap();
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
 
         let err = book.reduce_front_quantity(Side::Bid, 100, 20).unwrap_err();
         assert_eq!(
@@ -397,30 +1113,99 @@ mod tests {
 
     #[test]
     fn arena_full_rejects_insert() {
-        let mut book = OrderBook::with_capacity(2);
-        book.insert_order(bid(1, 100, 10, 1)).unwrap();
-        book.insert_order(bid(2, 101, 10, 2)).unwrap();
-        let err = book.insert_order(bid(3, 102, 10, 3)).unwrap_err();
+        let mut book = OrderBook::with_fixed_capacity(2);
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        book.insert_order(bid(2, 101, 10, 2), 0).unwrap();
+        let err = book.insert_order(bid(3, 102, 10, 3), 0).unwrap_err();
         assert_eq!(err, BookError::ArenaFull);
         assert_eq!(book.order_count(), 2);
     }
 
     #[test]
     fn cancel_frees_slot_for_reuse() {
-        let mut book = OrderBook::with_capacity(2);
-        book.insert_order(bid(1, 100, 10, 1)).unwrap();
-        book.insert_order(bid(2, 101, 10, 2)).unwrap();
+        let mut book = OrderBook::with_fixed_capacity(2);
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        book.insert_order(bid(2, 101, 10, 2), 0).unwrap();
         assert_eq!(
-            book.insert_order(bid(3, 102, 10, 3)).unwrap_err(),
+            book.insert_order(bid(3, 102, 10, 3), 0).unwrap_err(),
             BookError::ArenaFull
         );
 
         book.cancel_order(1).unwrap();
-        book.insert_order(bid(3, 102, 10, 3)).unwrap();
+        book.insert_order(bid(3, 102, 10, 3), 0).unwrap();
         assert_eq!(book.order_count(), 2);
         assert_eq!(book.best_bid(), Some(102));
     }
 
+    #[test]
+    fn level_depth_present_and_absent() {
+        let mut book = OrderBook::with_capacity(8);
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        book.insert_order(bid(2, 100, 15, 2), 0).unwrap();
+        book.insert_order(ask(3, 105, 7, 3), 0).unwrap();
+
+        assert_eq!(book.level_depth(Side::Bid, 100), Some((25, 2)));
+        assert_eq!(book.level_depth(Side::Ask, 105), Some((7, 1)));
+        assert_eq!(book.level_depth(Side::Bid, 105), None);
+        assert_eq!(book.level_depth(Side::Ask, 100), None);
+
+        book.cancel_order(3).unwrap();
+        assert_eq!(book.level_depth(Side::Ask, 105), None);
+    }
+
+    #[test]
+    fn queue_position_counts_orders_ahead() {
+        let mut book = OrderBook::with_capacity(8);
+        for id in 1..=3 {
+            book.insert_order(bid(id, 100, 10, id), 0).unwrap();
+        }
+        assert_eq!(book.queue_position(1), Some(0));
+        assert_eq!(book.queue_position(3), Some(2));
+
+        book.cancel_order(1).unwrap();
+        assert_eq!(book.queue_position(3), Some(1));
+        assert_eq!(book.queue_position(1), None);
+    }
+
+    #[test]
+    fn queue_ahead_sums_orders_before_target() {
+        let mut book = OrderBook::with_capacity(8);
+        book.insert_order(ask(1, 100, 10, 1), 0).unwrap();
+        book.insert_order(ask(2, 100, 20, 2), 0).unwrap();
+        book.insert_order(ask(3, 100, 30, 3), 0).unwrap();
+
+        assert_eq!(book.queue_ahead(1), Some((0, 0)));
+        assert_eq!(book.queue_ahead(2), Some((1, 10)));
+        assert_eq!(book.queue_ahead(3), Some((2, 30)));
+        assert_eq!(book.queue_ahead(4), None);
+    }
+
+    #[test]
+    fn worse_insert_after_cancelling_best() {
+        let mut book = OrderBook::with_capacity(8);
+        book.insert_order(bid(1, 105, 10, 1), 0).unwrap();
+        book.insert_order(ask(2, 110, 10, 2), 0).unwrap();
+        book.cancel_order(1).unwrap();
+        book.cancel_order(2).unwrap();
+
+        book.insert_order(bid(3, 90, 10, 3), 0).unwrap();
+        book.insert_order(ask(4, 120, 10, 4), 0).unwrap();
+        assert_eq!(book.best_bid(), Some(90));
+        assert_eq!(book.best_ask(), Some(120));
+    }
+
+    #[test]
+    fn growable_book_accepts_past_initial_capacity() {
+        let mut book = OrderBook::with_capacity(2);
+        for id in 1..=5 {
+            book.insert_order(bid(id, 100 + id as i64, 10, id), 0)
+                .unwrap();
+        }
+        assert_eq!(book.order_count(), 5);
+        assert_eq!(book.best_bid(), Some(105));
+        assert_eq!(book.cancel_order(1).unwrap().price, 101);
+    }
+
     #[test]
     fn all_resting_orders_empty_book() {
         let book = OrderBook::new();
@@ -431,11 +1216,11 @@ mod tests {
     fn all_resting_orders_canonical_order() {
         let mut book = OrderBook::with_capacity(16);
         // Insert in mixed order
-        book.insert_order(bid(1, 100, 10, 1)).unwrap();
-        book.insert_order(ask(2, 110, 20, 2)).unwrap();
-        book.insert_order(bid(3, 102, 30, 3)).unwrap();
-        book.insert_order(ask(4, 108, 40, 4)).unwrap();
-        book.insert_order(bid(5, 100, 50, 5)).unwrap(); // same level as id=1
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        book.insert_order(ask(2, 110, 20, 2), 0).unwrap();
+        book.insert_order(bid(3, 102, 30, 3), 0).unwrap();
+        book.insert_order(ask(4, 108, 40, 4), 0).unwrap();
+        book.insert_order(bid(5, 100, 50, 5), 0).unwrap(); // same level as id=1
 
         let orders = book.all_resting_orders();
         assert_eq!(orders.len(), 5);
@@ -453,7 +1238,7 @@ mod tests {
     #[test]
     fn all_resting_orders_reflects_partial_fills() {
         let mut book = OrderBook::with_capacity(8);
-        book.insert_order(ask(1, 105, 100, 1)).unwrap();
+        book.insert_order(ask(1, 105, 100, 1), 0).unwrap();
         book.reduce_front_quantity(Side::Ask, 105, 40).unwrap();
 
         let orders = book.all_resting_orders();
@@ -464,9 +1249,9 @@ mod tests {
     #[test]
     fn cancel_middle_of_level() {
         let mut book = OrderBook::with_capacity(8);
-        book.insert_order(bid(1, 100, 10, 1)).unwrap();
-        book.insert_order(bid(2, 100, 20, 2)).unwrap();
-        book.insert_order(bid(3, 100, 30, 3)).unwrap();
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        book.insert_order(bid(2, 100, 20, 2), 0).unwrap();
+        book.insert_order(bid(3, 100, 30, 3), 0).unwrap();
 
         book.cancel_order(2).unwrap();
         assert_eq!(book.order_count(), 2);
@@ -478,4 +1263,171 @@ mod tests {
         let front = book.peek_front(Side::Bid, 100).unwrap();
         assert_eq!(front.id, 3);
     }
+
+    #[test]
+    fn capacity_hint_sizes_arena_and_index_with_headroom() {
+        let book = OrderBook::with_capacity_hint(1000, 1.5);
+        assert_eq!(book.arena.capacity(), 1500);
+        assert!(book.order_index.capacity() >= 1500);
+
+        // Growth below 1 (or NaN) still leaves room for the expected count.
+        assert_eq!(OrderBook::with_capacity_hint(10, 0.5).arena.capacity(), 10);
+        assert_eq!(
+            OrderBook::with_capacity_hint(10, f64::NAN).arena.capacity(),
+            10
+        );
+
+        let mut book = OrderBook::with_capacity_hint(2, 1.0);
+        for id in 1..=5 {
+            book.insert_order(bid(id, 100, 1, id), id).unwrap();
+        }
+        assert_eq!(book.arena.capacity(), 8);
+        assert!(book.order_index.capacity() >= 8);
+    }
+
+    #[test]
+    fn quantity_overflow_is_an_error() {
+        let mut book = OrderBook::with_capacity(8);
+        book.insert_order(bid(1, 100, u64::MAX - 1, 1), 1).unwrap();
+        assert_eq!(
+            book.insert_order(bid(2, 100, 2, 2), 2),
+            Err(BookError::QuantityOverflow)
+        );
+        // Another level on the same side shares the side total.
+        assert_eq!(
+            book.insert_order(bid(3, 99, 2, 3), 3),
+            Err(BookError::QuantityOverflow)
+        );
+        book.insert_order(bid(4, 100, 1, 4), 4).unwrap();
+        book.insert_order(ask(5, 101, u64::MAX, 5), 5).unwrap();
+        assert_eq!(
+            book.set_order_quantity(4, 2),
+            Err(BookError::QuantityOverflow)
+        );
+
+        assert_eq!(book.order_count(), 3);
+        assert_eq!(book.total_bid_quantity(), u64::MAX);
+        assert_eq!(book.level_depth(Side::Bid, 100), Some((u64::MAX, 2)));
+        assert_eq!(book.get_order(4).unwrap().quantity, 1);
+    }
+
+    #[test]
+    fn set_order_quantity_keeps_queue_position() {
+        let mut book = OrderBook::with_capacity(8);
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        book.insert_order(bid(2, 100, 20, 2), 0).unwrap();
+
+        let before = book.set_order_quantity(1, 4).unwrap();
+        assert_eq!(before.quantity, 10);
+        assert_eq!(book.get_order(1).unwrap().quantity, 4);
+        assert_eq!(book.peek_front(Side::Bid, 100).unwrap().id, 1);
+
+        // Level quantity tracks the change: 4 + 20 fills fully.
+        assert_eq!(book.reduce_front_quantity(Side::Bid, 100, 4).unwrap(), 0);
+        assert_eq!(book.reduce_front_quantity(Side::Bid, 100, 20).unwrap(), 0);
+        assert_eq!(book.order_count(), 0);
+        assert_eq!(
+            book.set_order_quantity(1, 5),
+            Err(BookError::OrderNotFound(1))
+        );
+    }
+
+    #[test]
+    fn iter_levels_priority_order() {
+        let mut book = OrderBook::with_capacity(16);
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        book.insert_order(bid(2, 102, 5, 2), 0).unwrap();
+        book.insert_order(bid(3, 100, 7, 3), 0).unwrap();
+        book.insert_order(ask(4, 110, 20, 4), 0).unwrap();
+        book.insert_order(ask(5, 105, 3, 5), 0).unwrap();
+
+        let bids: Vec<LevelView> = book.iter_levels(Side::Bid).collect();
+        assert_eq!(
+            bids,
+            [
+                LevelView {
+                    price: 102,
+                    quantity: 5,
+                    order_count: 1
+                },
+                LevelView {
+                    price: 100,
+                    quantity: 17,
+                    order_count: 2
+                },
+            ]
+        );
+
+        let ask_prices: Vec<i64> = book.iter_levels(Side::Ask).map(|l| l.price).collect();
+        assert_eq!(ask_prices, [105, 110]);
+    }
+
+    #[test]
+    fn iter_levels_tracks_fills_and_cancels() {
+        let mut book = OrderBook::with_capacity(8);
+        book.insert_order(ask(1, 105, 10, 1), 0).unwrap();
+        book.insert_order(ask(2, 105, 10, 2), 0).unwrap();
+        book.reduce_front_quantity(Side::Ask, 105, 4).unwrap();
+
+        let level = book.iter_levels(Side::Ask).next().unwrap();
+        assert_eq!(level.quantity, 16);
+        assert_eq!(level.order_count, 2);
+
+        book.cancel_order(1).unwrap();
+        book.cancel_order(2).unwrap();
+        assert_eq!(book.iter_levels(Side::Ask).count(), 0);
+        assert_eq!(book.iter_levels(Side::Bid).count(), 0);
+    }
+
+    #[test]
+    fn ordered_dump_ranks_within_level() {
+        let mut book = OrderBook::with_capacity(16);
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        book.insert_order(ask(2, 110, 20, 2), 0).unwrap();
+        book.insert_order(bid(3, 100, 30, 3), 0).unwrap();
+        book.insert_order(bid(4, 101, 5, 4), 0).unwrap();
+        book.insert_order(bid(5, 100, 8, 5), 0).unwrap();
+        book.cancel_order(3).unwrap();
+
+        let dump: Vec<(u64, u32)> = book
+            .all_resting_orders_ordered()
+            .iter()
+            .map(|r| (r.order.id.0, r.queue_rank))
+            .collect();
+        assert_eq!(dump, [(2, 0), (4, 0), (1, 0), (5, 1)]);
+
+        let plain: Vec<Order> = book
+            .all_resting_orders_ordered()
+            .into_iter()
+            .map(|r| r.order)
+            .collect();
+        assert_eq!(plain, book.all_resting_orders());
+    }
+
+    #[test]
+    fn side_totals_track_inserts_fills_and_cancels() {
+        let mut book = OrderBook::with_capacity(8);
+        assert_eq!(book.total_bid_quantity(), 0);
+        assert_eq!(book.total_ask_quantity(), 0);
+
+        book.insert_order(bid(1, 100, 10, 1), 0).unwrap();
+        book.insert_order(bid(2, 99, 20, 2), 0).unwrap();
+        book.insert_order(ask(3, 105, 7, 3), 0).unwrap();
+        assert_eq!(book.total_bid_quantity(), 30);
+        assert_eq!(book.total_ask_quantity(), 7);
+
+        book.reduce_front_quantity(Side::Bid, 100, 4).unwrap();
+        assert_eq!(book.total_bid_quantity(), 26);
+
+        book.reduce_front_quantity(Side::Bid, 100, 6).unwrap();
+        assert_eq!(book.total_bid_quantity(), 20);
+
+        book.set_order_quantity(2, 5).unwrap();
+        assert_eq!(book.total_bid_quantity(), 5);
+
+        book.cancel_order(2).unwrap();
+        book.cancel_order(3).unwrap();
+        assert_eq!(book.total_bid_quantity(), 0);
+        assert_eq!(book.total_ask_quantity(), 0);
+    }
 }
@@ -0,0 +1,213 @@
+/// A 32-bit checksum fed in pieces.
+pub trait Checksum: Default {
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self) -> u32;
+}
+
+/// CRC-32 (IEEE), via `crc32fast`. The portable default.
+#[derive(Default)]
+pub struct Crc32(crc32fast::Hasher);
+
+impl Checksum for Crc32 {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self) -> u32 {
+        self.0.finalize()
+    }
+}
+
+/// CRC-32C (Castagnoli). Uses the SSE4.2 or ARMv8 CRC instructions when the
+/// CPU has them, checked at run time, and a table otherwise.
+pub struct Crc32c(u32);
+
+impl Default for Crc32c {
+    fn default() -> Self {
+        Self(!0)
+    }
+}
+
+impl Checksum for Crc32c {
+    fn update(&mut self, data: &[u8]) {
+        self.0 = crc32c_update(self.0, data);
+    }
+
+    fn finalize(self) -> u32 {
+        !self.0
+    }
+}
+
+/// Which checksum a file's records use, stored in its header so files
+/// written by a build with the other default still verify.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Crc32,
+    Crc32c,
+}
+
+impl Algorithm {
+    /// Used for new files. CRC-32C with the `crc32c` feature, else CRC-32.
+    pub const DEFAULT: Self = if cfg!(feature = "crc32c") {
+        Self::Crc32c
+    } else {
+        Self::Crc32
+    };
+
+    /// Header value. Files from before the field existed hold 0, CRC-32.
+    pub fn id(self) -> u32 {
+        match self {
+            Self::Crc32 => 0,
+            Self::Crc32c => 1,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Option<Self> {
+        match id {
+            0 => Some(Self::Crc32),
+            1 => Some(Self::Crc32c),
+            _ => None,
+        }
+    }
+
+    /// Checksum over `parts` as if they were one buffer.
+    pub fn checksum(self, parts: &[&[u8]]) -> u32 {
+        match self {
+            Self::Crc32 => digest::<Crc32>(parts),
+            Self::Crc32c => digest::<Crc32c>(parts),
+        }
+    }
+}
+
+fn digest<C: Checksum>(parts: &[&[u8]]) -> u32 {
+    let mut c = C::default();
+    for part in parts {
+        c.update(part);
+    }
+    c.finalize()
+}
+
+/// Reflected CRC-32C polynomial.
+const POLY: u32 = 0x82F6_3B78;
+
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32c_software(mut crc: u32, data: &[u8]) -> u32 {
+    for &b in data {
+        crc = TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(target_arch = "x86_64")]
+fn crc32c_update(crc: u32, data: &[u8]) -> u32 {
+    if std::arch::is_x86_feature_detected!("sse4.2") {
+        // SAFETY: The CPU supports SSE4.2, checked above.
+        unsafe { crc32c_sse42(crc, data) }
+    } else {
+        crc32c_software(crc, data)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+fn crc32c_sse42(crc: u32, data: &[u8]) -> u32 {
+    use std::arch::x86_64::{_mm_crc32_u8, _mm_crc32_u64};
+
+    let mut chunks = data.chunks_exact(8);
+    let mut crc = u64::from(crc);
+    for chunk in &mut chunks {
+        crc = _mm_crc32_u64(crc, u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    let mut crc = crc as u32;
+    for &b in chunks.remainder() {
+        crc = _mm_crc32_u8(crc, b);
+    }
+    crc
+}
+
+#[cfg(target_arch = "aarch64")]
+fn crc32c_update(crc: u32, data: &[u8]) -> u32 {
+    if std::arch::is_aarch64_feature_detected!("crc") {
+        // SAFETY: The CPU has the CRC extension, checked above.
+        unsafe { crc32c_armv8(crc, data) }
+    } else {
+        crc32c_software(crc, data)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "crc")]
+fn crc32c_armv8(crc: u32, data: &[u8]) -> u32 {
+    use std::arch::aarch64::{__crc32cb, __crc32cd};
+
+    let mut chunks = data.chunks_exact(8);
+    let mut crc = crc;
+    for chunk in &mut chunks {
+        crc = __crc32cd(crc, u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    for &b in chunks.remainder() {
+        crc = __crc32cb(crc, b);
+    }
+    crc
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn crc32c_update(crc: u32, data: &[u8]) -> u32 {
+    crc32c_software(crc, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_values() {
+        assert_eq!(Algorithm::Crc32.checksum(&[b"123456789"]), 0xCBF4_3926);
+        assert_eq!(Algorithm::Crc32c.checksum(&[b"123456789"]), 0xE306_9283);
+    }
+
+    #[test]
+    fn crc32c_paths_agree_for_every_length() {
+        let data: Vec<u8> = (0..100u8).map(|i| i.wrapping_mul(37)).collect();
+        for len in 0..data.len() {
+            let piece = &data[..len];
+            assert_eq!(
+                crc32c_update(!0, piece),
+                crc32c_software(!0, piece),
+                "len {len}"
+            );
+            let (a, b) = piece.split_at(len / 3);
+            assert_eq!(
+                Algorithm::Crc32c.checksum(&[a, b]),
+                !crc32c_software(!0, piece)
+            );
+        }
+    }
+
+    #[test]
+    fn ids_round_trip() {
+        for algorithm in [Algorithm::Crc32, Algorithm::Crc32c] {
+            assert_eq!(Algorithm::from_id(algorithm.id()), Some(algorithm));
+        }
+        assert_eq!(Algorithm::from_id(2), None);
+    }
+}
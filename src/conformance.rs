@@ -0,0 +1,150 @@
+use std::fs;
+use std::path::Path;
+
+use crate::matching::{Fill, MatchingEngine};
+use crate::order::Order;
+use crate::protocol::EngineCommand;
+use crate::recovery::{ReplayMode, apply_command, recover};
+use crate::wal::Wal;
+
+/// Runs `cmds` through a fresh engine, as the matching thread and WAL replay
+/// apply them, and returns every fill in order plus the final
+/// `OrderBook::state_hash`. Matching reads no clock and no randomness, so the
+/// same commands always give the same result.
+pub fn run_sequence(cmds: &[EngineCommand]) -> (Vec<Fill>, u64) {
+    let (fills, engine) = run(cmds, |_, _| {});
+    (fills, engine.book().state_hash())
+}
+
+/// Where `check_sequence` found two runs of the same commands disagreeing.
+#[derive(Debug)]
+pub enum ConformanceError {
+    /// Two fresh engines differed in the named part of their result.
+    RunsDiverged(&'static str),
+    /// The engine recovered from the WAL differed from a fresh one.
+    RecoveryDiverged(&'static str),
+    /// Writing or recovering the WAL failed.
+    Recovery(String),
+}
+
+impl std::fmt::Display for ConformanceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RunsDiverged(what) => write!(f, "two fresh runs differ in {what}"),
+            Self::RecoveryDiverged(what) => write!(f, "recovered engine differs in {what}"),
+            Self::Recovery(e) => write!(f, "wal round trip failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ConformanceError {}
+
+/// `run_sequence` on two fresh engines and once more through a WAL in
+/// `data_dir` and strict recovery, checking that fills, resting orders and
+/// state hashes all agree. Returns the first run's result.
+///
+/// `data_dir` should be empty: recovery picks up any WAL or snapshots
+/// already there.
+pub fn check_sequence(
+    cmds: &[EngineCommand],
+    data_dir: &Path,
+) -> Result<(Vec<Fill>, u64), ConformanceError> {
+    let recovery = |e: &dyn std::fmt::Display| ConformanceError::Recovery(e.to_string());
+    fs::create_dir_all(data_dir).map_err(|e| recovery(&e))?;
+    let mut wal = Wal::open(data_dir.join("wal.bin")).map_err(|e| recovery(&e))?;
+    let mut wal_error = None;
+    let (fills, first) = run(cmds, |cmd, outcome| {
+        if wal_error.is_none() {
+            match wal.append(cmd) {
+                Ok(_) => wal.record_outcome(outcome),
+                Err(e) => wal_error = Some(e),
+            }
+        }
+    });
+    if let Some(e) = wal_error {
+        return Err(recovery(&e));
+    }
+    wal.flush_async().map_err(|e| recovery(&e))?;
+    drop(wal);
+
+    let (second_fills, second) = run(cmds, |_, _| {});
+    compare(&first, &second).map_err(ConformanceError::RunsDiverged)?;
+    if fills != second_fills {
+        return Err(ConformanceError::RunsDiverged("fills"));
+    }
+
+    let (recovered, _) =
+        recover(data_dir, arena_capacity(cmds), ReplayMode::Strict).map_err(|e| recovery(&e))?;
+    compare(&first, &recovered).map_err(ConformanceError::RecoveryDiverged)?;
+
+    Ok((fills, first.book().state_hash()))
+}
+
+/// Applies `cmds` to a fresh engine, handing each command and its outcome to
+/// `after` once it has been applied.
+fn run(
+    cmds: &[EngineCommand],
+    mut after: impl FnMut(&EngineCommand, crate::wal::Outcome),
+) -> (Vec<Fill>, MatchingEngine) {
+    let mut engine = MatchingEngine::with_capacity(arena_capacity(cmds));
+    let mut fills = Vec::new();
+    for cmd in cmds {
+        let outcome = apply_command(&mut engine, cmd.clone(), |f| fills.extend_from_slice(f));
+        after(cmd, outcome);
+    }
+    (fills, engine)
+}
+
+/// Each command rests at most one order, so this never runs out of slots.
+fn arena_capacity(cmds: &[EngineCommand]) -> u32 {
+    u32::try_from(cmds.len()).unwrap_or(u32::MAX - 1).max(1)
+}
+
+fn compare(a: &MatchingEngine, b: &MatchingEngine) -> Result<(), &'static str> {
+    let resting = |e: &MatchingEngine| -> Vec<Order> { e.book().all_resting_orders() };
+    if resting(a) != resting(b) {
+        return Err("resting orders");
+    }
+    if a.book().state_hash() != b.book().state_hash() {
+        return Err("state hash");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::Side;
+
+    fn order(id: u64, side: Side, price: i64, qty: u64) -> Order {
+        Order::try_new(id, id, side, price, qty, id).unwrap()
+    }
+
+    #[test]
+    fn same_commands_give_same_fills_and_hash_through_recovery() {
+        let cmds = vec![
+            EngineCommand::NewOrder(order(1, Side::Ask, 105, 10)),
+            EngineCommand::NewOrder(order(2, Side::Ask, 105, 5)),
+            EngineCommand::NewOrder(order(3, Side::Ask, 106, 20)),
+            EngineCommand::NewOrder(order(4, Side::Bid, 100, 8)),
+            EngineCommand::CancelReplace {
+                old_id: 4,
+                new_order: order(5, Side::Bid, 106, 18),
+            },
+            EngineCommand::CancelOrder { order_id: 3 },
+            EngineCommand::NewOrder(order(6, Side::Bid, 104, 7)),
+            EngineCommand::CancelAll { trader_id: 6 },
+        ];
+
+        let dir = tempfile::tempdir().unwrap();
+        let (fills, hash) = check_sequence(&cmds, &dir.path().join("data")).unwrap();
+        assert_eq!((fills.clone(), hash), run_sequence(&cmds));
+
+        // Time priority at 105: order 1 fills before order 2, then 106.
+        let makers: Vec<_> = fills
+            .iter()
+            .map(|f| (f.maker_order_id, f.quantity))
+            .collect();
+        assert_eq!(makers, vec![(1, 10), (2, 5), (3, 3)]);
+    }
+}
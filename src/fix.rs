@@ -0,0 +1,500 @@
+use crate::matching::Fill;
+use crate::order::{Order, OrderError, Side};
+use crate::protocol::EngineCommand;
+
+pub const SOH: u8 = 0x01;
+
+pub const TAG_BEGIN_STRING: u32 = 8;
+pub const TAG_BODY_LENGTH: u32 = 9;
+pub const TAG_CHECKSUM: u32 = 10;
+pub const TAG_CL_ORD_ID: u32 = 11;
+pub const TAG_EXEC_ID: u32 = 17;
+pub const TAG_LAST_PX: u32 = 31;
+pub const TAG_LAST_QTY: u32 = 32;
+pub const TAG_MSG_TYPE: u32 = 35;
+pub const TAG_ORDER_QTY: u32 = 38;
+pub const TAG_ORD_STATUS: u32 = 39;
+pub const TAG_ORD_TYPE: u32 = 40;
+pub const TAG_PRICE: u32 = 44;
+pub const TAG_SIDE: u32 = 54;
+pub const TAG_EXEC_TYPE: u32 = 150;
+
+const BEGIN_STRING: &str = "FIX.4.2";
+const MSG_TYPE_NEW_ORDER_SINGLE: &str = "D";
+const MSG_TYPE_EXECUTION_REPORT: &str = "8";
+const ORD_TYPE_LIMIT: &str = "2";
+
+/// Decimal places used to map FIX decimal fields onto integer ticks and lots.
+///
+/// With `price_decimals = 2`, `44=150.05` becomes `price = 15005`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixScale {
+    pub price_decimals: u32,
+    pub quantity_decimals: u32,
+}
+
+impl Default for FixScale {
+    fn default() -> Self {
+        Self {
+            price_decimals: 2,
+            quantity_decimals: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FixError {
+    MalformedField,
+    MissingTag(u32),
+    InvalidValue(u32),
+    PrecisionLoss(u32),
+    UnexpectedMsgType(String),
+    UnsupportedOrdType(String),
+    InvalidSide(String),
+    ZeroQuantity,
+}
+
+impl std::fmt::Display for FixError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MalformedField => write!(f, "malformed tag=value field"),
+            Self::MissingTag(t) => write!(f, "missing required tag {t}"),
+            Self::InvalidValue(t) => write!(f, "invalid value for tag {t}"),
+            Self::PrecisionLoss(t) => {
+                write!(f, "tag {t} has more decimals than the configured scale")
+            }
+            Self::UnexpectedMsgType(m) => write!(f, "unexpected msg type: {m}"),
+            Self::UnsupportedOrdType(t) => write!(f, "unsupported ord type: {t}"),
+            Self::InvalidSide(s) => write!(f, "invalid side: {s}"),
+            Self::ZeroQuantity => write!(f, "zero quantity"),
+        }
+    }
+}
+
+impl std::error::Error for FixError {}
+
+/// Borrowed view of the fields the adapter understands; unknown tags are skipped.
+#[derive(Default)]
+struct NewOrderFields<'a> {
+    msg_type: Option<&'a str>,
+    cl_ord_id: Option<&'a str>,
+    side: Option<&'a str>,
+    order_qty: Option<&'a str>,
+    price: Option<&'a str>,
+    ord_type: Option<&'a str>,
+}
+
+fn parse_fields(buf: &[u8]) -> Result<NewOrderFields<'_>, FixError> {
+    let mut fields = NewOrderFields::default();
+
+    for field in buf.split(|&b| b == SOH).filter(|f| !f.is_empty()) {
+        let eq = field
+            .iter()
+            .position(|&b| b == b'=')
+            .ok_or(FixError::MalformedField)?;
+        let tag: u32 = std::str::from_utf8(&field[..eq])
+            .ok()
+            .and_then(|t| t.parse().ok())
+            .ok_or(FixError::MalformedField)?;
+        let value =
+            std::str::from_utf8(&field[eq + 1..]).map_err(|_| FixError::InvalidValue(tag))?;
+
+        match tag {
+            TAG_MSG_TYPE => fields.msg_type = Some(value),
+            TAG_CL_ORD_ID => fields.cl_ord_id = Some(value),
+            TAG_SIDE => fields.side = Some(value),
+            TAG_ORDER_QTY => fields.order_qty = Some(value),
+            TAG_PRICE => fields.price = Some(value),
+            TAG_ORD_TYPE => fields.ord_type = Some(value),
+            _ => {}
+        }
+    }
+
+    Ok(fields)
+}
+
+/// Parses a decimal string into an integer scaled by `10^decimals` without
+/// going through floating point. Extra non-zero decimals are rejected.
+fn parse_decimal(value: &str, decimals: u32, tag: u32) -> Result<i128, FixError> {
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value),
+    };
+    let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+
+    if int_part.is_empty() && frac_part.is_empty() {
+        return Err(FixError::InvalidValue(tag));
+    }
+    if !int_part
+        .bytes()
+        .chain(frac_part.bytes())
+        .all(|b| b.is_ascii_digit())
+    {
+        return Err(FixError::InvalidValue(tag));
+    }
+
+    let decimals = decimals as usize;
+    if frac_part.len() > decimals && frac_part[decimals..].bytes().any(|b| b != b'0') {
+        return Err(FixError::PrecisionLoss(tag));
+    }
+
+    let mut scaled: i128 = 0;
+    let frac_digits = frac_part
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(decimals);
+    for b in int_part.bytes().chain(frac_digits) {
+        scaled = scaled
+            .checked_mul(10)
+            .and_then(|v| v.checked_add((b - b'0') as i128))
+            .ok_or(FixError::InvalidValue(tag))?;
+    }
+
+    Ok(if negative { -scaled } else { scaled })
+}
+
+fn format_decimal(value: i128, decimals: u32) -> String {
+    if decimals == 0 {
+        return value.to_string();
+    }
+    let divisor = 10_i128.pow(decimals);
+    let sign = if value < 0 { "-" } else { "" };
+    let abs = value.unsigned_abs();
+    let divisor = divisor as u128;
+    format!(
+        "{sign}{}.{:0width$}",
+        abs / divisor,
+        abs % divisor,
+        width = decimals as usize
+    )
+}
+
+fn decode_fix_side(value: &str) -> Result<Side, FixError> {
+    match value {
+        "1" => Ok(Side::Bid),
+        "2" => Ok(Side::Ask),
+        other => Err(FixError::InvalidSide(other.to_string())),
+    }
+}
+
+/// Decodes a FIX 4.2 NewOrderSingle (`35=D`) into an engine command.
+///
+/// FIX carries no numeric trader identity at the message level, so the
+/// session owner supplies `trader_id`. Only limit orders (`40=2`) are accepted.
+pub fn decode_new_order_single(
+    buf: &[u8],
+    trader_id: u64,
+    scale: &FixScale,
+) -> Result<EngineCommand, FixError> {
+    let fields = parse_fields(buf)?;
+
+    if let Some(msg_type) = fields.msg_type
+        && msg_type != MSG_TYPE_NEW_ORDER_SINGLE
+    {
+        return Err(FixError::UnexpectedMsgType(msg_type.to_string()));
+    }
+
+    let cl_ord_id = fields
+        .cl_ord_id
+        .ok_or(FixError::MissingTag(TAG_CL_ORD_ID))?;
+    let side = fields.side.ok_or(FixError::MissingTag(TAG_SIDE))?;
+    let order_qty = fields
+        .order_qty
+        .ok_or(FixError::MissingTag(TAG_ORDER_QTY))?;
+    let ord_type = fields.ord_type.ok_or(FixError::MissingTag(TAG_ORD_TYPE))?;
+
+    if ord_type != ORD_TYPE_LIMIT {
+        return Err(FixError::UnsupportedOrdType(ord_type.to_string()));
+    }
+
+    let price = fields.price.ok_or(FixError::MissingTag(TAG_PRICE))?;
+
+    let id: u64 = cl_ord_id
+        .parse()
+        .map_err(|_| FixError::InvalidValue(TAG_CL_ORD_ID))?;
+    let side = decode_fix_side(side)?;
+    let quantity = u64::try_from(parse_decimal(
+        order_qty,
+        scale.quantity_decimals,
+        TAG_ORDER_QTY,
+    )?)
+    .map_err(|_| FixError::InvalidValue(TAG_ORDER_QTY))?;
+    let price = i64::try_from(parse_decimal(price, scale.price_decimals, TAG_PRICE)?)
+        .map_err(|_| FixError::InvalidValue(TAG_PRICE))?;
+
+    let order = Order::try_new(id, trader_id, side, price, quantity, 0).map_err(|e| match e {
+        OrderError::ZeroQuantity => FixError::ZeroQuantity,
+        OrderError::InvalidPrice(_) => FixError::InvalidValue(TAG_PRICE),
+    })?;
+    Ok(EngineCommand::NewOrder(order))
+}
+
+/// Renders one side of a `Fill` as a FIX 4.2 ExecutionReport (`35=8`).
+///
+/// `cl_ord_id` selects which order the report is addressed to and
+/// `fully_filled` drives `39`/`150` (`2` = filled, `1` = partially filled).
+pub fn encode_execution_report(
+    exec_id: u32,
+    cl_ord_id: u64,
+    fill: &Fill,
+    fully_filled: bool,
+    scale: &FixScale,
+) -> String {
+    let status = if fully_filled { "2" } else { "1" };
+    let last_px = format_decimal(fill.price as i128, scale.price_decimals);
+    let last_qty = format_decimal(fill.quantity as i128, scale.quantity_decimals);
+
+    let body = format!(
+        "{TAG_MSG_TYPE}={MSG_TYPE_EXECUTION_REPORT}\x01\
+         {TAG_CL_ORD_ID}={cl_ord_id}\x01\
+         {TAG_EXEC_ID}={exec_id}\x01\
+         {TAG_LAST_PX}={last_px}\x01\
+         {TAG_LAST_QTY}={last_qty}\x01\
+         {TAG_ORD_STATUS}={status}\x01\
+         {TAG_EXEC_TYPE}={status}\x01"
+    );
+
+    let mut msg = format!(
+        "{TAG_BEGIN_STRING}={BEGIN_STRING}\x01{TAG_BODY_LENGTH}={}\x01{body}",
+        body.len()
+    );
+    let checksum = msg.bytes().fold(0u8, |acc, b| acc.wrapping_add(b));
+    msg.push_str(&format!("{TAG_CHECKSUM}={checksum:03}\x01"));
+    msg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fix(fields: &[&str]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for f in fields {
+            buf.extend_from_slice(f.as_bytes());
+            buf.push(SOH);
+        }
+        buf
+    }
+
+    fn decode_order(buf: &[u8], scale: &FixScale) -> Result<Order, FixError> {
+        match decode_new_order_single(buf, 7, scale)? {
+            EngineCommand::NewOrder(order) => Ok(order),
+            other => panic!("expected NewOrder, got {other:?}"),
+        }
+    }
+
+    fn field(msg: &str, tag: u32) -> Option<&str> {
+        msg.split('\x01')
+            .filter_map(|f| f.split_once('='))
+            .find(|(t, _)| t.parse::<u32>().ok() == Some(tag))
+            .map(|(_, v)| v)
+    }
+
+    #[test]
+    fn decode_limit_buy() {
+        let buf = fix(&[
+            "8=FIX.4.2",
+            "35=D",
+            "11=42",
+            "54=1",
+            "38=100",
+            "44=150.05",
+            "40=2",
+        ]);
+        let order = decode_order(&buf, &FixScale::default()).unwrap();
+        assert_eq!(order.id, 42);
+        assert_eq!(order.trader_id, 7);
+        assert_eq!(order.side, Side::Bid);
+        assert_eq!(order.price, 15005);
+        assert_eq!(order.quantity, 100);
+        assert_eq!(order.timestamp, 0);
+    }
+
+    #[test]
+    fn decode_sell_with_lot_scale() {
+        let scale = FixScale {
+            price_decimals: 4,
+            quantity_decimals: 2,
+        };
+        let buf = fix(&["11=9", "54=2", "38=1.5", "44=-0.25", "40=2"]);
+        let order = decode_order(&buf, &scale).unwrap();
+        assert_eq!(order.side, Side::Ask);
+        assert_eq!(order.quantity, 150);
+        assert_eq!(order.price, -2500);
+    }
+
+    #[test]
+    fn unknown_tags_ignored() {
+        let buf = fix(&[
+            "11=1", "1=ACCT", "54=1", "9999=x", "38=10", "44=1", "40=2", "10=123",
+        ]);
+        let order = decode_order(&buf, &FixScale::default()).unwrap();
+        assert_eq!(order.price, 100);
+        assert_eq!(order.quantity, 10);
+    }
+
+    #[test]
+    fn missing_required_tags() {
+        let scale = FixScale::default();
+        let cases = [
+            (vec!["54=1", "38=10", "44=1", "40=2"], TAG_CL_ORD_ID),
+            (vec!["11=1", "38=10", "44=1", "40=2"], TAG_SIDE),
+            (vec!["11=1", "54=1", "44=1", "40=2"], TAG_ORDER_QTY),
+            (vec!["11=1", "54=1", "38=10", "40=2"], TAG_PRICE),
+            (vec!["11=1", "54=1", "38=10", "44=1"], TAG_ORD_TYPE),
+        ];
+        for (fields, tag) in cases {
+            let buf = fix(&fields);
+            assert_eq!(decode_order(&buf, &scale), Err(FixError::MissingTag(tag)));
+        }
+    }
+
+    #[test]
+    fn market_order_unsupported() {
+        let buf = fix(&["11=1", "54=1", "38=10", "40=1"]);
+        assert_eq!(
+            decode_order(&buf, &FixScale::default()),
+            Err(FixError::UnsupportedOrdType("1".to_string()))
+        );
+    }
+
+    #[test]
+    fn wrong_msg_type_rejected() {
+        let buf = fix(&["35=F", "11=1", "54=1", "38=10", "44=1", "40=2"]);
+        assert_eq!(
+            decode_order(&buf, &FixScale::default()),
+            Err(FixError::UnexpectedMsgType("F".to_string()))
+        );
+    }
+
+    #[test]
+    fn invalid_side_and_values() {
+        let scale = FixScale::default();
+        let buf = fix(&["11=1", "54=5", "38=10", "44=1", "40=2"]);
+        assert_eq!(
+            decode_order(&buf, &scale),
+            Err(FixError::InvalidSide("5".to_string()))
+        );
+
+        let buf = fix(&["11=abc", "54=1", "38=10", "44=1", "40=2"]);
+        assert_eq!(
+            decode_order(&buf, &scale),
+            Err(FixError::InvalidValue(TAG_CL_ORD_ID))
+        );
+
+        let buf = fix(&["11=1", "54=1", "38=-10", "44=1", "40=2"]);
+        assert_eq!(
+            decode_order(&buf, &scale),
+            Err(FixError::InvalidValue(TAG_ORDER_QTY))
+        );
+    }
+
+    #[test]
+    fn precision_beyond_scale_rejected() {
+        let scale = FixScale::default();
+        let buf = fix(&["11=1", "54=1", "38=10", "44=150.055", "40=2"]);
+        assert_eq!(
+            decode_order(&buf, &scale),
+            Err(FixError::PrecisionLoss(TAG_PRICE))
+        );
+
+        // Trailing zeros past the scale are harmless
+        let buf = fix(&["11=1", "54=1", "38=10", "44=150.0500", "40=2"]);
+        assert_eq!(decode_order(&buf, &scale).unwrap().price, 15005);
+    }
+
+    #[test]
+    fn zero_quantity_rejected() {
+        let buf = fix(&["11=1", "54=1", "38=0", "44=1", "40=2"]);
+        assert_eq!(
+            decode_order(&buf, &FixScale::default()),
+            Err(FixError::ZeroQuantity)
+        );
+    }
+
+    #[test]
+    fn malformed_field_rejected() {
+        let buf = fix(&["11=1", "garbage", "54=1"]);
+        assert_eq!(
+            decode_order(&buf, &FixScale::default()),
+            Err(FixError::MalformedField)
+        );
+    }
+
+    #[test]
+    fn encode_execution_report_fields() {
+        let fill = Fill {
+            taker_order_id: 10,
+            maker_order_id: 20,
+            price: 15005,
+            quantity: 50,
+            maker_fully_filled: true,
+            taker_seq: 0,
+            maker_seq: 0,
+        };
+        let msg =
+            encode_execution_report(3, fill.maker_order_id, &fill, true, &FixScale::default());
+
+        assert!(msg.starts_with("8=FIX.4.2\x019="));
+        assert_eq!(field(&msg, TAG_MSG_TYPE), Some("8"));
+        assert_eq!(field(&msg, TAG_CL_ORD_ID), Some("20"));
+        assert_eq!(field(&msg, TAG_EXEC_ID), Some("3"));
+        assert_eq!(field(&msg, TAG_LAST_PX), Some("150.05"));
+        assert_eq!(field(&msg, TAG_LAST_QTY), Some("50"));
+        assert_eq!(field(&msg, TAG_ORD_STATUS), Some("2"));
+        assert_eq!(field(&msg, TAG_EXEC_TYPE), Some("2"));
+    }
+
+    #[test]
+    fn encode_partial_fill_and_negative_price() {
+        let fill = Fill {
+            taker_order_id: 10,
+            maker_order_id: 20,
+            price: -5,
+            quantity: 7,
+            maker_fully_filled: false,
+            taker_seq: 0,
+            maker_seq: 0,
+        };
+        let msg =
+            encode_execution_report(1, fill.taker_order_id, &fill, false, &FixScale::default());
+        assert_eq!(field(&msg, TAG_LAST_PX), Some("-0.05"));
+        assert_eq!(field(&msg, TAG_ORD_STATUS), Some("1"));
+        assert_eq!(field(&msg, TAG_EXEC_TYPE), Some("1"));
+    }
+
+    #[test]
+    fn encode_body_length_and_checksum() {
+        let fill = Fill {
+            taker_order_id: 1,
+            maker_order_id: 2,
+            price: 100,
+            quantity: 1,
+            maker_fully_filled: true,
+            taker_seq: 0,
+            maker_seq: 0,
+        };
+        let msg = encode_execution_report(1, 1, &fill, true, &FixScale::default());
+
+        let checksum_at = msg.rfind("10=").unwrap();
+        let expected = msg[..checksum_at]
+            .bytes()
+            .fold(0u8, |acc, b| acc.wrapping_add(b));
+        assert_eq!(
+            field(&msg, TAG_CHECKSUM),
+            Some(format!("{expected:03}").as_str())
+        );
+
+        let body_start = msg.find("35=").unwrap();
+        let body_len: usize = field(&msg, TAG_BODY_LENGTH).unwrap().parse().unwrap();
+        assert_eq!(body_len, checksum_at - body_start);
+    }
+
+    #[test]
+    fn decimal_roundtrip() {
+        for (value, decimals) in [(15005_i128, 2), (-1, 4), (0, 3), (42, 0)] {
+            let s = format_decimal(value, decimals);
+            assert_eq!(parse_decimal(&s, decimals, 0).unwrap(), value);
+        }
+    }
+}
@@ -1,28 +1,192 @@
-use std::io::{self, Read};
+use std::io::{self, BufReader, Read, Write};
 use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::matching::MatchingEngine;
+use crate::latency::LatencyHistogram;
+use crate::matching::{AddOrderResult, AmendPolicy, CrossPolicy, FillPricing, MatchingEngine};
+use crate::order::{Order, Side};
 use crate::protocol::{
-    EXECUTION_REPORT_SIZE, EngineCommand, ProtocolError, decode_message, encode_execution_report,
-    message_size,
+    ADMIN_SNAPSHOT_REPLY_SIZE, AdminSnapshotReply, AggTrade, BOOK_UPDATE_SIZE, BookSnapshot,
+    BookUpdate, CancelReport, EXECUTION_REPORT_SIZE, EngineCommand, ExecutionReport,
+    FRAME_HEADER_SIZE, LevelDelta, MAX_FRAME_SIZE, MSG_BATCH, OrderAck, OrderReject, ProtocolError,
+    REJECT_ARENA_FULL, REJECT_RING_FULL, REJECT_SIZE, Reject, SNAPSHOT_DISABLED, SNAPSHOT_FAILED,
+    SNAPSHOT_SAVED, batch_size, decode_batch, decode_message, encode_admin_snapshot_reply,
+    encode_agg_trade, encode_book_snapshot, encode_book_update, encode_cancel_report,
+    encode_execution_report, encode_level_delta, encode_order_ack, encode_order_reject,
+    encode_reject, message_size, reject_reason,
 };
-use crate::ring::{self, Consumer, Producer};
-use crate::snapshot::Snapshot;
-use crate::wal::Wal;
+use crate::ring::{self, Consumer, Producer, RingError};
+use crate::snapshot::{DeltaSnapshot, Snapshot, SnapshotError};
+use crate::top_of_book::TopOfBookWriter;
+use crate::trade_log::TradeLog;
+use crate::wal::{Outcome, Wal, WalRetention};
+
+pub use crate::recovery::ReplayMode;
+pub use crate::snapshot::SnapshotCompression;
+
+/// Where inbound orders get their timestamps from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClockSource {
+    /// Nanoseconds since the Unix epoch.
+    #[default]
+    Wall,
+    /// A counter that ticks once per stamped order, continuing from the WAL
+    /// record count on restart. Runs are reproducible given the same input.
+    Logical,
+}
+
+/// What the matching thread does between polls of an empty ring.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WaitStrategy {
+    /// `thread::yield_now`: busy when alone on a core, gives it up to
+    /// other runnable threads.
+    #[default]
+    Yield,
+    /// `hint::spin_loop`: the lowest pickup latency, at the cost of a core
+    /// pinned at 100%.
+    Spin,
+    /// `thread::park_timeout`: sleeps between polls, so a command waits up to
+    /// `timeout` (plus timer slack) before it is seen. Nothing wakes the
+    /// thread early.
+    Park { timeout: Duration },
+    /// Spin for `spins` consecutive empty polls, then park as above.
+    SpinThenPark { spins: u32, timeout: Duration },
+}
+
+impl WaitStrategy {
+    /// Waits once; `empty_polls` counts the empty polls in a row so far.
+    fn idle(self, empty_polls: u32) {
+        match self {
+            Self::Yield => thread::yield_now(),
+            Self::Spin => std::hint::spin_loop(),
+            Self::Park { timeout } => thread::park_timeout(timeout),
+            Self::SpinThenPark { spins, timeout } => {
+                if empty_polls < spins {
+                    std::hint::spin_loop();
+                } else {
+                    thread::park_timeout(timeout);
+                }
+            }
+        }
+    }
+}
+
+/// How the network thread finds where one inbound message ends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// Sizes come from the type byte, plus the count for a batch.
+    #[default]
+    Fixed,
+    /// Each message is preceded by its length as a u32 LE, up to
+    /// `GatewayConfig::max_message_size`. Replies stay unframed.
+    LengthPrefixed,
+}
+
+/// What the network thread does when the ring to the matching thread is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RingFullPolicy {
+    /// Spin until a slot frees up, however long that takes.
+    #[default]
+    Block,
+    /// Spin for up to `timeout`, then drop the client with `GatewayError::RingFull`.
+    Disconnect { timeout: Duration },
+    /// Spin for up to `timeout`, then drop the command and send the client a
+    /// `MSG_REJECT`; later commands are still accepted.
+    Reject { timeout: Duration },
+}
+
+impl RingFullPolicy {
+    fn timeout(self) -> Option<Duration> {
+        match self {
+            Self::Block => None,
+            Self::Disconnect { timeout } | Self::Reject { timeout } => Some(timeout),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct GatewayConfig {
     pub listen_addr: SocketAddr,
     pub multicast_addr: SocketAddr,
+    /// Rounded up to the next power of two if it isn't one.
     pub ring_capacity: usize,
     pub arena_capacity: u32,
     pub data_dir: Option<PathBuf>,
     pub snapshot_interval: u64,
+    /// Also take a full snapshot once this many seconds have passed since
+    /// the last one, even if `snapshot_interval` commands haven't, checked
+    /// every `max_batch_before_housekeeping` commands and while the ring is
+    /// empty. `None` snapshots by command count only.
+    pub snapshot_interval_secs: Option<u64>,
+    /// Commands the matching thread runs back to back before it reads the
+    /// clock for `snapshot_interval_secs`, so a flood that never empties the
+    /// ring can't postpone a due snapshot past this many commands. 0 acts
+    /// as 1.
+    pub max_batch_before_housekeeping: u32,
+    /// Commands between delta snapshots taken in between full ones; `None`
+    /// disables deltas and the engine's change tracking.
+    pub delta_snapshot_interval: Option<u64>,
+    pub snapshot_compression: SnapshotCompression,
+    /// Full snapshots kept on disk; older ones are deleted after each new save.
+    pub snapshot_retention: usize,
+    /// Start a new WAL segment once the active one would grow past this
+    /// many bytes; `None` keeps the WAL in one file.
+    pub wal_segment_size: Option<u64>,
+    /// Applied to sealed WAL segments after each full snapshot.
+    pub wal_retention: WalRetention,
+    /// Order expiries are still compared against wall-clock time.
+    pub clock_source: ClockSource,
+    pub replay_mode: ReplayMode,
+    /// Price every fill at the maker's level or at the mid with the taker's
+    /// limit. A change takes effect with a full snapshot at startup, so the
+    /// WAL before it still replays under the old rule.
+    pub fill_pricing: FillPricing,
+    /// Which same-id cancel/replaces keep queue priority. Pinned by a
+    /// snapshot at startup like `fill_pricing`.
+    pub amend_policy: AmendPolicy,
+    /// Whether an order at exactly the opposite best trades or rests. Pinned
+    /// by a snapshot at startup like `fill_pricing`.
+    pub cross_policy: CrossPolicy,
+    /// Run `MatchingEngine::warmup` and pre-fault the WAL's mapped pages
+    /// before accepting connections. Adds startup time and, the first time
+    /// the WAL is flushed, writes back its whole mapping once.
+    pub warmup: bool,
+    /// Multicast a `MSG_BOOK_UPDATE` whenever the top of book changes.
+    pub publish_book_updates: bool,
+    /// Follow each run of same-price fills with a `MSG_AGG_TRADE` summing it.
+    pub publish_agg_trades: bool,
+    /// Multicast a `MSG_LEVEL_DELTA` for every price level a command changes.
+    pub publish_level_deltas: bool,
+    pub ring_full_policy: RingFullPolicy,
+    pub framing: Framing,
+    /// Longest inbound message or frame, checked before its body is read;
+    /// a client sending more is dropped. Values above
+    /// `protocol::MAX_FRAME_SIZE` act as that.
+    pub max_message_size: usize,
+    /// End a client that disconnects partway through a message with
+    /// `ProtocolError::TruncatedMessage` instead of treating it as a clean
+    /// disconnect. Either way it is logged and counted.
+    pub reject_truncated_messages: bool,
+    /// Idle behaviour of the matching thread when the ring is empty.
+    pub wait_strategy: WaitStrategy,
+    /// `SO_SNDBUF` for the multicast socket, in bytes; `None` keeps the OS
+    /// default. Linux caps it at `net.core.wmem_max`.
+    pub feed_send_buffer: Option<usize>,
+    /// Extra attempts for a feed message whose send fails before it is
+    /// dropped and counted.
+    pub feed_send_retries: u32,
+    /// Local address of the interface the feed goes out on; `None` leaves
+    /// the choice to the routing table.
+    pub multicast_interface: Option<Ipv4Addr>,
+    /// Router hops the feed may cross; 1 keeps it on the local subnet.
+    pub multicast_ttl: u32,
+    /// Deliver the feed to subscribers on this host as well.
+    pub multicast_loop: bool,
 }
 
 impl Default for GatewayConfig {
@@ -34,6 +198,32 @@ impl Default for GatewayConfig {
             arena_capacity: 1_048_576,
             data_dir: None,
             snapshot_interval: 10_000,
+            snapshot_interval_secs: None,
+            max_batch_before_housekeeping: 256,
+            delta_snapshot_interval: None,
+            snapshot_compression: SnapshotCompression::None,
+            snapshot_retention: 3,
+            wal_segment_size: None,
+            wal_retention: WalRetention::default(),
+            clock_source: ClockSource::Wall,
+            replay_mode: ReplayMode::Fast,
+            fill_pricing: FillPricing::MakerPrice,
+            amend_policy: AmendPolicy::ResetPriority,
+            cross_policy: CrossPolicy::AtEqualPrice,
+            warmup: false,
+            publish_book_updates: false,
+            publish_agg_trades: false,
+            publish_level_deltas: false,
+            ring_full_policy: RingFullPolicy::Block,
+            framing: Framing::Fixed,
+            max_message_size: MAX_FRAME_SIZE,
+            reject_truncated_messages: false,
+            wait_strategy: WaitStrategy::Yield,
+            feed_send_buffer: None,
+            feed_send_retries: 0,
+            multicast_interface: None,
+            multicast_ttl: 1,
+            multicast_loop: true,
         }
     }
 }
@@ -42,6 +232,11 @@ impl Default for GatewayConfig {
 pub enum GatewayError {
     Io(io::Error),
     Protocol(ProtocolError),
+    /// The ring stayed full for the whole `RingFullPolicy::Disconnect` timeout.
+    RingFull,
+    Ring(RingError),
+    /// `multicast_interface` is not a local address the feed can be sent from.
+    MulticastInterface(Ipv4Addr, io::Error),
 }
 
 impl std::fmt::Display for GatewayError {
@@ -49,6 +244,11 @@ impl std::fmt::Display for GatewayError {
         match self {
             Self::Io(e) => write!(f, "io error: {e}"),
             Self::Protocol(e) => write!(f, "protocol error: {e}"),
+            Self::RingFull => write!(f, "ring buffer full, client disconnected"),
+            Self::Ring(e) => write!(f, "ring error: {e}"),
+            Self::MulticastInterface(addr, e) => {
+                write!(f, "cannot send multicast from interface {addr}: {e}")
+            }
         }
     }
 }
@@ -58,6 +258,9 @@ impl std::error::Error for GatewayError {
         match self {
             Self::Io(e) => Some(e),
             Self::Protocol(e) => Some(e),
+            Self::Ring(e) => Some(e),
+            Self::MulticastInterface(_, e) => Some(e),
+            Self::RingFull => None,
         }
     }
 }
@@ -74,6 +277,12 @@ impl From<ProtocolError> for GatewayError {
     }
 }
 
+impl From<RingError> for GatewayError {
+    fn from(e: RingError) -> Self {
+        Self::Ring(e)
+    }
+}
+
 fn now_nanos() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -81,284 +290,2180 @@ fn now_nanos() -> u64 {
         .as_nanos() as u64
 }
 
+/// Stamps orders on the network thread. Wall stamps never go backwards:
+/// time priority assumes they don't, and the system clock can be stepped
+/// back by NTP or an operator. A reading behind the last stamp is clamped to
+/// it until the clock catches up.
+struct Clock {
+    source: ClockSource,
+    next: u64,
+    wall: Box<dyn FnMut() -> u64>,
+    /// Latest wall stamp handed out.
+    last_wall: u64,
+    /// Wall stamps clamped so far.
+    regressions: u64,
+}
+
+impl Clock {
+    fn new(source: ClockSource, start: u64) -> Self {
+        Self {
+            source,
+            next: start,
+            wall: Box::new(now_nanos),
+            last_wall: 0,
+            regressions: 0,
+        }
+    }
+
+    /// Reads wall time from `wall` instead of the system clock.
+    #[cfg(test)]
+    fn with_wall(mut self, wall: impl FnMut() -> u64 + 'static) -> Self {
+        self.wall = Box::new(wall);
+        self
+    }
+
+    fn stamp(&mut self) -> u64 {
+        match self.source {
+            ClockSource::Wall => {
+                let now = (self.wall)();
+                if now >= self.last_wall {
+                    self.last_wall = now;
+                } else {
+                    if self.regressions == 0 {
+                        eprintln!(
+                            "ferrox: wall clock went back {} ns, holding order timestamps until it catches up",
+                            self.last_wall - now
+                        );
+                    }
+                    self.regressions += 1;
+                }
+                self.last_wall
+            }
+            ClockSource::Logical => {
+                self.next += 1;
+                self.next
+            }
+        }
+    }
+}
+
+/// Socket reads go through a buffer this large, so a burst of small messages
+/// is parsed from memory rather than with a syscall per message.
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// `snapshots` delivers the matching thread's answers to `MSG_REQUEST_SNAPSHOT`
+/// and `MSG_ADMIN_SNAPSHOT`; without it such requests go unanswered.
 fn handle_client(
-    mut stream: TcpStream,
+    stream: TcpStream,
     producer: &mut Producer<EngineCommand>,
+    clock: &mut Clock,
+    metrics: &mut ClientMetrics,
+    config: &GatewayConfig,
+    snapshots: Option<&Receiver<Vec<u8>>>,
     shutdown: &AtomicBool,
 ) -> Result<(), GatewayError> {
-    let mut type_buf = [0u8; 1];
+    let policy = config.ring_full_policy;
+    let mut reader = BufReader::with_capacity(READ_BUFFER_SIZE, &stream);
+    let mut buf = [0u8; MAX_FRAME_SIZE];
+    let msg_buf = &mut buf[..config.max_message_size.min(MAX_FRAME_SIZE)];
+    let mut backlogged = false;
 
     loop {
-        match stream.read_exact(&mut type_buf) {
-            Ok(()) => {}
-            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
-            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => break,
-            Err(e) => return Err(e.into()),
+        let size = match config.framing {
+            Framing::Fixed => read_fixed(&mut reader, msg_buf)?,
+            Framing::LengthPrefixed => read_framed(&mut reader, msg_buf)?,
+        };
+        let size = match size {
+            Inbound::Message(size) => size,
+            Inbound::Closed => break,
+            Inbound::Truncated { received, expected } => {
+                metrics.truncated_messages += 1;
+                eprintln!(
+                    "ferrox: client disconnected {received} bytes into a {expected}-byte message"
+                );
+                if config.reject_truncated_messages {
+                    return Err(ProtocolError::TruncatedMessage { received, expected }.into());
+                }
+                break;
+            }
+        };
+
+        if msg_buf[0] == MSG_BATCH {
+            for cmd in decode_batch(&msg_buf[..size])? {
+                push_command(producer, clock, policy, &stream, cmd)?;
+            }
+        } else {
+            let cmd = decode_message(&msg_buf[..size])?;
+            let wants_reply = matches!(
+                cmd,
+                EngineCommand::RequestSnapshot | EngineCommand::AdminSnapshot
+            );
+            // The reply follows every command queued before the request, so
+            // reading waits for it to keep the client's view in order.
+            if push_command(producer, clock, policy, &stream, cmd)?
+                && wants_reply
+                && let Some(snapshots) = snapshots
+            {
+                match snapshots.recv() {
+                    Ok(reply) => (&stream).write_all(&reply)?,
+                    Err(_) => break,
+                }
+            }
+        }
+
+        let over = producer.len() * 5 > producer.capacity() * 4;
+        if over && !backlogged {
+            eprintln!("ferrox: ring buffer over 80% full, matching thread is falling behind");
         }
+        backlogged = over;
+    }
 
-        let msg_type = type_buf[0];
-        let size = message_size(msg_type)?;
+    shutdown.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// Network-thread counters for one client connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ClientMetrics {
+    /// Disconnects that cut a message short, as opposed to clean ones
+    /// between messages.
+    truncated_messages: u64,
+}
 
-        let mut msg_buf = [0u8; 48];
-        msg_buf[0] = msg_type;
+/// How reading the next message ended.
+#[derive(Debug, PartialEq, Eq)]
+enum Inbound {
+    /// A whole message of this many bytes is in the buffer.
+    Message(usize),
+    /// The client went away between messages.
+    Closed,
+    /// The client went away `received` bytes into a message or frame of
+    /// `expected`.
+    Truncated { received: usize, expected: usize },
+}
 
-        if size > 1 {
-            match stream.read_exact(&mut msg_buf[1..size]) {
-                Ok(()) => {}
-                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
-                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => break,
-                Err(e) => return Err(e.into()),
-            }
+/// Reads one message sized by its type byte (and a batch's count). A
+/// message longer than `buf` is an error.
+fn read_fixed(stream: &mut impl Read, buf: &mut [u8]) -> Result<Inbound, GatewayError> {
+    if read_up_to(stream, &mut buf[..1])? == 0 {
+        return Ok(Inbound::Closed);
+    }
+    let mut size = check_size(message_size(buf[0])?, buf.len())?;
+    let received = 1 + read_up_to(stream, &mut buf[1..size])?;
+    if received < size {
+        return Ok(Inbound::Truncated {
+            received,
+            expected: size,
+        });
+    }
+    if buf[0] == MSG_BATCH {
+        let header_size = size;
+        size = check_size(batch_size(&buf[..header_size])?, buf.len())?;
+        let received = header_size + read_up_to(stream, &mut buf[header_size..size])?;
+        if received < size {
+            return Ok(Inbound::Truncated {
+                received,
+                expected: size,
+            });
+        }
+    }
+    Ok(Inbound::Message(size))
+}
+
+/// Reads a 4-byte LE length and then that many bytes, up to `buf`'s length.
+/// The message decoder only checks the frame holds its type's fields;
+/// trailing bytes are ignored.
+fn read_framed(stream: &mut impl Read, buf: &mut [u8]) -> Result<Inbound, GatewayError> {
+    let mut header = [0u8; FRAME_HEADER_SIZE];
+    match read_up_to(stream, &mut header)? {
+        0 => return Ok(Inbound::Closed),
+        FRAME_HEADER_SIZE => {}
+        received => {
+            return Ok(Inbound::Truncated {
+                received,
+                expected: FRAME_HEADER_SIZE,
+            });
         }
+    }
+    let len = u32::from_le_bytes(header);
+    if len == 0 {
+        return Err(ProtocolError::InvalidFrameLength(len).into());
+    }
+    let size = check_size(len as usize, buf.len())?;
+    let received = read_up_to(stream, &mut buf[..size])?;
+    if received < size {
+        return Ok(Inbound::Truncated {
+            received: FRAME_HEADER_SIZE + received,
+            expected: FRAME_HEADER_SIZE + size,
+        });
+    }
+    Ok(Inbound::Message(size))
+}
 
-        let mut cmd = decode_message(&msg_buf[..size])?;
+fn check_size(size: usize, max: usize) -> Result<usize, GatewayError> {
+    if size > max {
+        return Err(ProtocolError::MessageTooLarge { size, max }.into());
+    }
+    Ok(size)
+}
 
-        if let EngineCommand::NewOrder(ref mut order) = cmd {
-            order.timestamp = now_nanos();
+/// Fills `buf` from the stream and returns the bytes read, fewer than
+/// `buf.len()` only if the client went away first.
+fn read_up_to(stream: &mut impl Read, buf: &mut [u8]) -> Result<usize, GatewayError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match stream.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => break,
+            Err(e) => return Err(e.into()),
         }
+    }
+    Ok(filled)
+}
 
-        loop {
-            match producer.push(cmd) {
-                Ok(()) => break,
-                Err(ring::Full(returned)) => {
-                    cmd = returned;
-                    thread::yield_now();
+/// Stamps `cmd` and pushes it, spinning while the ring is full for as long
+/// as `policy` allows. Returns false if the command was rejected instead.
+fn push_command(
+    producer: &mut Producer<EngineCommand>,
+    clock: &mut Clock,
+    policy: RingFullPolicy,
+    mut stream: &TcpStream,
+    mut cmd: EngineCommand,
+) -> Result<bool, GatewayError> {
+    match cmd {
+        EngineCommand::NewOrder(ref mut order)
+        | EngineCommand::CancelReplace {
+            new_order: ref mut order,
+            ..
+        } => order.timestamp = clock.stamp(),
+        EngineCommand::CancelOrder { .. }
+        | EngineCommand::CancelAll { .. }
+        | EngineCommand::ReduceOrder { .. }
+        | EngineCommand::Halt { .. }
+        | EngineCommand::Resume
+        | EngineCommand::RequestSnapshot
+        | EngineCommand::AdminSnapshot => {}
+    }
+
+    let mut full_since = None;
+    loop {
+        match producer.push(cmd) {
+            Ok(()) => return Ok(true),
+            Err(ring::Full(returned)) => {
+                cmd = returned;
+                let since = *full_since.get_or_insert_with(Instant::now);
+                if let Some(timeout) = policy.timeout()
+                    && since.elapsed() >= timeout
+                {
+                    break;
                 }
+                thread::yield_now();
             }
         }
     }
 
-    shutdown.store(true, Ordering::Release);
-    Ok(())
+    if let RingFullPolicy::Disconnect { .. } = policy {
+        return Err(GatewayError::RingFull);
+    }
+    let order_id = match cmd {
+        EngineCommand::NewOrder(order)
+        | EngineCommand::CancelReplace {
+            new_order: order, ..
+        } => order.id.0,
+        EngineCommand::CancelOrder { order_id } | EngineCommand::ReduceOrder { order_id, .. } => {
+            order_id
+        }
+        EngineCommand::CancelAll { trader_id } => trader_id,
+        EngineCommand::Halt { .. }
+        | EngineCommand::Resume
+        | EngineCommand::RequestSnapshot
+        | EngineCommand::AdminSnapshot => 0,
+    };
+    let mut buf = [0u8; REJECT_SIZE];
+    encode_reject(
+        &mut buf,
+        &Reject {
+            order_id,
+            reason: REJECT_RING_FULL,
+        },
+    )?;
+    stream.write_all(&buf)?;
+    Ok(false)
 }
 
 fn process_command(
     cmd: EngineCommand,
     engine: &mut MatchingEngine,
     wal: &mut Option<Wal>,
-    udp: &UdpSocket,
-    multicast_addr: SocketAddr,
-    seq_num: &mut u32,
-    report_buf: &mut [u8; EXECUTION_REPORT_SIZE],
+    trades: &mut Option<TradeLog>,
+    publisher: &mut Publisher,
 ) {
-    if let Some(w) = wal {
-        let _ = w.append(&cmd);
+    match cmd {
+        EngineCommand::RequestSnapshot => {
+            publisher.reply_snapshot(engine);
+            return;
+        }
+        // Needs the snapshotter, so `matching_loop` answers it instead.
+        EngineCommand::AdminSnapshot => return,
+        _ => {}
     }
+    let wal_record = wal.as_mut().and_then(|w| w.append(&cmd).ok()).unwrap_or(0);
+    // After a failed append the WAL's last record is an earlier command's,
+    // so only an appended command gets an outcome.
+    let mut record_outcome = |outcome| {
+        if let Some(w) = wal.as_mut().filter(|_| wal_record != 0) {
+            w.record_outcome(outcome);
+        }
+    };
+    publisher.watch_levels(engine, &cmd);
 
-    match cmd {
+    let (result, order_id, timestamp, side) = match cmd {
         EngineCommand::NewOrder(order) => {
-            let timestamp = order.timestamp;
-            if let Ok(result) = engine.add_order(order) {
-                for fill in &result.fills {
-                    *seq_num = seq_num.wrapping_add(1);
-                    if encode_execution_report(report_buf, *seq_num, fill, timestamp).is_ok() {
-                        let _ = udp.send_to(report_buf, multicast_addr);
-                    }
-                }
-            }
+            let (order_id, timestamp, side) = (order.id, order.timestamp, order.side);
+            (engine.add_order(order), order_id, timestamp, side)
         }
         EngineCommand::CancelOrder { order_id } => {
-            let _ = engine.cancel_order(order_id);
+            let result = engine.cancel_order(order_id);
+            record_outcome(Outcome::of_cancel(&result));
+            publisher.publish_level_deltas(engine, [], None);
+            publisher.publish_top_of_book(engine, None);
+            return;
         }
-    }
-}
+        EngineCommand::ReduceOrder {
+            order_id,
+            reduce_by,
+        } => {
+            let result = engine.reduce_order(order_id, reduce_by);
+            record_outcome(Outcome::of_reduce(&result));
+            publisher.publish_level_deltas(engine, [], None);
+            publisher.publish_top_of_book(engine, None);
+            return;
+        }
+        EngineCommand::CancelAll { trader_id } => {
+            let cancelled = engine.cancel_all_for_trader(trader_id);
+            record_outcome(Outcome::of_cancel_all(&cancelled));
+            publisher.publish_cancels(&cancelled);
+            publisher.publish_level_deltas(engine, cancelled.iter().map(level_of), None);
+            publisher.publish_top_of_book(engine, None);
+            return;
+        }
+        EngineCommand::Halt { policy } => {
+            engine.halt(policy);
+            record_outcome(Outcome::Applied);
+            return;
+        }
+        EngineCommand::Resume => {
+            engine.resume();
+            record_outcome(Outcome::Applied);
+            return;
+        }
+        EngineCommand::RequestSnapshot | EngineCommand::AdminSnapshot => {
+            unreachable!("answered above")
+        }
+        EngineCommand::CancelReplace { old_id, new_order } => {
+            let (order_id, timestamp, side) = (new_order.id, new_order.timestamp, new_order.side);
+            (
+                engine.cancel_replace(old_id, new_order),
+                order_id,
+                timestamp,
+                side,
+            )
+        }
+    };
 
-fn matching_loop(
-    mut consumer: Consumer<EngineCommand>,
-    mut engine: MatchingEngine,
-    mut wal: Option<Wal>,
-    snapshot_dir: Option<PathBuf>,
-    snapshot_interval: u64,
-    udp: UdpSocket,
-    multicast_addr: SocketAddr,
-    shutdown: Arc<AtomicBool>,
-) {
-    let mut seq_num: u32 = 0;
-    let mut report_buf = [0u8; EXECUTION_REPORT_SIZE];
-    let mut cmds_since_snapshot: u64 = 0;
+    record_outcome(Outcome::of_add(&result));
 
-    loop {
-        match consumer.pop() {
-            Ok(cmd) => {
-                process_command(
-                    cmd,
-                    &mut engine,
-                    &mut wal,
-                    &udp,
-                    multicast_addr,
-                    &mut seq_num,
-                    &mut report_buf,
-                );
+    if let (Ok(result), Some(t)) = (&result, trades) {
+        for fill in &result.fills {
+            let _ = t.append(wal_record, fill, timestamp);
+        }
+    }
 
-                cmds_since_snapshot += 1;
-                if let (Some(w), Some(dir)) = (&wal, &snapshot_dir) {
-                    if cmds_since_snapshot >= snapshot_interval {
-                        let snap = Snapshot::capture(&engine, w.record_count());
-                        let _ = snap.save(dir);
-                        let _ = w.flush_async();
-                        cmds_since_snapshot = 0;
-                    }
-                }
-            }
-            Err(_empty) => {
-                if shutdown.load(Ordering::Acquire) {
-                    // Drain remaining commands
-                    while let Ok(cmd) = consumer.pop() {
-                        process_command(
-                            cmd,
-                            &mut engine,
-                            &mut wal,
-                            &udp,
-                            multicast_addr,
-                            &mut seq_num,
-                            &mut report_buf,
-                        );
-                    }
-                    break;
-                }
-                thread::yield_now();
+    match &result {
+        Ok(result) => {
+            publisher.publish_ack(result, timestamp);
+            publisher.publish_fills(result, timestamp);
+        }
+        Err(e) => {
+            let reason = reject_reason(e);
+            publisher.publish_reject(order_id.0, reason, timestamp);
+            if reason == REJECT_ARENA_FULL && engine.metrics().arena_full_rejects == 1 {
+                eprintln!("ferrox: arena is full, rejecting orders that would rest");
             }
         }
     }
+    let maker_side = match side {
+        Side::Bid => Side::Ask,
+        Side::Ask => Side::Bid,
+    };
+    let fills = result.as_ref().map_or(&[][..], |r| &r.fills[..]);
+    publisher.publish_level_deltas(
+        engine,
+        fills.iter().map(|f| (maker_side, f.price)),
+        Some(timestamp),
+    );
+    publisher.publish_top_of_book(engine, Some(timestamp));
 }
 
-pub fn run(config: GatewayConfig) -> Result<(), GatewayError> {
-    let (mut producer, consumer) = ring::ring_buffer::<EngineCommand>(config.ring_capacity);
-
-    let shutdown = Arc::new(AtomicBool::new(false));
-    let shutdown_match = Arc::clone(&shutdown);
+fn level_of(order: &Order) -> (Side, i64) {
+    (order.side, order.price.0)
+}
 
-    let (engine, wal, snapshot_dir) = if let Some(ref data_dir) = config.data_dir {
-        match crate::recovery::recover(data_dir, config.arena_capacity) {
-            Ok((engine, wal)) => {
-                let snap_dir = data_dir.join("snapshots");
-                (engine, Some(wal), Some(snap_dir))
-            }
-            Err(e) => {
-                eprintln!("ferrox: recovery failed: {e}, starting fresh");
-                (
-                    MatchingEngine::with_capacity(config.arena_capacity),
-                    None,
-                    None,
-                )
-            }
-        }
-    } else {
-        (
-            MatchingEngine::with_capacity(config.arena_capacity),
-            None,
-            None,
-        )
+/// Cancels resting orders whose expiry has passed, reading the clock only
+/// while one is pending. Each is logged as a cancel so replay drops it too.
+/// Returns the expired orders.
+fn expire_due_orders(engine: &mut MatchingEngine, wal: &mut Option<Wal>) -> Vec<Order> {
+    let Some(next) = engine.next_expiry() else {
+        return Vec::new();
     };
+    let now = now_nanos();
+    if next > now {
+        return Vec::new();
+    }
+    let expired = engine.expire_orders(now);
+    for order in &expired {
+        if let Some(w) = wal
+            && w.append(&EngineCommand::CancelOrder {
+                order_id: order.id.0,
+            })
+            .is_ok()
+        {
+            w.record_outcome(Outcome::Cancelled);
+        }
+    }
+    expired
+}
 
-    let udp = UdpSocket::bind("0.0.0.0:0")?;
-    udp.set_multicast_ttl_v4(1)?;
+const FEED_BUF_SIZE: usize = if EXECUTION_REPORT_SIZE > BOOK_UPDATE_SIZE {
+    EXECUTION_REPORT_SIZE
+} else {
+    BOOK_UPDATE_SIZE
+};
 
-    let multicast_addr = config.multicast_addr;
-    let snapshot_interval = config.snapshot_interval;
+type TopOfBook = (Option<(i64, u64)>, Option<(i64, u64)>);
 
-    let match_thread = thread::spawn(move || {
-        matching_loop(
-            consumer,
-            engine,
-            wal,
-            snapshot_dir,
-            snapshot_interval,
-            udp,
-            multicast_addr,
-            shutdown_match,
-        );
-    });
+/// Minimum time between two warnings about dropped feed messages.
+const DROP_WARNING_INTERVAL: Duration = Duration::from_secs(1);
 
-    let listener = TcpListener::bind(config.listen_addr)?;
-    eprintln!("ferrox: listening on {}", config.listen_addr);
+/// Multicast send counters. A dropped message leaves a gap in the feed's
+/// sequence that subscribers have to recover over TCP.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct FeedMetrics {
+    sent: u64,
+    /// Sends that failed and were attempted again.
+    retries: u64,
+    /// Messages given up on once the retries ran out.
+    dropped: u64,
+}
 
-    let (stream, peer) = listener.accept()?;
-    eprintln!("ferrox: client connected from {peer}");
+/// Level a command may change, with its `(quantity, order_count)` before it ran.
+type WatchedLevel = (Side, i64, Option<(u64, u32)>);
 
-    let result = handle_client(stream, &mut producer, &shutdown);
+/// Multicast output. Execution reports, book updates, level deltas, acks and
+/// rejects share one sequence so subscribers can detect gaps across the
+/// whole feed.
+struct Publisher {
+    udp: UdpSocket,
+    addr: SocketAddr,
+    seq_num: u32,
+    buf: [u8; FEED_BUF_SIZE],
+    book_updates: bool,
+    agg_trades: bool,
+    level_deltas: bool,
+    /// Filled by `watch_levels`, drained by `publish_level_deltas`.
+    watched: Vec<WatchedLevel>,
+    last_top: TopOfBook,
+    snapshots: Option<Sender<Vec<u8>>>,
+    /// In-process copy of every execution report, for a co-located consumer.
+    output_ring: Option<Producer<ExecutionReport>>,
+    /// Reports the output ring had no room for.
+    output_dropped: u64,
+    top_of_book: Option<TopOfBookWriter>,
+    wal_lag: Option<Arc<WalLag>>,
+    latency: Option<Arc<LatencyHistogram>>,
+    send_retries: u32,
+    metrics: FeedMetrics,
+    /// When drops were last warned about, and how many happened since.
+    last_drop_warning: Option<Instant>,
+    drops_since_warning: u64,
+}
 
-    shutdown.store(true, Ordering::Release);
-    eprintln!("ferrox: client disconnected, shutting down");
+impl Publisher {
+    fn new(udp: UdpSocket, addr: SocketAddr, book_updates: bool) -> Self {
+        Self {
+            udp,
+            addr,
+            seq_num: 0,
+            buf: [0u8; FEED_BUF_SIZE],
+            book_updates,
+            agg_trades: false,
+            level_deltas: false,
+            watched: Vec::new(),
+            last_top: (None, None),
+            snapshots: None,
+            output_ring: None,
+            output_dropped: 0,
+            top_of_book: None,
+            wal_lag: None,
+            latency: None,
+            send_retries: 0,
+            metrics: FeedMetrics::default(),
+            last_drop_warning: None,
+            drops_since_warning: 0,
+        }
+    }
 
-    match_thread.join().expect("matching thread panicked");
+    fn with_send_retries(mut self, retries: u32) -> Self {
+        self.send_retries = retries;
+        self
+    }
 
-    result
-}
+    /// Sends the first `n` bytes of `buf`, retrying up to `send_retries`
+    /// times. A message still unsent is dropped, counted and warned about at
+    /// most once per `DROP_WARNING_INTERVAL`.
+    fn send(&mut self, n: usize) {
+        let mut attempts = 0;
+        let err = loop {
+            match self.udp.send_to(&self.buf[..n], self.addr) {
+                Ok(_) => {
+                    self.metrics.sent += 1;
+                    return;
+                }
+                Err(_) if attempts < self.send_retries => {
+                    attempts += 1;
+                    self.metrics.retries += 1;
+                    thread::yield_now();
+                }
+                Err(e) => break e,
+            }
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::order::{Order, Side};
-    use crate::protocol::{self, EXECUTION_REPORT_SIZE, NEW_ORDER_SIZE, encode_new_order};
-    use std::io::Write;
-    use std::net::TcpStream;
-    use std::time::Duration;
+        self.metrics.dropped += 1;
+        self.drops_since_warning += 1;
+        if self
+            .last_drop_warning
+            .is_none_or(|at| at.elapsed() >= DROP_WARNING_INTERVAL)
+        {
+            eprintln!(
+                "ferrox: dropped {} feed message(s) since the last warning, {} in total: {err}",
+                self.drops_since_warning, self.metrics.dropped
+            );
+            self.last_drop_warning = Some(Instant::now());
+            self.drops_since_warning = 0;
+        }
+    }
 
-    #[test]
-    fn engine_command_is_send() {
-        fn assert_send<T: Send>() {}
-        assert_send::<EngineCommand>();
+    fn with_agg_trades(mut self, agg_trades: bool) -> Self {
+        self.agg_trades = agg_trades;
+        self
     }
 
-    #[test]
-    fn engine_command_size() {
-        let size = std::mem::size_of::<EngineCommand>();
-        assert!(size <= 64, "EngineCommand too large: {size} bytes");
+    fn with_level_deltas(mut self, level_deltas: bool) -> Self {
+        self.level_deltas = level_deltas;
+        self
     }
 
-    #[test]
-    fn gateway_config_defaults() {
-        let config = GatewayConfig::default();
-        assert_eq!(config.listen_addr.port(), 9000);
-        assert_eq!(config.multicast_addr.port(), 9001);
-        assert_eq!(config.ring_capacity, 65536);
-        assert_eq!(config.arena_capacity, 1_048_576);
-        assert!(config.data_dir.is_none());
-        assert_eq!(config.snapshot_interval, 10_000);
+    fn with_snapshot_replies(mut self, snapshots: Sender<Vec<u8>>) -> Self {
+        self.snapshots = Some(snapshots);
+        self
     }
 
-    #[test]
-    fn tcp_to_ring_roundtrip() {
-        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
-        let addr = listener.local_addr().unwrap();
+    fn with_output_ring(mut self, output_ring: Option<Producer<ExecutionReport>>) -> Self {
+        self.output_ring = output_ring;
+        self
+    }
 
-        let (mut producer, mut consumer) = ring::ring_buffer::<EngineCommand>(64);
-        let shutdown = Arc::new(AtomicBool::new(false));
-        let shutdown_ref = &shutdown;
+    fn with_top_of_book(mut self, top_of_book: Option<TopOfBookWriter>) -> Self {
+        self.top_of_book = top_of_book;
+        self
+    }
 
-        let client = thread::spawn(move || {
-            let mut stream = TcpStream::connect(addr).unwrap();
-            let order = Order {
-                id: 42,
-                trader_id: 7,
-                side: Side::Bid,
-                price: 15005,
-                quantity: 100,
-                timestamp: 0,
-            };
-            let mut buf = [0u8; NEW_ORDER_SIZE];
-            encode_new_order(&mut buf, &order).unwrap();
-            stream.write_all(&buf).unwrap();
-        });
+    /// Refreshes the shared top of book, if there is one, once a command and
+    /// the expiries after it have run.
+    fn publish_book_view(&mut self, engine: &MatchingEngine) {
+        if let Some(writer) = &mut self.top_of_book {
+            writer.publish(engine.book());
+        }
+    }
 
-        let (stream, _) = listener.accept().unwrap();
-        handle_client(stream, &mut producer, shutdown_ref).unwrap();
+    fn with_wal_lag(mut self, wal_lag: Option<Arc<WalLag>>) -> Self {
+        self.wal_lag = wal_lag;
+        self
+    }
 
-        client.join().unwrap();
+    fn with_latency(mut self, latency: Option<Arc<LatencyHistogram>>) -> Self {
+        self.latency = latency;
+        self
+    }
 
-        let cmd = consumer.pop().unwrap();
-        match cmd {
-            EngineCommand::NewOrder(order) => {
-                assert_eq!(order.id, 42);
-                assert_eq!(order.trader_id, 7);
-                assert_eq!(order.side, Side::Bid);
-                assert_eq!(order.price, 15005);
+    /// When a command was popped, if its latency is being recorded.
+    fn start_timer(&self) -> Option<Instant> {
+        self.latency.as_ref().map(|_| Instant::now())
+    }
+
+    fn record_latency(&self, popped: Option<Instant>) {
+        if let (Some(latency), Some(popped)) = (&self.latency, popped) {
+            latency.record(popped.elapsed());
+        }
+    }
+
+    /// Refreshes the shared `WalLag`, if there is one, after anything that
+    /// appends to or flushes the WAL.
+    fn publish_wal_lag(&self, wal: &Option<Wal>) {
+        if let (Some(lag), Some(wal)) = (&self.wal_lag, wal) {
+            lag.store(wal);
+        }
+    }
+
+    fn reply_admin_snapshot(&self, reply: &AdminSnapshotReply) {
+        let Some(snapshots) = &self.snapshots else {
+            return;
+        };
+        let mut buf = vec![0u8; ADMIN_SNAPSHOT_REPLY_SIZE];
+        if encode_admin_snapshot_reply(&mut buf, reply).is_ok() {
+            let _ = snapshots.send(buf);
+        }
+    }
+
+    /// Encodes the book's depth for the client that asked. Not multicast, so
+    /// it takes no sequence number; `seq_num` is the last one published.
+    fn reply_snapshot(&self, engine: &MatchingEngine) {
+        let Some(snapshots) = &self.snapshots else {
+            return;
+        };
+        let depth = |side| {
+            engine
+                .book()
+                .iter_levels(side)
+                .map(|l| (l.price, l.quantity))
+                .collect()
+        };
+        let snapshot = BookSnapshot {
+            seq_num: self.seq_num,
+            bids: depth(Side::Bid),
+            asks: depth(Side::Ask),
+            timestamp: now_nanos(),
+        };
+        let mut buf = vec![0u8; snapshot.encoded_size()];
+        if encode_book_snapshot(&mut buf, &snapshot).is_ok() {
+            let _ = snapshots.send(buf);
+        }
+    }
+
+    /// One execution report per fill, also pushed to the output ring if
+    /// there is one. With aggregation on, each run of fills at one price is
+    /// followed by an `AggTrade` summing it.
+    fn publish_fills(&mut self, result: &AddOrderResult, timestamp: u64) {
+        for level in result.fills.chunk_by(|a, b| a.price == b.price) {
+            for fill in level {
+                self.seq_num = self.seq_num.wrapping_add(1);
+                if let Ok(n) = encode_execution_report(&mut self.buf, self.seq_num, fill, timestamp)
+                {
+                    self.send(n);
+                }
+                // Never waits: a consumer that falls behind loses reports
+                // rather than stalling matching.
+                if let Some(ring) = &mut self.output_ring {
+                    let report = ExecutionReport {
+                        seq_num: self.seq_num,
+                        taker_order_id: fill.taker_order_id,
+                        maker_order_id: fill.maker_order_id,
+                        price: fill.price,
+                        quantity: fill.quantity,
+                        timestamp,
+                        taker_seq: fill.taker_seq,
+                        maker_seq: fill.maker_seq,
+                    };
+                    if ring.push(report).is_err() {
+                        self.output_dropped += 1;
+                    }
+                }
+            }
+            if !self.agg_trades {
+                continue;
+            }
+
+            self.seq_num = self.seq_num.wrapping_add(1);
+            let trade = AggTrade {
+                seq_num: self.seq_num,
+                taker_order_id: result.order_id,
+                price: level[0].price,
+                quantity: level.iter().map(|f| f.quantity).sum(),
+                fill_count: level.len() as u32,
+                timestamp,
+            };
+            if let Ok(n) = encode_agg_trade(&mut self.buf, &trade) {
+                self.send(n);
+            }
+        }
+    }
+
+    fn publish_ack(&mut self, result: &AddOrderResult, timestamp: u64) {
+        self.seq_num = self.seq_num.wrapping_add(1);
+        let ack = OrderAck {
+            seq_num: self.seq_num,
+            order_id: result.order_id,
+            status: result.status,
+            became_best: result.became_best,
+            timestamp,
+        };
+        if let Ok(n) = encode_order_ack(&mut self.buf, &ack) {
+            self.send(n);
+        }
+    }
+
+    /// One cancel report per order, all stamped with the same wall-clock time.
+    fn publish_cancels(&mut self, cancelled: &[Order]) {
+        if cancelled.is_empty() {
+            return;
+        }
+        let timestamp = now_nanos();
+        for order in cancelled {
+            self.seq_num = self.seq_num.wrapping_add(1);
+            let report = CancelReport {
+                seq_num: self.seq_num,
+                order_id: order.id.0,
+                quantity: order.quantity.0,
+                timestamp,
+            };
+            if let Ok(n) = encode_cancel_report(&mut self.buf, &report) {
+                self.send(n);
+            }
+        }
+    }
+
+    fn publish_reject(&mut self, order_id: u64, reason: u8, timestamp: u64) {
+        self.seq_num = self.seq_num.wrapping_add(1);
+        let reject = OrderReject {
+            seq_num: self.seq_num,
+            order_id,
+            reason,
+            timestamp,
+        };
+        if let Ok(n) = encode_order_reject(&mut self.buf, &reject) {
+            self.send(n);
+        }
+    }
+
+    /// Records the depth of the levels `cmd` names, each resting order it
+    /// targets and where a new order would rest, before it runs.
+    fn watch_levels(&mut self, engine: &MatchingEngine, cmd: &EngineCommand) {
+        if !self.level_deltas {
+            return;
+        }
+        let book = engine.book();
+        let (targeted, new_order) = match cmd {
+            EngineCommand::NewOrder(order) => (None, Some(order)),
+            EngineCommand::CancelOrder { order_id }
+            | EngineCommand::ReduceOrder { order_id, .. } => (Some(*order_id), None),
+            EngineCommand::CancelReplace { old_id, new_order } => (Some(*old_id), Some(new_order)),
+            _ => (None, None),
+        };
+        let targeted = targeted.and_then(|id| book.get_order(id));
+        for (side, price) in targeted.iter().chain(new_order).map(level_of) {
+            if !self
+                .watched
+                .iter()
+                .any(|&(s, p, _)| s == side && p == price)
+            {
+                self.watched
+                    .push((side, price, book.level_depth(side, price)));
+            }
+        }
+    }
+
+    /// Sends a `MSG_LEVEL_DELTA` for each watched level whose depth moved and
+    /// for each level in `changed`, once per level, then clears the watch
+    /// list. `changed` must not repeat a level except back to back. Without
+    /// a command timestamp the wall clock is used.
+    fn publish_level_deltas(
+        &mut self,
+        engine: &MatchingEngine,
+        changed: impl IntoIterator<Item = (Side, i64)>,
+        timestamp: Option<u64>,
+    ) {
+        if !self.level_deltas {
+            return;
+        }
+        let book = engine.book();
+        let mut levels = std::mem::take(&mut self.watched);
+        let watched = levels.len();
+        for (side, price) in changed {
+            let seen = |&(s, p, _): &WatchedLevel| s == side && p == price;
+            if levels.last().is_some_and(seen) || levels[..watched].iter().any(seen) {
+                continue;
+            }
+            levels.push((side, price, None));
+        }
+
+        let mut timestamp = timestamp;
+        for (i, &(side, price, before)) in levels.iter().enumerate() {
+            let depth = book.level_depth(side, price);
+            if i < watched && depth == before {
+                continue;
+            }
+            let (quantity, order_count) = depth.unwrap_or_default();
+            self.seq_num = self.seq_num.wrapping_add(1);
+            let delta = LevelDelta {
+                seq_num: self.seq_num,
+                side,
+                price,
+                quantity,
+                order_count,
+                timestamp: *timestamp.get_or_insert_with(now_nanos),
+            };
+            if let Ok(n) = encode_level_delta(&mut self.buf, &delta) {
+                self.send(n);
+            }
+        }
+        levels.clear();
+        self.watched = levels;
+    }
+
+    /// Level deltas and a book update for orders cancelled on expiry.
+    /// Expiries come in expiry order, which can return to a level, so the
+    /// levels are sorted and deduplicated first.
+    fn publish_expiries(&mut self, engine: &MatchingEngine, expired: &[Order]) {
+        if expired.is_empty() {
+            return;
+        }
+        let mut levels: Vec<_> = expired.iter().map(level_of).collect();
+        levels.sort_unstable_by_key(|&(side, price)| (side == Side::Ask, price));
+        levels.dedup();
+        self.publish_level_deltas(engine, levels, None);
+        self.publish_top_of_book(engine, None);
+    }
+
+    /// Sends a book update if the best price or size on either side moved
+    /// since the last one. Without a command timestamp the wall clock is used.
+    fn publish_top_of_book(&mut self, engine: &MatchingEngine, timestamp: Option<u64>) {
+        if !self.book_updates {
+            return;
+        }
+        let book = engine.book();
+        let best = |side| book.iter_levels(side).next().map(|l| (l.price, l.quantity));
+        let top = (best(Side::Bid), best(Side::Ask));
+        if top == self.last_top {
+            return;
+        }
+        self.last_top = top;
+
+        self.seq_num = self.seq_num.wrapping_add(1);
+        let update = BookUpdate {
+            seq_num: self.seq_num,
+            best_bid: top.0,
+            best_ask: top.1,
+            timestamp: timestamp.unwrap_or_else(now_nanos),
+        };
+        if let Ok(n) = encode_book_update(&mut self.buf, &update) {
+            self.send(n);
+        }
+    }
+}
+
+/// Decides when the matching loop writes full and delta snapshots.
+#[derive(Debug)]
+struct Snapshotter {
+    dir: PathBuf,
+    interval: u64,
+    max_age: Option<Duration>,
+    /// Commands between checks of `max_age` while the ring stays busy.
+    check_every: u32,
+    cmds_since_check: u32,
+    delta_interval: Option<u64>,
+    compression: SnapshotCompression,
+    retention: usize,
+    wal_retention: WalRetention,
+    cmds_since_full: u64,
+    cmds_since_delta: u64,
+    /// When the last full snapshot was attempted, or the snapshotter created.
+    last_full: Instant,
+    /// WAL record count of the last capture saved in this run. Deltas chain
+    /// from it, so the first capture of a run is always full.
+    last_capture: Option<u64>,
+}
+
+impl Snapshotter {
+    fn new(dir: PathBuf, config: &GatewayConfig) -> Self {
+        Self {
+            dir,
+            interval: config.snapshot_interval,
+            max_age: config.snapshot_interval_secs.map(Duration::from_secs),
+            check_every: config.max_batch_before_housekeeping.max(1),
+            cmds_since_check: 0,
+            delta_interval: config.delta_snapshot_interval,
+            compression: config.snapshot_compression,
+            retention: config.snapshot_retention,
+            wal_retention: config.wal_retention,
+            cmds_since_full: 0,
+            cmds_since_delta: 0,
+            last_full: Instant::now(),
+            last_capture: None,
+        }
+    }
+
+    fn full_due_by_time(&self) -> bool {
+        self.max_age
+            .is_some_and(|age| self.last_full.elapsed() >= age)
+    }
+
+    fn after_command(&mut self, engine: &mut MatchingEngine, wal: &mut Wal) {
+        self.cmds_since_full += 1;
+        self.cmds_since_delta += 1;
+
+        let delta_due = self
+            .delta_interval
+            .is_some_and(|interval| self.cmds_since_delta >= interval);
+        let record_count = wal.record_count();
+        self.cmds_since_check += 1;
+        let time_due = self.cmds_since_check >= self.check_every && {
+            self.cmds_since_check = 0;
+            self.full_due_by_time()
+        };
+
+        match self.last_capture {
+            Some(base) if delta_due && self.cmds_since_full < self.interval && !time_due => {
+                let delta = DeltaSnapshot::capture(engine, base, record_count);
+                // The changeset is already drained, so a lost delta must be
+                // followed by a full snapshot rather than a delta on a gap.
+                self.last_capture = delta
+                    .save_with(&self.dir, self.compression)
+                    .ok()
+                    .map(|_| record_count);
+            }
+            _ if delta_due || self.cmds_since_full >= self.interval || time_due => {
+                let _ = self.save_full(engine, wal);
+                return;
+            }
+            _ => return,
+        }
+
+        self.cmds_since_delta = 0;
+        let _ = wal.flush_async();
+    }
+
+    /// Called while the ring is empty: takes the time-based snapshot a quiet
+    /// market would otherwise go without, unless the last capture already
+    /// covers every WAL record.
+    fn when_idle(&mut self, engine: &mut MatchingEngine, wal: &mut Wal) {
+        let record_count = wal.record_count();
+        if record_count > 0 && self.last_capture != Some(record_count) && self.full_due_by_time() {
+            let _ = self.save_full(engine, wal);
+        }
+    }
+
+    /// Writes a full snapshot now and restarts both intervals, pruning old
+    /// snapshots and applying WAL retention if it was saved. Returns the WAL
+    /// record count it covers.
+    fn save_full(
+        &mut self,
+        engine: &mut MatchingEngine,
+        wal: &mut Wal,
+    ) -> Result<u64, SnapshotError> {
+        let record_count = wal.record_count();
+        let snap = Snapshot::capture(engine, record_count);
+        engine.clear_changes();
+        let saved = snap.save_with(&self.dir, self.compression);
+        self.last_capture = saved.as_ref().ok().map(|_| record_count);
+        if saved.is_ok() {
+            let _ = Snapshot::prune(&self.dir, self.retention);
+            if let Err(e) = wal.apply_retention(&self.wal_retention, record_count) {
+                eprintln!("ferrox: wal retention failed: {e}");
+            }
+        }
+        self.cmds_since_full = 0;
+        self.cmds_since_delta = 0;
+        self.last_full = Instant::now();
+        let _ = wal.flush_async();
+        saved.map(|_| record_count)
+    }
+}
+
+/// Answers `MSG_ADMIN_SNAPSHOT` with a full snapshot taken between the
+/// commands before it and those after.
+fn admin_snapshot(
+    engine: &mut MatchingEngine,
+    wal: &mut Option<Wal>,
+    snapshotter: &mut Option<Snapshotter>,
+    publisher: &Publisher,
+) {
+    let reply = match (wal, snapshotter) {
+        (Some(w), Some(s)) => match s.save_full(engine, w) {
+            Ok(record_count) => AdminSnapshotReply {
+                status: SNAPSHOT_SAVED,
+                record_count,
+            },
+            Err(e) => {
+                eprintln!("ferrox: admin snapshot failed: {e}");
+                AdminSnapshotReply {
+                    status: SNAPSHOT_FAILED,
+                    record_count: 0,
+                }
+            }
+        },
+        _ => AdminSnapshotReply {
+            status: SNAPSHOT_DISABLED,
+            record_count: 0,
+        },
+    };
+    publisher.reply_admin_snapshot(&reply);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn matching_loop(
+    mut consumer: Consumer<EngineCommand>,
+    mut engine: MatchingEngine,
+    mut wal: Option<Wal>,
+    mut trades: Option<TradeLog>,
+    mut snapshotter: Option<Snapshotter>,
+    mut publisher: Publisher,
+    wait: WaitStrategy,
+    shutdown: Arc<AtomicBool>,
+) {
+    if let Some(s) = &snapshotter {
+        engine.set_change_tracking(s.delta_interval.is_some());
+    }
+
+    let mut empty_polls = 0u32;
+    loop {
+        match consumer.pop() {
+            Ok(EngineCommand::AdminSnapshot) => {
+                empty_polls = 0;
+                admin_snapshot(&mut engine, &mut wal, &mut snapshotter, &publisher);
+                publisher.publish_wal_lag(&wal);
+            }
+            Ok(cmd) => {
+                empty_polls = 0;
+                let popped = publisher.start_timer();
+                process_command(cmd, &mut engine, &mut wal, &mut trades, &mut publisher);
+                let expired = expire_due_orders(&mut engine, &mut wal);
+                publisher.publish_expiries(&engine, &expired);
+                publisher.publish_book_view(&engine);
+                publisher.record_latency(popped);
+
+                if let (Some(w), Some(s)) = (&mut wal, &mut snapshotter) {
+                    s.after_command(&mut engine, w);
+                }
+                publisher.publish_wal_lag(&wal);
+            }
+            Err(_empty) => {
+                if shutdown.load(Ordering::Acquire) {
+                    // Drain remaining commands
+                    while let Ok(cmd) = consumer.pop() {
+                        if cmd == EngineCommand::AdminSnapshot {
+                            admin_snapshot(&mut engine, &mut wal, &mut snapshotter, &publisher);
+                        } else {
+                            process_command(
+                                cmd,
+                                &mut engine,
+                                &mut wal,
+                                &mut trades,
+                                &mut publisher,
+                            );
+                            publisher.publish_book_view(&engine);
+                        }
+                    }
+                    publisher.publish_wal_lag(&wal);
+                    if let Some(t) = &trades {
+                        let _ = t.flush_async();
+                    }
+                    let m = publisher.metrics;
+                    if m.dropped > 0 {
+                        eprintln!(
+                            "ferrox: feed sent {} messages, dropped {} after {} retries",
+                            m.sent, m.dropped, m.retries
+                        );
+                    }
+                    let rejects = engine.metrics().arena_full_rejects;
+                    if rejects > 0 {
+                        eprintln!("ferrox: rejected {rejects} orders with the arena full");
+                    }
+                    if let Some(p) = publisher
+                        .latency
+                        .as_ref()
+                        .map(|l| l.engine_latency_percentiles())
+                        && p.samples > 0
+                    {
+                        eprintln!(
+                            "ferrox: {} commands, latency p50 {:?} p99 {:?} p99.9 {:?}",
+                            p.samples, p.p50, p.p99, p.p999
+                        );
+                    }
+                    if publisher.output_dropped > 0 {
+                        eprintln!(
+                            "ferrox: output ring was full for {} execution reports",
+                            publisher.output_dropped
+                        );
+                    }
+                    break;
+                }
+                let expired = expire_due_orders(&mut engine, &mut wal);
+                publisher.publish_expiries(&engine, &expired);
+                if !expired.is_empty() {
+                    publisher.publish_book_view(&engine);
+                }
+                if let (Some(w), Some(s)) = (&mut wal, &mut snapshotter) {
+                    s.when_idle(&mut engine, w);
+                }
+                publisher.publish_wal_lag(&wal);
+                wait.idle(empty_polls);
+                empty_polls = empty_polls.saturating_add(1);
+            }
+        }
+    }
+}
+
+/// Sets `SO_SNDBUF`. The kernel may round or clamp the size.
+#[cfg(unix)]
+fn set_send_buffer_size(udp: &UdpSocket, bytes: usize) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let size = libc::c_int::try_from(bytes).map_err(|_| io::ErrorKind::InvalidInput)?;
+    // SAFETY: The fd is an open socket and the option value is a c_int of
+    // the length passed.
+    let rc = unsafe {
+        libc::setsockopt(
+            udp.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_SNDBUF,
+            (&size as *const libc::c_int).cast(),
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn set_send_buffer_size(_udp: &UdpSocket, _bytes: usize) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Sets `IP_MULTICAST_IF`, which std doesn't expose.
+#[cfg(unix)]
+fn set_multicast_interface(udp: &UdpSocket, interface: Ipv4Addr) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let addr = libc::in_addr {
+        s_addr: u32::from_ne_bytes(interface.octets()),
+    };
+    // SAFETY: The fd is an open socket and the option value is an in_addr of
+    // the length passed.
+    let rc = unsafe {
+        libc::setsockopt(
+            udp.as_raw_fd(),
+            libc::IPPROTO_IP,
+            libc::IP_MULTICAST_IF,
+            (&addr as *const libc::in_addr).cast(),
+            std::mem::size_of::<libc::in_addr>() as libc::socklen_t,
+        )
+    };
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn set_multicast_interface(_udp: &UdpSocket, _interface: Ipv4Addr) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Opens the multicast sender. With an interface set, binding to its address
+/// checks it belongs to this host before the feed starts.
+fn feed_socket(config: &GatewayConfig) -> Result<UdpSocket, GatewayError> {
+    let udp = match config.multicast_interface {
+        Some(interface) => {
+            let bad_interface = |e| GatewayError::MulticastInterface(interface, e);
+            let udp = UdpSocket::bind((interface, 0)).map_err(bad_interface)?;
+            set_multicast_interface(&udp, interface).map_err(bad_interface)?;
+            udp
+        }
+        None => UdpSocket::bind("0.0.0.0:0")?,
+    };
+    udp.set_multicast_ttl_v4(config.multicast_ttl)?;
+    udp.set_multicast_loop_v4(config.multicast_loop)?;
+    if let Some(bytes) = config.feed_send_buffer {
+        set_send_buffer_size(&udp, bytes)?;
+    }
+    Ok(udp)
+}
+
+pub fn run(config: GatewayConfig) -> Result<(), GatewayError> {
+    run_with_outputs(config, InProcessOutputs::default())
+}
+
+/// Where the matching thread also publishes for consumers in the same
+/// process, besides the multicast feed, which keeps going for external
+/// subscribers. Neither output ever makes the matching thread wait.
+#[derive(Default)]
+pub struct InProcessOutputs {
+    /// Every execution report, drained from the ring's `Consumer` with no
+    /// encoding or network in between. Reports carry the same sequence
+    /// numbers as on the feed; a full ring drops them.
+    pub output_ring: Option<Producer<ExecutionReport>>,
+    /// Best levels of each side, published after every command for
+    /// `TopOfBookReader`s on other threads.
+    pub top_of_book: Option<TopOfBookWriter>,
+    /// How far the WAL is ahead of its last flush, refreshed after every
+    /// command. Stays at zero without a `data_dir`.
+    pub wal_lag: Option<Arc<WalLag>>,
+    /// Time from popping each command off the ring to having sent its
+    /// reports and refreshed the top of book, before any snapshot it
+    /// triggers. Read `engine_latency_percentiles()` from any thread. Left
+    /// out, nothing is timed.
+    pub engine_latency: Option<Arc<LatencyHistogram>>,
+}
+
+/// WAL records and bytes appended since the last flush, which a machine
+/// crash could lose. The WAL is flushed with each snapshot, so this is the
+/// recovery point objective of the running gateway. Safe to read from any
+/// thread; the two counts may be from consecutive commands.
+#[derive(Debug, Default)]
+pub struct WalLag {
+    records: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl WalLag {
+    pub fn records(&self) -> u64 {
+        self.records.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    fn store(&self, wal: &Wal) {
+        self.records
+            .store(wal.records_since_flush(), Ordering::Relaxed);
+        self.bytes.store(wal.bytes_since_flush(), Ordering::Relaxed);
+    }
+}
+
+/// `run`, also publishing to `outputs`.
+pub fn run_with_outputs(
+    config: GatewayConfig,
+    outputs: InProcessOutputs,
+) -> Result<(), GatewayError> {
+    let (mut producer, consumer) =
+        ring::ring_buffer_rounded::<EngineCommand>(config.ring_capacity)?;
+    if producer.capacity() != config.ring_capacity {
+        eprintln!(
+            "ferrox: ring_capacity {} is not a power of two, using {}",
+            config.ring_capacity,
+            producer.capacity()
+        );
+    }
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let shutdown_match = Arc::clone(&shutdown);
+
+    let (mut engine, mut wal, trades, mut snapshotter) = if let Some(ref data_dir) = config.data_dir
+    {
+        match crate::recovery::recover(data_dir, config.arena_capacity, config.replay_mode) {
+            Ok((engine, mut wal)) => {
+                wal.set_segment_size(config.wal_segment_size);
+                let trades = TradeLog::open(data_dir.join("trades.bin"))
+                    .inspect_err(|e| eprintln!("ferrox: trade log unavailable: {e}"))
+                    .ok();
+                let snapshotter = Snapshotter::new(data_dir.join("snapshots"), &config);
+                (engine, Some(wal), trades, Some(snapshotter))
+            }
+            Err(e) => {
+                eprintln!("ferrox: recovery failed: {e}, starting fresh");
+                (
+                    MatchingEngine::with_capacity(config.arena_capacity),
+                    None,
+                    None,
+                    None,
+                )
+            }
+        }
+    } else {
+        (
+            MatchingEngine::with_capacity(config.arena_capacity),
+            None,
+            None,
+            None,
+        )
+    };
+
+    // Recovery restores the rules the last snapshot recorded. Pin changed
+    // ones with a snapshot before any command runs under them; an empty WAL
+    // gets one too, since replay without a snapshot uses the defaults.
+    let rules = (
+        config.fill_pricing,
+        config.amend_policy,
+        config.cross_policy,
+    );
+    if (
+        engine.fill_pricing(),
+        engine.amend_policy(),
+        engine.cross_policy(),
+    ) != rules
+    {
+        engine.set_fill_pricing(config.fill_pricing);
+        engine.set_amend_policy(config.amend_policy);
+        engine.set_cross_policy(config.cross_policy);
+        if let (Some(wal), Some(snapshotter)) = (wal.as_mut(), snapshotter.as_mut())
+            && let Err(e) = snapshotter.save_full(&mut engine, wal)
+        {
+            eprintln!("ferrox: snapshot for matching rules failed: {e}");
+        }
+    }
+
+    if config.warmup {
+        engine.warmup();
+        if let Some(wal) = wal.as_mut() {
+            wal.prefault();
+        }
+    }
+
+    let mut clock = Clock::new(
+        config.clock_source,
+        wal.as_ref().map_or(0, Wal::record_count),
+    );
+
+    let udp = feed_socket(&config)?;
+
+    let (snapshot_tx, snapshot_rx) = mpsc::channel();
+    let publisher = Publisher::new(udp, config.multicast_addr, config.publish_book_updates)
+        .with_agg_trades(config.publish_agg_trades)
+        .with_level_deltas(config.publish_level_deltas)
+        .with_send_retries(config.feed_send_retries)
+        .with_snapshot_replies(snapshot_tx)
+        .with_output_ring(outputs.output_ring)
+        .with_top_of_book(outputs.top_of_book)
+        .with_wal_lag(outputs.wal_lag)
+        .with_latency(outputs.engine_latency);
+
+    let match_thread = thread::spawn(move || {
+        matching_loop(
+            consumer,
+            engine,
+            wal,
+            trades,
+            snapshotter,
+            publisher,
+            config.wait_strategy,
+            shutdown_match,
+        );
+    });
+
+    let listener = TcpListener::bind(config.listen_addr)?;
+    eprintln!("ferrox: listening on {}", config.listen_addr);
+
+    let (stream, peer) = listener.accept()?;
+    eprintln!("ferrox: client connected from {peer}");
+
+    let result = handle_client(
+        stream,
+        &mut producer,
+        &mut clock,
+        &mut ClientMetrics::default(),
+        &config,
+        Some(&snapshot_rx),
+        &shutdown,
+    );
+
+    shutdown.store(true, Ordering::Release);
+    eprintln!("ferrox: client disconnected, shutting down");
+    if clock.regressions > 0 {
+        eprintln!(
+            "ferrox: held {} order timestamps while the wall clock was behind",
+            clock.regressions
+        );
+    }
+
+    match_thread.join().expect("matching thread panicked");
+
+    result
+}
+
+/// Reads commands from one client into `producer` until it disconnects,
+/// timestamped and framed as `config` says, without a matching thread on the
+/// other end. Snapshot requests go unanswered.
+pub fn ingest(
+    stream: TcpStream,
+    producer: &mut Producer<EngineCommand>,
+    config: &GatewayConfig,
+) -> Result<(), GatewayError> {
+    let mut clock = Clock::new(config.clock_source, 0);
+    handle_client(
+        stream,
+        producer,
+        &mut clock,
+        &mut ClientMetrics::default(),
+        config,
+        None,
+        &AtomicBool::new(false),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::{Order, Side};
+    use crate::protocol::{self, EXECUTION_REPORT_SIZE, NEW_ORDER_SIZE, encode_new_order};
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    fn wall_clock() -> Clock {
+        Clock::new(ClockSource::Wall, 0)
+    }
+
+    /// Next feed message of `msg_type`, skipping any others; `None` once the
+    /// socket's read timeout passes.
+    fn recv_feed(udp: &UdpSocket, msg_type: u8) -> Option<[u8; FEED_BUF_SIZE]> {
+        let mut buf = [0u8; FEED_BUF_SIZE];
+        loop {
+            udp.recv_from(&mut buf).ok()?;
+            if buf[0] == msg_type {
+                return Some(buf);
+            }
+        }
+    }
+
+    #[test]
+    fn engine_command_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<EngineCommand>();
+    }
+
+    #[test]
+    fn engine_command_size() {
+        let size = std::mem::size_of::<EngineCommand>();
+        assert!(size <= 64, "EngineCommand too large: {size} bytes");
+    }
+
+    #[test]
+    fn gateway_config_defaults() {
+        let config = GatewayConfig::default();
+        assert_eq!(config.listen_addr.port(), 9000);
+        assert_eq!(config.multicast_addr.port(), 9001);
+        assert_eq!(config.ring_capacity, 65536);
+        assert_eq!(config.arena_capacity, 1_048_576);
+        assert!(config.data_dir.is_none());
+        assert_eq!(config.snapshot_interval, 10_000);
+        assert_eq!(config.snapshot_interval_secs, None);
+        assert_eq!(config.max_batch_before_housekeeping, 256);
+        assert_eq!(config.delta_snapshot_interval, None);
+        assert_eq!(config.snapshot_retention, 3);
+        assert_eq!(config.wal_segment_size, None);
+        assert_eq!(config.wal_retention, WalRetention::default());
+        assert_eq!(config.snapshot_compression, SnapshotCompression::None);
+        assert_eq!(config.clock_source, ClockSource::Wall);
+        assert_eq!(config.replay_mode, ReplayMode::Fast);
+        assert_eq!(config.fill_pricing, FillPricing::MakerPrice);
+        assert_eq!(config.amend_policy, AmendPolicy::ResetPriority);
+        assert_eq!(config.cross_policy, CrossPolicy::AtEqualPrice);
+        assert!(!config.warmup);
+        assert_eq!(config.ring_full_policy, RingFullPolicy::Block);
+        assert_eq!(config.framing, Framing::Fixed);
+        assert!(!config.reject_truncated_messages);
+        assert_eq!(config.wait_strategy, WaitStrategy::Yield);
+        assert_eq!(config.multicast_interface, None);
+        assert_eq!(config.multicast_ttl, 1);
+        assert!(config.multicast_loop);
+    }
+
+    #[test]
+    fn logical_clock_continues_from_start() {
+        let mut clock = Clock::new(ClockSource::Logical, 41);
+        assert_eq!(clock.stamp(), 42);
+        assert_eq!(clock.stamp(), 43);
+    }
+
+    #[test]
+    fn wall_stamps_hold_through_a_clock_step_back() {
+        let mut readings = [100, 200, 150, 120, 200, 250, 90].into_iter();
+        let mut clock = wall_clock().with_wall(move || readings.next().unwrap());
+
+        let stamps: Vec<_> = (0..7).map(|_| clock.stamp()).collect();
+        assert_eq!(stamps, vec![100, 200, 200, 200, 200, 250, 250]);
+        assert_eq!(clock.regressions, 3);
+    }
+
+    #[test]
+    fn tcp_to_ring_roundtrip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (mut producer, mut consumer) = ring::ring_buffer::<EngineCommand>(64);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let shutdown_ref = &shutdown;
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let order = Order {
+                id: 42.into(),
+                trader_id: 7.into(),
+                symbol_id: 0,
+                side: Side::Bid,
+                price: 15005.into(),
+                quantity: 100.into(),
+                timestamp: 0,
+                expiry: None,
+                reduce_only: false,
+                post_only: false,
+            };
+            let mut buf = [0u8; NEW_ORDER_SIZE];
+            encode_new_order(&mut buf, &order).unwrap();
+            stream.write_all(&buf).unwrap();
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        handle_client(
+            stream,
+            &mut producer,
+            &mut wall_clock(),
+            &mut ClientMetrics::default(),
+            &GatewayConfig::default(),
+            None,
+            shutdown_ref,
+        )
+        .unwrap();
+
+        client.join().unwrap();
+
+        let cmd = consumer.pop().unwrap();
+        match cmd {
+            EngineCommand::NewOrder(order) => {
+                assert_eq!(order.id, 42);
+                assert_eq!(order.trader_id, 7);
+                assert_eq!(order.side, Side::Bid);
+                assert_eq!(order.price, 15005);
                 assert_eq!(order.quantity, 100);
                 assert!(order.timestamp > 0, "timestamp should be assigned");
             }
-            _ => panic!("expected NewOrder"),
+            _ => panic!("expected NewOrder"),
+        }
+    }
+
+    #[test]
+    fn messages_split_across_writes_are_reassembled() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (mut producer, mut consumer) = ring::ring_buffer::<EngineCommand>(64);
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.set_nodelay(true).unwrap();
+            let mut buf = [0u8; 3 * NEW_ORDER_SIZE];
+            for (id, chunk) in (1..).zip(buf.chunks_exact_mut(NEW_ORDER_SIZE)) {
+                let order = Order::try_new(id, 7, Side::Bid, 100, 10, 0).unwrap();
+                encode_new_order(chunk, &order).unwrap();
+            }
+            // Both cuts land mid-message.
+            for part in [
+                &buf[..13],
+                &buf[13..NEW_ORDER_SIZE + 30],
+                &buf[NEW_ORDER_SIZE + 30..],
+            ] {
+                stream.write_all(part).unwrap();
+                thread::sleep(Duration::from_millis(5));
+            }
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        ingest(stream, &mut producer, &GatewayConfig::default()).unwrap();
+        client.join().unwrap();
+
+        for id in 1..=3 {
+            match consumer.pop().unwrap() {
+                EngineCommand::NewOrder(order) => assert_eq!(order.id, id),
+                other => panic!("expected NewOrder, got {other:?}"),
+            }
+        }
+        assert!(consumer.pop().is_err());
+    }
+
+    #[test]
+    fn batch_pushed_in_order() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (mut producer, mut consumer) = ring::ring_buffer::<EngineCommand>(64);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let shutdown_ref = &shutdown;
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let orders: Vec<Order> = (1..=3)
+                .map(|id| Order::try_new(id, 7, Side::Bid, 100, 10, 0).unwrap())
+                .collect();
+            let mut buf = [0u8; protocol::MAX_BATCH_SIZE];
+            let n = protocol::encode_batch(&mut buf, &orders).unwrap();
+            stream.write_all(&buf[..n]).unwrap();
+            let cancel = [protocol::MSG_CANCEL_ORDER; protocol::CANCEL_ORDER_SIZE];
+            stream.write_all(&cancel).unwrap();
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut clock = Clock::new(ClockSource::Logical, 0);
+        handle_client(
+            stream,
+            &mut producer,
+            &mut clock,
+            &mut ClientMetrics::default(),
+            &GatewayConfig::default(),
+            None,
+            shutdown_ref,
+        )
+        .unwrap();
+
+        client.join().unwrap();
+
+        for id in 1..=3 {
+            match consumer.pop().unwrap() {
+                EngineCommand::NewOrder(order) => {
+                    assert_eq!(order.id, id);
+                    assert_eq!(order.timestamp, id);
+                }
+                other => panic!("expected NewOrder, got {other:?}"),
+            }
+        }
+        assert!(matches!(
+            consumer.pop().unwrap(),
+            EngineCommand::CancelOrder { .. }
+        ));
+        assert!(consumer.pop().is_err());
+    }
+
+    /// Runs a length-prefixed client handler over the given frames.
+    fn handle_framed(frames: Vec<Vec<u8>>) -> (Result<(), GatewayError>, Vec<EngineCommand>) {
+        let mut bytes = Vec::new();
+        for frame in frames {
+            bytes.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&frame);
+        }
+        let config = GatewayConfig {
+            framing: Framing::LengthPrefixed,
+            ..GatewayConfig::default()
+        };
+        handle_bytes(&config, bytes)
+    }
+
+    /// Runs a client handler over whatever `bytes` a client sends before
+    /// closing its end.
+    fn handle_bytes(
+        config: &GatewayConfig,
+        bytes: Vec<u8>,
+    ) -> (Result<(), GatewayError>, Vec<EngineCommand>) {
+        handle_bytes_counted(config, bytes, &mut ClientMetrics::default())
+    }
+
+    fn handle_bytes_counted(
+        config: &GatewayConfig,
+        bytes: Vec<u8>,
+        metrics: &mut ClientMetrics,
+    ) -> (Result<(), GatewayError>, Vec<EngineCommand>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (mut producer, mut consumer) = ring::ring_buffer::<EngineCommand>(64);
+        let shutdown = AtomicBool::new(false);
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(&bytes).unwrap();
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut clock = Clock::new(ClockSource::Logical, 0);
+        let result = handle_client(
+            stream,
+            &mut producer,
+            &mut clock,
+            metrics,
+            config,
+            None,
+            &shutdown,
+        );
+        client.join().unwrap();
+
+        let mut commands = Vec::new();
+        while let Ok(cmd) = consumer.pop() {
+            commands.push(cmd);
+        }
+        (result, commands)
+    }
+
+    #[test]
+    fn length_prefixed_frames_are_dispatched() {
+        let orders: Vec<Order> = (1..=3)
+            .map(|id| Order::try_new(id, 7, Side::Bid, 100, 10, 0).unwrap())
+            .collect();
+        let mut buf = [0u8; protocol::MAX_BATCH_SIZE];
+        let n = protocol::encode_batch(&mut buf, &orders).unwrap();
+        // Bytes past a message's fields are ignored.
+        let mut cancel = vec![protocol::MSG_CANCEL_ORDER; protocol::CANCEL_ORDER_SIZE];
+        cancel.extend_from_slice(&[0xFF; 4]);
+
+        let (result, commands) = handle_framed(vec![buf[..n].to_vec(), cancel]);
+        result.unwrap();
+
+        let ids: Vec<_> = commands[..3]
+            .iter()
+            .map(|cmd| match cmd {
+                EngineCommand::NewOrder(order) => order.id,
+                other => panic!("expected NewOrder, got {other:?}"),
+            })
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert!(matches!(commands[3], EngineCommand::CancelOrder { .. }));
+        assert_eq!(commands.len(), 4);
+    }
+
+    #[test]
+    fn empty_frame_is_rejected() {
+        let (result, commands) = handle_framed(vec![Vec::new()]);
+        assert!(matches!(
+            result,
+            Err(GatewayError::Protocol(ProtocolError::InvalidFrameLength(0)))
+        ));
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn oversized_messages_drop_the_client() {
+        // A 4 GiB length prefix is refused from the header alone; the body
+        // that would follow is never read.
+        let config = GatewayConfig {
+            framing: Framing::LengthPrefixed,
+            ..GatewayConfig::default()
+        };
+        let (result, commands) = handle_bytes(&config, u32::MAX.to_le_bytes().to_vec());
+        assert!(matches!(
+            result,
+            Err(GatewayError::Protocol(ProtocolError::MessageTooLarge {
+                size,
+                max: MAX_FRAME_SIZE,
+            })) if size == u32::MAX as usize
+        ));
+        assert!(commands.is_empty());
+
+        // Under fixed framing a batch's count is checked against the limit
+        // before its orders are read.
+        let orders: Vec<Order> = (1..=4)
+            .map(|id| Order::try_new(id, 7, Side::Bid, 100, 10, 0).unwrap())
+            .collect();
+        let mut buf = [0u8; protocol::MAX_BATCH_SIZE];
+        let n = protocol::encode_batch(&mut buf, &orders).unwrap();
+        let mut bytes = vec![0u8; protocol::CANCEL_ORDER_SIZE];
+        protocol::encode_cancel_order(&mut bytes, 9).unwrap();
+        bytes.extend_from_slice(&buf[..n]);
+        let config = GatewayConfig {
+            max_message_size: 2 * NEW_ORDER_SIZE,
+            ..GatewayConfig::default()
+        };
+        let (result, commands) = handle_bytes(&config, bytes);
+        assert!(matches!(
+            result,
+            Err(GatewayError::Protocol(ProtocolError::MessageTooLarge { size, max }))
+                if size == n && max == 2 * NEW_ORDER_SIZE
+        ));
+        assert_eq!(commands, vec![EngineCommand::CancelOrder { order_id: 9 }]);
+    }
+
+    #[test]
+    fn disconnect_mid_message_is_counted_apart_from_a_clean_one() {
+        let mut metrics = ClientMetrics::default();
+        let (result, commands) =
+            handle_bytes_counted(&GatewayConfig::default(), Vec::new(), &mut metrics);
+        assert!(result.is_ok());
+        assert!(commands.is_empty());
+        assert_eq!(metrics.truncated_messages, 0);
+
+        // Only the type byte of a new order arrives before the close.
+        let (result, commands) = handle_bytes_counted(
+            &GatewayConfig::default(),
+            vec![protocol::MSG_NEW_ORDER],
+            &mut metrics,
+        );
+        assert!(result.is_ok());
+        assert!(commands.is_empty());
+        assert_eq!(metrics.truncated_messages, 1);
+
+        let strict = GatewayConfig {
+            reject_truncated_messages: true,
+            ..GatewayConfig::default()
+        };
+        let (result, _) =
+            handle_bytes_counted(&strict, vec![protocol::MSG_NEW_ORDER], &mut metrics);
+        assert!(matches!(
+            result,
+            Err(GatewayError::Protocol(ProtocolError::TruncatedMessage {
+                received: 1,
+                expected: NEW_ORDER_SIZE,
+            }))
+        ));
+        assert_eq!(metrics.truncated_messages, 2);
+
+        // A frame cut off inside its length prefix counts the same way.
+        let framed = GatewayConfig {
+            framing: Framing::LengthPrefixed,
+            reject_truncated_messages: true,
+            ..GatewayConfig::default()
+        };
+        let (result, _) = handle_bytes_counted(&framed, vec![8, 0], &mut metrics);
+        assert!(matches!(
+            result,
+            Err(GatewayError::Protocol(ProtocolError::TruncatedMessage {
+                received: 2,
+                expected: FRAME_HEADER_SIZE,
+            }))
+        ));
+        assert_eq!(metrics.truncated_messages, 3);
+    }
+
+    /// Sends orders 1..=count into a client handler whose ring has room for
+    /// two and is never drained. Returns the handler's result and whatever
+    /// the client read back.
+    fn flood_tiny_ring(
+        count: u64,
+        policy: RingFullPolicy,
+    ) -> (Result<(), GatewayError>, Vec<u8>, Consumer<EngineCommand>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (mut producer, consumer) = ring::ring_buffer::<EngineCommand>(2);
+        let shutdown = AtomicBool::new(false);
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let mut buf = [0u8; NEW_ORDER_SIZE];
+            for id in 1..=count {
+                let order = Order::try_new(id, 7, Side::Bid, 100, 10, 0).unwrap();
+                encode_new_order(&mut buf, &order).unwrap();
+                if stream.write_all(&buf).is_err() {
+                    break;
+                }
+            }
+            let _ = stream.shutdown(std::net::Shutdown::Write);
+            let mut replies = Vec::new();
+            let _ = stream.read_to_end(&mut replies);
+            replies
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let result = handle_client(
+            stream,
+            &mut producer,
+            &mut wall_clock(),
+            &mut ClientMetrics::default(),
+            &GatewayConfig {
+                ring_full_policy: policy,
+                ..GatewayConfig::default()
+            },
+            None,
+            &shutdown,
+        );
+        (result, client.join().unwrap(), consumer)
+    }
+
+    #[test]
+    fn ring_full_reject_sends_nacks() {
+        let policy = RingFullPolicy::Reject {
+            timeout: Duration::from_millis(5),
+        };
+        let (result, replies, mut consumer) = flood_tiny_ring(5, policy);
+        result.unwrap();
+
+        let rejected: Vec<u64> = replies
+            .chunks(protocol::REJECT_SIZE)
+            .map(|m| protocol::decode_reject(m).unwrap())
+            .inspect(|r| assert_eq!(r.reason, protocol::REJECT_RING_FULL))
+            .map(|r| r.order_id)
+            .collect();
+        assert_eq!(rejected, vec![3, 4, 5]);
+
+        for id in 1..=2 {
+            match consumer.pop().unwrap() {
+                EngineCommand::NewOrder(order) => assert_eq!(order.id, id),
+                other => panic!("expected NewOrder, got {other:?}"),
+            }
+        }
+        assert!(consumer.pop().is_err());
+    }
+
+    #[test]
+    fn ring_full_disconnect_drops_client() {
+        let policy = RingFullPolicy::Disconnect {
+            timeout: Duration::from_millis(5),
+        };
+        let (result, replies, consumer) = flood_tiny_ring(5, policy);
+        assert!(matches!(result, Err(GatewayError::RingFull)));
+        assert!(replies.is_empty());
+        assert_eq!(consumer.len(), 2);
+    }
+
+    #[test]
+    fn failed_feed_sends_are_retried_then_counted() {
+        // An IPv4 socket can't send to an IPv6 address, so every send fails
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut publisher =
+            Publisher::new(udp, "[::1]:9".parse().unwrap(), false).with_send_retries(2);
+        publisher.publish_reject(1, protocol::REJECT_HALTED, 0);
+        publisher.publish_reject(2, protocol::REJECT_HALTED, 0);
+        assert_eq!(
+            publisher.metrics,
+            FeedMetrics {
+                sent: 0,
+                retries: 4,
+                dropped: 2,
+            }
+        );
+        // Both drops fall in one warning interval
+        assert_eq!(publisher.drops_since_warning, 1);
+
+        let udp_recv = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut publisher = Publisher::new(udp, udp_recv.local_addr().unwrap(), false);
+        publisher.publish_reject(1, protocol::REJECT_HALTED, 0);
+        assert_eq!(publisher.metrics.sent, 1);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn send_buffer_size_is_applied() {
+        use std::os::fd::AsRawFd;
+
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        set_send_buffer_size(&udp, 65_536).unwrap();
+        let mut size: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        // SAFETY: Valid socket, and `size`/`len` describe a c_int buffer.
+        let rc = unsafe {
+            libc::getsockopt(
+                udp.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_SNDBUF,
+                (&mut size as *mut libc::c_int).cast(),
+                &mut len,
+            )
+        };
+        assert_eq!(rc, 0);
+        // Linux doubles the request to leave room for bookkeeping
+        assert!(size >= 65_536, "SO_SNDBUF is {size}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn feed_socket_applies_multicast_options() {
+        let config = GatewayConfig {
+            multicast_interface: Some(Ipv4Addr::LOCALHOST),
+            multicast_ttl: 4,
+            multicast_loop: false,
+            ..GatewayConfig::default()
+        };
+        let udp = feed_socket(&config).unwrap();
+        assert_eq!(udp.local_addr().unwrap().ip(), Ipv4Addr::LOCALHOST);
+        assert_eq!(udp.multicast_ttl_v4().unwrap(), 4);
+        assert!(!udp.multicast_loop_v4().unwrap());
+    }
+
+    #[test]
+    fn foreign_multicast_interface_is_rejected() {
+        // TEST-NET-1, never assigned to a real host.
+        let interface = Ipv4Addr::new(192, 0, 2, 1);
+        let config = GatewayConfig {
+            multicast_interface: Some(interface),
+            ..GatewayConfig::default()
+        };
+        match feed_socket(&config) {
+            Err(GatewayError::MulticastInterface(addr, _)) => assert_eq!(addr, interface),
+            other => panic!("expected MulticastInterface, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn snapshot_request_is_answered_on_the_client_connection() {
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tcp_addr = tcp_listener.local_addr().unwrap();
+        let udp_recv = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let udp_recv_addr = udp_recv.local_addr().unwrap();
+
+        let (mut producer, consumer) = ring::ring_buffer::<EngineCommand>(64);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let shutdown_match = Arc::clone(&shutdown);
+        let (snapshot_tx, snapshot_rx) = mpsc::channel();
+        let publisher = Publisher::new(UdpSocket::bind("0.0.0.0:0").unwrap(), udp_recv_addr, false)
+            .with_snapshot_replies(snapshot_tx);
+        let match_thread = thread::spawn(move || {
+            matching_loop(
+                consumer,
+                MatchingEngine::with_capacity(1024),
+                None,
+                None,
+                None,
+                publisher,
+                WaitStrategy::Yield,
+                shutdown_match,
+            );
+        });
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(tcp_addr).unwrap();
+            let mut buf = [0u8; NEW_ORDER_SIZE];
+            for (id, side, price) in [(1, Side::Bid, 99), (2, Side::Bid, 99), (3, Side::Ask, 101)] {
+                let order = Order::try_new(id, 10, side, price, 5, 0).unwrap();
+                encode_new_order(&mut buf, &order).unwrap();
+                stream.write_all(&buf).unwrap();
+            }
+            let n = protocol::encode_request_snapshot(&mut buf).unwrap();
+            stream.write_all(&buf[..n]).unwrap();
+
+            let mut reply = vec![0u8; protocol::book_snapshot_size(1, 1)];
+            stream.read_exact(&mut reply).unwrap();
+            protocol::decode_book_snapshot(&reply).unwrap()
+        });
+
+        let (stream, _) = tcp_listener.accept().unwrap();
+        handle_client(
+            stream,
+            &mut producer,
+            &mut wall_clock(),
+            &mut ClientMetrics::default(),
+            &GatewayConfig::default(),
+            Some(&snapshot_rx),
+            &shutdown,
+        )
+        .unwrap();
+        match_thread.join().unwrap();
+
+        let snapshot = client.join().unwrap();
+        assert_eq!(snapshot.bids, vec![(99, 10)]);
+        assert_eq!(snapshot.asks, vec![(101, 5)]);
+        // The three acks were the last feed messages before it
+        assert_eq!(snapshot.seq_num, 3);
+        assert!(snapshot.timestamp > 0);
+    }
+
+    #[test]
+    fn admin_snapshot_captures_the_book_at_its_place_in_the_stream() {
+        let dir = tempfile::tempdir().unwrap();
+        let snap_dir = dir.path().join("snapshots");
+        let wal = Wal::open_with_size(dir.path().join("wal.bin"), 4096).unwrap();
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tcp_addr = tcp_listener.local_addr().unwrap();
+        let udp_recv = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let (mut producer, consumer) = ring::ring_buffer::<EngineCommand>(64);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let shutdown_match = Arc::clone(&shutdown);
+        let (snapshot_tx, snapshot_rx) = mpsc::channel();
+        let publisher = Publisher::new(
+            UdpSocket::bind("0.0.0.0:0").unwrap(),
+            udp_recv.local_addr().unwrap(),
+            false,
+        )
+        .with_snapshot_replies(snapshot_tx);
+        let snapshotter = Snapshotter::new(snap_dir.clone(), &GatewayConfig::default());
+        let match_thread = thread::spawn(move || {
+            matching_loop(
+                consumer,
+                MatchingEngine::with_capacity(1024),
+                Some(wal),
+                None,
+                Some(snapshotter),
+                publisher,
+                WaitStrategy::Yield,
+                shutdown_match,
+            );
+        });
+
+        let orders = [
+            (1, Side::Bid, 99, 5),
+            (2, Side::Ask, 101, 5),
+            (3, Side::Bid, 101, 2),
+            (4, Side::Bid, 98, 7),
+        ];
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(tcp_addr).unwrap();
+            let mut buf = [0u8; NEW_ORDER_SIZE];
+            let mut send = |stream: &mut TcpStream, (id, side, price, qty)| {
+                let order = Order::try_new(id, id, side, price, qty, 0).unwrap();
+                encode_new_order(&mut buf, &order).unwrap();
+                stream.write_all(&buf).unwrap();
+            };
+            for order in &orders[..3] {
+                send(&mut stream, *order);
+            }
+            let mut admin = [0u8; protocol::ADMIN_SNAPSHOT_SIZE];
+            protocol::encode_admin_snapshot(&mut admin).unwrap();
+            stream.write_all(&admin).unwrap();
+            let mut reply = [0u8; protocol::ADMIN_SNAPSHOT_REPLY_SIZE];
+            stream.read_exact(&mut reply).unwrap();
+            // Trading carries on after the snapshot.
+            send(&mut stream, orders[3]);
+            protocol::decode_admin_snapshot_reply(&reply).unwrap()
+        });
+
+        let (stream, _) = tcp_listener.accept().unwrap();
+        handle_client(
+            stream,
+            &mut producer,
+            &mut Clock::new(ClockSource::Logical, 0),
+            &mut ClientMetrics::default(),
+            &GatewayConfig::default(),
+            Some(&snapshot_rx),
+            &shutdown,
+        )
+        .unwrap();
+        match_thread.join().unwrap();
+
+        let reply = client.join().unwrap();
+        assert_eq!(
+            reply,
+            protocol::AdminSnapshotReply {
+                status: protocol::SNAPSHOT_SAVED,
+                record_count: 3,
+            }
+        );
+
+        // The snapshot holds exactly the first three orders' book, timestamps
+        // as the gateway's logical clock stamped them.
+        let mut expected = MatchingEngine::with_capacity(1024);
+        for (ts, &(id, side, price, qty)) in (1..).zip(&orders[..3]) {
+            let order = Order::try_new(id, id, side, price, qty, ts).unwrap();
+            expected.add_order(order).unwrap();
         }
+        let snapshot = Snapshot::load_latest(&snap_dir).unwrap().unwrap();
+        assert_eq!(snapshot.wal_record_count, 3);
+        let restored = snapshot.restore(1024).unwrap();
+        assert_eq!(restored.book().state_hash(), expected.book().state_hash());
+        assert_eq!(restored.book().order_count(), 2);
+    }
+
+    #[test]
+    fn admin_snapshot_without_persistence_says_so() {
+        let (tx, rx) = mpsc::channel();
+        let publisher = Publisher::new(
+            UdpSocket::bind("0.0.0.0:0").unwrap(),
+            "127.0.0.1:9".parse().unwrap(),
+            false,
+        )
+        .with_snapshot_replies(tx);
+        let mut engine = MatchingEngine::with_capacity(16);
+        admin_snapshot(&mut engine, &mut None, &mut None, &publisher);
+
+        let reply = rx.recv().unwrap();
+        assert_eq!(
+            protocol::decode_admin_snapshot_reply(&reply)
+                .unwrap()
+                .status,
+            protocol::SNAPSHOT_DISABLED
+        );
     }
 
     #[test]
@@ -385,9 +2490,9 @@ mod tests {
                 engine,
                 None,
                 None,
-                10_000,
-                udp_send,
-                udp_recv_addr,
+                None,
+                Publisher::new(udp_send, udp_recv_addr, false),
+                WaitStrategy::Yield,
                 shutdown_match,
             );
         });
@@ -396,24 +2501,32 @@ mod tests {
             let mut stream = TcpStream::connect(tcp_addr).unwrap();
 
             let ask = Order {
-                id: 1,
-                trader_id: 10,
+                id: 1.into(),
+                trader_id: 10.into(),
+                symbol_id: 0,
                 side: Side::Ask,
-                price: 100,
-                quantity: 50,
+                price: 100.into(),
+                quantity: 50.into(),
                 timestamp: 0,
+                expiry: None,
+                reduce_only: false,
+                post_only: false,
             };
             let mut buf = [0u8; NEW_ORDER_SIZE];
             encode_new_order(&mut buf, &ask).unwrap();
             stream.write_all(&buf).unwrap();
 
             let bid = Order {
-                id: 2,
-                trader_id: 20,
+                id: 2.into(),
+                trader_id: 20.into(),
+                symbol_id: 0,
                 side: Side::Bid,
-                price: 100,
-                quantity: 50,
+                price: 100.into(),
+                quantity: 50.into(),
                 timestamp: 0,
+                expiry: None,
+                reduce_only: false,
+                post_only: false,
             };
             encode_new_order(&mut buf, &bid).unwrap();
             stream.write_all(&buf).unwrap();
@@ -423,22 +2536,49 @@ mod tests {
 
         let (stream, _) = tcp_listener.accept().unwrap();
         let shutdown_ref = &shutdown;
-        handle_client(stream, &mut producer, shutdown_ref).unwrap();
+        handle_client(
+            stream,
+            &mut producer,
+            &mut wall_clock(),
+            &mut ClientMetrics::default(),
+            &GatewayConfig::default(),
+            None,
+            shutdown_ref,
+        )
+        .unwrap();
         shutdown.store(true, Ordering::Release);
 
         client.join().unwrap();
         match_thread.join().unwrap();
 
+        let mut ack_buf = [0u8; protocol::ORDER_ACK_SIZE];
+        let (n, _) = udp_recv.recv_from(&mut ack_buf).unwrap();
+        assert_eq!(n, protocol::ORDER_ACK_SIZE);
+        let ack = protocol::decode_order_ack(&ack_buf).unwrap();
+        assert_eq!(ack.seq_num, 1);
+        assert_eq!(ack.order_id, 1);
+        assert_eq!(ack.status, crate::matching::OrderStatus::Resting);
+        assert!(ack.became_best);
+        assert!(ack.timestamp > 0);
+
+        let ack =
+            protocol::decode_order_ack(&recv_feed(&udp_recv, protocol::MSG_ORDER_ACK).unwrap())
+                .unwrap();
+        assert_eq!(ack.seq_num, 2);
+        assert_eq!(ack.status, crate::matching::OrderStatus::FullyFilled);
+
         let mut report_buf = [0u8; EXECUTION_REPORT_SIZE];
         let (n, _) = udp_recv.recv_from(&mut report_buf).unwrap();
         assert_eq!(n, EXECUTION_REPORT_SIZE);
 
         let report = protocol::decode_execution_report(&report_buf).unwrap();
-        assert_eq!(report.seq_num, 1);
+        assert_eq!(report.seq_num, 3);
+        assert_eq!(report.timestamp, ack.timestamp);
         assert_eq!(report.taker_order_id, 2);
         assert_eq!(report.maker_order_id, 1);
         assert_eq!(report.price, 100);
         assert_eq!(report.quantity, 50);
+        assert_eq!((report.taker_seq, report.maker_seq), (2, 1));
         assert!(report.timestamp > 0);
     }
 
@@ -450,6 +2590,7 @@ mod tests {
         let snap_dir = data_dir.join("snapshots");
 
         let wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+        let trades = TradeLog::open(data_dir.join("trades.bin")).unwrap();
         let engine = MatchingEngine::with_capacity(1024);
 
         let udp_recv = UdpSocket::bind("127.0.0.1:0").unwrap();
@@ -469,29 +2610,37 @@ mod tests {
                 consumer,
                 engine,
                 Some(wal),
-                Some(snap_dir),
-                10_000,
-                udp_send,
-                udp_recv_addr,
+                Some(trades),
+                Some(Snapshotter::new(snap_dir, &GatewayConfig::default())),
+                Publisher::new(udp_send, udp_recv_addr, false),
+                WaitStrategy::Yield,
                 shutdown_match,
             );
         });
 
         let ask_order = Order {
-            id: 1,
-            trader_id: 10,
+            id: 1.into(),
+            trader_id: 10.into(),
+            symbol_id: 0,
             side: Side::Ask,
-            price: 100,
-            quantity: 50,
+            price: 100.into(),
+            quantity: 50.into(),
             timestamp: 1_000_000,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
         };
         let bid_order = Order {
-            id: 2,
-            trader_id: 20,
+            id: 2.into(),
+            trader_id: 20.into(),
+            symbol_id: 0,
             side: Side::Bid,
-            price: 100,
-            quantity: 50,
+            price: 100.into(),
+            quantity: 50.into(),
             timestamp: 2_000_000,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
         };
 
         producer.push(EngineCommand::NewOrder(ask_order)).unwrap();
@@ -503,13 +2652,776 @@ mod tests {
 
         let wal = Wal::open(data_dir.join("wal.bin")).unwrap();
         assert_eq!(wal.record_count(), 2);
+        let outcomes: Vec<_> = wal
+            .iter_from(0)
+            .with_outcomes()
+            .map(|r| r.unwrap().2)
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![Some(Outcome::Resting), Some(Outcome::FullyFilled)]
+        );
 
-        let mut report_buf = [0u8; EXECUTION_REPORT_SIZE];
-        let (n, _) = udp_recv.recv_from(&mut report_buf).unwrap();
-        assert_eq!(n, EXECUTION_REPORT_SIZE);
+        let trades = TradeLog::open(data_dir.join("trades.bin")).unwrap();
+        let logged: Vec<_> = trades.iter().map(Result::unwrap).collect();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(
+            (logged[0].seq_num, logged[0].wal_record),
+            (1, 2),
+            "the fill points back at the bid's WAL record"
+        );
+        assert_eq!((logged[0].taker_order_id, logged[0].maker_order_id), (2, 1));
+        assert_eq!((logged[0].quantity, logged[0].timestamp), (50, 2_000_000));
 
+        let report_buf = recv_feed(&udp_recv, protocol::MSG_EXECUTION_REPORT).unwrap();
         let report = protocol::decode_execution_report(&report_buf).unwrap();
-        assert_eq!(report.seq_num, 1);
+        // Preceded by the acks for both orders
+        assert_eq!(report.seq_num, 3);
         assert_eq!(report.quantity, 50);
     }
+
+    #[test]
+    fn snapshotter_interleaves_full_and_delta() {
+        let dir = tempfile::tempdir().unwrap();
+        let snap_dir = dir.path().join("snapshots");
+        let mut wal = Wal::open(dir.path().join("wal.bin")).unwrap();
+        let mut engine = MatchingEngine::with_capacity(1024);
+        engine.set_change_tracking(true);
+
+        let config = GatewayConfig {
+            snapshot_interval: 10,
+            delta_snapshot_interval: Some(3),
+            ..GatewayConfig::default()
+        };
+        let mut snapshotter = Snapshotter::new(snap_dir.clone(), &config);
+
+        for id in 1..=13 {
+            let cmd =
+                EngineCommand::NewOrder(Order::try_new(id, id, Side::Bid, 100, 1, id).unwrap());
+            wal.append(&cmd).unwrap();
+            if let EngineCommand::NewOrder(order) = cmd {
+                engine.add_order(order).unwrap();
+            }
+            snapshotter.after_command(&mut engine, &mut wal);
+        }
+
+        let mut files: Vec<String> = std::fs::read_dir(&snap_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                "delta_0000000003_0000000006.bin",
+                "delta_0000000006_0000000009.bin",
+                "delta_0000000009_0000000012.bin",
+                "snapshot_0000000003.bin",
+                "snapshot_0000000013.bin",
+            ]
+        );
+    }
+
+    #[test]
+    fn idle_snapshotter_saves_once_the_time_interval_passes() {
+        let dir = tempfile::tempdir().unwrap();
+        let snap_dir = dir.path().join("snapshots");
+        let mut wal = Wal::open(dir.path().join("wal.bin")).unwrap();
+        let mut engine = MatchingEngine::with_capacity(1024);
+
+        let config = GatewayConfig {
+            snapshot_interval_secs: Some(60),
+            ..GatewayConfig::default()
+        };
+        let mut snapshotter = Snapshotter::new(snap_dir.clone(), &config);
+        let order = Order::try_new(1, 1, Side::Bid, 100, 1, 1).unwrap();
+        wal.append(&EngineCommand::NewOrder(order.clone())).unwrap();
+        engine.add_order(order).unwrap();
+        snapshotter.after_command(&mut engine, &mut wal);
+
+        let count = || std::fs::read_dir(&snap_dir).map_or(0, |d| d.count());
+        snapshotter.when_idle(&mut engine, &mut wal);
+        assert_eq!(count(), 0);
+
+        let backdate = |s: &mut Snapshotter| {
+            s.last_full = Instant::now().checked_sub(Duration::from_secs(61)).unwrap();
+        };
+        backdate(&mut snapshotter);
+        snapshotter.when_idle(&mut engine, &mut wal);
+        assert!(snap_dir.join("snapshot_0000000001.bin").exists());
+
+        // Nothing new since, so another interval passing writes nothing.
+        backdate(&mut snapshotter);
+        snapshotter.when_idle(&mut engine, &mut wal);
+        assert_eq!(count(), 1);
+    }
+
+    #[test]
+    fn busy_snapshotter_checks_the_time_interval_every_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let snap_dir = dir.path().join("snapshots");
+        let mut wal = Wal::open(dir.path().join("wal.bin")).unwrap();
+        let mut engine = MatchingEngine::with_capacity(1024);
+
+        let config = GatewayConfig {
+            snapshot_interval_secs: Some(60),
+            max_batch_before_housekeeping: 4,
+            ..GatewayConfig::default()
+        };
+        let mut snapshotter = Snapshotter::new(snap_dir.clone(), &config);
+        snapshotter.last_full = Instant::now().checked_sub(Duration::from_secs(61)).unwrap();
+
+        // The ring never empties, so only the batch bound gets the check in.
+        for id in 1..=4 {
+            let order = Order::try_new(id, id, Side::Bid, 100, 1, id).unwrap();
+            wal.append(&EngineCommand::NewOrder(order.clone())).unwrap();
+            engine.add_order(order).unwrap();
+            snapshotter.after_command(&mut engine, &mut wal);
+            assert_eq!(
+                snap_dir.join("snapshot_0000000004.bin").exists(),
+                id == 4,
+                "after command {id}"
+            );
+        }
+        assert_eq!(std::fs::read_dir(&snap_dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn expired_orders_cancelled_and_logged() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut wal = Some(Wal::open(dir.path().join("wal.bin")).unwrap());
+        let mut engine = MatchingEngine::with_capacity(1024);
+        let far_future = u64::MAX - 1;
+        engine
+            .add_order(
+                Order::try_new(1, 1, Side::Bid, 100, 10, 1)
+                    .unwrap()
+                    .with_expiry(1),
+            )
+            .unwrap();
+        engine
+            .add_order(
+                Order::try_new(2, 2, Side::Bid, 99, 10, 2)
+                    .unwrap()
+                    .with_expiry(far_future),
+            )
+            .unwrap();
+
+        assert_eq!(expire_due_orders(&mut engine, &mut wal).len(), 1);
+        assert!(expire_due_orders(&mut engine, &mut wal).is_empty());
+
+        assert!(!engine.book().contains_order(1));
+        assert!(engine.book().contains_order(2));
+        assert_eq!(engine.next_expiry(), Some(far_future));
+
+        let wal = wal.unwrap();
+        let records: Vec<_> = wal.iter_from(0).map(|r| r.unwrap().1).collect();
+        assert_eq!(records, [EngineCommand::CancelOrder { order_id: 1 }]);
+    }
+
+    #[test]
+    fn failed_wal_append_records_no_outcome() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+        let mut wal = Wal::open_with_size(&path, 4096).unwrap();
+        // One record per segment, and the second segment can't be created,
+        // so the second append fails as if the disk were full.
+        wal.set_segment_size(Some(crate::wal::FILE_HEADER_SIZE as u64 + 56));
+        std::fs::create_dir(dir.path().join("wal.bin.00000000000000000002")).unwrap();
+        let mut wal = Some(wal);
+        let mut engine = MatchingEngine::with_capacity(1024);
+        let mut publisher = Publisher::new(
+            UdpSocket::bind("0.0.0.0:0").unwrap(),
+            UdpSocket::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap(),
+            false,
+        );
+
+        for (id, side) in [(1, Side::Ask), (2, Side::Bid)] {
+            let order = Order::try_new(id, id, side, 100, 10, id).unwrap();
+            process_command(
+                EngineCommand::NewOrder(order),
+                &mut engine,
+                &mut wal,
+                &mut None,
+                &mut publisher,
+            );
+        }
+        assert_eq!(engine.metrics().fills, 1);
+
+        // The ask's record keeps its own outcome, not the bid's fill.
+        let wal = wal.unwrap();
+        assert_eq!(wal.record_count(), 1);
+        let outcomes: Vec<_> = wal
+            .iter_from(0)
+            .with_outcomes()
+            .map(|r| r.unwrap().2)
+            .collect();
+        assert_eq!(outcomes, vec![Some(Outcome::Resting)]);
+    }
+
+    #[test]
+    fn book_updates_sent_on_top_of_book_change() {
+        let udp_recv = UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp_recv
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let udp_send = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let mut publisher = Publisher::new(udp_send, udp_recv.local_addr().unwrap(), true);
+        let mut engine = MatchingEngine::with_capacity(1024);
+        let mut wal = None;
+
+        let recv = || {
+            recv_feed(&udp_recv, protocol::MSG_BOOK_UPDATE)
+                .map(|buf| protocol::decode_book_update(&buf).unwrap())
+        };
+
+        let bid = |id, price, qty| {
+            EngineCommand::NewOrder(Order::try_new(id, id, Side::Bid, price, qty, id).unwrap())
+        };
+        process_command(
+            bid(1, 100, 10),
+            &mut engine,
+            &mut wal,
+            &mut None,
+            &mut publisher,
+        );
+        // Each accepted order's ack comes first
+        let update = recv().unwrap();
+        assert_eq!(update.seq_num, 2);
+        assert_eq!(update.best_bid, Some((100, 10)));
+        assert_eq!(update.best_ask, None);
+        assert_eq!(update.timestamp, 1);
+
+        // Behind the best bid: top of book unchanged, nothing sent
+        process_command(
+            bid(2, 99, 10),
+            &mut engine,
+            &mut wal,
+            &mut None,
+            &mut publisher,
+        );
+        // Same price adds size
+        process_command(
+            bid(3, 100, 5),
+            &mut engine,
+            &mut wal,
+            &mut None,
+            &mut publisher,
+        );
+        let update = recv().unwrap();
+        assert_eq!(update.seq_num, 5);
+        assert_eq!(update.best_bid, Some((100, 15)));
+
+        process_command(
+            EngineCommand::CancelOrder { order_id: 2 },
+            &mut engine,
+            &mut wal,
+            &mut None,
+            &mut publisher,
+        );
+        assert!(recv().is_none());
+    }
+
+    #[test]
+    fn level_deltas_follow_each_changed_level() {
+        let udp_recv = UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp_recv
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let udp_send = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let mut publisher =
+            Publisher::new(udp_send, udp_recv.local_addr().unwrap(), false).with_level_deltas(true);
+        let mut engine = MatchingEngine::with_capacity(1024);
+
+        let recv = || {
+            recv_feed(&udp_recv, protocol::MSG_LEVEL_DELTA)
+                .map(|buf| protocol::decode_level_delta(&buf).unwrap())
+        };
+        let mut submit =
+            |cmd| process_command(cmd, &mut engine, &mut None, &mut None, &mut publisher);
+
+        for (id, price, qty) in [(1, 100, 10), (2, 100, 5), (3, 101, 10)] {
+            let order = Order::try_new(id, 1, Side::Ask, price, qty, id).unwrap();
+            submit(EngineCommand::NewOrder(order));
+        }
+        let adds: Vec<_> = (0..3)
+            .map(|_| {
+                let d = recv().unwrap();
+                (d.price, d.quantity, d.order_count)
+            })
+            .collect();
+        assert_eq!(adds, [(100, 10, 1), (100, 15, 2), (101, 10, 1)]);
+
+        // Takes all of order 1 and 2 of order 2; the bid fills completely, so
+        // only the ask level moves.
+        let bid = Order::try_new(4, 2, Side::Bid, 100, 12, 4).unwrap();
+        submit(EngineCommand::NewOrder(bid));
+        assert_eq!(
+            recv().unwrap(),
+            LevelDelta {
+                seq_num: 10,
+                side: Side::Ask,
+                price: 100,
+                quantity: 3,
+                order_count: 1,
+                timestamp: 4,
+            }
+        );
+
+        submit(EngineCommand::CancelOrder { order_id: 2 });
+        let removed = recv().unwrap();
+        assert_eq!(
+            (removed.seq_num, removed.price, removed.quantity),
+            (11, 100, 0)
+        );
+
+        // Cancelling nothing changes no level.
+        submit(EngineCommand::CancelOrder { order_id: 2 });
+        assert!(recv().is_none());
+    }
+
+    #[test]
+    fn interleaved_expiries_send_one_delta_per_level() {
+        let udp_recv = UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp_recv
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let udp_send = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let mut publisher =
+            Publisher::new(udp_send, udp_recv.local_addr().unwrap(), false).with_level_deltas(true);
+        let mut engine = MatchingEngine::with_capacity(1024);
+
+        // Expiring 100, 101, 100 in that order, leaving one order at 100.
+        for (id, price, expiry) in [(1, 100, 1), (2, 101, 2), (3, 100, 3), (4, 100, u64::MAX)] {
+            let order = Order::try_new(id, 1, Side::Ask, price, 10, id)
+                .unwrap()
+                .with_expiry(expiry);
+            engine.add_order(order).unwrap();
+        }
+        let expired = engine.expire_orders(3);
+        let ids: Vec<_> = expired.iter().map(|o| o.id.0).collect();
+        assert_eq!(ids, [1, 2, 3]);
+        publisher.publish_expiries(&engine, &expired);
+
+        let mut deltas = Vec::new();
+        while let Some(buf) = recv_feed(&udp_recv, protocol::MSG_LEVEL_DELTA) {
+            let d = protocol::decode_level_delta(&buf).unwrap();
+            deltas.push((d.price, d.quantity, d.order_count));
+        }
+        assert_eq!(deltas, [(100, 10, 1), (101, 0, 0)]);
+    }
+
+    #[test]
+    fn book_updates_off_by_default() {
+        let udp_recv = UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp_recv
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let udp_send = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let config = GatewayConfig::default();
+        let mut publisher = Publisher::new(
+            udp_send,
+            udp_recv.local_addr().unwrap(),
+            config.publish_book_updates,
+        );
+        let mut engine = MatchingEngine::with_capacity(1024);
+
+        let order = Order::try_new(1, 1, Side::Ask, 100, 10, 1).unwrap();
+        process_command(
+            EngineCommand::NewOrder(order),
+            &mut engine,
+            &mut None,
+            &mut None,
+            &mut publisher,
+        );
+        assert!(recv_feed(&udp_recv, protocol::MSG_ORDER_ACK).is_some());
+        assert!(recv_feed(&udp_recv, protocol::MSG_BOOK_UPDATE).is_none());
+    }
+
+    #[test]
+    fn agg_trades_follow_each_swept_level() {
+        let udp_recv = UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp_recv
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let udp_send = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let mut publisher =
+            Publisher::new(udp_send, udp_recv.local_addr().unwrap(), false).with_agg_trades(true);
+        let mut engine = MatchingEngine::with_capacity(1024);
+
+        for (id, price) in [(1, 100), (2, 100), (3, 100), (4, 101)] {
+            let order = Order::try_new(id, 1, Side::Ask, price, 10, id).unwrap();
+            process_command(
+                EngineCommand::NewOrder(order),
+                &mut engine,
+                &mut None,
+                &mut None,
+                &mut publisher,
+            );
+        }
+        let taker = Order::try_new(5, 2, Side::Bid, 101, 35, 9).unwrap();
+        process_command(
+            EngineCommand::NewOrder(taker),
+            &mut engine,
+            &mut None,
+            &mut None,
+            &mut publisher,
+        );
+
+        // Acks 1-5, reports 6-8, AggTrade 9, report 10, AggTrade 11
+        let recv = || {
+            let buf = recv_feed(&udp_recv, protocol::MSG_AGG_TRADE).unwrap();
+            protocol::decode_agg_trade(&buf).unwrap()
+        };
+        let first = recv();
+        assert_eq!(
+            (first.seq_num, first.taker_order_id, first.price),
+            (9, 5, 100)
+        );
+        assert_eq!(
+            (first.quantity, first.fill_count, first.timestamp),
+            (30, 3, 9)
+        );
+        let second = recv();
+        assert_eq!(
+            (
+                second.seq_num,
+                second.price,
+                second.quantity,
+                second.fill_count
+            ),
+            (11, 101, 5, 1)
+        );
+    }
+
+    #[test]
+    fn cancel_all_publishes_a_report_per_order() {
+        let udp_recv = UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp_recv
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let udp_send = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let mut publisher = Publisher::new(udp_send, udp_recv.local_addr().unwrap(), false);
+        let mut engine = MatchingEngine::with_capacity(1024);
+
+        for (id, trader, qty) in [(1, 7, 10), (2, 8, 20), (3, 7, 30)] {
+            let order = Order::try_new(id, trader, Side::Ask, 100, qty, id).unwrap();
+            process_command(
+                EngineCommand::NewOrder(order),
+                &mut engine,
+                &mut None,
+                &mut None,
+                &mut publisher,
+            );
+        }
+        process_command(
+            EngineCommand::CancelAll { trader_id: 7 },
+            &mut engine,
+            &mut None,
+            &mut None,
+            &mut publisher,
+        );
+
+        // Acks 1-3, then the reports in queue order
+        let recv = || {
+            recv_feed(&udp_recv, protocol::MSG_CANCEL_REPORT)
+                .map(|buf| protocol::decode_cancel_report(&buf).unwrap())
+        };
+        let first = recv().unwrap();
+        assert_eq!((first.seq_num, first.order_id, first.quantity), (4, 1, 10));
+        let second = recv().unwrap();
+        assert_eq!(
+            (second.seq_num, second.order_id, second.quantity),
+            (5, 3, 30)
+        );
+        assert!(recv().is_none());
+        assert!(engine.book().contains_order(2));
+    }
+
+    #[test]
+    fn output_ring_gets_the_same_reports_as_the_feed() {
+        let udp_recv = UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp_recv
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let (output, mut reports) = ring::ring_buffer::<ExecutionReport>(2);
+        let mut publisher = Publisher::new(
+            UdpSocket::bind("0.0.0.0:0").unwrap(),
+            udp_recv.local_addr().unwrap(),
+            false,
+        )
+        .with_output_ring(Some(output));
+        let mut engine = MatchingEngine::with_capacity(1024);
+
+        for (id, price) in [(1, 100), (2, 101), (3, 102)] {
+            let order = Order::try_new(id, id, Side::Ask, price, 10, id).unwrap();
+            process_command(
+                EngineCommand::NewOrder(order),
+                &mut engine,
+                &mut None,
+                &mut None,
+                &mut publisher,
+            );
+        }
+        process_command(
+            EngineCommand::NewOrder(Order::try_new(4, 4, Side::Bid, 102, 30, 4).unwrap()),
+            &mut engine,
+            &mut None,
+            &mut None,
+            &mut publisher,
+        );
+
+        let feed: Vec<_> = (0..3)
+            .map(|_| {
+                let buf = recv_feed(&udp_recv, protocol::MSG_EXECUTION_REPORT).unwrap();
+                protocol::decode_execution_report(&buf).unwrap()
+            })
+            .collect();
+        let mut in_process = Vec::new();
+        while let Ok(report) = reports.pop() {
+            in_process.push(report);
+        }
+        // The ring holds two; the third report is dropped, not waited for.
+        assert_eq!(in_process, feed[..2]);
+        assert_eq!(publisher.output_dropped, 1);
+        assert_eq!(publisher.metrics.dropped, 0);
+    }
+
+    #[test]
+    fn matching_loop_publishes_top_of_book_for_readers() {
+        let (writer, reader) = crate::top_of_book::top_of_book();
+        let (mut producer, consumer) = ring::ring_buffer::<EngineCommand>(64);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let shutdown_match = Arc::clone(&shutdown);
+        let publisher = Publisher::new(
+            UdpSocket::bind("0.0.0.0:0").unwrap(),
+            UdpSocket::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap(),
+            false,
+        )
+        .with_top_of_book(Some(writer));
+        let match_thread = thread::spawn(move || {
+            matching_loop(
+                consumer,
+                MatchingEngine::with_capacity(1024),
+                None,
+                None,
+                None,
+                publisher,
+                WaitStrategy::Yield,
+                shutdown_match,
+            );
+        });
+
+        // Read from another thread while commands are still arriving.
+        let watcher = {
+            let reader = reader.clone();
+            thread::spawn(move || {
+                while reader.read().updates < 3 {
+                    thread::yield_now();
+                }
+                reader.read()
+            })
+        };
+        for (id, side, price) in [(1, Side::Bid, 99), (2, Side::Bid, 98), (3, Side::Ask, 101)] {
+            let order = Order::try_new(id, id, side, price, 5, id).unwrap();
+            producer.push(EngineCommand::NewOrder(order)).unwrap();
+        }
+        let seen = watcher.join().unwrap();
+        producer
+            .push(EngineCommand::CancelOrder { order_id: 1 })
+            .unwrap();
+        shutdown.store(true, Ordering::Release);
+        match_thread.join().unwrap();
+
+        assert_eq!((seen.best_bid(), seen.best_ask()), (Some(99), Some(101)));
+        let top = reader.read();
+        assert_eq!(top.updates, 4);
+        assert_eq!((top.best_bid(), top.best_ask()), (Some(98), Some(101)));
+        assert!(top.bids[1].is_none());
+    }
+
+    #[test]
+    fn matching_loop_times_every_command() {
+        let latency = Arc::new(LatencyHistogram::new());
+        let (mut producer, consumer) = ring::ring_buffer::<EngineCommand>(64);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let shutdown_match = Arc::clone(&shutdown);
+        let publisher = Publisher::new(
+            UdpSocket::bind("0.0.0.0:0").unwrap(),
+            UdpSocket::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap(),
+            false,
+        )
+        .with_latency(Some(Arc::clone(&latency)));
+        let match_thread = thread::spawn(move || {
+            matching_loop(
+                consumer,
+                MatchingEngine::with_capacity(1024),
+                None,
+                None,
+                None,
+                publisher,
+                WaitStrategy::Yield,
+                shutdown_match,
+            );
+        });
+
+        // Ten resting asks, then a bid that sweeps them.
+        for id in 1..=10 {
+            let order = Order::try_new(id, id, Side::Ask, 100 + id as i64, 1, id).unwrap();
+            producer.push(EngineCommand::NewOrder(order)).unwrap();
+        }
+        let sweep = Order::try_new(11, 11, Side::Bid, 110, 10, 11).unwrap();
+        producer.push(EngineCommand::NewOrder(sweep)).unwrap();
+        while latency.samples() < 11 {
+            thread::yield_now();
+        }
+        shutdown.store(true, Ordering::Release);
+        match_thread.join().unwrap();
+
+        let p = latency.engine_latency_percentiles();
+        assert_eq!(p.samples, 11);
+        assert!(p.p50 > Duration::ZERO);
+        assert!(p.p50 <= p.p99 && p.p99 <= p.p999);
+    }
+
+    #[test]
+    fn matching_loop_reports_wal_lag_since_the_last_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = GatewayConfig {
+            snapshot_interval: 2,
+            max_batch_before_housekeeping: 1,
+            ..GatewayConfig::default()
+        };
+        let wal = Wal::open_with_size(dir.path().join("wal.bin"), 4096).unwrap();
+        let snapshotter = Snapshotter::new(dir.path().join("snapshots"), &config);
+        let lag = Arc::new(WalLag::default());
+        let publisher = Publisher::new(
+            UdpSocket::bind("0.0.0.0:0").unwrap(),
+            UdpSocket::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap(),
+            false,
+        )
+        .with_wal_lag(Some(Arc::clone(&lag)));
+        let (mut producer, consumer) = ring::ring_buffer::<EngineCommand>(64);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let shutdown_match = Arc::clone(&shutdown);
+        let match_thread = thread::spawn(move || {
+            matching_loop(
+                consumer,
+                MatchingEngine::with_capacity(1024),
+                Some(wal),
+                None,
+                Some(snapshotter),
+                publisher,
+                WaitStrategy::Yield,
+                shutdown_match,
+            );
+        });
+
+        // The second command's snapshot flushes the WAL; the third is not
+        // flushed yet.
+        for id in 1..=3 {
+            let order = Order::try_new(id, id, Side::Bid, 100, 5, id).unwrap();
+            producer.push(EngineCommand::NewOrder(order)).unwrap();
+        }
+        shutdown.store(true, Ordering::Release);
+        match_thread.join().unwrap();
+
+        assert_eq!(lag.records(), 1);
+        let wal = Wal::open_with_size(dir.path().join("wal.bin"), 4096).unwrap();
+        let record_size = (wal.write_pos() - crate::wal::FILE_HEADER_SIZE as u64) / 3;
+        assert_eq!(lag.bytes(), record_size);
+    }
+
+    #[test]
+    fn full_arena_rejects_with_capacity_reason() {
+        let udp_recv = UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp_recv
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let udp_send = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let mut publisher = Publisher::new(udp_send, udp_recv.local_addr().unwrap(), false);
+        let mut engine = MatchingEngine::with_fixed_capacity(2);
+
+        for id in 1..=3 {
+            process_command(
+                EngineCommand::NewOrder(Order::try_new(id, id, Side::Bid, 100, 10, id).unwrap()),
+                &mut engine,
+                &mut None,
+                &mut None,
+                &mut publisher,
+            );
+        }
+
+        let buf = recv_feed(&udp_recv, protocol::MSG_ORDER_REJECT).unwrap();
+        let reject = protocol::decode_order_reject(&buf).unwrap();
+        assert_eq!((reject.seq_num, reject.order_id), (3, 3));
+        assert_eq!(reject.reason, protocol::REJECT_ARENA_FULL);
+        assert_eq!(engine.metrics().arena_full_rejects, 1);
+        assert!(!engine.book().contains_order(3));
+    }
+
+    #[test]
+    fn engine_rejects_published_with_reason() {
+        let udp_recv = UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp_recv
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let udp_send = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let mut publisher = Publisher::new(udp_send, udp_recv.local_addr().unwrap(), false);
+        let mut engine = MatchingEngine::with_capacity(1024);
+
+        let order = Order::try_new(1, 1, Side::Ask, 100, 10, 5).unwrap();
+        process_command(
+            EngineCommand::NewOrder(order.clone()),
+            &mut engine,
+            &mut None,
+            &mut None,
+            &mut publisher,
+        );
+        process_command(
+            EngineCommand::NewOrder(order),
+            &mut engine,
+            &mut None,
+            &mut None,
+            &mut publisher,
+        );
+        process_command(
+            EngineCommand::CancelReplace {
+                old_id: 9,
+                new_order: Order::try_new(2, 1, Side::Ask, 100, 10, 6).unwrap(),
+            },
+            &mut engine,
+            &mut None,
+            &mut None,
+            &mut publisher,
+        );
+
+        let recv = || {
+            let buf = recv_feed(&udp_recv, protocol::MSG_ORDER_REJECT).unwrap();
+            protocol::decode_order_reject(&buf).unwrap()
+        };
+        // Sequence 1 is the first order's ack
+        let dup = recv();
+        assert_eq!(
+            (dup.seq_num, dup.order_id, dup.reason, dup.timestamp),
+            (2, 1, protocol::REJECT_DUPLICATE_ORDER_ID, 5)
+        );
+        let missing = recv();
+        assert_eq!(
+            (missing.seq_num, missing.order_id, missing.reason),
+            (3, 2, protocol::REJECT_ORDER_NOT_FOUND)
+        );
+    }
 }
@@ -0,0 +1,176 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Each power of two is split into this many buckets, so a bucket is at
+/// most 1/16 (about 6%) wider than the values in it.
+const SUB_BUCKETS: usize = 16;
+const SUB_BUCKET_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+
+/// Values below `2 * SUB_BUCKETS` get a bucket each; above that, 16 per
+/// power of two up to `u64::MAX`.
+const BUCKETS: usize = (65 - SUB_BUCKET_BITS as usize) * SUB_BUCKETS;
+
+/// Log-linear histogram of nanosecond latencies, in the style of
+/// HdrHistogram with fixed precision: the buckets are allocated up front and
+/// recording is an index computation and one atomic increment. Any number
+/// of threads may record and read at once; a read sees every sample
+/// recorded before it and possibly some of those during it.
+pub struct LatencyHistogram {
+    counts: Box<[AtomicU64; BUCKETS]>,
+}
+
+/// Snapshot of a `LatencyHistogram`. Each percentile is the upper bound of
+/// the bucket holding it, so it overstates by at most about 6%.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyPercentiles {
+    pub samples: u64,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub p999: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            counts: Box::new(std::array::from_fn(|_| AtomicU64::new(0))),
+        }
+    }
+
+    pub fn record(&self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.counts[bucket(nanos)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Samples recorded so far. O(buckets).
+    pub fn samples(&self) -> u64 {
+        self.counts.iter().map(|c| c.load(Ordering::Relaxed)).sum()
+    }
+
+    /// The smallest bucket bound at or above a `quantile` (0 to 1) of the
+    /// samples, or zero with none recorded. O(buckets).
+    pub fn percentile(&self, quantile: f64) -> Duration {
+        self.percentiles_of(&[quantile])[0]
+    }
+
+    /// Median, 99th and 99.9th percentiles from one pass over the buckets.
+    pub fn engine_latency_percentiles(&self) -> LatencyPercentiles {
+        let samples = self.samples();
+        let [p50, p99, p999] = self.percentiles_of(&[0.50, 0.99, 0.999]);
+        LatencyPercentiles {
+            samples,
+            p50,
+            p99,
+            p999,
+        }
+    }
+
+    fn percentiles_of<const N: usize>(&self, quantiles: &[f64; N]) -> [Duration; N] {
+        let counts: [u64; BUCKETS] =
+            std::array::from_fn(|i| self.counts[i].load(Ordering::Relaxed));
+        let total: u64 = counts.iter().sum();
+        quantiles.map(|q| {
+            if total == 0 {
+                return Duration::ZERO;
+            }
+            let rank = ((q.clamp(0.0, 1.0) * total as f64).ceil() as u64).max(1);
+            let mut seen = 0;
+            let index = counts
+                .iter()
+                .position(|&c| {
+                    seen += c;
+                    seen >= rank
+                })
+                .unwrap_or(BUCKETS - 1);
+            Duration::from_nanos(upper_bound(index))
+        })
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LatencyHistogram")
+            .field("samples", &self.samples())
+            .finish()
+    }
+}
+
+fn bucket(nanos: u64) -> usize {
+    if nanos < 2 * SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+    // `nanos >> shift` keeps the top five bits: 16 to 31.
+    let shift = 63 - nanos.leading_zeros() - SUB_BUCKET_BITS;
+    (shift as usize + 1) * SUB_BUCKETS + (nanos >> shift) as usize - SUB_BUCKETS
+}
+
+/// Largest value that lands in bucket `index`.
+fn upper_bound(index: usize) -> u64 {
+    if index < 2 * SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    let top = (index % SUB_BUCKETS + SUB_BUCKETS + 1) as u64;
+    (top << shift).wrapping_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_are_contiguous_and_bound_their_values() {
+        for nanos in (0..5_000).chain([u64::MAX / 3, u64::MAX - 1, u64::MAX]) {
+            let index = bucket(nanos);
+            assert!(nanos <= upper_bound(index), "{nanos}");
+            assert!(index == 0 || nanos > upper_bound(index - 1), "{nanos}");
+        }
+        assert_eq!(bucket(u64::MAX), BUCKETS - 1);
+        assert_eq!(upper_bound(BUCKETS - 1), u64::MAX);
+    }
+
+    #[test]
+    fn percentiles_land_in_the_right_bucket() {
+        let histogram = LatencyHistogram::new();
+        assert_eq!(
+            histogram.engine_latency_percentiles(),
+            LatencyPercentiles::default()
+        );
+
+        // 1..=1000 µs, one sample each.
+        for micros in 1..=1_000 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        let p = histogram.engine_latency_percentiles();
+        assert_eq!(p.samples, 1_000);
+        for (got, want) in [(p.p50, 500), (p.p99, 990), (p.p999, 999)] {
+            let want = Duration::from_micros(want);
+            assert!(
+                got >= want && got <= want + want / 16,
+                "{got:?} for {want:?}"
+            );
+        }
+        assert!(histogram.percentile(1.0) >= Duration::from_millis(1));
+        assert!(histogram.percentile(0.0) < Duration::from_nanos(1_100));
+    }
+
+    #[test]
+    fn concurrent_recorders_lose_no_samples() {
+        let histogram = LatencyHistogram::new();
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        histogram.record(Duration::from_nanos(500));
+                    }
+                });
+            }
+        });
+        assert_eq!(histogram.samples(), 40_000);
+    }
+}
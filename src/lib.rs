@@ -1,10 +1,18 @@
 pub(crate) mod arena;
 pub mod book;
+pub mod checksum;
+pub mod conformance;
+pub mod fix;
 pub mod gateway;
+pub mod latency;
 pub mod matching;
+pub mod multi_book;
 pub mod order;
 pub mod protocol;
 pub(crate) mod recovery;
+pub mod replay;
 pub mod ring;
 pub(crate) mod snapshot;
-pub(crate) mod wal;
+pub mod top_of_book;
+pub mod trade_log;
+pub mod wal;
@@ -7,6 +7,9 @@ fn main() -> Result<(), GatewayError> {
     eprintln!("ferrox v{}", env!("CARGO_PKG_VERSION"));
     eprintln!("  tcp listen:  {}", config.listen_addr);
     eprintln!("  udp multicast: {}", config.multicast_addr);
+    if let Some(interface) = config.multicast_interface {
+        eprintln!("  multicast if:  {interface}");
+    }
     eprintln!("  ring capacity: {}", config.ring_capacity);
     eprintln!("  arena capacity: {}", config.arena_capacity);
     match &config.data_dir {
@@ -1,5 +1,10 @@
-use crate::book::{BookError, OrderBook};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::book::{BookError, LevelQueue, OrderBook};
 use crate::order::{Order, Side};
+use crate::protocol::{self, EngineCommand, ProtocolError};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Fill {
@@ -8,6 +13,9 @@ pub struct Fill {
     pub price: i64,
     pub quantity: u64,
     pub maker_fully_filled: bool,
+    /// Engine sequence numbers of the two orders; see `AddOrderResult::seq`.
+    pub taker_seq: u64,
+    pub maker_seq: u64,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -16,19 +24,130 @@ pub enum OrderStatus {
     PartiallyFilled,
     Resting,
     CancelledSelfTrade,
+    /// A reduce-only order was cut down so it couldn't grow or flip the
+    /// trader's position. `fills` shows what traded; anything left of the
+    /// reduced quantity rests.
+    ReduceOnlyClamped,
+    /// A post-only order would have crossed the opposite best price. Nothing
+    /// traded and the book is unchanged.
+    RejectedPostOnly,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AddOrderResult {
     pub order_id: u64,
+    /// Assigned by the engine to every order it accepts, starting at 1 and
+    /// increasing by one per order. Unlike `order_id` it is unique even when
+    /// clients' ids collide, and it orders arrivals regardless of timestamps.
+    /// 0 for a post-only rejection.
+    pub seq: u64,
     pub status: OrderStatus,
     pub fills: Vec<Fill>,
+    /// The order's remainder rests at the best price on its side: it joined
+    /// or improved the top of book.
+    pub became_best: bool,
+}
+
+/// Outcome of `MatchingEngine::uncross`. `price` is `None` when the book
+/// didn't cross and nothing traded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UncrossResult {
+    pub price: Option<i64>,
+    pub fills: Vec<Fill>,
+}
+
+impl UncrossResult {
+    pub fn volume(&self) -> u64 {
+        self.fills.iter().map(|f| f.quantity).sum()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MatchingError {
     Book(BookError),
     ZeroQuantity,
+    InvalidTick {
+        price: i64,
+        tick_size: u64,
+    },
+    PriceBandViolation {
+        price: i64,
+        reference: i64,
+    },
+    QuantityLimitExceeded {
+        quantity: u64,
+        limit: u64,
+    },
+    /// `notional` is exact: a price times quantity can pass `u64::MAX`.
+    NotionalLimitExceeded {
+        notional: u128,
+        limit: u64,
+    },
+    /// The trader already has `limit` orders resting.
+    TraderOrderLimitExceeded {
+        trader_id: u64,
+        limit: u32,
+    },
+    /// Trading is halted and the order is one the halt policy turns away.
+    Halted,
+    /// The id was used before and `RiskConfig::order_ids` forbids reuse.
+    DuplicateOrderId(u64),
+    /// A reduce asked for more than the order has left.
+    ReduceExceedsQuantity {
+        order_id: u64,
+        remaining: u64,
+    },
+    /// The order is for a different instrument than `RiskConfig::symbol_id`.
+    WrongSymbol {
+        symbol_id: u32,
+        expected: u32,
+    },
+}
+
+impl std::fmt::Display for MatchingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Book(e) => write!(f, "book error: {e}"),
+            Self::ZeroQuantity => write!(f, "quantity is zero"),
+            Self::InvalidTick { price, tick_size } => {
+                write!(
+                    f,
+                    "price {price} is not a multiple of tick size {tick_size}"
+                )
+            }
+            Self::PriceBandViolation { price, reference } => {
+                write!(f, "price {price} is outside the band around {reference}")
+            }
+            Self::QuantityLimitExceeded { quantity, limit } => {
+                write!(f, "quantity {quantity} exceeds limit {limit}")
+            }
+            Self::NotionalLimitExceeded { notional, limit } => {
+                write!(f, "notional {notional} exceeds limit {limit}")
+            }
+            Self::TraderOrderLimitExceeded { trader_id, limit } => {
+                write!(f, "trader {trader_id} already has {limit} orders resting")
+            }
+            Self::Halted => write!(f, "trading is halted"),
+            Self::DuplicateOrderId(id) => write!(f, "order id {id} was already used"),
+            Self::ReduceExceedsQuantity {
+                order_id,
+                remaining,
+            } => write!(f, "reduce exceeds the {remaining} left on order {order_id}"),
+            Self::WrongSymbol {
+                symbol_id,
+                expected,
+            } => write!(f, "symbol {symbol_id} is not this book's {expected}"),
+        }
+    }
+}
+
+impl std::error::Error for MatchingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Book(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 impl From<BookError> for MatchingError {
@@ -36,26 +155,379 @@ impl From<BookError> for MatchingError {
         Self::Book(e)
     }
 }
+
+/// Why `MatchingEngine::apply_bytes` failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApplyError {
+    /// The buffer isn't a valid message; the engine is untouched.
+    Protocol(ProtocolError),
+    /// The engine refused the command.
+    Matching(MatchingError),
+}
+
+impl std::fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Protocol(e) => write!(f, "message decode failed: {e}"),
+            Self::Matching(e) => write!(f, "command rejected: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ApplyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Protocol(e) => Some(e),
+            Self::Matching(e) => Some(e),
+        }
+    }
+}
+
+impl From<ProtocolError> for ApplyError {
+    fn from(e: ProtocolError) -> Self {
+        Self::Protocol(e)
+    }
+}
+
+impl From<MatchingError> for ApplyError {
+    fn from(e: MatchingError) -> Self {
+        Self::Matching(e)
+    }
+}
 const FILLS_INITIAL_CAPACITY: usize = 16;
+/// Orders `warmup` pushes through its scratch engine, two per round.
+const WARMUP_ROUNDS: u64 = 1_000;
+/// Largest buffer `recycle_fills` keeps, so one huge sweep doesn't pin its
+/// allocation for the engine's lifetime.
+const FILLS_RETAINED_CAPACITY: usize = 4_096;
+
+/// Pre-trade checks applied at the top of `add_order`. Every limit is
+/// optional and the default disables all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RiskConfig {
+    /// Prices must be a multiple of this. `None`, 0 and 1 accept any price.
+    pub tick_size: Option<u64>,
+    /// Maximum distance from the reference price, in ticks.
+    pub price_band_ticks: Option<u64>,
+    /// Maximum distance from the reference price, as a percentage of it.
+    pub price_band_percent: Option<u32>,
+    /// Fallback reference when the opposite side is empty and nothing has traded.
+    /// With no fallback the band is skipped.
+    pub reference_price: Option<i64>,
+    /// Largest accepted order quantity.
+    pub max_order_quantity: Option<u64>,
+    /// Largest accepted `|price| * quantity`.
+    pub max_notional: Option<u64>,
+    /// Most orders one trader may have resting. Checked on submission, so a
+    /// trader at the limit is rejected even if the new order would fully fill.
+    pub max_resting_orders: Option<u32>,
+    /// Which ids a new order may reuse.
+    pub order_ids: OrderIdPolicy,
+    /// The instrument this book trades; orders for any other are rejected.
+    /// `None` accepts every `symbol_id`.
+    pub symbol_id: Option<u32>,
+}
+
+/// How far back duplicate order ids are detected. The book always rejects
+/// the id of a resting order; the stricter policies also cover ids of
+/// orders that have filled, been cancelled or been rejected after the check.
+/// Id history lives in memory only and is not part of snapshots.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OrderIdPolicy {
+    /// Ids of orders no longer resting may be reused.
+    #[default]
+    Resting,
+    /// No id is accepted twice. Keeps every accepted id, so memory grows
+    /// with the number of orders.
+    Unique,
+    /// Each id must be greater than every id accepted before it. Constant
+    /// memory, for clients that allocate ids from a counter.
+    Increasing,
+}
+
+/// Which new orders a halt turns away. Cancels are accepted either way, and
+/// nothing trades until `resume`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HaltPolicy {
+    /// Orders that would cross are rejected; the rest rest passively, so the
+    /// book can be rebuilt ahead of the reopening.
+    #[default]
+    RejectMarketable,
+    /// Every new order and replacement is rejected.
+    RejectAll,
+}
+
+/// How a fill between an incoming order and a resting one is priced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FillPricing {
+    /// The maker's posted price.
+    #[default]
+    MakerPrice,
+    /// The mid of the book's best bid and ask just before each fill, rounded
+    /// toward the maker's price; the incoming order isn't in the book yet.
+    /// The maker's side of that mid is its own level, so the maker gives up
+    /// half the spread. With the incoming order's side empty there's no mid
+    /// and the fill is at the maker's price.
+    Midpoint,
+}
+
+impl FillPricing {
+    /// `taker_side_best` is the book's best price on the incoming order's
+    /// side.
+    fn price(self, maker_price: i64, taker_side_best: Option<i64>) -> i64 {
+        match (self, taker_side_best) {
+            (Self::MakerPrice, _) | (Self::Midpoint, None) => maker_price,
+            // Truncating the half-spread rounds toward the maker's price.
+            (Self::Midpoint, Some(best)) => {
+                let half = (best as i128 - maker_price as i128) / 2;
+                (maker_price as i128 + half) as i64
+            }
+        }
+    }
+}
+
+/// Whether an order whose limit equals the opposite best price trades with
+/// it. Only the equal-price case differs: a limit through the opposite best
+/// trades under both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CrossPolicy {
+    /// A bid at the best ask, or an ask at the best bid, is marketable.
+    #[default]
+    AtEqualPrice,
+    /// An order at the opposite best rests beside it, leaving the book
+    /// locked; it has to improve on that price to trade.
+    StrictlyThrough,
+}
+
+impl CrossPolicy {
+    /// True if an order on `side` limited at `limit` trades with a resting
+    /// order at `resting`.
+    fn crosses(self, side: Side, limit: i64, resting: i64) -> bool {
+        match (side, self) {
+            (Side::Bid, Self::AtEqualPrice) => resting <= limit,
+            (Side::Bid, Self::StrictlyThrough) => resting < limit,
+            (Side::Ask, Self::AtEqualPrice) => resting >= limit,
+            (Side::Ask, Self::StrictlyThrough) => resting > limit,
+        }
+    }
+}
+
+/// Which same-id cancel/replaces keep the order's place in its queue. A kept
+/// order is amended in place: same seq, old timestamp, no matching. Anything
+/// else about the order, such as a new price, id or expiry, always requeues
+/// it at the back, as do reduce-only replacements.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AmendPolicy {
+    /// Every replacement requeues.
+    #[default]
+    ResetPriority,
+    /// Cutting the quantity keeps priority; raising it requeues.
+    KeepOnDecrease,
+    /// Any quantity change at the same price keeps priority.
+    KeepOnSamePrice,
+}
+
+impl AmendPolicy {
+    fn keeps_priority(self, old_quantity: u64, new_quantity: u64) -> bool {
+        match self {
+            Self::ResetPriority => false,
+            Self::KeepOnDecrease => new_quantity <= old_quantity,
+            Self::KeepOnSamePrice => true,
+        }
+    }
+}
+
+/// Per-trader risk view. `exposure` is the sum of `price * quantity` over
+/// the trader's resting orders on both sides, negative only through
+/// negative prices, and `resting_orders` their count; `position` is net
+/// filled quantity (bids positive, asks negative).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraderStats {
+    pub exposure: i128,
+    pub position: i128,
+    pub resting_orders: u32,
+}
+
+/// Cumulative engine counters, copied out by `MatchingEngine::metrics`.
+/// Submissions through both `add_order` and `cancel_replace` are counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EngineMetrics {
+    pub orders_accepted: u64,
+    pub orders_rejected: u64,
+    pub fills: u64,
+    /// Orders removed by cancel, cancel-replace or expiry.
+    pub cancels: u64,
+    pub self_trade_cancels: u64,
+    /// Total quantity traded across all fills.
+    pub quantity_matched: u64,
+    /// Rejections because the arena had no slot left to rest an order in,
+    /// also counted in `orders_rejected`. Only a fixed-capacity arena, or
+    /// one grown to its limit, runs out.
+    pub arena_full_rejects: u64,
+}
+
+impl EngineMetrics {
+    fn record_submission(&mut self, result: &Result<AddOrderResult, MatchingError>) {
+        match result {
+            Ok(r) => self.record(
+                Ok(r.status),
+                r.fills.len() as u64,
+                r.fills.iter().map(|f| f.quantity).sum(),
+            ),
+            Err(e) => self.record(Err(e), 0, 0),
+        }
+    }
+
+    /// `outcome` is the submission's status or why it was rejected; `fills`
+    /// and `quantity` are what it traded.
+    fn record(&mut self, outcome: Result<OrderStatus, &MatchingError>, fills: u64, quantity: u64) {
+        match outcome {
+            Err(e) => {
+                self.orders_rejected += 1;
+                self.arena_full_rejects +=
+                    u64::from(matches!(e, MatchingError::Book(BookError::ArenaFull)));
+            }
+            Ok(OrderStatus::RejectedPostOnly) => self.orders_rejected += 1,
+            Ok(status) => {
+                self.orders_accepted += 1;
+                self.fills += fills;
+                self.quantity_matched += quantity;
+                self.self_trade_cancels += u64::from(status == OrderStatus::CancelledSelfTrade);
+            }
+        }
+    }
+}
+
+/// `new` replaces `old` with at most its quantity and timestamp changed.
+fn is_amendment(old: &Order, new: &Order) -> bool {
+    !new.reduce_only
+        && Order {
+            quantity: old.quantity,
+            timestamp: old.timestamp,
+            ..new.clone()
+        } == *old
+}
+
+fn notional(price: i64, quantity: u64) -> i128 {
+    price as i128 * quantity as i128
+}
+
+fn signed_quantity(side: Side, quantity: u64) -> i128 {
+    match side {
+        Side::Bid => quantity as i128,
+        Side::Ask => -(quantity as i128),
+    }
+}
+
+/// `seq` as reported in a result: a post-only rejection never took its number.
+fn result_seq(seq: u64, status: OrderStatus) -> u64 {
+    if status == OrderStatus::RejectedPostOnly {
+        0
+    } else {
+        seq
+    }
+}
+
+/// Status of a matched order; `remaining` is what is left to rest.
+fn final_status(self_trade: bool, clamped: bool, remaining: u64, filled: bool) -> OrderStatus {
+    if self_trade {
+        OrderStatus::CancelledSelfTrade
+    } else if clamped {
+        OrderStatus::ReduceOnlyClamped
+    } else if remaining == 0 {
+        OrderStatus::FullyFilled
+    } else if filled {
+        OrderStatus::PartiallyFilled
+    } else {
+        OrderStatus::Resting
+    }
+}
+
+/// Order ids touched since the last snapshot capture.
+#[derive(Debug, Default)]
+struct ChangeSet {
+    /// Ids inserted into the book, in insertion (and so queue) order.
+    inserted: Vec<u64>,
+    modified: HashSet<u64>,
+    removed: HashSet<u64>,
+}
+
+/// Book changes between two captures. Applied in field order: removals,
+/// in-place quantity updates, then appends to the tail of each level.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct BookDelta {
+    /// Ids removed at any point since the last capture, sorted.
+    pub(crate) removed: Vec<u64>,
+    /// `(id, quantity)` for pre-existing orders that were partially filled, sorted by id.
+    pub(crate) modified: Vec<(u64, u64)>,
+    /// Orders that went into the book since the last capture and still rest,
+    /// in queue order, with their sequence numbers.
+    pub(crate) added: Vec<(Order, u64)>,
+}
 
 #[derive(Debug)]
 pub struct MatchingEngine {
     book: OrderBook,
     fills_buf: Vec<Fill>,
+    trader_stats: HashMap<u64, TraderStats>,
+    risk: RiskConfig,
+    fill_pricing: FillPricing,
+    amend_policy: AmendPolicy,
+    cross_policy: CrossPolicy,
+    last_trade_price: Option<i64>,
+    changes: Option<ChangeSet>,
+    /// `(expiry, order_id)` for every resting order with an expiry.
+    expiries: BTreeSet<(u64, u64)>,
+    metrics: EngineMetrics,
+    halt: Option<HaltPolicy>,
+    auction: bool,
+    /// Accepted ids, tracked under `OrderIdPolicy::Unique` only.
+    seen_ids: HashSet<u64>,
+    max_order_id: Option<u64>,
+    /// Sequence number the next accepted order gets.
+    next_seq: u64,
 }
 
 impl MatchingEngine {
     pub fn new() -> Self {
-        Self {
-            book: OrderBook::new(),
-            fills_buf: Vec::with_capacity(FILLS_INITIAL_CAPACITY),
-        }
+        Self::with_book(OrderBook::new())
     }
 
     pub fn with_capacity(arena_capacity: u32) -> Self {
+        Self::with_book(OrderBook::with_capacity(arena_capacity))
+    }
+
+    /// See `OrderBook::with_capacity_hint`.
+    pub fn with_capacity_hint(expected_resting: u32, growth_factor: f64) -> Self {
+        Self::with_book(OrderBook::with_capacity_hint(
+            expected_resting,
+            growth_factor,
+        ))
+    }
+
+    /// See `OrderBook::with_fixed_capacity`.
+    pub fn with_fixed_capacity(arena_capacity: u32) -> Self {
+        Self::with_book(OrderBook::with_fixed_capacity(arena_capacity))
+    }
+
+    fn with_book(book: OrderBook) -> Self {
         Self {
-            book: OrderBook::with_capacity(arena_capacity),
+            book,
             fills_buf: Vec::with_capacity(FILLS_INITIAL_CAPACITY),
+            trader_stats: HashMap::new(),
+            risk: RiskConfig::default(),
+            fill_pricing: FillPricing::default(),
+            amend_policy: AmendPolicy::default(),
+            cross_policy: CrossPolicy::default(),
+            last_trade_price: None,
+            changes: None,
+            expiries: BTreeSet::new(),
+            metrics: EngineMetrics::default(),
+            halt: None,
+            auction: false,
+            seen_ids: HashSet::new(),
+            max_order_id: None,
+            next_seq: 1,
         }
     }
 
@@ -63,129 +535,1170 @@ impl MatchingEngine {
         &self.book
     }
 
-    pub fn add_order(&mut self, mut order: Order) -> Result<AddOrderResult, MatchingError> {
+    /// Resets to a fresh engine's state, keeping the book's allocations, the
+    /// risk config, fill pricing and whether change tracking is on.
+    pub fn clear(&mut self) {
+        self.book.clear();
+        self.fills_buf.clear();
+        self.trader_stats.clear();
+        self.last_trade_price = None;
+        self.clear_changes();
+        self.expiries.clear();
+        self.metrics = EngineMetrics::default();
+        self.halt = None;
+        self.auction = false;
+        self.seen_ids.clear();
+        self.max_order_id = None;
+        self.next_seq = 1;
+    }
+
+    /// Gets the engine ready for its first order rather than paying for it
+    /// on that order: pre-faults the order index's pages if the book is
+    /// empty, then runs rest, fill, reduce and cancel on a scratch engine
+    /// to warm the instruction cache and branch predictors. The arena needs
+    /// nothing, since creating it writes every slot. Leaves this engine's
+    /// state unchanged.
+    pub fn warmup(&mut self) {
+        self.book.prefault_index();
+
+        let mut scratch = MatchingEngine::with_capacity(WARMUP_ROUNDS as u32 * 2);
+        for round in 0..WARMUP_ROUNDS {
+            let (ask, bid) = (round * 2 + 1, round * 2 + 2);
+            let order = |id, side, qty| Order::try_new(id, id, side, 100, qty, id).unwrap();
+            let _ = scratch.add_order(order(ask, Side::Ask, 10));
+            let _ = scratch.add_order(order(bid, Side::Bid, 4));
+            let _ = scratch.reduce_order(ask, 2);
+            let _ = scratch.cancel_order(ask);
+        }
+    }
+
+    pub fn risk_config(&self) -> &RiskConfig {
+        &self.risk
+    }
+
+    pub fn set_risk_config(&mut self, risk: RiskConfig) {
+        self.risk = risk;
+    }
+
+    pub fn fill_pricing(&self) -> FillPricing {
+        self.fill_pricing
+    }
+
+    /// Applies to fills from the next order on. Snapshots record it, but
+    /// replay of WAL records past the latest snapshot uses whatever is set.
+    pub fn set_fill_pricing(&mut self, pricing: FillPricing) {
+        self.fill_pricing = pricing;
+    }
+
+    pub fn amend_policy(&self) -> AmendPolicy {
+        self.amend_policy
+    }
+
+    /// Recorded in snapshots the same way as `set_fill_pricing`.
+    pub fn set_amend_policy(&mut self, policy: AmendPolicy) {
+        self.amend_policy = policy;
+    }
+
+    pub fn cross_policy(&self) -> CrossPolicy {
+        self.cross_policy
+    }
+
+    /// Recorded in snapshots the same way as `set_fill_pricing`. Switching
+    /// to `AtEqualPrice` with a locked book leaves it locked until an order
+    /// trades one side away.
+    pub fn set_cross_policy(&mut self, policy: CrossPolicy) {
+        self.cross_policy = policy;
+    }
+
+    pub fn metrics(&self) -> EngineMetrics {
+        self.metrics
+    }
+
+    pub fn last_trade_price(&self) -> Option<i64> {
+        self.last_trade_price
+    }
+
+    /// The active halt's policy, or `None` while trading.
+    pub fn halt_policy(&self) -> Option<HaltPolicy> {
+        self.halt
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halt.is_some()
+    }
+
+    /// Stops matching. Halting again replaces the policy.
+    pub fn halt(&mut self, policy: HaltPolicy) {
+        self.halt = Some(policy);
+    }
+
+    pub fn resume(&mut self) {
+        self.halt = None;
+    }
+
+    pub fn in_auction(&self) -> bool {
+        self.auction
+    }
+
+    /// Checks that the book is not crossed; a locked one passes. Orders rest
+    /// without matching during an auction call, so a crossed book is allowed
+    /// then. Debug builds run this after every order, replace and uncross.
+    pub fn verify(&self) -> Result<(), MatchingError> {
+        if self.auction {
+            return Ok(());
+        }
+        match self.book.verify() {
+            // Equal best prices rest under `StrictlyThrough`, and stay
+            // resting after a switch back to `AtEqualPrice`.
+            Err(BookError::Crossed { bid, ask }) if bid == ask => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    /// Enters the auction call phase. Until `uncross`, accepted orders rest
+    /// without matching, so the book may be crossed. Post-only and
+    /// reduce-only flags are kept on the order but not applied.
+    pub fn start_auction(&mut self) {
+        self.auction = true;
+    }
+
+    /// Equilibrium price and volume `uncross` would trade at now, or `None` if
+    /// the book doesn't cross. Among the prices resting on either side, picks
+    /// the one executing the most volume, then the smallest imbalance between
+    /// demand and supply, then the closest to the last trade (or the
+    /// configured reference), then the lowest.
+    pub fn indicative_uncross(&self) -> Option<(i64, u64)> {
+        let bids: Vec<_> = self.book.iter_levels(Side::Bid).collect();
+        let asks: Vec<_> = self.book.iter_levels(Side::Ask).collect();
+        let (high, low) = (bids.first()?.price, asks.first()?.price);
+        if high < low {
+            return None;
+        }
+
+        let reference = self.last_trade_price.or(self.risk.reference_price);
+        let mut candidates: Vec<i64> = bids
+            .iter()
+            .chain(&asks)
+            .map(|l| l.price)
+            .filter(|p| (low..=high).contains(p))
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        candidates
+            .into_iter()
+            .map(|price| {
+                let demand: u64 = bids
+                    .iter()
+                    .take_while(|l| l.price >= price)
+                    .map(|l| l.quantity)
+                    .sum();
+                let supply: u64 = asks
+                    .iter()
+                    .take_while(|l| l.price <= price)
+                    .map(|l| l.quantity)
+                    .sum();
+                let distance = reference.map_or(0, |r| (price as i128 - r as i128).abs());
+                let key = (
+                    demand.min(supply),
+                    std::cmp::Reverse(demand.abs_diff(supply)),
+                    std::cmp::Reverse(distance),
+                    std::cmp::Reverse(price),
+                );
+                (key, price)
+            })
+            .max()
+            .map(|((volume, ..), price)| (price, volume))
+    }
+
+    /// Ends the auction and executes every crossing order at the single
+    /// `indicative_uncross` price. Orders fill in price-time priority on each
+    /// side; in each fill the later of the two orders is reported as the
+    /// taker. Self-trade prevention does not apply. Fails with `Halted`, and
+    /// stays in the auction, while trading is halted.
+    pub fn uncross(&mut self) -> Result<UncrossResult, MatchingError> {
+        if self.is_halted() {
+            return Err(MatchingError::Halted);
+        }
+        self.auction = false;
+        let Some((price, volume)) = self.indicative_uncross() else {
+            return Ok(UncrossResult::default());
+        };
+
+        let mut fills = Vec::new();
+        let mut remaining = volume;
+        while remaining > 0 {
+            let (Some(bid_price), Some(ask_price)) = (self.book.best_bid(), self.book.best_ask())
+            else {
+                break;
+            };
+            let front_quantity =
+                |side, level| self.book.peek_front(side, level).map(|n| n.quantity);
+            let (Some(bid_qty), Some(ask_qty)) = (
+                front_quantity(Side::Bid, bid_price),
+                front_quantity(Side::Ask, ask_price),
+            ) else {
+                break;
+            };
+
+            let quantity = remaining.min(bid_qty).min(ask_qty);
+            let (bid, bid_seq, bid_filled) =
+                self.settle_auction_fill(Side::Bid, bid_price, quantity)?;
+            let (ask, ask_seq, ask_filled) =
+                self.settle_auction_fill(Side::Ask, ask_price, quantity)?;
+            let ((taker, taker_seq), (maker, maker_seq), maker_fully_filled) = if ask_seq > bid_seq
+            {
+                ((ask.id, ask_seq), (bid.id, bid_seq), bid_filled)
+            } else {
+                ((bid.id, bid_seq), (ask.id, ask_seq), ask_filled)
+            };
+            fills.push(Fill {
+                taker_order_id: taker.0,
+                maker_order_id: maker.0,
+                price,
+                quantity,
+                maker_fully_filled,
+                taker_seq,
+                maker_seq,
+            });
+            remaining -= quantity;
+        }
+
+        self.last_trade_price = Some(price);
+        self.metrics.fills += fills.len() as u64;
+        self.metrics.quantity_matched += volume - remaining;
+        debug_assert_eq!(self.verify(), Ok(()));
+        Ok(UncrossResult {
+            price: Some(price),
+            fills,
+        })
+    }
+
+    /// Takes `quantity` off the front order at `level` and updates its
+    /// trader's stats. Returns the order as it was, its sequence number and
+    /// whether it filled.
+    fn settle_auction_fill(
+        &mut self,
+        side: Side,
+        level: i64,
+        quantity: u64,
+    ) -> Result<(Order, u64, bool), MatchingError> {
+        let (id, seq) = self
+            .book
+            .peek_front_seq(side, level)
+            .map(|(n, seq)| (n.id, seq))
+            .ok_or(BookError::PriceLevelNotFound(level))?;
+        let order = self
+            .book
+            .get_order(id)
+            .ok_or(BookError::OrderNotFound(id))?;
+        let remaining = self.book.reduce_front_quantity(side, level, quantity)?;
+
+        let stats = self.stats_mut(order.trader_id.0);
+        stats.exposure -= notional(order.price.0, quantity);
+        stats.position += signed_quantity(side, quantity);
+        let filled = remaining == 0;
+        if filled {
+            stats.resting_orders -= 1;
+            if let Some(expiry) = order.expiry {
+                self.expiries.remove(&(expiry.get(), id));
+            }
+        }
+        self.track_fill(id, filled);
+        Ok((order, seq, filled))
+    }
+
+    pub fn trader_stats(&self, trader_id: u64) -> Option<&TraderStats> {
+        self.trader_stats.get(&trader_id)
+    }
+
+    /// Signed notional of the trader's resting orders; zero for unknown traders.
+    pub fn trader_exposure(&self, trader_id: u64) -> i128 {
+        self.trader_stats(trader_id).map_or(0, |s| s.exposure)
+    }
+
+    /// Net filled quantity (bought minus sold); zero for unknown traders.
+    pub fn trader_position(&self, trader_id: u64) -> i128 {
+        self.trader_stats(trader_id).map_or(0, |s| s.position)
+    }
+
+    pub fn add_order(&mut self, order: Order) -> Result<AddOrderResult, MatchingError> {
+        let (order_id, seq, side, price) = (order.id.0, self.next_seq, order.side, order.price.0);
+        let mut fills = self.take_fills_buf();
+        let status = self.add_order_with(order, |fill| fills.push(fill.clone()))?;
+        Ok(AddOrderResult {
+            order_id,
+            seq: result_seq(seq, status),
+            status,
+            fills,
+            became_best: self.rests_at_top(order_id, seq, side, price),
+        })
+    }
+
+    /// Whether the order with this id and engine seq is resting at the best
+    /// price on `side`.
+    fn rests_at_top(&self, order_id: u64, seq: u64, side: Side, price: i64) -> bool {
+        let best = match side {
+            Side::Bid => self.book.best_bid(),
+            Side::Ask => self.book.best_ask(),
+        };
+        best == Some(price) && self.book.order_seq(order_id) == Some(seq)
+    }
+
+    /// `add_order` into a caller-owned buffer: `fills` is cleared, then gets
+    /// the order's fills. Reusing one buffer across calls keeps its capacity,
+    /// so large sweeps stop reallocating.
+    pub fn add_order_into(
+        &mut self,
+        order: Order,
+        fills: &mut Vec<Fill>,
+    ) -> Result<OrderStatus, MatchingError> {
+        fills.clear();
+        self.add_order_with(order, |fill| fills.push(fill.clone()))
+    }
+
+    /// Hands a result's `fills` back so the next `add_order` or
+    /// `cancel_replace` reuses its allocation. Buffers above 4,096 fills of
+    /// capacity are dropped instead.
+    pub fn recycle_fills(&mut self, fills: Vec<Fill>) {
+        if fills.capacity() <= FILLS_RETAINED_CAPACITY
+            && fills.capacity() > self.fills_buf.capacity()
+        {
+            self.fills_buf = fills;
+        }
+    }
+
+    /// `add_order` without collecting fills: `on_fill` sees each one as it is
+    /// matched, before the rest of the order is processed, and no `Vec` is
+    /// allocated. An error can still follow fills already reported.
+    pub fn add_order_with<F: FnMut(&Fill)>(
+        &mut self,
+        order: Order,
+        mut on_fill: F,
+    ) -> Result<OrderStatus, MatchingError> {
+        let (mut fills, mut quantity) = (0, 0);
+        let id = order.id;
+        let result = self
+            .validate_order(&order)
+            .and_then(|()| self.check_order_id(order.id.0, None))
+            .and_then(|()| self.check_halt(&order))
+            .and_then(|()| self.check_resting_limit(&order, false))
+            .and_then(|()| {
+                self.match_order_with(order, &mut |fill: &Fill| {
+                    fills += 1;
+                    quantity += fill.quantity;
+                    on_fill(fill);
+                })
+            });
+        if result.is_ok() {
+            self.note_order_id(id.0);
+        }
+        self.metrics
+            .record(result.as_ref().copied(), fills, quantity);
+        debug_assert_eq!(self.verify(), Ok(()));
+        result
+    }
+
+    /// What `add_order` would do with `order` right now, computed against the
+    /// book without changing it. Pre-trade checks are not applied, so an order
+    /// `add_order` would reject is still evaluated as if accepted.
+    pub fn simulate_order(&self, order: &Order) -> AddOrderResult {
+        if order.post_only && self.would_cross(order) {
+            return AddOrderResult {
+                order_id: order.id.0,
+                seq: 0,
+                status: OrderStatus::RejectedPostOnly,
+                fills: Vec::new(),
+                became_best: false,
+            };
+        }
+
+        let mut remaining = order.quantity.0;
+        let mut clamped = false;
+        if order.reduce_only {
+            let reducible = self.reducible_quantity(order.trader_id.0, order.side);
+            if remaining > reducible {
+                remaining = reducible;
+                clamped = true;
+            }
+        }
+
+        let mut fills = Vec::new();
+        let mut self_trade = false;
+        let (opposite, taker_side_best) = match order.side {
+            Side::Bid => (Side::Ask, self.book.best_bid()),
+            Side::Ask => (Side::Bid, self.book.best_ask()),
+        };
+        for (price, maker) in self.book.iter_queue(opposite) {
+            if remaining == 0 || !self.cross_policy.crosses(order.side, order.price.0, price) {
+                break;
+            }
+            if maker.trader_id == order.trader_id.0 {
+                self_trade = true;
+                break;
+            }
+
+            let fill_qty = remaining.min(maker.quantity);
+            fills.push(Fill {
+                taker_order_id: order.id.0,
+                maker_order_id: maker.id,
+                price: self.fill_pricing.price(price, taker_side_best),
+                quantity: fill_qty,
+                maker_fully_filled: fill_qty == maker.quantity,
+                taker_seq: self.next_seq,
+                maker_seq: self.book.order_seq(maker.id).unwrap_or_default(),
+            });
+            remaining -= fill_qty;
+        }
+
+        let best = match order.side {
+            Side::Bid => self.book.best_bid().filter(|&best| best > order.price.0),
+            Side::Ask => self.book.best_ask().filter(|&best| best < order.price.0),
+        };
+        AddOrderResult {
+            order_id: order.id.0,
+            seq: self.next_seq,
+            status: final_status(self_trade, clamped, remaining, !fills.is_empty()),
+            fills,
+            became_best: !self_trade && remaining > 0 && best.is_none(),
+        }
+    }
+
+    /// Cancels `old_id` and submits `new_order` as one step. Every rejection the
+    /// replacement could hit is checked before the cancel, so on error the
+    /// original order is left resting untouched.
+    pub fn cancel_replace(
+        &mut self,
+        old_id: u64,
+        new_order: Order,
+    ) -> Result<AddOrderResult, MatchingError> {
+        let id = new_order.id;
+        let result = self.replace_order(old_id, new_order);
+        if result.is_ok() {
+            self.note_order_id(id.0);
+        }
+        self.metrics.record_submission(&result);
+        debug_assert_eq!(self.verify(), Ok(()));
+        result
+    }
+
+    fn replace_order(
+        &mut self,
+        old_id: u64,
+        new_order: Order,
+    ) -> Result<AddOrderResult, MatchingError> {
+        if !self.book.contains_order(old_id) {
+            return Err(BookError::OrderNotFound(old_id).into());
+        }
+        if new_order.id != old_id && self.book.contains_order(new_order.id.0) {
+            return Err(BookError::DuplicateOrderId(new_order.id.0).into());
+        }
+        self.validate_order(&new_order)?;
+        self.check_order_id(new_order.id.0, Some(old_id))?;
+        self.check_halt(&new_order)?;
+        let frees_slot = self
+            .book
+            .get_order(old_id)
+            .is_some_and(|old| old.trader_id == new_order.trader_id);
+        self.check_resting_limit(&new_order, frees_slot)?;
+        if new_order.post_only && !self.auction && self.would_cross(&new_order) {
+            return Ok(AddOrderResult {
+                order_id: new_order.id.0,
+                seq: 0,
+                status: OrderStatus::RejectedPostOnly,
+                fills: Vec::new(),
+                became_best: false,
+            });
+        }
+
+        if let Some(old) = self.book.get_order(old_id)
+            && is_amendment(&old, &new_order)
+            && self
+                .amend_policy
+                .keeps_priority(old.quantity.0, new_order.quantity.0)
+        {
+            return self.amend_in_place(old, new_order.quantity.0);
+        }
+
+        // The cancel frees an arena slot, so the replacement cannot hit ArenaFull.
+        self.cancel_order(old_id)?;
+        self.match_order(new_order)
+    }
+
+    /// Sets a resting order's quantity without touching its queue position.
+    /// Its price is already resting, so nothing can match.
+    fn amend_in_place(
+        &mut self,
+        old: Order,
+        quantity: u64,
+    ) -> Result<AddOrderResult, MatchingError> {
+        let order_id = old.id.0;
+        self.book.set_order_quantity(order_id, quantity)?;
+        self.stats_mut(old.trader_id.0).exposure +=
+            notional(old.price.0, quantity) - notional(old.price.0, old.quantity.0);
+        if let Some(changes) = &mut self.changes {
+            changes.modified.insert(order_id);
+        }
+        Ok(AddOrderResult {
+            order_id,
+            seq: self.book.order_seq(order_id).unwrap_or(0),
+            status: OrderStatus::Resting,
+            fills: Vec::new(),
+            became_best: false,
+        })
+    }
+
+    fn validate_order(&self, order: &Order) -> Result<(), MatchingError> {
         if order.quantity == 0 {
             return Err(MatchingError::ZeroQuantity);
         }
+        if let Some(expected) = self.risk.symbol_id
+            && order.symbol_id != expected
+        {
+            return Err(MatchingError::WrongSymbol {
+                symbol_id: order.symbol_id,
+                expected,
+            });
+        }
+        if let Some(tick_size) = self.risk.tick_size
+            && tick_size > 1
+            && (order.price.0 as i128).rem_euclid(tick_size as i128) != 0
+        {
+            return Err(MatchingError::InvalidTick {
+                price: order.price.0,
+                tick_size,
+            });
+        }
 
-        if self.fills_buf.capacity() == 0 {
-            self.fills_buf.reserve(FILLS_INITIAL_CAPACITY);
+        self.check_order_limits(order)?;
+        self.check_price_band(order)?;
+        self.check_totals(order)
+    }
+
+    /// Rejects an order that, if it all rested, would overflow its side's
+    /// quantity total or its trader's exposure. Checked before matching so a
+    /// rejection never follows fills.
+    fn check_totals(&self, order: &Order) -> Result<(), MatchingError> {
+        let side_total = match order.side {
+            Side::Bid => self.book.total_bid_quantity(),
+            Side::Ask => self.book.total_ask_quantity(),
+        };
+        let exposure = self
+            .trader_exposure(order.trader_id.0)
+            .checked_add(notional(order.price.0, order.quantity.0));
+        if side_total.checked_add(order.quantity.0).is_none() || exposure.is_none() {
+            return Err(BookError::QuantityOverflow.into());
+        }
+        Ok(())
+    }
+
+    fn match_order(&mut self, order: Order) -> Result<AddOrderResult, MatchingError> {
+        let (order_id, seq, side, price) = (order.id.0, self.next_seq, order.side, order.price.0);
+        let mut fills = self.take_fills_buf();
+        let status = self.match_order_with(order, &mut |fill: &Fill| fills.push(fill.clone()))?;
+        Ok(AddOrderResult {
+            order_id,
+            seq: result_seq(seq, status),
+            status,
+            fills,
+            became_best: self.rests_at_top(order_id, seq, side, price),
+        })
+    }
+
+    /// `fills_buf`'s allocation, handed out with the next result. Empty unless
+    /// a buffer was recycled since.
+    fn take_fills_buf(&mut self) -> Vec<Fill> {
+        let mut fills = std::mem::take(&mut self.fills_buf);
+        fills.clear();
+        if fills.capacity() == 0 {
+            fills.reserve(FILLS_INITIAL_CAPACITY);
+        }
+        fills
+    }
+
+    fn match_order_with(
+        &mut self,
+        mut order: Order,
+        on_fill: &mut impl FnMut(&Fill),
+    ) -> Result<OrderStatus, MatchingError> {
+        if order.post_only && !self.auction && self.would_cross(&order) {
+            return Ok(OrderStatus::RejectedPostOnly);
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if self.auction {
+            self.rest_order(order, seq)?;
+            return Ok(OrderStatus::Resting);
         }
-        self.fills_buf.clear();
 
-        let order_id = order.id;
+        let mut filled = false;
         let mut self_trade = false;
+        let mut clamped = false;
+        if order.reduce_only {
+            let reducible = self.reducible_quantity(order.trader_id.0, order.side);
+            if order.quantity > reducible {
+                order.quantity = reducible.into();
+                clamped = true;
+            }
+        }
 
         match order.side {
             Side::Bid => {
                 while order.quantity > 0 {
                     let best_ask = match self.book.best_ask() {
-                        Some(p) if p <= order.price => p,
+                        Some(p) if self.cross_policy.crosses(Side::Bid, order.price.0, p) => p,
                         _ => break,
                     };
 
-                    let maker = match self.book.peek_front(Side::Ask, best_ask) {
+                    let (maker, maker_seq) = match self.book.peek_front_seq(Side::Ask, best_ask) {
                         Some(m) => m,
                         None => break,
                     };
 
-                    if maker.trader_id == order.trader_id {
+                    if maker.trader_id == order.trader_id.0 {
                         self_trade = true;
                         break;
                     }
 
-                    let fill_qty = order.quantity.min(maker.quantity);
+                    let fill_qty = order.quantity.0.min(maker.quantity);
                     let maker_id = maker.id;
-                    let fill_price = maker.price;
+                    let maker_trader_id = maker.trader_id;
+                    let fill_price = self.fill_pricing.price(best_ask, self.book.best_bid());
+                    let maker_expiry = if fill_qty == maker.quantity {
+                        self.book.front_expiry(Side::Ask, best_ask)
+                    } else {
+                        None
+                    };
 
                     let maker_remaining =
                         self.book
                             .reduce_front_quantity(Side::Ask, best_ask, fill_qty)?;
+                    self.record_fill(&order, maker_trader_id, best_ask, fill_price, fill_qty);
+                    if maker_remaining == 0 {
+                        self.stats_mut(maker_trader_id).resting_orders -= 1;
+                    }
+                    self.track_fill(maker_id, maker_remaining == 0);
+                    if maker_remaining == 0
+                        && let Some(expiry) = maker_expiry
+                    {
+                        self.expiries.remove(&(expiry.get(), maker_id));
+                    }
 
-                    self.fills_buf.push(Fill {
-                        taker_order_id: order.id,
+                    on_fill(&Fill {
+                        taker_order_id: order.id.0,
                         maker_order_id: maker_id,
                         price: fill_price,
                         quantity: fill_qty,
                         maker_fully_filled: maker_remaining == 0,
+                        taker_seq: seq,
+                        maker_seq,
                     });
+                    filled = true;
 
-                    order.quantity -= fill_qty;
+                    order.quantity.0 -= fill_qty;
                 }
             }
             Side::Ask => {
                 while order.quantity > 0 {
                     let best_bid = match self.book.best_bid() {
-                        Some(p) if p >= order.price => p,
+                        Some(p) if self.cross_policy.crosses(Side::Ask, order.price.0, p) => p,
                         _ => break,
                     };
 
-                    let maker = match self.book.peek_front(Side::Bid, best_bid) {
+                    let (maker, maker_seq) = match self.book.peek_front_seq(Side::Bid, best_bid) {
                         Some(m) => m,
                         None => break,
                     };
 
-                    if maker.trader_id == order.trader_id {
+                    if maker.trader_id == order.trader_id.0 {
                         self_trade = true;
                         break;
                     }
 
-                    let fill_qty = order.quantity.min(maker.quantity);
+                    let fill_qty = order.quantity.0.min(maker.quantity);
                     let maker_id = maker.id;
-                    let fill_price = maker.price;
+                    let maker_trader_id = maker.trader_id;
+                    let fill_price = self.fill_pricing.price(best_bid, self.book.best_ask());
+                    let maker_expiry = if fill_qty == maker.quantity {
+                        self.book.front_expiry(Side::Bid, best_bid)
+                    } else {
+                        None
+                    };
 
                     let maker_remaining =
                         self.book
                             .reduce_front_quantity(Side::Bid, best_bid, fill_qty)?;
+                    self.record_fill(&order, maker_trader_id, best_bid, fill_price, fill_qty);
+                    if maker_remaining == 0 {
+                        self.stats_mut(maker_trader_id).resting_orders -= 1;
+                    }
+                    self.track_fill(maker_id, maker_remaining == 0);
+                    if maker_remaining == 0
+                        && let Some(expiry) = maker_expiry
+                    {
+                        self.expiries.remove(&(expiry.get(), maker_id));
+                    }
 
-                    self.fills_buf.push(Fill {
-                        taker_order_id: order.id,
+                    on_fill(&Fill {
+                        taker_order_id: order.id.0,
                         maker_order_id: maker_id,
                         price: fill_price,
                         quantity: fill_qty,
                         maker_fully_filled: maker_remaining == 0,
+                        taker_seq: seq,
+                        maker_seq,
                     });
+                    filled = true;
 
-                    order.quantity -= fill_qty;
+                    order.quantity.0 -= fill_qty;
                 }
             }
         }
 
-        let status = if self_trade {
-            OrderStatus::CancelledSelfTrade
-        } else if order.quantity == 0 {
-            OrderStatus::FullyFilled
-        } else {
-            self.book.insert_order(order)?;
-            if self.fills_buf.is_empty() {
-                OrderStatus::Resting
-            } else {
-                OrderStatus::PartiallyFilled
-            }
-        };
+        let status = final_status(self_trade, clamped, order.quantity.0, filled);
+        if !self_trade && order.quantity > 0 {
+            self.rest_order(order, seq)?;
+        }
+        Ok(status)
+    }
 
-        Ok(AddOrderResult {
-            order_id,
-            status,
-            fills: std::mem::take(&mut self.fills_buf),
-        })
+    pub fn cancel_order(&mut self, order_id: u64) -> Result<Order, MatchingError> {
+        let order = self.book.cancel_order(order_id)?;
+        let stats = self.stats_mut(order.trader_id.0);
+        stats.exposure -= notional(order.price.0, order.quantity.0);
+        stats.resting_orders -= 1;
+        if let Some(expiry) = order.expiry {
+            self.expiries.remove(&(expiry.get(), order_id));
+        }
+        if let Some(changes) = &mut self.changes {
+            changes.removed.insert(order_id);
+        }
+        self.metrics.cancels += 1;
+        Ok(order)
     }
 
-    pub fn cancel_order(&mut self, order_id: u64) -> Result<Order, MatchingError> {
-        Ok(self.book.cancel_order(order_id)?)
+    /// Takes `reduce_by` off a resting order's quantity in place, so unlike a
+    /// cancel-replace it keeps its place in the queue. Reducing it to zero
+    /// cancels it. Returns the order with its new quantity.
+    pub fn reduce_order(&mut self, order_id: u64, reduce_by: u64) -> Result<Order, MatchingError> {
+        if reduce_by == 0 {
+            return Err(MatchingError::ZeroQuantity);
+        }
+        let remaining = self
+            .book
+            .get_order(order_id)
+            .ok_or(BookError::OrderNotFound(order_id))?
+            .quantity
+            .0;
+        if reduce_by > remaining {
+            return Err(MatchingError::ReduceExceedsQuantity {
+                order_id,
+                remaining,
+            });
+        }
+        if reduce_by == remaining {
+            let mut cancelled = self.cancel_order(order_id)?;
+            cancelled.quantity = 0.into();
+            return Ok(cancelled);
+        }
+
+        let quantity = remaining - reduce_by;
+        let mut order = self.book.set_order_quantity(order_id, quantity)?;
+        self.stats_mut(order.trader_id.0).exposure -= notional(order.price.0, reduce_by);
+        if let Some(changes) = &mut self.changes {
+            changes.modified.insert(order_id);
+        }
+        order.quantity = quantity.into();
+        Ok(order)
+    }
+
+    /// Cancels every resting order of `trader_id` and returns them, bids best
+    /// price first and then asks, each level in queue order. The scan stops
+    /// once the trader's resting count is reached.
+    pub fn cancel_all_for_trader(&mut self, trader_id: u64) -> Vec<Order> {
+        let resting = self
+            .trader_stats(trader_id)
+            .map_or(0, |s| s.resting_orders as usize);
+        if resting == 0 {
+            return Vec::new();
+        }
+
+        let ids: Vec<u64> = [Side::Bid, Side::Ask]
+            .into_iter()
+            .flat_map(|side| self.book.iter_queue(side))
+            .filter(|(_, node)| node.trader_id == trader_id)
+            .map(|(_, node)| node.id)
+            .take(resting)
+            .collect();
+        ids.into_iter()
+            .filter_map(|id| self.cancel_order(id).ok())
+            .collect()
+    }
+
+    /// Decodes one protocol message from `buf` and applies it, for embedding
+    /// the engine without the gateway: what the matching thread does with a
+    /// command, less the WAL, trade log and feed. New orders and
+    /// cancel-replaces return their result; every other command returns
+    /// `None` once applied, or its error. Orders keep the timestamp on the
+    /// wire, which clients send as 0 for the gateway to stamp. Snapshot
+    /// requests need the gateway and are ignored, and nothing expires unless
+    /// `expire_orders` is called.
+    pub fn apply_bytes(&mut self, buf: &[u8]) -> Result<Option<AddOrderResult>, ApplyError> {
+        match protocol::decode_message(buf)? {
+            EngineCommand::NewOrder(order) => return Ok(Some(self.add_order(order)?)),
+            EngineCommand::CancelReplace { old_id, new_order } => {
+                return Ok(Some(self.cancel_replace(old_id, new_order)?));
+            }
+            EngineCommand::CancelOrder { order_id } => {
+                self.cancel_order(order_id)?;
+            }
+            EngineCommand::CancelAll { trader_id } => {
+                self.cancel_all_for_trader(trader_id);
+            }
+            EngineCommand::ReduceOrder {
+                order_id,
+                reduce_by,
+            } => {
+                self.reduce_order(order_id, reduce_by)?;
+            }
+            EngineCommand::Halt { policy } => self.halt(policy),
+            EngineCommand::Resume => self.resume(),
+            EngineCommand::RequestSnapshot | EngineCommand::AdminSnapshot => {}
+        }
+        Ok(None)
+    }
+
+    /// Cancels every resting order whose expiry is at or before `now_nanos`,
+    /// earliest expiry first, and returns them.
+    pub fn expire_orders(&mut self, now_nanos: u64) -> Vec<Order> {
+        let mut expired = Vec::new();
+        while let Some(&(expiry, order_id)) = self.expiries.first() {
+            if expiry > now_nanos {
+                break;
+            }
+            self.expiries.pop_first();
+            if let Ok(order) = self.cancel_order(order_id) {
+                expired.push(order);
+            }
+        }
+        expired
+    }
+
+    /// Earliest pending expiry, if any order has one.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.expiries.first().map(|&(expiry, _)| expiry)
+    }
+
+    /// True if the order would trade with the opposite side's best price.
+    fn would_cross(&self, order: &Order) -> bool {
+        let best = match order.side {
+            Side::Bid => self.book.best_ask(),
+            Side::Ask => self.book.best_bid(),
+        };
+        best.is_some_and(|p| self.cross_policy.crosses(order.side, order.price.0, p))
+    }
+
+    /// Largest quantity a `side` order can trade without the trader's
+    /// position growing in that direction.
+    fn reducible_quantity(&self, trader_id: u64, side: Side) -> u64 {
+        let position = self.trader_position(trader_id);
+        let opposite = match side {
+            Side::Bid => -position,
+            Side::Ask => position,
+        };
+        opposite.clamp(0, u64::MAX as i128) as u64
+    }
+
+    /// Books a non-crossing order and updates exposure, expiry and change tracking.
+    fn rest_order(&mut self, order: Order, seq: u64) -> Result<(), BookError> {
+        let (id, trader_id, price, quantity, expiry) = (
+            order.id.0,
+            order.trader_id.0,
+            order.price.0,
+            order.quantity.0,
+            order.expiry,
+        );
+        self.book.insert_order(order, seq)?;
+        let stats = self.stats_mut(trader_id);
+        stats.exposure += notional(price, quantity);
+        stats.resting_orders += 1;
+        if let Some(expiry) = expiry {
+            self.expiries.insert((expiry.get(), id));
+        }
+        if let Some(changes) = &mut self.changes {
+            changes.inserted.push(id);
+        }
+        Ok(())
+    }
+
+    /// Starts or stops recording the changeset used for delta snapshots.
+    /// Enabling starts from an empty changeset.
+    pub(crate) fn set_change_tracking(&mut self, enabled: bool) {
+        self.changes = enabled.then(ChangeSet::default);
+    }
+
+    /// Drops the changes recorded so far, e.g. after a full snapshot.
+    pub(crate) fn clear_changes(&mut self) {
+        if let Some(changes) = &mut self.changes {
+            *changes = ChangeSet::default();
+        }
+    }
+
+    /// Resolves the recorded changes against the current book and starts a
+    /// new changeset. Empty when tracking is disabled.
+    pub(crate) fn take_delta(&mut self) -> BookDelta {
+        let Some(changes) = self.changes.as_mut().map(std::mem::take) else {
+            return BookDelta::default();
+        };
+
+        // A reused id is only resting from its last insertion.
+        let mut seen = HashSet::with_capacity(changes.inserted.len());
+        let mut added: Vec<(Order, u64)> = changes
+            .inserted
+            .iter()
+            .rev()
+            .filter(|&&id| seen.insert(id))
+            .filter_map(|&id| Some((self.book.get_order(id)?, self.book.order_seq(id)?)))
+            .collect();
+        added.reverse();
+
+        let mut modified: Vec<(u64, u64)> = changes
+            .modified
+            .iter()
+            .filter(|id| !seen.contains(id))
+            .filter_map(|&id| self.book.get_order(id).map(|o| (id, o.quantity.0)))
+            .collect();
+        modified.sort_unstable();
+
+        let mut removed: Vec<u64> = changes.removed.into_iter().collect();
+        removed.sort_unstable();
+
+        BookDelta {
+            removed,
+            modified,
+            added,
+        }
+    }
+
+    /// Replays a delta captured by `take_delta` on top of the state it was
+    /// captured against. Exposure follows the book.
+    pub(crate) fn apply_delta(&mut self, delta: &BookDelta) -> Result<(), MatchingError> {
+        for &id in &delta.removed {
+            // Ids added and removed within the same delta were never here.
+            if self.book.contains_order(id) {
+                self.cancel_order(id)?;
+            }
+        }
+        for &(id, quantity) in &delta.modified {
+            let before = self.book.set_order_quantity(id, quantity)?;
+            self.stats_mut(before.trader_id.0).exposure +=
+                notional(before.price.0, quantity) - notional(before.price.0, before.quantity.0);
+        }
+        for (order, seq) in &delta.added {
+            self.rest_order(order.clone(), *seq)?;
+        }
+        Ok(())
     }
 
-    /// Inserts directly into the book without matching (non-crossed snapshot state).
-    pub(crate) fn restore_from_orders(
-        orders: &[Order],
+    /// Rebuilds the book level by level, appending each queue head first so
+    /// every order keeps its exact queue position. Nothing is matched.
+    /// Exposure and the expiry index are rebuilt from the resting orders.
+    pub(crate) fn restore_exact(
+        levels: &[LevelQueue],
         arena_capacity: u32,
     ) -> Result<Self, MatchingError> {
         let mut engine = Self::with_capacity(arena_capacity);
-        for order in orders {
-            engine.book.insert_order(order.clone())?;
+        for level in levels {
+            for (order, seq) in &level.orders {
+                if order.side != level.side || order.price != level.price {
+                    return Err(BookError::LevelMismatch {
+                        order_id: order.id.0,
+                    }
+                    .into());
+                }
+                engine.rest_order(order.clone(), *seq)?;
+            }
         }
         Ok(engine)
     }
+
+    /// Sequence number the next accepted order gets, for snapshotting.
+    pub(crate) fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    pub(crate) fn restore_next_seq(&mut self, next_seq: u64) {
+        self.next_seq = next_seq;
+    }
+
+    /// Price-band and uncross reference carried over from a snapshot.
+    pub(crate) fn restore_last_trade_price(&mut self, price: Option<i64>) {
+        self.last_trade_price = price;
+    }
+
+    /// Re-enters or leaves the auction call phase as a snapshot recorded
+    /// it. The orders collected for the uncross are restored with the book.
+    pub(crate) fn restore_auction(&mut self, in_auction: bool) {
+        self.auction = in_auction;
+    }
+
+    /// Net positions sorted by trader id, for snapshotting.
+    pub(crate) fn trader_positions(&self) -> Vec<(u64, i128)> {
+        let mut positions: Vec<(u64, i128)> = self
+            .trader_stats
+            .iter()
+            .filter(|(_, s)| s.position != 0)
+            .map(|(&id, s)| (id, s.position))
+            .collect();
+        positions.sort_unstable_by_key(|&(id, _)| id);
+        positions
+    }
+
+    /// Replaces every trader's position; traders not listed end up flat.
+    pub(crate) fn restore_positions(&mut self, positions: &[(u64, i128)]) {
+        for stats in self.trader_stats.values_mut() {
+            stats.position = 0;
+        }
+        for &(trader_id, position) in positions {
+            self.stats_mut(trader_id).position = position;
+        }
+    }
+
+    fn track_fill(&mut self, maker_id: u64, maker_fully_filled: bool) {
+        if let Some(changes) = &mut self.changes {
+            if maker_fully_filled {
+                changes.removed.insert(maker_id);
+            } else {
+                changes.modified.insert(maker_id);
+            }
+        }
+    }
+
+    fn stats_mut(&mut self, trader_id: u64) -> &mut TraderStats {
+        self.trader_stats.entry(trader_id).or_default()
+    }
+
+    fn check_order_limits(&self, order: &Order) -> Result<(), MatchingError> {
+        if let Some(limit) = self.risk.max_order_quantity
+            && order.quantity > limit
+        {
+            return Err(MatchingError::QuantityLimitExceeded {
+                quantity: order.quantity.0,
+                limit,
+            });
+        }
+        if let Some(limit) = self.risk.max_notional {
+            let notional = order.price.0.unsigned_abs() as u128 * order.quantity.0 as u128;
+            if notional > limit as u128 {
+                return Err(MatchingError::NotionalLimitExceeded { notional, limit });
+            }
+        }
+        Ok(())
+    }
+
+    /// A replacement keeping the id of the order it replaces always passes.
+    fn check_order_id(&self, id: u64, replaced: Option<u64>) -> Result<(), MatchingError> {
+        let reused = replaced != Some(id)
+            && match self.risk.order_ids {
+                OrderIdPolicy::Resting => false,
+                OrderIdPolicy::Unique => self.seen_ids.contains(&id),
+                OrderIdPolicy::Increasing => self.max_order_id.is_some_and(|max| id <= max),
+            };
+        if reused {
+            return Err(MatchingError::DuplicateOrderId(id));
+        }
+        Ok(())
+    }
+
+    fn note_order_id(&mut self, id: u64) {
+        self.max_order_id = self.max_order_id.max(Some(id));
+        if self.risk.order_ids == OrderIdPolicy::Unique {
+            self.seen_ids.insert(id);
+        }
+    }
+
+    fn check_halt(&self, order: &Order) -> Result<(), MatchingError> {
+        match self.halt {
+            None => Ok(()),
+            Some(HaltPolicy::RejectMarketable) if !self.would_cross(order) => Ok(()),
+            Some(_) => Err(MatchingError::Halted),
+        }
+    }
+
+    /// `frees_slot` is set for a replace whose cancelled order was the same
+    /// trader's, which leaves room for one more.
+    fn check_resting_limit(&self, order: &Order, frees_slot: bool) -> Result<(), MatchingError> {
+        let Some(limit) = self.risk.max_resting_orders else {
+            return Ok(());
+        };
+        let resting = self
+            .trader_stats(order.trader_id.0)
+            .map_or(0, |s| s.resting_orders)
+            - u32::from(frees_slot);
+        if resting >= limit {
+            return Err(MatchingError::TraderOrderLimitExceeded {
+                trader_id: order.trader_id.0,
+                limit,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reference is the best opposite price, then the last trade, then the
+    /// configured fallback.
+    fn check_price_band(&self, order: &Order) -> Result<(), MatchingError> {
+        if self.risk.price_band_ticks.is_none() && self.risk.price_band_percent.is_none() {
+            return Ok(());
+        }
+
+        let opposite = match order.side {
+            Side::Bid => self.book.best_ask(),
+            Side::Ask => self.book.best_bid(),
+        };
+        let Some(reference) = opposite
+            .or(self.last_trade_price)
+            .or(self.risk.reference_price)
+        else {
+            return Ok(());
+        };
+
+        let deviation = (order.price.0 as i128 - reference as i128).unsigned_abs();
+        let violation = MatchingError::PriceBandViolation {
+            price: order.price.0,
+            reference,
+        };
+
+        if let Some(ticks) = self.risk.price_band_ticks
+            && deviation > ticks as u128
+        {
+            return Err(violation);
+        }
+        if let Some(percent) = self.risk.price_band_percent
+            && deviation * 100 > percent as u128 * reference.unsigned_abs() as u128
+        {
+            return Err(violation);
+        }
+        Ok(())
+    }
+
+    /// `maker_price` is the level the maker rested at, which its exposure
+    /// was counted at; `price` is what the fill traded at.
+    fn record_fill(
+        &mut self,
+        taker: &Order,
+        maker_trader_id: u64,
+        maker_price: i64,
+        price: i64,
+        quantity: u64,
+    ) {
+        self.last_trade_price = Some(price);
+
+        let maker = self.stats_mut(maker_trader_id);
+        maker.exposure -= notional(maker_price, quantity);
+        maker.position -= signed_quantity(taker.side, quantity);
+
+        self.stats_mut(taker.trader_id.0).position += signed_quantity(taker.side, quantity);
+    }
 }
 
 impl Default for MatchingEngine {
@@ -206,291 +1719,1650 @@ mod tests {
     }
 
     fn bid(id: u64, price: i64, qty: u64, ts: u64) -> Order {
-        Order::new(id, id, Side::Bid, price, qty, ts).unwrap()
+        Order::try_new(id, id, Side::Bid, price, qty, ts).unwrap()
     }
 
     fn ask(id: u64, price: i64, qty: u64, ts: u64) -> Order {
-        Order::new(id, id, Side::Ask, price, qty, ts).unwrap()
+        Order::try_new(id, id, Side::Ask, price, qty, ts).unwrap()
+    }
+
+    fn bid_trader(id: u64, trader_id: u64, price: i64, qty: u64, ts: u64) -> Order {
+        Order::try_new(id, trader_id, Side::Bid, price, qty, ts).unwrap()
+    }
+
+    fn ask_trader(id: u64, trader_id: u64, price: i64, qty: u64, ts: u64) -> Order {
+        Order::try_new(id, trader_id, Side::Ask, price, qty, ts).unwrap()
+    }
+
+    #[test]
+    fn no_match_resting() {
+        let mut engine = engine();
+
+        let result = engine.add_order(bid(1, 100, 10, 1)).unwrap();
+        assert_eq!(result.status, OrderStatus::Resting);
+        assert!(result.fills.is_empty());
+
+        let result = engine.add_order(ask(2, 105, 10, 2)).unwrap();
+        assert_eq!(result.status, OrderStatus::Resting);
+        assert!(result.fills.is_empty());
+
+        assert_eq!(engine.book().best_bid(), Some(100));
+        assert_eq!(engine.book().best_ask(), Some(105));
+        assert_eq!(engine.book().order_count(), 2);
+    }
+
+    #[test]
+    fn full_fill_equal_quantities() {
+        let mut engine = engine();
+        engine.add_order(ask(1, 100, 10, 1)).unwrap();
+
+        let result = engine.add_order(bid(2, 100, 10, 2)).unwrap();
+        assert_eq!(result.status, OrderStatus::FullyFilled);
+        assert_eq!(result.fills.len(), 1);
+        assert_eq!(result.fills[0].quantity, 10);
+        assert_eq!(result.fills[0].price, 100);
+        assert_eq!(result.fills[0].taker_order_id, 2);
+        assert_eq!(result.fills[0].maker_order_id, 1);
+        assert!(result.fills[0].maker_fully_filled);
+
+        assert_eq!(engine.book().order_count(), 0);
+    }
+
+    #[test]
+    fn partial_fill_taker_has_more() {
+        let mut engine = engine();
+        engine.add_order(ask(1, 100, 5, 1)).unwrap();
+
+        let result = engine.add_order(bid(2, 100, 10, 2)).unwrap();
+        assert_eq!(result.status, OrderStatus::PartiallyFilled);
+        assert_eq!(result.fills.len(), 1);
+        assert_eq!(result.fills[0].quantity, 5);
+        assert!(result.fills[0].maker_fully_filled);
+
+        assert_eq!(engine.book().best_bid(), Some(100));
+        assert_eq!(engine.book().order_count(), 1);
+    }
+
+    #[test]
+    fn partial_fill_maker_has_more() {
+        let mut engine = engine();
+        engine.add_order(bid(1, 100, 20, 1)).unwrap();
+
+        let result = engine.add_order(ask(2, 100, 5, 2)).unwrap();
+        assert_eq!(result.status, OrderStatus::FullyFilled);
+        assert_eq!(result.fills.len(), 1);
+        assert_eq!(result.fills[0].quantity, 5);
+        assert!(!result.fills[0].maker_fully_filled);
+
+        assert_eq!(engine.book().order_count(), 1);
+        assert_eq!(engine.book().best_bid(), Some(100));
+    }
+
+    #[test]
+    fn multi_level_matching() {
+        let mut engine = engine();
+        engine.add_order(ask(1, 100, 5, 1)).unwrap();
+        engine.add_order(ask(2, 101, 5, 2)).unwrap();
+        engine.add_order(ask(3, 102, 5, 3)).unwrap();
+
+        let result = engine.add_order(bid(4, 102, 12, 4)).unwrap();
+        assert_eq!(result.status, OrderStatus::FullyFilled);
+        assert_eq!(result.fills.len(), 3);
+
+        assert_eq!(result.fills[0].price, 100);
+        assert_eq!(result.fills[0].quantity, 5);
+        assert_eq!(result.fills[1].price, 101);
+        assert_eq!(result.fills[1].quantity, 5);
+        assert_eq!(result.fills[2].price, 102);
+        assert_eq!(result.fills[2].quantity, 2);
+        assert!(!result.fills[2].maker_fully_filled);
+
+        assert_eq!(engine.book().order_count(), 1);
+        assert_eq!(engine.book().best_ask(), Some(102));
+    }
+
+    #[test]
+    fn fifo_within_price_level() {
+        let mut engine = engine();
+        engine.add_order(ask(1, 100, 10, 1)).unwrap();
+        engine.add_order(ask(2, 100, 10, 2)).unwrap();
+        engine.add_order(ask(3, 100, 10, 3)).unwrap();
+
+        let result = engine.add_order(bid(4, 100, 15, 4)).unwrap();
+        assert_eq!(result.fills.len(), 2);
+        assert_eq!(result.fills[0].maker_order_id, 1);
+        assert_eq!(result.fills[0].quantity, 10);
+        assert_eq!(result.fills[1].maker_order_id, 2);
+        assert_eq!(result.fills[1].quantity, 5);
+    }
+
+    #[test]
+    fn fill_price_is_maker_price() {
+        let mut engine = engine();
+        engine.add_order(ask(1, 100, 10, 1)).unwrap();
+
+        let result = engine.add_order(bid(2, 110, 10, 2)).unwrap();
+        assert_eq!(result.fills[0].price, 100);
+    }
+
+    #[test]
+    fn ask_taker_matches_bids() {
+        let mut engine = engine();
+        engine.add_order(bid(1, 102, 10, 1)).unwrap();
+        engine.add_order(bid(2, 101, 10, 2)).unwrap();
+
+        let result = engine.add_order(ask(3, 101, 15, 3)).unwrap();
+        assert_eq!(result.fills.len(), 2);
+        assert_eq!(result.fills[0].maker_order_id, 1);
+        assert_eq!(result.fills[0].price, 102);
+        assert_eq!(result.fills[0].quantity, 10);
+        assert_eq!(result.fills[1].maker_order_id, 2);
+        assert_eq!(result.fills[1].price, 101);
+        assert_eq!(result.fills[1].quantity, 5);
+
+        assert_eq!(result.status, OrderStatus::FullyFilled);
+    }
+
+    #[test]
+    fn midpoint_pricing_uses_the_book_mid() {
+        let mut engine = engine();
+        engine.set_fill_pricing(FillPricing::Midpoint);
+        engine.add_order(ask_trader(1, 1, 100, 5, 1)).unwrap();
+        engine.add_order(ask_trader(2, 2, 103, 5, 2)).unwrap();
+        engine.add_order(bid_trader(3, 3, 90, 5, 3)).unwrap();
+
+        // Each fill is at the mid of the resting 90 bid and the best ask at
+        // the time, whatever the taker's limit of 110; 96.5 rounds to 97.
+        let result = engine.add_order(bid_trader(4, 4, 110, 10, 4)).unwrap();
+        let prices: Vec<_> = result.fills.iter().map(|f| f.price).collect();
+        assert_eq!(prices, vec![95, 97]);
+        assert_eq!(engine.last_trade_price(), Some(97));
+        // Maker exposure is released at the price it was counted at.
+        assert_eq!(engine.trader_exposure(1), 0);
+        assert_eq!(engine.trader_exposure(2), 0);
+
+        // With no asks left there's no mid: the ask fills at the bid's 90.
+        let result = engine.add_order(ask_trader(5, 5, 85, 1, 5)).unwrap();
+        assert_eq!(result.fills[0].price, 90);
+
+        // An odd spread rounds toward the maker, for asks too: 95.5 is 95.
+        engine.add_order(ask_trader(6, 6, 101, 1, 6)).unwrap();
+        let result = engine.add_order(ask_trader(7, 7, 80, 1, 7)).unwrap();
+        assert_eq!(result.fills[0].price, 95);
+
+        // A one-sided book prices at the maker, for bids too.
+        let mut one_sided = MatchingEngine::with_capacity(TEST_CAPACITY);
+        one_sided.set_fill_pricing(FillPricing::Midpoint);
+        one_sided.add_order(ask_trader(1, 1, 100, 5, 1)).unwrap();
+        let result = one_sided.add_order(bid_trader(2, 2, 110, 5, 2)).unwrap();
+        assert_eq!(result.fills[0].price, 100);
+
+        let mut plain = MatchingEngine::with_capacity(TEST_CAPACITY);
+        plain.add_order(ask_trader(1, 1, 100, 5, 1)).unwrap();
+        let result = plain.add_order(bid_trader(2, 2, 110, 5, 2)).unwrap();
+        assert_eq!(result.fills[0].price, 100);
     }
 
-    fn bid_trader(id: u64, trader_id: u64, price: i64, qty: u64, ts: u64) -> Order {
-        Order::new(id, trader_id, Side::Bid, price, qty, ts).unwrap()
+    #[test]
+    fn cancel_resting_order() {
+        let mut engine = engine();
+        engine.add_order(bid(1, 100, 10, 1)).unwrap();
+
+        let cancelled = engine.cancel_order(1).unwrap();
+        assert_eq!(cancelled.id, 1);
+        assert_eq!(engine.book().order_count(), 0);
+    }
+
+    #[test]
+    fn cancel_nonexistent_fails() {
+        let mut engine = engine();
+        let err = engine.cancel_order(999).unwrap_err();
+        assert_eq!(err, MatchingError::Book(BookError::OrderNotFound(999)));
+    }
+
+    #[test]
+    fn reduce_keeps_queue_position() {
+        let mut engine = engine();
+        engine.add_order(bid_trader(1, 1, 100, 10, 1)).unwrap();
+        engine.add_order(bid_trader(2, 2, 100, 10, 2)).unwrap();
+        engine.add_order(bid_trader(3, 3, 100, 10, 3)).unwrap();
+
+        let reduced = engine.reduce_order(2, 6).unwrap();
+        assert_eq!(reduced.quantity, 4);
+        assert_eq!(engine.book().level_depth(Side::Bid, 100), Some((24, 3)));
+        assert_eq!(engine.trader_exposure(2), 400);
+
+        // Order 2 still trades ahead of order 3, which arrived after it.
+        let result = engine.add_order(ask_trader(4, 4, 100, 12, 4)).unwrap();
+        let makers: Vec<_> = result
+            .fills
+            .iter()
+            .map(|f| (f.maker_order_id, f.quantity))
+            .collect();
+        assert_eq!(makers, vec![(1, 10), (2, 2)]);
+
+        assert_eq!(
+            engine.reduce_order(2, 3),
+            Err(MatchingError::ReduceExceedsQuantity {
+                order_id: 2,
+                remaining: 2,
+            })
+        );
+        assert_eq!(engine.reduce_order(2, 2).unwrap().quantity, 0);
+        assert!(!engine.book().contains_order(2));
+        assert_eq!(engine.trader_exposure(2), 0);
+        assert_eq!(engine.reduce_order(3, 0), Err(MatchingError::ZeroQuantity));
+    }
+
+    #[test]
+    fn zero_quantity_rejected() {
+        let mut engine = engine();
+        let order = Order {
+            id: 1.into(),
+            trader_id: 1.into(),
+            symbol_id: 0,
+            side: Side::Bid,
+            price: 100.into(),
+            quantity: 0.into(),
+            timestamp: 1,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
+        };
+        let err = engine.add_order(order).unwrap_err();
+        assert_eq!(err, MatchingError::ZeroQuantity);
+    }
+
+    #[test]
+    fn empty_book_no_match() {
+        let mut engine = engine();
+        let result = engine.add_order(bid(1, 100, 10, 1)).unwrap();
+        assert_eq!(result.status, OrderStatus::Resting);
+        assert!(result.fills.is_empty());
+    }
+
+    #[test]
+    fn bid_below_best_ask_no_match() {
+        let mut engine = engine();
+        engine.add_order(ask(1, 105, 10, 1)).unwrap();
+
+        let result = engine.add_order(bid(2, 100, 10, 2)).unwrap();
+        assert_eq!(result.status, OrderStatus::Resting);
+        assert!(result.fills.is_empty());
+        assert_eq!(engine.book().order_count(), 2);
+    }
+
+    #[test]
+    fn self_trade_prevented_cancel_newest() {
+        let mut engine = engine();
+        engine.add_order(ask_trader(1, 1, 100, 10, 1)).unwrap();
+
+        let result = engine.add_order(bid_trader(2, 1, 100, 10, 2)).unwrap();
+        assert_eq!(result.status, OrderStatus::CancelledSelfTrade);
+        assert!(result.fills.is_empty());
+
+        assert_eq!(engine.book().order_count(), 1);
+        assert_eq!(engine.book().best_ask(), Some(100));
+    }
+
+    #[test]
+    fn self_trade_different_traders_allowed() {
+        let mut engine = engine();
+        engine.add_order(ask_trader(1, 1, 100, 10, 1)).unwrap();
+
+        let result = engine.add_order(bid_trader(2, 2, 100, 10, 2)).unwrap();
+        assert_eq!(result.status, OrderStatus::FullyFilled);
+        assert_eq!(result.fills.len(), 1);
+        assert_eq!(result.fills[0].quantity, 10);
+        assert_eq!(engine.book().order_count(), 0);
+    }
+
+    #[test]
+    fn self_trade_partial_fill_then_cancel() {
+        let mut engine = engine();
+        engine.add_order(ask_trader(1, 10, 100, 5, 1)).unwrap();
+        engine.add_order(ask_trader(2, 20, 101, 10, 2)).unwrap();
+
+        // Fills against trader A, then hits own ask — cancelled
+        let result = engine.add_order(bid_trader(3, 20, 101, 15, 3)).unwrap();
+        assert_eq!(result.status, OrderStatus::CancelledSelfTrade);
+        assert_eq!(result.fills.len(), 1);
+        assert_eq!(result.fills[0].maker_order_id, 1);
+        assert_eq!(result.fills[0].quantity, 5);
+
+        assert_eq!(engine.book().order_count(), 1);
+        assert_eq!(engine.book().best_ask(), Some(101));
+    }
+
+    /// Restores `orders` as they would be snapshotted, each in its own
+    /// queue entry with seqs in the given order.
+    fn restore(orders: &[Order]) -> MatchingEngine {
+        let levels: Vec<LevelQueue> = (1..)
+            .zip(orders)
+            .map(|(seq, o)| LevelQueue {
+                side: o.side,
+                price: o.price.0,
+                orders: vec![(o.clone(), seq)],
+            })
+            .collect();
+        MatchingEngine::restore_exact(&levels, TEST_CAPACITY).unwrap()
+    }
+
+    #[test]
+    fn restore_empty() {
+        let engine = restore(&[]);
+        assert_eq!(engine.book().order_count(), 0);
+        assert_eq!(engine.book().best_bid(), None);
+        assert_eq!(engine.book().best_ask(), None);
+    }
+
+    #[test]
+    fn restore_exact_rebuilds_book() {
+        let orders = vec![
+            ask(1, 105, 10, 1),
+            ask(2, 110, 20, 2),
+            bid(3, 100, 30, 3),
+            bid(4, 98, 40, 4),
+        ];
+
+        let engine = restore(&orders);
+        assert_eq!(engine.book().order_count(), 4);
+        assert_eq!(engine.book().best_bid(), Some(100));
+        assert_eq!(engine.book().best_ask(), Some(105));
+    }
+
+    #[test]
+    fn restore_then_match() {
+        let mut engine = restore(&[ask(1, 100, 10, 1), ask(2, 101, 20, 2)]);
+
+        let result = engine.add_order(bid(3, 101, 15, 3)).unwrap();
+        assert_eq!(result.fills.len(), 2);
+        assert_eq!(result.fills[0].maker_order_id, 1);
+        assert_eq!(result.fills[0].quantity, 10);
+        assert_eq!(result.fills[1].maker_order_id, 2);
+        assert_eq!(result.fills[1].quantity, 5);
+        assert_eq!(result.status, OrderStatus::FullyFilled);
+    }
+
+    #[test]
+    fn self_trade_multiple_resting_same_trader() {
+        let mut engine = engine();
+        engine.add_order(ask_trader(1, 1, 100, 10, 1)).unwrap();
+        engine.add_order(ask_trader(2, 1, 101, 10, 2)).unwrap();
+
+        let result = engine.add_order(bid_trader(3, 1, 105, 30, 3)).unwrap();
+        assert_eq!(result.status, OrderStatus::CancelledSelfTrade);
+        assert!(result.fills.is_empty());
+
+        assert_eq!(engine.book().order_count(), 2);
+    }
+
+    #[test]
+    fn exposure_tracks_resting_orders() {
+        let mut engine = engine();
+        engine.add_order(bid_trader(1, 7, 100, 10, 1)).unwrap();
+        engine.add_order(ask_trader(2, 7, 110, 5, 2)).unwrap();
+
+        assert_eq!(engine.trader_exposure(7), 100 * 10 + 110 * 5);
+        assert_eq!(engine.trader_position(7), 0);
+        assert_eq!(engine.trader_exposure(99), 0);
+        assert!(engine.trader_stats(99).is_none());
+    }
+
+    #[test]
+    fn exposure_decreases_as_orders_fill() {
+        let mut engine = engine();
+        engine.add_order(ask_trader(1, 10, 100, 30, 1)).unwrap();
+        assert_eq!(engine.trader_exposure(10), 3_000);
+
+        engine.add_order(bid_trader(2, 20, 100, 10, 2)).unwrap();
+        assert_eq!(engine.trader_exposure(10), 2_000);
+
+        engine.add_order(bid_trader(3, 20, 100, 15, 3)).unwrap();
+        assert_eq!(engine.trader_exposure(10), 500);
+
+        engine.add_order(bid_trader(4, 20, 100, 5, 4)).unwrap();
+        assert_eq!(engine.trader_exposure(10), 0);
+
+        assert_eq!(engine.trader_position(10), -30);
+        assert_eq!(engine.trader_position(20), 30);
+        assert_eq!(engine.trader_exposure(20), 0);
+    }
+
+    #[test]
+    fn partially_filled_taker_rests_remainder_as_exposure() {
+        let mut engine = engine();
+        engine.add_order(ask_trader(1, 10, 100, 5, 1)).unwrap();
+
+        let result = engine.add_order(bid_trader(2, 20, 101, 8, 2)).unwrap();
+        assert_eq!(result.status, OrderStatus::PartiallyFilled);
+
+        assert_eq!(engine.trader_position(20), 5);
+        assert_eq!(engine.trader_exposure(20), 101 * 3);
+        assert_eq!(engine.trader_position(10), -5);
+        assert_eq!(engine.trader_exposure(10), 0);
+    }
+
+    #[test]
+    fn cancel_releases_exposure() {
+        let mut engine = engine();
+        engine.add_order(bid_trader(1, 7, 100, 10, 1)).unwrap();
+        engine.add_order(bid_trader(2, 7, 99, 10, 2)).unwrap();
+
+        engine.cancel_order(1).unwrap();
+        assert_eq!(engine.trader_exposure(7), 990);
+
+        engine.cancel_order(2).unwrap();
+        assert_eq!(engine.trader_exposure(7), 0);
+    }
+
+    #[test]
+    fn self_trade_cancel_leaves_stats_untouched() {
+        let mut engine = engine();
+        engine.add_order(ask_trader(1, 1, 100, 10, 1)).unwrap();
+        engine.add_order(bid_trader(2, 1, 100, 10, 2)).unwrap();
+
+        assert_eq!(engine.trader_exposure(1), 1_000);
+        assert_eq!(engine.trader_position(1), 0);
+    }
+
+    #[test]
+    fn restore_rebuilds_exposure_and_positions() {
+        let orders = vec![ask_trader(1, 10, 100, 10, 1), bid_trader(2, 20, 90, 5, 2)];
+        let mut engine = restore(&orders);
+        engine.restore_positions(&[(10, -4), (20, 4)]);
+
+        assert_eq!(engine.trader_exposure(10), 1_000);
+        assert_eq!(engine.trader_exposure(20), 450);
+        assert_eq!(engine.trader_positions(), vec![(10, -4), (20, 4)]);
+    }
+
+    fn banded(risk: RiskConfig) -> MatchingEngine {
+        let mut engine = engine();
+        engine.set_risk_config(risk);
+        engine
+    }
+
+    fn ticked(tick_size: u64) -> MatchingEngine {
+        banded(RiskConfig {
+            tick_size: Some(tick_size),
+            ..RiskConfig::default()
+        })
+    }
+
+    #[test]
+    fn tick_size_rejects_off_grid_price() {
+        let mut engine = ticked(5);
+        assert_eq!(
+            engine.add_order(bid(1, 102, 10, 1)),
+            Err(MatchingError::InvalidTick {
+                price: 102,
+                tick_size: 5
+            })
+        );
+        assert_eq!(engine.book().order_count(), 0);
+
+        let result = engine.add_order(bid(2, 100, 10, 2)).unwrap();
+        assert_eq!(result.status, OrderStatus::Resting);
+    }
+
+    #[test]
+    fn configured_symbol_rejects_other_instruments() {
+        let mut pinned = banded(RiskConfig {
+            symbol_id: Some(7),
+            ..RiskConfig::default()
+        });
+        assert_eq!(
+            pinned.add_order(bid(1, 100, 10, 1)),
+            Err(MatchingError::WrongSymbol {
+                symbol_id: 0,
+                expected: 7
+            })
+        );
+        let result = pinned.add_order(bid(2, 100, 10, 2).with_symbol(7)).unwrap();
+        assert_eq!(result.status, OrderStatus::Resting);
+        assert_eq!(pinned.book().get_order(2).unwrap().symbol_id, 7);
+
+        // Without a configured symbol every order is accepted.
+        let mut open = engine();
+        open.add_order(bid(1, 100, 10, 1).with_symbol(3)).unwrap();
+    }
+
+    #[test]
+    fn tick_size_handles_negative_prices() {
+        let mut engine = ticked(5);
+        engine.add_order(bid(1, -10, 10, 1)).unwrap();
+        assert!(matches!(
+            engine.add_order(bid(2, -12, 10, 2)),
+            Err(MatchingError::InvalidTick { price: -12, .. })
+        ));
+    }
+
+    #[test]
+    fn tick_size_one_or_zero_accepts_any_price() {
+        for tick_size in [0, 1] {
+            let mut engine = ticked(tick_size);
+            engine.add_order(bid(1, 101, 10, 1)).unwrap();
+        }
+    }
+
+    #[test]
+    fn tick_size_checked_on_cancel_replace() {
+        let mut engine = ticked(5);
+        engine.add_order(bid(1, 100, 10, 1)).unwrap();
+
+        let err = engine.cancel_replace(1, bid(2, 103, 10, 2)).unwrap_err();
+        assert!(matches!(err, MatchingError::InvalidTick { .. }));
+        assert!(engine.book().contains_order(1));
+    }
+
+    #[test]
+    fn price_band_off_by_default() {
+        let mut engine = engine();
+        engine.add_order(ask(1, 100, 10, 1)).unwrap();
+        let result = engine.add_order(bid(2, 1_000_000, 5, 2)).unwrap();
+        assert_eq!(result.status, OrderStatus::FullyFilled);
+    }
+
+    #[test]
+    fn price_band_ticks_rejects_outliers() {
+        let mut engine = banded(RiskConfig {
+            price_band_ticks: Some(10),
+            ..RiskConfig::default()
+        });
+        engine.add_order(ask(1, 100, 10, 1)).unwrap();
+
+        engine.add_order(bid(2, 90, 1, 2)).unwrap();
+        let err = engine.add_order(bid(3, 89, 1, 3)).unwrap_err();
+        assert_eq!(
+            err,
+            MatchingError::PriceBandViolation {
+                price: 89,
+                reference: 100
+            }
+        );
+        let err = engine.add_order(bid(4, 111, 1, 4)).unwrap_err();
+        assert!(matches!(err, MatchingError::PriceBandViolation { .. }));
+        assert_eq!(engine.book().order_count(), 2);
+    }
+
+    #[test]
+    fn price_band_percent_rejects_outliers() {
+        let mut engine = banded(RiskConfig {
+            price_band_percent: Some(5),
+            ..RiskConfig::default()
+        });
+        engine.add_order(bid(1, 1_000, 10, 1)).unwrap();
+
+        engine.add_order(ask(2, 1_050, 1, 2)).unwrap();
+        let err = engine.add_order(ask(3, 1_051, 1, 3)).unwrap_err();
+        assert_eq!(
+            err,
+            MatchingError::PriceBandViolation {
+                price: 1_051,
+                reference: 1_000
+            }
+        );
+    }
+
+    #[test]
+    fn price_band_skipped_on_empty_book() {
+        let mut engine = banded(RiskConfig {
+            price_band_ticks: Some(1),
+            ..RiskConfig::default()
+        });
+        engine.add_order(bid(1, 5_000, 1, 1)).unwrap();
+        // Same-side liquidity is not a reference
+        engine.add_order(bid(2, 1, 1, 2)).unwrap();
+    }
+
+    #[test]
+    fn price_band_uses_configured_reference_when_empty() {
+        let mut engine = banded(RiskConfig {
+            price_band_ticks: Some(5),
+            reference_price: Some(100),
+            ..RiskConfig::default()
+        });
+        engine.add_order(bid(1, 95, 1, 1)).unwrap();
+        let err = engine.add_order(bid(2, 94, 1, 2)).unwrap_err();
+        assert_eq!(
+            err,
+            MatchingError::PriceBandViolation {
+                price: 94,
+                reference: 100
+            }
+        );
+    }
+
+    #[test]
+    fn price_band_falls_back_to_last_trade() {
+        let mut engine = banded(RiskConfig {
+            price_band_ticks: Some(5),
+            reference_price: Some(198),
+            ..RiskConfig::default()
+        });
+        engine.add_order(ask(1, 200, 10, 1)).unwrap();
+        engine.add_order(bid(2, 200, 10, 2)).unwrap();
+        assert_eq!(engine.last_trade_price(), Some(200));
+        assert!(engine.book().best_ask().is_none());
+
+        engine.add_order(bid(3, 195, 1, 3)).unwrap();
+        let err = engine.add_order(bid(4, 194, 1, 4)).unwrap_err();
+        assert_eq!(
+            err,
+            MatchingError::PriceBandViolation {
+                price: 194,
+                reference: 200
+            }
+        );
+    }
+
+    #[test]
+    fn quantity_limit_boundary() {
+        let mut engine = banded(RiskConfig {
+            max_order_quantity: Some(100),
+            ..RiskConfig::default()
+        });
+        engine.add_order(bid(1, 100, 100, 1)).unwrap();
+        let err = engine.add_order(bid(2, 100, 101, 2)).unwrap_err();
+        assert_eq!(
+            err,
+            MatchingError::QuantityLimitExceeded {
+                quantity: 101,
+                limit: 100
+            }
+        );
+        assert_eq!(engine.book().order_count(), 1);
+    }
+
+    #[test]
+    fn resting_order_limit_per_trader() {
+        let mut engine = banded(RiskConfig {
+            max_resting_orders: Some(2),
+            ..RiskConfig::default()
+        });
+        engine.add_order(bid_trader(1, 7, 100, 10, 1)).unwrap();
+        engine.add_order(bid_trader(2, 7, 99, 10, 2)).unwrap();
+        let err = engine.add_order(bid_trader(3, 7, 98, 10, 3)).unwrap_err();
+        assert_eq!(
+            err,
+            MatchingError::TraderOrderLimitExceeded {
+                trader_id: 7,
+                limit: 2
+            }
+        );
+        assert_eq!(engine.trader_stats(7).unwrap().resting_orders, 2);
+
+        // Another trader is unaffected, and a replace reuses the cancelled slot
+        engine.add_order(bid_trader(4, 8, 98, 10, 4)).unwrap();
+        engine
+            .cancel_replace(2, bid_trader(5, 7, 97, 10, 5))
+            .unwrap();
+
+        // A fill and a cancel each free a slot
+        engine.add_order(ask_trader(6, 9, 100, 10, 6)).unwrap();
+        engine.cancel_order(5).unwrap();
+        assert_eq!(engine.trader_stats(7).unwrap().resting_orders, 0);
+        engine.add_order(bid_trader(7, 7, 90, 10, 7)).unwrap();
+        engine.add_order(bid_trader(8, 7, 90, 10, 8)).unwrap();
+    }
+
+    #[test]
+    fn unique_ids_reject_filled_and_cancelled_ids() {
+        let mut engine = MatchingEngine::new();
+        engine.set_risk_config(RiskConfig {
+            order_ids: OrderIdPolicy::Unique,
+            ..RiskConfig::default()
+        });
+        engine.add_order(ask(1, 100, 10, 1)).unwrap();
+        engine.add_order(bid(2, 100, 10, 2)).unwrap();
+        engine.add_order(bid(3, 99, 10, 3)).unwrap();
+        engine.cancel_order(3).unwrap();
+
+        for id in [1, 2, 3] {
+            assert_eq!(
+                engine.add_order(bid(id, 90, 10, 4)).unwrap_err(),
+                MatchingError::DuplicateOrderId(id)
+            );
+        }
+        // Ids need not increase, and a replace may keep its own id
+        engine.add_order(bid(9, 90, 10, 5)).unwrap();
+        engine.add_order(bid(5, 91, 10, 6)).unwrap();
+        engine.cancel_replace(5, bid(5, 92, 10, 7)).unwrap();
+        assert_eq!(
+            engine.cancel_replace(5, bid(1, 92, 10, 8)).unwrap_err(),
+            MatchingError::DuplicateOrderId(1)
+        );
+    }
+
+    #[test]
+    fn increasing_ids_reject_any_id_not_above_the_last() {
+        let mut engine = MatchingEngine::new();
+        engine.set_risk_config(RiskConfig {
+            order_ids: OrderIdPolicy::Increasing,
+            ..RiskConfig::default()
+        });
+        engine.add_order(bid(5, 99, 10, 1)).unwrap();
+        engine.cancel_order(5).unwrap();
+        assert_eq!(
+            engine.add_order(bid(5, 99, 10, 2)).unwrap_err(),
+            MatchingError::DuplicateOrderId(5)
+        );
+        assert_eq!(
+            engine.add_order(bid(4, 99, 10, 3)).unwrap_err(),
+            MatchingError::DuplicateOrderId(4)
+        );
+
+        engine.add_order(bid(7, 99, 10, 4)).unwrap();
+        engine.cancel_replace(7, bid(7, 98, 10, 5)).unwrap();
+        engine.cancel_replace(7, bid(8, 98, 10, 6)).unwrap();
+        assert!(engine.book().contains_order(8));
+    }
+
+    #[test]
+    fn accepted_orders_get_consecutive_seqs() {
+        let mut engine = engine();
+        assert_eq!(engine.add_order(ask(1, 100, 10, 5)).unwrap().seq, 1);
+        let rejected = engine
+            .add_order(bid(2, 100, 10, 5).with_post_only(true))
+            .unwrap();
+        assert_eq!(rejected.seq, 0);
+        assert_eq!(engine.add_order(ask(3, 101, 10, 5)).unwrap().seq, 2);
+
+        let result = engine.add_order(bid(4, 101, 15, 5)).unwrap();
+        assert_eq!(result.seq, 3);
+        let seqs: Vec<_> = result
+            .fills
+            .iter()
+            .map(|f| (f.taker_seq, f.maker_seq))
+            .collect();
+        assert_eq!(seqs, vec![(3, 1), (3, 2)]);
+
+        // A replacement is a new arrival
+        let result = engine.cancel_replace(3, ask(3, 102, 5, 5)).unwrap();
+        assert_eq!(result.seq, 4);
+        assert_eq!(engine.book().order_seq(3), Some(4));
+    }
+
+    #[test]
+    fn clear_resets_to_a_fresh_engine() {
+        let mut engine = engine();
+        engine.set_risk_config(RiskConfig {
+            order_ids: OrderIdPolicy::Unique,
+            ..RiskConfig::default()
+        });
+        engine.add_order(ask(1, 100, 10, 5)).unwrap();
+        engine.add_order(bid(2, 100, 4, 5)).unwrap();
+        engine.halt(HaltPolicy::RejectAll);
+
+        engine.clear();
+        assert_eq!(engine.book().order_count(), 0);
+        assert_eq!(engine.last_trade_price(), None);
+        assert_eq!(engine.metrics(), EngineMetrics::default());
+        assert_eq!(engine.halt_policy(), None);
+        assert_eq!(engine.risk_config().order_ids, OrderIdPolicy::Unique);
+        // Ids and seqs start over.
+        assert_eq!(engine.add_order(ask(1, 100, 10, 5)).unwrap().seq, 1);
+    }
+
+    #[test]
+    fn warmup_leaves_the_engine_as_it_was() {
+        let mut empty = engine();
+        empty.warmup();
+        assert_eq!(empty.metrics(), EngineMetrics::default());
+        assert_eq!(empty.add_order(ask(1, 100, 10, 5)).unwrap().seq, 1);
+
+        let mut engine = engine();
+        engine.add_order(ask(1, 100, 10, 5)).unwrap();
+        engine.add_order(bid(2, 100, 4, 6)).unwrap();
+        let (hash, metrics) = (engine.book().state_hash(), engine.metrics());
+        engine.warmup();
+        assert_eq!(engine.book().state_hash(), hash);
+        assert_eq!(engine.metrics(), metrics);
+        assert_eq!(engine.add_order(bid(3, 100, 6, 7)).unwrap().seq, 3);
+    }
+
+    #[test]
+    fn auction_taker_is_the_later_arrival_at_equal_timestamps() {
+        let mut engine = engine();
+        engine.start_auction();
+        engine.add_order(bid(1, 100, 10, 7)).unwrap();
+        engine.add_order(ask(2, 100, 10, 7)).unwrap();
+
+        let fill = &engine.uncross().unwrap().fills[0];
+        assert_eq!((fill.taker_order_id, fill.maker_order_id), (2, 1));
+        assert_eq!((fill.taker_seq, fill.maker_seq), (2, 1));
+    }
+
+    #[test]
+    fn cancel_all_removes_only_that_trader() {
+        let mut engine = MatchingEngine::new();
+        engine.add_order(bid_trader(1, 7, 101, 10, 1)).unwrap();
+        engine.add_order(bid_trader(2, 8, 100, 10, 2)).unwrap();
+        engine.add_order(ask_trader(3, 7, 103, 10, 3)).unwrap();
+        engine.add_order(ask_trader(4, 8, 104, 10, 4)).unwrap();
+        engine.add_order(bid_trader(5, 7, 100, 5, 5)).unwrap();
+
+        let ids: Vec<_> = engine
+            .cancel_all_for_trader(7)
+            .iter()
+            .map(|o| o.id)
+            .collect();
+        assert_eq!(ids, vec![1, 5, 3]);
+        assert_eq!(engine.book().order_count(), 2);
+        assert_eq!(engine.book().best_bid(), Some(100));
+        assert_eq!(engine.book().best_ask(), Some(104));
+        assert_eq!(engine.book().level_depth(Side::Bid, 100), Some((10, 1)));
+        assert_eq!(engine.trader_stats(7).unwrap().resting_orders, 0);
+        assert_eq!(engine.trader_exposure(7), 0);
+        assert_eq!(engine.metrics().cancels, 3);
+
+        assert!(engine.cancel_all_for_trader(7).is_empty());
+        assert!(engine.cancel_all_for_trader(99).is_empty());
+    }
+
+    #[test]
+    fn halt_stops_crosses_but_accepts_cancels() {
+        let mut engine = MatchingEngine::new();
+        engine.add_order(ask(1, 100, 10, 1)).unwrap();
+        engine.add_order(ask(2, 101, 10, 2)).unwrap();
+        engine.halt(HaltPolicy::RejectMarketable);
+
+        assert_eq!(
+            engine.add_order(bid(3, 100, 10, 3)),
+            Err(MatchingError::Halted)
+        );
+        assert_eq!(
+            engine.cancel_replace(2, bid(4, 101, 10, 4)),
+            Err(MatchingError::Halted)
+        );
+        assert_eq!(engine.metrics().fills, 0);
+        assert_eq!(engine.book().order_count(), 2);
+
+        // Passive orders still rest, and cancels go through
+        let result = engine.add_order(bid(5, 99, 10, 5)).unwrap();
+        assert_eq!(result.status, OrderStatus::Resting);
+        engine.cancel_order(2).unwrap();
+
+        engine.halt(HaltPolicy::RejectAll);
+        assert_eq!(
+            engine.add_order(bid(6, 98, 10, 6)),
+            Err(MatchingError::Halted)
+        );
+        engine.cancel_order(5).unwrap();
+
+        engine.resume();
+        assert!(!engine.is_halted());
+        let result = engine.add_order(bid(7, 100, 10, 7)).unwrap();
+        assert_eq!(result.status, OrderStatus::FullyFilled);
+    }
+
+    #[test]
+    fn auction_uncross_at_max_volume_price() {
+        let mut engine = engine();
+        engine.start_auction();
+        for order in [
+            bid(1, 102, 10, 1),
+            bid(2, 101, 20, 2),
+            bid(3, 100, 30, 3),
+            ask(4, 99, 15, 4),
+            ask(5, 100, 10, 5),
+            ask(6, 101, 25, 6),
+        ] {
+            let result = engine.add_order(order).unwrap();
+            assert_eq!(result.status, OrderStatus::Resting);
+        }
+        // Crossed while the auction collects orders
+        assert_eq!(engine.book().best_bid(), Some(102));
+        assert_eq!(engine.book().best_ask(), Some(99));
+        assert_eq!(engine.verify(), Ok(()));
+        assert_eq!(engine.indicative_uncross(), Some((101, 30)));
+
+        let result = engine.uncross().unwrap();
+        assert!(!engine.in_auction());
+        assert_eq!(engine.verify(), Ok(()));
+        assert_eq!(result.price, Some(101));
+        assert_eq!(result.volume(), 30);
+        let pairs: Vec<_> = result
+            .fills
+            .iter()
+            .map(|f| (f.taker_order_id, f.maker_order_id, f.quantity))
+            .collect();
+        assert_eq!(pairs, vec![(4, 1, 10), (4, 2, 5), (5, 2, 10), (6, 2, 5)]);
+        assert!(result.fills.iter().all(|f| f.price == 101));
+
+        assert_eq!(engine.book().best_bid(), Some(100));
+        assert_eq!(engine.book().best_ask(), Some(101));
+        assert_eq!(engine.book().level_depth(Side::Ask, 101), Some((20, 1)));
+        assert_eq!(engine.last_trade_price(), Some(101));
+        assert_eq!(engine.trader_position(2), 20);
+        assert_eq!(engine.trader_position(4), -15);
+        assert_eq!(engine.trader_exposure(2), 0);
+        assert_eq!(engine.trader_exposure(6), 101 * 20);
+        assert_eq!(engine.metrics().quantity_matched, 30);
+
+        // Continuous trading resumes
+        let result = engine.add_order(bid(7, 101, 5, 7)).unwrap();
+        assert_eq!(result.status, OrderStatus::FullyFilled);
+    }
+
+    #[test]
+    fn auction_tie_breaks_on_reference_then_lowest_price() {
+        let mut engine = engine();
+        engine.start_auction();
+        engine.add_order(bid(1, 101, 10, 1)).unwrap();
+        engine.add_order(ask(2, 99, 10, 2)).unwrap();
+        assert_eq!(engine.indicative_uncross(), Some((99, 10)));
+
+        engine.set_risk_config(RiskConfig {
+            reference_price: Some(102),
+            ..RiskConfig::default()
+        });
+        assert_eq!(engine.indicative_uncross(), Some((101, 10)));
+    }
+
+    #[test]
+    fn uncross_without_a_cross_trades_nothing() {
+        let mut engine = engine();
+        engine.start_auction();
+        engine.add_order(bid(1, 99, 10, 1)).unwrap();
+        engine.add_order(ask(2, 101, 10, 2)).unwrap();
+        engine.halt(HaltPolicy::RejectAll);
+        assert_eq!(engine.uncross(), Err(MatchingError::Halted));
+        assert!(engine.in_auction());
+
+        engine.resume();
+        assert_eq!(engine.uncross().unwrap(), UncrossResult::default());
+        assert_eq!(engine.book().order_count(), 2);
+    }
+
+    #[test]
+    fn add_order_with_streams_the_same_fills() {
+        let book = |engine: &mut MatchingEngine| {
+            engine.add_order(ask(1, 100, 10, 1)).unwrap();
+            engine.add_order(ask(2, 100, 10, 2)).unwrap();
+            engine.add_order(ask(3, 101, 10, 3)).unwrap();
+        };
+        let (mut collected, mut streamed) = (engine(), engine());
+        book(&mut collected);
+        book(&mut streamed);
+
+        let expected = collected.add_order(bid(4, 101, 25, 4)).unwrap();
+        let mut fills = Vec::new();
+        let status = streamed
+            .add_order_with(bid(4, 101, 25, 4), |fill| {
+                fills.push((fill.maker_order_id, fill.quantity))
+            })
+            .unwrap();
+
+        assert_eq!(status, expected.status);
+        let expected_fills: Vec<_> = expected
+            .fills
+            .iter()
+            .map(|f| (f.maker_order_id, f.quantity))
+            .collect();
+        assert_eq!(fills, expected_fills);
+        assert_eq!(streamed.metrics(), collected.metrics());
+        assert_eq!(streamed.book().level_depth(Side::Ask, 101), Some((5, 1)));
+
+        // Rejections never reach the callback
+        let zero = Order {
+            quantity: 0.into(),
+            ..bid(5, 101, 1, 5)
+        };
+        let err = streamed.add_order_with(zero, |_| panic!("no fills"));
+        assert_eq!(err, Err(MatchingError::ZeroQuantity));
+        assert_eq!(streamed.metrics().orders_rejected, 1);
+    }
+
+    #[test]
+    fn add_order_into_reuses_the_callers_buffer() {
+        let mut engine = engine();
+        for id in 1..=40 {
+            engine.add_order(ask(id, 100 + id as i64, 1, id)).unwrap();
+        }
+
+        let mut fills = Vec::new();
+        let status = engine
+            .add_order_into(bid(100, 120, 20, 100), &mut fills)
+            .unwrap();
+        assert_eq!(status, OrderStatus::FullyFilled);
+        assert_eq!(fills.len(), 20);
+        let (capacity, ptr) = (fills.capacity(), fills.as_ptr());
+
+        // Cleared, then refilled in place
+        engine
+            .add_order_into(bid(101, 140, 20, 101), &mut fills)
+            .unwrap();
+        assert_eq!(fills.len(), 20);
+        assert_eq!(fills[0].maker_order_id, 21);
+        assert_eq!((fills.capacity(), fills.as_ptr()), (capacity, ptr));
+    }
+
+    #[test]
+    fn recycled_fills_back_the_next_result() {
+        let mut engine = engine();
+        engine.add_order(ask(1, 100, 10, 1)).unwrap();
+        engine.add_order(ask(2, 100, 10, 2)).unwrap();
+
+        let buf = Vec::with_capacity(64);
+        let ptr = buf.as_ptr();
+        engine.recycle_fills(buf);
+        let result = engine.add_order(bid(3, 100, 10, 3)).unwrap();
+        assert_eq!(result.fills.as_ptr(), ptr);
+
+        // Oversized buffers aren't kept
+        engine.recycle_fills(Vec::with_capacity(FILLS_RETAINED_CAPACITY + 1));
+        let result = engine.add_order(bid(4, 100, 10, 4)).unwrap();
+        assert_eq!(result.fills.capacity(), FILLS_INITIAL_CAPACITY);
+    }
+
+    #[test]
+    fn notional_limit_boundary() {
+        let mut engine = banded(RiskConfig {
+            max_notional: Some(10_000),
+            ..RiskConfig::default()
+        });
+        engine.add_order(bid(1, 100, 100, 1)).unwrap();
+        let err = engine.add_order(bid(2, 100, 101, 2)).unwrap_err();
+        assert_eq!(
+            err,
+            MatchingError::NotionalLimitExceeded {
+                notional: 10_100,
+                limit: 10_000
+            }
+        );
+
+        // Negative prices count by magnitude
+        engine.add_order(ask(3, -100, 100, 3)).unwrap();
+        let err = engine.add_order(ask(4, -101, 100, 4)).unwrap_err();
+        assert!(matches!(err, MatchingError::NotionalLimitExceeded { .. }));
+    }
+
+    #[test]
+    fn notional_past_u64_exceeds_even_the_largest_limit() {
+        let mut engine = banded(RiskConfig {
+            max_notional: Some(u64::MAX),
+            ..RiskConfig::default()
+        });
+        let err = engine.add_order(bid(1, i64::MAX, 3, 1)).unwrap_err();
+        assert_eq!(
+            err,
+            MatchingError::NotionalLimitExceeded {
+                notional: i64::MAX as u128 * 3,
+                limit: u64::MAX
+            }
+        );
+        engine.add_order(bid(2, i64::MAX, 2, 2)).unwrap();
+    }
+
+    #[test]
+    fn limits_unlimited_by_default() {
+        let mut engine = engine();
+        engine.add_order(bid(1, i64::MAX, u64::MAX, 1)).unwrap();
+        assert_eq!(engine.book().order_count(), 1);
+    }
+
+    #[test]
+    fn cancel_replace_swaps_orders() {
+        let mut engine = engine();
+        engine.add_order(bid_trader(1, 7, 100, 10, 1)).unwrap();
+
+        let result = engine
+            .cancel_replace(1, bid_trader(2, 7, 101, 20, 2))
+            .unwrap();
+        assert_eq!(result.order_id, 2);
+        assert_eq!(result.status, OrderStatus::Resting);
+        assert_eq!(engine.book().order_count(), 1);
+        assert_eq!(engine.book().best_bid(), Some(101));
+        assert!(!engine.book().contains_order(1));
+        assert_eq!(engine.trader_exposure(7), 101 * 20);
+    }
+
+    #[test]
+    fn cancel_replace_can_reuse_old_id_and_match() {
+        let mut engine = engine();
+        engine.add_order(ask_trader(1, 10, 105, 10, 1)).unwrap();
+        engine.add_order(bid_trader(2, 20, 100, 10, 2)).unwrap();
+
+        let result = engine
+            .cancel_replace(2, bid_trader(2, 20, 105, 10, 3))
+            .unwrap();
+        assert_eq!(result.status, OrderStatus::FullyFilled);
+        assert_eq!(result.fills[0].maker_order_id, 1);
+        assert_eq!(engine.book().order_count(), 0);
+    }
+
+    #[test]
+    fn cancel_replace_duplicate_id_keeps_original() {
+        let mut engine = engine();
+        engine.add_order(bid(1, 100, 10, 1)).unwrap();
+        engine.add_order(bid(2, 99, 10, 2)).unwrap();
+
+        let err = engine.cancel_replace(1, bid(2, 101, 5, 3)).unwrap_err();
+        assert_eq!(err, MatchingError::Book(BookError::DuplicateOrderId(2)));
+        assert!(engine.book().contains_order(1));
+        assert_eq!(engine.book().best_bid(), Some(100));
+        assert_eq!(engine.book().order_count(), 2);
+    }
+
+    #[test]
+    fn cancel_replace_risk_rejection_keeps_original() {
+        let mut engine = banded(RiskConfig {
+            max_order_quantity: Some(10),
+            ..RiskConfig::default()
+        });
+        engine.add_order(bid(1, 100, 10, 1)).unwrap();
+
+        let err = engine.cancel_replace(1, bid(2, 100, 11, 2)).unwrap_err();
+        assert!(matches!(err, MatchingError::QuantityLimitExceeded { .. }));
+        assert!(engine.book().contains_order(1));
+        assert!(!engine.book().contains_order(2));
+    }
+
+    #[test]
+    fn cancel_replace_unknown_old_id() {
+        let mut engine = engine();
+        let err = engine.cancel_replace(9, bid(2, 100, 10, 1)).unwrap_err();
+        assert_eq!(err, MatchingError::Book(BookError::OrderNotFound(9)));
+        assert_eq!(engine.book().order_count(), 0);
+    }
+
+    #[test]
+    fn cancel_replace_in_full_arena() {
+        let mut engine = MatchingEngine::with_fixed_capacity(2);
+        engine.add_order(bid(1, 100, 10, 1)).unwrap();
+        engine.add_order(bid(2, 99, 10, 2)).unwrap();
+
+        engine.cancel_replace(1, bid(3, 98, 10, 3)).unwrap();
+        assert_eq!(engine.book().order_count(), 2);
+        assert_eq!(engine.book().best_bid(), Some(99));
     }
 
-    fn ask_trader(id: u64, trader_id: u64, price: i64, qty: u64, ts: u64) -> Order {
-        Order::new(id, trader_id, Side::Ask, price, qty, ts).unwrap()
+    #[test]
+    fn amend_policy_matrix() {
+        use AmendPolicy::*;
+        // (policy, new price, new quantity, keeps priority) for an order at
+        // 100 x 10 with another bid behind it and one already resting at 99.
+        let cases = [
+            (ResetPriority, 100, 4, false),
+            (ResetPriority, 100, 15, false),
+            (ResetPriority, 99, 10, false),
+            (KeepOnDecrease, 100, 4, true),
+            (KeepOnDecrease, 100, 15, false),
+            (KeepOnDecrease, 99, 10, false),
+            (KeepOnSamePrice, 100, 4, true),
+            (KeepOnSamePrice, 100, 15, true),
+            (KeepOnSamePrice, 99, 4, false),
+        ];
+        for (policy, price, qty, keeps) in cases {
+            let mut engine = engine();
+            engine.set_amend_policy(policy);
+            engine.add_order(bid_trader(1, 7, 100, 10, 1)).unwrap();
+            engine.add_order(bid_trader(2, 8, 100, 10, 2)).unwrap();
+            engine.add_order(bid_trader(3, 8, 99, 10, 3)).unwrap();
+
+            let result = engine
+                .cancel_replace(1, bid_trader(1, 7, price, qty, 4))
+                .unwrap();
+            let case = format!("{policy:?} to {price} x {qty}");
+            assert_eq!(result.status, OrderStatus::Resting, "{case}");
+            assert_eq!(result.seq == 1, keeps, "{case}");
+            assert_eq!(engine.book().queue_position(1) == Some(0), keeps, "{case}");
+            let order = engine.book().get_order(1).unwrap();
+            assert_eq!((order.price.0, order.quantity.0), (price, qty), "{case}");
+            assert_eq!(order.timestamp == 1, keeps, "{case}");
+            assert_eq!(engine.trader_exposure(7), price as i128 * qty as i128);
+            assert_eq!(engine.verify(), Ok(()));
+        }
     }
 
     #[test]
-    fn no_match_resting() {
+    fn amend_with_other_changes_requeues() {
         let mut engine = engine();
+        engine.set_amend_policy(AmendPolicy::KeepOnSamePrice);
+        engine.add_order(bid(1, 100, 10, 1)).unwrap();
+        engine.add_order(bid(2, 100, 10, 2)).unwrap();
 
-        let result = engine.add_order(bid(1, 100, 10, 1)).unwrap();
-        assert_eq!(result.status, OrderStatus::Resting);
-        assert!(result.fills.is_empty());
-
-        let result = engine.add_order(ask(2, 105, 10, 2)).unwrap();
-        assert_eq!(result.status, OrderStatus::Resting);
-        assert!(result.fills.is_empty());
-
-        assert_eq!(engine.book().best_bid(), Some(100));
-        assert_eq!(engine.book().best_ask(), Some(105));
-        assert_eq!(engine.book().order_count(), 2);
+        engine
+            .cancel_replace(1, gtd(bid(1, 100, 4, 3), 500))
+            .unwrap();
+        assert_eq!(engine.book().queue_position(1), Some(1));
     }
 
     #[test]
-    fn full_fill_equal_quantities() {
+    fn apply_bytes_decodes_and_applies_raw_messages() {
         let mut engine = engine();
-        engine.add_order(ask(1, 100, 10, 1)).unwrap();
-
-        let result = engine.add_order(bid(2, 100, 10, 2)).unwrap();
-        assert_eq!(result.status, OrderStatus::FullyFilled);
-        assert_eq!(result.fills.len(), 1);
-        assert_eq!(result.fills[0].quantity, 10);
-        assert_eq!(result.fills[0].price, 100);
-        assert_eq!(result.fills[0].taker_order_id, 2);
-        assert_eq!(result.fills[0].maker_order_id, 1);
-        assert!(result.fills[0].maker_fully_filled);
+        let mut buf = [0u8; protocol::NEW_ORDER_SIZE];
+        let encode = |buf: &mut [u8], order: &Order| {
+            let n = protocol::encode_new_order(buf, order).unwrap();
+            buf[..n].to_vec()
+        };
 
+        let rested = engine
+            .apply_bytes(&encode(&mut buf, &ask(1, 100, 10, 0)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(rested.status, OrderStatus::Resting);
+        let filled = engine
+            .apply_bytes(&encode(&mut buf, &bid(2, 100, 4, 0)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(filled.status, OrderStatus::FullyFilled);
+        assert_eq!(filled.fills[0].maker_order_id, 1);
+
+        let mut cancel = [0u8; protocol::CANCEL_ORDER_SIZE];
+        protocol::encode_cancel_order(&mut cancel, 1).unwrap();
+        assert_eq!(engine.apply_bytes(&cancel), Ok(None));
+        assert_eq!(engine.book().order_count(), 0);
+        assert_eq!(
+            engine.apply_bytes(&cancel),
+            Err(ApplyError::Matching(MatchingError::Book(
+                BookError::OrderNotFound(1)
+            )))
+        );
+
+        // A bad buffer leaves the engine untouched.
+        let bytes = encode(&mut buf, &bid(3, 100, 4, 0));
+        assert_eq!(
+            engine.apply_bytes(&bytes[..10]),
+            Err(ApplyError::Protocol(ProtocolError::BufferTooShort))
+        );
         assert_eq!(engine.book().order_count(), 0);
     }
 
     #[test]
-    fn partial_fill_taker_has_more() {
-        let mut engine = engine();
-        engine.add_order(ask(1, 100, 5, 1)).unwrap();
+    fn equal_price_cross_follows_the_cross_policy() {
+        for side in [Side::Bid, Side::Ask] {
+            let (maker, taker) = match side {
+                Side::Bid => (ask(1, 100, 10, 1), bid(2, 100, 4, 2)),
+                Side::Ask => (bid(1, 100, 10, 1), ask(2, 100, 4, 2)),
+            };
 
-        let result = engine.add_order(bid(2, 100, 10, 2)).unwrap();
-        assert_eq!(result.status, OrderStatus::PartiallyFilled);
-        assert_eq!(result.fills.len(), 1);
-        assert_eq!(result.fills[0].quantity, 5);
-        assert!(result.fills[0].maker_fully_filled);
+            let mut equal = engine();
+            equal.add_order(maker.clone()).unwrap();
+            let result = equal.add_order(taker.clone()).unwrap();
+            assert_eq!(result.status, OrderStatus::FullyFilled);
+            assert_eq!(result.fills[0].quantity, 4);
 
-        assert_eq!(engine.book().best_bid(), Some(100));
-        assert_eq!(engine.book().order_count(), 1);
+            let mut engine = engine();
+            engine.set_cross_policy(CrossPolicy::StrictlyThrough);
+            engine.add_order(maker).unwrap();
+            let result = engine.add_order(taker).unwrap();
+            assert_eq!(result.status, OrderStatus::Resting);
+            assert!(result.fills.is_empty());
+            // Locked, not crossed.
+            assert_eq!(engine.book().best_bid(), engine.book().best_ask());
+            assert_eq!(engine.verify(), Ok(()));
+
+            // Switching back leaves it locked, and passive orders still rest.
+            engine.set_cross_policy(CrossPolicy::AtEqualPrice);
+            let passive = match side {
+                Side::Bid => bid(3, 99, 1, 3),
+                Side::Ask => ask(3, 101, 1, 3),
+            };
+            engine.add_order(passive).unwrap();
+            assert_eq!(engine.book().best_bid(), engine.book().best_ask());
+        }
     }
 
     #[test]
-    fn partial_fill_maker_has_more() {
+    fn strictly_through_still_trades_past_the_best() {
         let mut engine = engine();
-        engine.add_order(bid(1, 100, 20, 1)).unwrap();
+        engine.set_cross_policy(CrossPolicy::StrictlyThrough);
+        engine.add_order(ask(1, 100, 5, 1)).unwrap();
+        engine.add_order(ask(2, 101, 5, 2)).unwrap();
 
-        let result = engine.add_order(ask(2, 100, 5, 2)).unwrap();
-        assert_eq!(result.status, OrderStatus::FullyFilled);
+        // Fills the level below its limit but not the one at it.
+        let result = engine.add_order(bid(3, 101, 8, 3)).unwrap();
         assert_eq!(result.fills.len(), 1);
-        assert_eq!(result.fills[0].quantity, 5);
-        assert!(!result.fills[0].maker_fully_filled);
-
-        assert_eq!(engine.book().order_count(), 1);
-        assert_eq!(engine.book().best_bid(), Some(100));
+        assert_eq!(result.fills[0].maker_order_id, 1);
+        assert_eq!(engine.book().best_bid(), Some(101));
+        assert_eq!(engine.book().best_ask(), Some(101));
     }
 
     #[test]
-    fn multi_level_matching() {
+    fn strictly_through_treats_equal_price_as_passive() {
         let mut engine = engine();
-        engine.add_order(ask(1, 100, 5, 1)).unwrap();
-        engine.add_order(ask(2, 101, 5, 2)).unwrap();
-        engine.add_order(ask(3, 102, 5, 3)).unwrap();
+        engine.set_cross_policy(CrossPolicy::StrictlyThrough);
+        engine.add_order(ask(1, 100, 10, 1)).unwrap();
 
-        let result = engine.add_order(bid(4, 102, 12, 4)).unwrap();
-        assert_eq!(result.status, OrderStatus::FullyFilled);
-        assert_eq!(result.fills.len(), 3);
+        let result = engine
+            .add_order(bid(2, 100, 5, 2).with_post_only(true))
+            .unwrap();
+        assert_eq!(result.status, OrderStatus::Resting);
 
-        assert_eq!(result.fills[0].price, 100);
-        assert_eq!(result.fills[0].quantity, 5);
-        assert_eq!(result.fills[1].price, 101);
-        assert_eq!(result.fills[1].quantity, 5);
-        assert_eq!(result.fills[2].price, 102);
-        assert_eq!(result.fills[2].quantity, 2);
-        assert!(!result.fills[2].maker_fully_filled);
+        engine.halt(HaltPolicy::RejectMarketable);
+        assert!(engine.add_order(bid(3, 100, 5, 3)).is_ok());
+        assert_eq!(
+            engine.add_order(bid(4, 101, 5, 4)),
+            Err(MatchingError::Halted)
+        );
+    }
 
-        assert_eq!(engine.book().order_count(), 1);
-        assert_eq!(engine.book().best_ask(), Some(102));
+    fn gtd(order: Order, expiry: u64) -> Order {
+        order.with_expiry(expiry)
+    }
+
+    fn expire_ids(engine: &mut MatchingEngine, now_nanos: u64) -> Vec<u64> {
+        engine
+            .expire_orders(now_nanos)
+            .iter()
+            .map(|o| o.id.0)
+            .collect()
     }
 
     #[test]
-    fn fifo_within_price_level() {
+    fn expire_orders_cancels_due_orders_in_expiry_order() {
         let mut engine = engine();
-        engine.add_order(ask(1, 100, 10, 1)).unwrap();
-        engine.add_order(ask(2, 100, 10, 2)).unwrap();
-        engine.add_order(ask(3, 100, 10, 3)).unwrap();
+        engine.add_order(gtd(bid(1, 100, 10, 1), 300)).unwrap();
+        engine.add_order(gtd(ask(2, 110, 10, 2), 100)).unwrap();
+        engine.add_order(bid(3, 99, 10, 3)).unwrap();
+        engine.add_order(gtd(ask(4, 111, 10, 4), 500)).unwrap();
 
-        let result = engine.add_order(bid(4, 100, 15, 4)).unwrap();
-        assert_eq!(result.fills.len(), 2);
-        assert_eq!(result.fills[0].maker_order_id, 1);
-        assert_eq!(result.fills[0].quantity, 10);
-        assert_eq!(result.fills[1].maker_order_id, 2);
-        assert_eq!(result.fills[1].quantity, 5);
+        assert!(engine.expire_orders(99).is_empty());
+        assert_eq!(expire_ids(&mut engine, 300), vec![2, 1]);
+        assert_eq!(engine.book().order_count(), 2);
+        assert_eq!(engine.trader_exposure(1), 0);
+        assert_eq!(engine.next_expiry(), Some(500));
     }
 
     #[test]
-    fn fill_price_is_maker_price() {
+    fn filled_gtd_order_leaves_expiry_index() {
         let mut engine = engine();
-        engine.add_order(ask(1, 100, 10, 1)).unwrap();
-
-        let result = engine.add_order(bid(2, 110, 10, 2)).unwrap();
-        assert_eq!(result.fills[0].price, 100);
+        engine.add_order(gtd(ask(1, 100, 10, 1), 50)).unwrap();
+        engine.add_order(bid(2, 100, 10, 2)).unwrap();
+        assert_eq!(engine.next_expiry(), None);
+
+        // Id 1 reused without expiry: a stale entry would cancel it.
+        engine.add_order(ask(1, 105, 10, 3)).unwrap();
+        assert!(engine.expire_orders(u64::MAX).is_empty());
+        assert!(engine.book().contains_order(1));
     }
 
     #[test]
-    fn ask_taker_matches_bids() {
+    fn partially_filled_gtd_order_still_expires() {
         let mut engine = engine();
-        engine.add_order(bid(1, 102, 10, 1)).unwrap();
-        engine.add_order(bid(2, 101, 10, 2)).unwrap();
+        engine.add_order(gtd(ask(1, 100, 10, 1), 50)).unwrap();
+        engine.add_order(bid(2, 100, 4, 2)).unwrap();
+        assert_eq!(expire_ids(&mut engine, 50), vec![1]);
+        assert_eq!(engine.book().order_count(), 0);
+    }
 
-        let result = engine.add_order(ask(3, 101, 15, 3)).unwrap();
-        assert_eq!(result.fills.len(), 2);
-        assert_eq!(result.fills[0].maker_order_id, 1);
-        assert_eq!(result.fills[0].price, 102);
-        assert_eq!(result.fills[0].quantity, 10);
-        assert_eq!(result.fills[1].maker_order_id, 2);
-        assert_eq!(result.fills[1].price, 101);
-        assert_eq!(result.fills[1].quantity, 5);
+    #[test]
+    fn cancelled_gtd_order_leaves_expiry_index() {
+        let mut engine = engine();
+        engine.add_order(gtd(bid(1, 100, 10, 1), 50)).unwrap();
+        engine.cancel_order(1).unwrap();
+        assert_eq!(engine.next_expiry(), None);
+    }
 
-        assert_eq!(result.status, OrderStatus::FullyFilled);
+    #[test]
+    fn restore_rebuilds_expiry_index() {
+        let mut live = engine();
+        live.add_order(gtd(bid(1, 100, 10, 1), 50)).unwrap();
+        live.add_order(bid(2, 99, 10, 2)).unwrap();
+
+        let mut restored =
+            MatchingEngine::restore_exact(&live.book().level_queues(), TEST_CAPACITY).unwrap();
+        assert_eq!(restored.next_expiry(), Some(50));
+        assert_eq!(expire_ids(&mut restored, 50), vec![1]);
     }
 
     #[test]
-    fn cancel_resting_order() {
+    fn take_delta_without_tracking_is_empty() {
         let mut engine = engine();
         engine.add_order(bid(1, 100, 10, 1)).unwrap();
-
-        let cancelled = engine.cancel_order(1).unwrap();
-        assert_eq!(cancelled.id, 1);
-        assert_eq!(engine.book().order_count(), 0);
+        assert_eq!(engine.take_delta(), BookDelta::default());
     }
 
     #[test]
-    fn cancel_nonexistent_fails() {
-        let mut engine = engine();
-        let err = engine.cancel_order(999).unwrap_err();
-        assert_eq!(err, MatchingError::Book(BookError::OrderNotFound(999)));
+    fn delta_reproduces_book_and_exposure() {
+        let mut live = engine();
+        live.add_order(ask(1, 105, 10, 1)).unwrap();
+        live.add_order(ask(2, 105, 10, 2)).unwrap();
+        live.add_order(bid(3, 100, 10, 3)).unwrap();
+        let base = live.book().level_queues();
+
+        live.set_change_tracking(true);
+        live.add_order(bid(4, 105, 15, 4)).unwrap(); // fills 1, partially fills 2
+        live.cancel_order(3).unwrap();
+        live.add_order(bid(3, 101, 5, 5)).unwrap(); // reuses id 3
+        live.add_order(ask(6, 105, 7, 6)).unwrap(); // queues behind 2
+        live.add_order(bid(7, 99, 1, 7)).unwrap();
+        live.cancel_order(7).unwrap();
+
+        let delta = live.take_delta();
+        assert_eq!(delta.removed, vec![1, 3, 7]);
+        assert_eq!(delta.modified, vec![(2, 5)]);
+        let added: Vec<u64> = delta.added.iter().map(|(o, _)| o.id.0).collect();
+        assert_eq!(added, vec![3, 6]);
+
+        let mut restored = MatchingEngine::restore_exact(&base, TEST_CAPACITY).unwrap();
+        restored.apply_delta(&delta).unwrap();
+        assert_eq!(
+            restored.book().all_resting_orders(),
+            live.book().all_resting_orders()
+        );
+        for trader in 1..=7 {
+            assert_eq!(
+                restored.trader_exposure(trader),
+                live.trader_exposure(trader)
+            );
+        }
+
+        assert_eq!(live.take_delta(), BookDelta::default());
     }
 
     #[test]
-    fn zero_quantity_rejected() {
+    fn reduce_only_sell_clamped_to_long_position() {
         let mut engine = engine();
-        let order = Order {
-            id: 1,
-            trader_id: 1,
-            side: Side::Bid,
-            price: 100,
-            quantity: 0,
-            timestamp: 1,
-        };
-        let err = engine.add_order(order).unwrap_err();
-        assert_eq!(err, MatchingError::ZeroQuantity);
+        // Trader 1 goes long 30
+        engine.add_order(ask_trader(1, 2, 100, 30, 1)).unwrap();
+        engine.add_order(bid_trader(2, 1, 100, 30, 2)).unwrap();
+        assert_eq!(engine.trader_position(1), 30);
+
+        engine.add_order(bid_trader(3, 3, 99, 20, 3)).unwrap();
+        let sell = ask_trader(4, 1, 99, 50, 4).with_reduce_only(true);
+        let result = engine.add_order(sell).unwrap();
+
+        assert_eq!(result.status, OrderStatus::ReduceOnlyClamped);
+        assert_eq!(result.fills.len(), 1);
+        assert_eq!(result.fills[0].quantity, 20);
+        assert_eq!(engine.trader_position(1), 10);
+        let resting = engine.book().get_order(4).unwrap();
+        assert_eq!(resting.quantity, 10);
+        assert!(resting.reduce_only);
     }
 
     #[test]
-    fn empty_book_no_match() {
+    fn reduce_only_within_position_not_clamped() {
         let mut engine = engine();
-        let result = engine.add_order(bid(1, 100, 10, 1)).unwrap();
+        engine.add_order(bid_trader(1, 2, 100, 30, 1)).unwrap();
+        engine.add_order(ask_trader(2, 1, 100, 30, 2)).unwrap();
+        assert_eq!(engine.trader_position(1), -30);
+
+        let buy = bid_trader(3, 1, 98, 30, 3).with_reduce_only(true);
+        let result = engine.add_order(buy).unwrap();
         assert_eq!(result.status, OrderStatus::Resting);
-        assert!(result.fills.is_empty());
+        assert_eq!(engine.book().get_order(3).unwrap().quantity, 30);
     }
 
     #[test]
-    fn bid_below_best_ask_no_match() {
+    fn reduce_only_without_position_cancelled() {
         let mut engine = engine();
-        engine.add_order(ask(1, 105, 10, 1)).unwrap();
+        engine.add_order(bid_trader(1, 2, 100, 10, 1)).unwrap();
 
-        let result = engine.add_order(bid(2, 100, 10, 2)).unwrap();
-        assert_eq!(result.status, OrderStatus::Resting);
+        let sell = ask_trader(2, 1, 100, 10, 2).with_reduce_only(true);
+        let result = engine.add_order(sell).unwrap();
+        assert_eq!(result.status, OrderStatus::ReduceOnlyClamped);
         assert!(result.fills.is_empty());
-        assert_eq!(engine.book().order_count(), 2);
+        assert!(!engine.book().contains_order(2));
+        assert_eq!(engine.book().best_bid(), Some(100));
     }
 
     #[test]
-    fn self_trade_prevented_cancel_newest() {
+    fn post_only_bid_crossing_best_ask_rejected() {
         let mut engine = engine();
-        engine.add_order(ask_trader(1, 1, 100, 10, 1)).unwrap();
-
-        let result = engine.add_order(bid_trader(2, 1, 100, 10, 2)).unwrap();
-        assert_eq!(result.status, OrderStatus::CancelledSelfTrade);
-        assert!(result.fills.is_empty());
+        engine.add_order(ask(1, 100, 10, 1)).unwrap();
 
-        assert_eq!(engine.book().order_count(), 1);
-        assert_eq!(engine.book().best_ask(), Some(100));
+        for (id, price) in [(2, 100), (3, 101)] {
+            let result = engine
+                .add_order(bid(id, price, 5, id).with_post_only(true))
+                .unwrap();
+            assert_eq!(result.status, OrderStatus::RejectedPostOnly);
+            assert!(result.fills.is_empty());
+            assert!(!engine.book().contains_order(id));
+        }
+        assert_eq!(engine.book().get_order(1).unwrap().quantity, 10);
+        assert_eq!(engine.book().best_bid(), None);
+        assert_eq!(engine.metrics().orders_rejected, 2);
     }
 
     #[test]
-    fn self_trade_different_traders_allowed() {
+    fn post_only_bid_below_best_ask_rests() {
         let mut engine = engine();
-        engine.add_order(ask_trader(1, 1, 100, 10, 1)).unwrap();
+        engine.add_order(ask(1, 100, 10, 1)).unwrap();
 
-        let result = engine.add_order(bid_trader(2, 2, 100, 10, 2)).unwrap();
-        assert_eq!(result.status, OrderStatus::FullyFilled);
-        assert_eq!(result.fills.len(), 1);
-        assert_eq!(result.fills[0].quantity, 10);
-        assert_eq!(engine.book().order_count(), 0);
+        let result = engine
+            .add_order(bid(2, 99, 5, 2).with_post_only(true))
+            .unwrap();
+        assert_eq!(result.status, OrderStatus::Resting);
+        assert!(engine.book().get_order(2).unwrap().post_only);
+        assert_eq!(engine.book().best_bid(), Some(99));
     }
 
     #[test]
-    fn self_trade_partial_fill_then_cancel() {
+    fn post_only_replace_crossing_keeps_original() {
         let mut engine = engine();
-        engine.add_order(ask_trader(1, 10, 100, 5, 1)).unwrap();
-        engine.add_order(ask_trader(2, 20, 101, 10, 2)).unwrap();
+        engine.add_order(ask(1, 100, 10, 1)).unwrap();
+        engine.add_order(bid(2, 98, 5, 2)).unwrap();
+
+        let result = engine
+            .cancel_replace(2, bid(3, 100, 5, 3).with_post_only(true))
+            .unwrap();
+        assert_eq!(result.status, OrderStatus::RejectedPostOnly);
+        assert!(engine.book().contains_order(2));
+        assert!(!engine.book().contains_order(3));
+    }
 
-        // Fills against trader A, then hits own ask — cancelled
-        let result = engine.add_order(bid_trader(3, 20, 101, 15, 3)).unwrap();
-        assert_eq!(result.status, OrderStatus::CancelledSelfTrade);
-        assert_eq!(result.fills.len(), 1);
-        assert_eq!(result.fills[0].maker_order_id, 1);
-        assert_eq!(result.fills[0].quantity, 5);
+    #[test]
+    fn became_best_when_resting_at_or_above_the_top() {
+        let mut engine = engine();
+        assert!(engine.add_order(bid(1, 100, 10, 1)).unwrap().became_best);
+        assert!(engine.add_order(bid(2, 101, 10, 2)).unwrap().became_best);
+        assert!(!engine.add_order(bid(3, 99, 10, 3)).unwrap().became_best);
+        // Joining the best level counts too.
+        assert!(engine.add_order(bid(4, 101, 10, 4)).unwrap().became_best);
+
+        engine.add_order(ask(5, 105, 10, 5)).unwrap();
+        // Fully filled: nothing rests.
+        assert!(!engine.add_order(bid(6, 105, 10, 6)).unwrap().became_best);
+        // Sweeps the ask and rests the rest as the new best bid.
+        engine.add_order(ask(7, 104, 5, 7)).unwrap();
+        let result = engine.add_order(bid(8, 104, 10, 8)).unwrap();
+        assert_eq!(result.status, OrderStatus::PartiallyFilled);
+        assert!(result.became_best);
 
-        assert_eq!(engine.book().order_count(), 1);
-        assert_eq!(engine.book().best_ask(), Some(101));
+        let replaced = engine.cancel_replace(3, bid(9, 98, 10, 9)).unwrap();
+        assert!(!replaced.became_best);
     }
 
     #[test]
-    fn restore_empty() {
-        let engine = MatchingEngine::restore_from_orders(&[], TEST_CAPACITY).unwrap();
-        assert_eq!(engine.book().order_count(), 0);
-        assert_eq!(engine.book().best_bid(), None);
-        assert_eq!(engine.book().best_ask(), None);
+    fn near_max_quantities_at_one_price_reject_cleanly() {
+        let mut engine = engine();
+        engine.add_order(bid(1, 100, u64::MAX - 10, 1)).unwrap();
+        let before = engine.book().all_resting_orders();
+
+        assert_eq!(
+            engine.add_order(bid(2, 100, u64::MAX - 10, 2)),
+            Err(MatchingError::Book(BookError::QuantityOverflow))
+        );
+        assert_eq!(engine.book().all_resting_orders(), before);
+        assert_eq!(engine.book().total_bid_quantity(), u64::MAX - 10);
+        assert_eq!(engine.trader_stats(2), None);
+        // The ask side keeps its own total.
+        engine.add_order(ask(3, 101, u64::MAX, 3)).unwrap();
     }
 
     #[test]
-    fn restore_from_orders_rebuilds_book() {
-        let orders = vec![
-            ask(1, 105, 10, 1),
-            ask(2, 110, 20, 2),
-            bid(3, 100, 30, 3),
-            bid(4, 98, 40, 4),
-        ];
-
-        let engine = MatchingEngine::restore_from_orders(&orders, TEST_CAPACITY).unwrap();
-        assert_eq!(engine.book().order_count(), 4);
-        assert_eq!(engine.book().best_bid(), Some(100));
-        assert_eq!(engine.book().best_ask(), Some(105));
+    fn simulate_matches_real_add() {
+        let mut engine = engine();
+        engine.add_order(ask_trader(1, 1, 100, 10, 1)).unwrap();
+        engine.add_order(ask_trader(2, 2, 100, 5, 2)).unwrap();
+        engine.add_order(ask_trader(3, 1, 102, 20, 3)).unwrap();
+        let before = engine.book().all_resting_orders();
+
+        let order = bid_trader(4, 3, 102, 30, 4);
+        let simulated = engine.simulate_order(&order);
+        assert_eq!(engine.book().all_resting_orders(), before);
+        assert_eq!(simulated.fills.len(), 3);
+        assert_eq!(simulated, engine.add_order(order).unwrap());
     }
 
     #[test]
-    fn restore_then_match() {
-        let orders = vec![ask(1, 100, 10, 1), ask(2, 101, 20, 2)];
-        let mut engine = MatchingEngine::restore_from_orders(&orders, TEST_CAPACITY).unwrap();
+    fn simulate_stops_at_self_trade() {
+        let mut engine = engine();
+        engine.add_order(ask_trader(1, 1, 100, 10, 1)).unwrap();
+        engine.add_order(ask_trader(2, 2, 101, 10, 2)).unwrap();
 
-        let result = engine.add_order(bid(3, 101, 15, 3)).unwrap();
-        assert_eq!(result.fills.len(), 2);
-        assert_eq!(result.fills[0].maker_order_id, 1);
-        assert_eq!(result.fills[0].quantity, 10);
-        assert_eq!(result.fills[1].maker_order_id, 2);
-        assert_eq!(result.fills[1].quantity, 5);
-        assert_eq!(result.status, OrderStatus::FullyFilled);
+        let order = bid_trader(3, 2, 101, 15, 3);
+        let simulated = engine.simulate_order(&order);
+        assert_eq!(simulated.status, OrderStatus::CancelledSelfTrade);
+        assert_eq!(simulated, engine.add_order(order).unwrap());
     }
 
     #[test]
-    fn self_trade_multiple_resting_same_trader() {
+    fn metrics_count_engine_activity() {
         let mut engine = engine();
+        assert_eq!(engine.metrics(), EngineMetrics::default());
+
         engine.add_order(ask_trader(1, 1, 100, 10, 1)).unwrap();
         engine.add_order(ask_trader(2, 1, 101, 10, 2)).unwrap();
+        engine.add_order(bid_trader(3, 2, 101, 15, 3)).unwrap();
+        engine.add_order(bid_trader(4, 1, 101, 5, 4)).unwrap();
+        let zero = Order {
+            quantity: 0.into(),
+            ..bid(7, 100, 1, 5)
+        };
+        engine.add_order(zero).unwrap_err();
+        engine.add_order(bid(5, 90, 5, 6)).unwrap();
+        engine
+            .cancel_replace(5, bid_trader(6, 3, 91, 5, 7))
+            .unwrap();
+        engine.cancel_order(6).unwrap();
+        engine.cancel_order(6).unwrap_err();
+
+        assert_eq!(
+            engine.metrics(),
+            EngineMetrics {
+                orders_accepted: 6,
+                orders_rejected: 1,
+                fills: 2,
+                cancels: 2,
+                self_trade_cancels: 1,
+                quantity_matched: 15,
+                arena_full_rejects: 0,
+            }
+        );
+    }
 
-        let result = engine.add_order(bid_trader(3, 1, 105, 30, 3)).unwrap();
-        assert_eq!(result.status, OrderStatus::CancelledSelfTrade);
-        assert!(result.fills.is_empty());
-
-        assert_eq!(engine.book().order_count(), 2);
+    #[test]
+    fn arena_full_rejects_are_counted() {
+        let mut engine = MatchingEngine::with_fixed_capacity(2);
+        engine.add_order(bid(1, 100, 5, 1)).unwrap();
+        engine.add_order(bid(2, 99, 5, 2)).unwrap();
+        assert_eq!(
+            engine.add_order(bid(3, 98, 5, 3)).unwrap_err(),
+            MatchingError::Book(BookError::ArenaFull)
+        );
+        // Fully filling needs no slot, so it still trades.
+        engine.add_order(ask(4, 100, 5, 4)).unwrap();
+
+        let metrics = engine.metrics();
+        assert_eq!(metrics.arena_full_rejects, 1);
+        assert_eq!(metrics.orders_rejected, 1);
+        assert_eq!(metrics.orders_accepted, 3);
     }
 }
 
@@ -518,9 +3390,9 @@ mod proptests {
             taker_qty in 1_u64..=1000,
         ) {
             let mut engine = engine();
-            engine.add_order(Order::new(1, 1, Side::Ask, price, maker_qty, 1).unwrap()).unwrap();
+            engine.add_order(Order::try_new(1, 1, Side::Ask, price, maker_qty, 1).unwrap()).unwrap();
 
-            let result = engine.add_order(Order::new(2, 2, Side::Bid, price, taker_qty, 2).unwrap()).unwrap();
+            let result = engine.add_order(Order::try_new(2, 2, Side::Bid, price, taker_qty, 2).unwrap()).unwrap();
 
             let filled: u64 = result.fills.iter().map(|f| f.quantity).sum();
             let remainder = match result.status {
@@ -528,7 +3400,11 @@ mod proptests {
                 OrderStatus::PartiallyFilled | OrderStatus::Resting => {
                     taker_qty - filled
                 }
-                OrderStatus::CancelledSelfTrade => taker_qty - filled,
+                OrderStatus::CancelledSelfTrade
+                | OrderStatus::ReduceOnlyClamped
+                | OrderStatus::RejectedPostOnly => {
+                    taker_qty - filled
+                }
             };
             prop_assert_eq!(filled + remainder, taker_qty);
         }
@@ -543,7 +3419,7 @@ mod proptests {
             let mut engine = engine();
             for (i, (side, price, qty)) in orders.into_iter().enumerate() {
                 let id = (i + 1) as u64;
-                let order = Order::new(id, id, side, price, qty, id).unwrap();
+                let order = Order::try_new(id, id, side, price, qty, id).unwrap();
                 let _ = engine.add_order(order);
             }
 
@@ -552,6 +3428,56 @@ mod proptests {
             }
         }
 
+        #[test]
+        fn uncross_leaves_book_uncrossed(
+            orders in proptest::collection::vec(
+                (arb_side(), 1_i64..=100, 1_u64..=100),
+                1..50,
+            )
+        ) {
+            let mut engine = engine();
+            engine.start_auction();
+            for (i, (side, price, qty)) in orders.into_iter().enumerate() {
+                let id = (i + 1) as u64;
+                engine.add_order(Order::try_new(id, id, side, price, qty, id).unwrap()).unwrap();
+            }
+            let expected = engine.indicative_uncross();
+            let total = engine.book().total_bid_quantity() + engine.book().total_ask_quantity();
+
+            let result = engine.uncross().unwrap();
+            prop_assert_eq!(result.price, expected.map(|(price, _)| price));
+            prop_assert_eq!(result.volume(), expected.map_or(0, |(_, volume)| volume));
+            let left = engine.book().total_bid_quantity() + engine.book().total_ask_quantity();
+            prop_assert_eq!(left + 2 * result.volume(), total);
+            if let (Some(bb), Some(ba)) = (engine.book().best_bid(), engine.book().best_ask()) {
+                prop_assert!(bb < ba, "crossed after uncross: best_bid={bb} >= best_ask={ba}");
+            }
+        }
+
+        #[test]
+        fn cached_best_matches_levels(
+            ops in proptest::collection::vec(
+                (any::<bool>(), arb_side(), 1_i64..=50, 1_u64..=100),
+                1..100,
+            )
+        ) {
+            let mut engine = engine();
+            let mut next_id = 1u64;
+            for (cancel, side, price, qty) in ops {
+                if cancel && next_id > 1 {
+                    let _ = engine.cancel_order(price as u64 % next_id);
+                } else {
+                    let order = Order::try_new(next_id, next_id, side, price, qty, next_id).unwrap();
+                    let _ = engine.add_order(order);
+                    next_id += 1;
+                }
+
+                let book = engine.book();
+                prop_assert_eq!(book.best_bid(), book.iter_levels(Side::Bid).next().map(|l| l.price));
+                prop_assert_eq!(book.best_ask(), book.iter_levels(Side::Ask).next().map(|l| l.price));
+            }
+        }
+
         #[test]
         fn crosses_produce_fills(
             price in 1_i64..=1000,
@@ -559,9 +3485,9 @@ mod proptests {
             taker_qty in 1_u64..=1000,
         ) {
             let mut engine = engine();
-            engine.add_order(Order::new(1, 1, Side::Ask, price, maker_qty, 1).unwrap()).unwrap();
+            engine.add_order(Order::try_new(1, 1, Side::Ask, price, maker_qty, 1).unwrap()).unwrap();
 
-            let result = engine.add_order(Order::new(2, 2, Side::Bid, price, taker_qty, 2).unwrap()).unwrap();
+            let result = engine.add_order(Order::try_new(2, 2, Side::Bid, price, taker_qty, 2).unwrap()).unwrap();
             prop_assert!(!result.fills.is_empty(), "bid >= ask but no fills produced");
         }
 
@@ -575,7 +3501,7 @@ mod proptests {
             let mut engine = engine();
             for (i, (side, price, qty)) in orders.into_iter().enumerate() {
                 let id = (i + 1) as u64;
-                let order = Order::new(id, id, side, price, qty, id).unwrap();
+                let order = Order::try_new(id, id, side, price, qty, id).unwrap();
                 if let Ok(result) = engine.add_order(order) {
                     for fill in &result.fills {
                         prop_assert!(fill.quantity > 0, "fill with zero quantity");
@@ -584,6 +3510,22 @@ mod proptests {
             }
         }
 
+        #[test]
+        fn simulate_then_add_agree(
+            orders in proptest::collection::vec(
+                (arb_side(), 1_u64..=3, 1_i64..=20, 1_u64..=50),
+                1..40,
+            )
+        ) {
+            let mut engine = engine();
+            for (i, (side, trader_id, price, qty)) in orders.into_iter().enumerate() {
+                let id = (i + 1) as u64;
+                let order = Order::try_new(id, trader_id, side, price, qty, id).unwrap();
+                let simulated = engine.simulate_order(&order);
+                prop_assert_eq!(simulated, engine.add_order(order).unwrap());
+            }
+        }
+
         #[test]
         fn no_self_trade_fills(
             price in 1_i64..=100,
@@ -591,9 +3533,9 @@ mod proptests {
             taker_qty in 1_u64..=100,
         ) {
             let mut engine = engine();
-            engine.add_order(Order::new(1, 42, Side::Ask, price, maker_qty, 1).unwrap()).unwrap();
+            engine.add_order(Order::try_new(1, 42, Side::Ask, price, maker_qty, 1).unwrap()).unwrap();
 
-            let result = engine.add_order(Order::new(2, 42, Side::Bid, price, taker_qty, 2).unwrap()).unwrap();
+            let result = engine.add_order(Order::try_new(2, 42, Side::Bid, price, taker_qty, 2).unwrap()).unwrap();
 
             prop_assert!(result.fills.is_empty(), "self-trade produced fills");
             prop_assert_eq!(result.status, OrderStatus::CancelledSelfTrade);
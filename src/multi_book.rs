@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+
+use crate::book::BookError;
+use crate::matching::{
+    AddOrderResult, EngineMetrics, Fill, HaltPolicy, MatchingEngine, MatchingError, RiskConfig,
+};
+use crate::order::Order;
+use crate::protocol::EngineCommand;
+
+/// One `MatchingEngine` per instrument, routed by `Order::symbol_id`. Books
+/// are created on the first order for their symbol. Commands that name only
+/// an order id find its book through an index of resting orders, so order
+/// ids are unique across all books while they rest.
+///
+/// Each book has its own engine sequence, metrics and trader stats; a
+/// trader's exposure and position are per instrument.
+#[derive(Debug)]
+pub struct MultiBookEngine {
+    books: HashMap<u32, MatchingEngine>,
+    /// Symbol of every resting order.
+    order_symbols: HashMap<u64, u32>,
+    arena_capacity: u32,
+    risk: RiskConfig,
+    halt: Option<HaltPolicy>,
+    /// Orders turned away before reaching a book.
+    unrouted_rejects: u64,
+}
+
+impl MultiBookEngine {
+    /// `arena_capacity` is per book.
+    pub fn new(arena_capacity: u32) -> Self {
+        Self {
+            books: HashMap::new(),
+            order_symbols: HashMap::new(),
+            arena_capacity,
+            risk: RiskConfig::default(),
+            halt: None,
+            unrouted_rejects: 0,
+        }
+    }
+
+    /// Applied to every book, existing and future, each with `symbol_id`
+    /// pinned to its own symbol.
+    pub fn set_risk_config(&mut self, risk: RiskConfig) {
+        for (&symbol, engine) in &mut self.books {
+            engine.set_risk_config(pinned(&risk, symbol));
+        }
+        self.risk = risk;
+    }
+
+    pub fn book(&self, symbol_id: u32) -> Option<&MatchingEngine> {
+        self.books.get(&symbol_id)
+    }
+
+    /// Symbols with a book, ascending.
+    pub fn symbols(&self) -> Vec<u32> {
+        let mut symbols: Vec<u32> = self.books.keys().copied().collect();
+        symbols.sort_unstable();
+        symbols
+    }
+
+    pub fn best_bid(&self, symbol_id: u32) -> Option<i64> {
+        self.book(symbol_id)?.book().best_bid()
+    }
+
+    pub fn best_ask(&self, symbol_id: u32) -> Option<i64> {
+        self.book(symbol_id)?.book().best_ask()
+    }
+
+    /// Book the resting order `order_id` is in.
+    pub fn symbol_of(&self, order_id: u64) -> Option<u32> {
+        self.order_symbols.get(&order_id).copied()
+    }
+
+    /// Counters summed over every book.
+    pub fn metrics(&self) -> EngineMetrics {
+        let mut total = EngineMetrics {
+            orders_rejected: self.unrouted_rejects,
+            ..EngineMetrics::default()
+        };
+        for m in self.books.values().map(MatchingEngine::metrics) {
+            total.orders_accepted += m.orders_accepted;
+            total.orders_rejected += m.orders_rejected;
+            total.fills += m.fills;
+            total.cancels += m.cancels;
+            total.self_trade_cancels += m.self_trade_cancels;
+            total.quantity_matched += m.quantity_matched;
+            total.arena_full_rejects += m.arena_full_rejects;
+        }
+        total
+    }
+
+    pub fn add_order(&mut self, order: Order) -> Result<AddOrderResult, MatchingError> {
+        if let Some(&symbol) = self.order_symbols.get(&order.id.0)
+            && symbol != order.symbol_id
+        {
+            self.unrouted_rejects += 1;
+            return Err(BookError::DuplicateOrderId(order.id.0).into());
+        }
+        let symbol = order.symbol_id;
+        let result = self.engine_mut(symbol).add_order(order);
+        if let Ok(r) = &result {
+            self.track(symbol, r);
+        }
+        result
+    }
+
+    pub fn cancel_order(&mut self, order_id: u64) -> Result<Order, MatchingError> {
+        let symbol = self.route(order_id)?;
+        let order = self.engine_mut(symbol).cancel_order(order_id)?;
+        self.order_symbols.remove(&order_id);
+        Ok(order)
+    }
+
+    /// Runs in the book of `old_id`, which rejects a replacement for another
+    /// symbol with `WrongSymbol`.
+    pub fn cancel_replace(
+        &mut self,
+        old_id: u64,
+        new_order: Order,
+    ) -> Result<AddOrderResult, MatchingError> {
+        let symbol = self.route(old_id)?;
+        let new_id = new_order.id.0;
+        if new_id != old_id && self.order_symbols.contains_key(&new_id) {
+            self.unrouted_rejects += 1;
+            return Err(BookError::DuplicateOrderId(new_id).into());
+        }
+        let result = self.engine_mut(symbol).cancel_replace(old_id, new_order);
+        if let Ok(r) = &result {
+            self.order_symbols.remove(&old_id);
+            self.track(symbol, r);
+        }
+        result
+    }
+
+    pub fn reduce_order(&mut self, order_id: u64, reduce_by: u64) -> Result<Order, MatchingError> {
+        let symbol = self.route(order_id)?;
+        let order = self.engine_mut(symbol).reduce_order(order_id, reduce_by)?;
+        if order.quantity == 0 {
+            self.order_symbols.remove(&order_id);
+        }
+        Ok(order)
+    }
+
+    /// Cancels the trader's orders in every book, books in symbol order.
+    pub fn cancel_all_for_trader(&mut self, trader_id: u64) -> Vec<Order> {
+        let mut cancelled = Vec::new();
+        for symbol in self.symbols() {
+            cancelled.extend(self.engine_mut(symbol).cancel_all_for_trader(trader_id));
+        }
+        for order in &cancelled {
+            self.order_symbols.remove(&order.id.0);
+        }
+        cancelled
+    }
+
+    /// Cancels expired orders in every book, books in symbol order.
+    pub fn expire_orders(&mut self, now_nanos: u64) -> Vec<Order> {
+        let mut expired = Vec::new();
+        for symbol in self.symbols() {
+            expired.extend(self.engine_mut(symbol).expire_orders(now_nanos));
+        }
+        for order in &expired {
+            self.order_symbols.remove(&order.id.0);
+        }
+        expired
+    }
+
+    /// Halts every book, including ones created while halted.
+    pub fn halt(&mut self, policy: HaltPolicy) {
+        for engine in self.books.values_mut() {
+            engine.halt(policy);
+        }
+        self.halt = Some(policy);
+    }
+
+    pub fn resume(&mut self) {
+        for engine in self.books.values_mut() {
+            engine.resume();
+        }
+        self.halt = None;
+    }
+
+    /// Routes one command as the gateway or WAL replay would and returns
+    /// the fills it produced.
+    pub fn apply(&mut self, cmd: EngineCommand) -> Result<Vec<Fill>, MatchingError> {
+        match cmd {
+            EngineCommand::NewOrder(order) => self.add_order(order).map(|r| r.fills),
+            EngineCommand::CancelOrder { order_id } => self.cancel_order(order_id).map(|_| vec![]),
+            EngineCommand::CancelReplace { old_id, new_order } => {
+                self.cancel_replace(old_id, new_order).map(|r| r.fills)
+            }
+            EngineCommand::ReduceOrder {
+                order_id,
+                reduce_by,
+            } => self.reduce_order(order_id, reduce_by).map(|_| vec![]),
+            EngineCommand::CancelAll { trader_id } => {
+                self.cancel_all_for_trader(trader_id);
+                Ok(vec![])
+            }
+            EngineCommand::Halt { policy } => {
+                self.halt(policy);
+                Ok(vec![])
+            }
+            EngineCommand::Resume => {
+                self.resume();
+                Ok(vec![])
+            }
+            EngineCommand::RequestSnapshot | EngineCommand::AdminSnapshot => Ok(vec![]),
+        }
+    }
+
+    fn route(&self, order_id: u64) -> Result<u32, MatchingError> {
+        self.symbol_of(order_id)
+            .ok_or_else(|| BookError::OrderNotFound(order_id).into())
+    }
+
+    fn engine_mut(&mut self, symbol: u32) -> &mut MatchingEngine {
+        let (capacity, risk, halt) = (self.arena_capacity, &self.risk, self.halt);
+        self.books.entry(symbol).or_insert_with(|| {
+            let mut engine = MatchingEngine::with_capacity(capacity);
+            engine.set_risk_config(pinned(risk, symbol));
+            if let Some(policy) = halt {
+                engine.halt(policy);
+            }
+            engine
+        })
+    }
+
+    /// Drops makers the result filled and indexes the order if it rests.
+    fn track(&mut self, symbol: u32, result: &AddOrderResult) {
+        for fill in result.fills.iter().filter(|f| f.maker_fully_filled) {
+            self.order_symbols.remove(&fill.maker_order_id);
+        }
+        if self.books[&symbol].book().contains_order(result.order_id) {
+            self.order_symbols.insert(result.order_id, symbol);
+        }
+    }
+}
+
+fn pinned(risk: &RiskConfig, symbol: u32) -> RiskConfig {
+    RiskConfig {
+        symbol_id: Some(symbol),
+        ..risk.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::Side;
+    use crate::wal::{Wal, WalReader};
+
+    fn order(id: u64, symbol: u32, side: Side, price: i64, qty: u64) -> Order {
+        Order::try_new(id, id, side, price, qty, id)
+            .unwrap()
+            .with_symbol(symbol)
+    }
+
+    #[test]
+    fn books_are_isolated_by_symbol() {
+        let mut engine = MultiBookEngine::new(64);
+        engine.add_order(order(1, 1, Side::Ask, 100, 10)).unwrap();
+
+        // Crosses book 1's ask in price but is for symbol 2.
+        let result = engine.add_order(order(2, 2, Side::Bid, 105, 10)).unwrap();
+        assert!(result.fills.is_empty());
+        assert_eq!(engine.best_ask(1), Some(100));
+        assert_eq!(engine.best_bid(2), Some(105));
+        assert_eq!(engine.best_bid(1), None);
+        assert_eq!(engine.symbols(), vec![1, 2]);
+
+        let result = engine.add_order(order(3, 1, Side::Bid, 100, 4)).unwrap();
+        assert_eq!(result.fills.len(), 1);
+        assert_eq!(result.fills[0].maker_order_id, 1);
+        assert_eq!(engine.metrics().fills, 1);
+        assert_eq!(engine.metrics().orders_accepted, 3);
+    }
+
+    #[test]
+    fn id_only_commands_find_the_right_book() {
+        let mut engine = MultiBookEngine::new(64);
+        engine.add_order(order(1, 1, Side::Bid, 100, 10)).unwrap();
+        engine.add_order(order(2, 2, Side::Bid, 100, 10)).unwrap();
+
+        assert_eq!(
+            engine.add_order(order(1, 2, Side::Bid, 99, 1)),
+            Err(MatchingError::Book(BookError::DuplicateOrderId(1)))
+        );
+        assert_eq!(engine.metrics().orders_rejected, 1);
+
+        engine.reduce_order(2, 4).unwrap();
+        assert_eq!(engine.book(2).unwrap().book().total_bid_quantity(), 6);
+
+        engine
+            .cancel_replace(1, order(3, 1, Side::Bid, 101, 10))
+            .unwrap();
+        assert_eq!((engine.symbol_of(1), engine.symbol_of(3)), (None, Some(1)));
+        assert_eq!(
+            engine.cancel_replace(3, order(4, 2, Side::Bid, 101, 10)),
+            Err(MatchingError::WrongSymbol {
+                symbol_id: 2,
+                expected: 1
+            })
+        );
+
+        engine.cancel_order(3).unwrap();
+        assert_eq!(engine.best_bid(1), None);
+        assert_eq!(
+            engine.cancel_order(3),
+            Err(MatchingError::Book(BookError::OrderNotFound(3)))
+        );
+
+        // A fill that empties the maker drops it from the index.
+        engine.add_order(order(5, 2, Side::Ask, 100, 6)).unwrap();
+        assert_eq!(engine.symbol_of(2), None);
+    }
+
+    #[test]
+    fn wal_replay_routes_by_recorded_symbol() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+        let cmds = [
+            EngineCommand::NewOrder(order(1, 1, Side::Ask, 100, 10)),
+            EngineCommand::NewOrder(order(2, 2, Side::Ask, 200, 10)),
+            EngineCommand::NewOrder(order(3, 2, Side::Bid, 200, 3)),
+            EngineCommand::CancelOrder { order_id: 1 },
+        ];
+        let mut live = MultiBookEngine::new(64);
+        {
+            let mut wal = Wal::open(&path).unwrap();
+            for cmd in &cmds {
+                wal.append(cmd).unwrap();
+                live.apply(cmd.clone()).unwrap();
+            }
+        }
+
+        let mut replayed = MultiBookEngine::new(64);
+        for record in WalReader::open(&path).unwrap().iter() {
+            replayed.apply(record.unwrap().1).unwrap();
+        }
+        for symbol in [1, 2] {
+            assert_eq!(
+                replayed.book(symbol).unwrap().book().state_hash(),
+                live.book(symbol).unwrap().book().state_hash()
+            );
+        }
+        assert_eq!(replayed.best_ask(1), None);
+        assert_eq!(replayed.book(2).unwrap().book().total_ask_quantity(), 7);
+    }
+}
@@ -1,40 +1,213 @@
+use std::fmt;
+use std::num::NonZeroU64;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum Side {
     Bid,
     Ask,
 }
 
+/// Defines a transparent wrapper around an integer, convertible both ways
+/// and comparable with the bare integer, so literals keep working while
+/// values of different wrappers can't be swapped for one another.
+macro_rules! newtype {
+    ($(#[$meta:meta])* $name:ident($inner:ty)) => {
+        $(#[$meta])*
+        #[derive(
+            Debug,
+            Clone,
+            Copy,
+            Default,
+            PartialEq,
+            Eq,
+            PartialOrd,
+            Ord,
+            Hash,
+            serde::Serialize,
+            serde::Deserialize,
+        )]
+        #[serde(transparent)]
+        #[repr(transparent)]
+        pub struct $name(pub $inner);
+
+        impl From<$inner> for $name {
+            fn from(value: $inner) -> Self {
+                Self(value)
+            }
+        }
+
+        impl From<$name> for $inner {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl PartialEq<$inner> for $name {
+            fn eq(&self, other: &$inner) -> bool {
+                self.0 == *other
+            }
+        }
+
+        impl PartialOrd<$inner> for $name {
+            fn partial_cmp(&self, other: &$inner) -> Option<std::cmp::Ordering> {
+                self.0.partial_cmp(other)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+    };
+}
+
+newtype!(
+    /// Client-assigned order id.
+    OrderId(u64)
+);
+newtype!(TraderId(u64));
+newtype!(
+    /// Limit price in ticks. May be negative.
+    Price(i64)
+);
+newtype!(Quantity(u64));
+
+impl Add for Quantity {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Quantity {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl AddAssign for Quantity {
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0;
+    }
+}
+
+impl SubAssign for Quantity {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.0 -= rhs.0;
+    }
+}
+
+/// Fields are typed so that passing one where another is expected, say a
+/// trader id as the order id, fails to compile. The engine's storage and
+/// the wire structs keep bare integers and convert at the boundary.
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct Order {
-    pub id: u64,
-    pub trader_id: u64,
+    pub id: OrderId,
+    pub trader_id: TraderId,
+    /// Instrument the order is for. The engine runs one book and ignores it
+    /// unless `RiskConfig::symbol_id` is set; carried on the wire and in the
+    /// WAL so a multi-book gateway can route by it.
+    pub symbol_id: u32,
     pub side: Side,
-    pub price: i64,
-    pub quantity: u64,
+    pub price: Price,
+    pub quantity: Quantity,
     pub timestamp: u64,
+    /// Good-till-date: wall-clock nanos after which the engine cancels the
+    /// order. Non-zero so `EngineCommand` still fits a 64-byte ring slot.
+    pub expiry: Option<NonZeroU64>,
+    /// Only ever reduces the trader's position: the engine cuts the quantity
+    /// down to the trader's opposite position before matching.
+    pub reduce_only: bool,
+    /// Maker-only: rejected instead of trading if it would cross on arrival.
+    pub post_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderError {
+    ZeroQuantity,
+    /// `i64::MIN` can't be negated, so it's rejected outright.
+    InvalidPrice(i64),
 }
 
+impl std::fmt::Display for OrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ZeroQuantity => write!(f, "order quantity must be non-zero"),
+            Self::InvalidPrice(p) => write!(f, "invalid order price {p}"),
+        }
+    }
+}
+
+impl std::error::Error for OrderError {}
+
 impl Order {
+    #[deprecated(note = "use `Order::try_new`, which reports why an order is invalid")]
     pub fn new(
-        id: u64,
-        trader_id: u64,
+        id: impl Into<OrderId>,
+        trader_id: impl Into<TraderId>,
         side: Side,
-        price: i64,
-        quantity: u64,
+        price: impl Into<Price>,
+        quantity: impl Into<Quantity>,
         timestamp: u64,
     ) -> Option<Self> {
+        Self::try_new(id, trader_id, side, price, quantity, timestamp).ok()
+    }
+
+    /// Takes the typed ids and amounts or bare integers, which convert.
+    pub fn try_new(
+        id: impl Into<OrderId>,
+        trader_id: impl Into<TraderId>,
+        side: Side,
+        price: impl Into<Price>,
+        quantity: impl Into<Quantity>,
+        timestamp: u64,
+    ) -> Result<Self, OrderError> {
+        let (price, quantity) = (price.into(), quantity.into());
         if quantity == 0 {
-            return None;
+            return Err(OrderError::ZeroQuantity);
+        }
+        if price == i64::MIN {
+            return Err(OrderError::InvalidPrice(price.0));
         }
-        Some(Self {
-            id,
-            trader_id,
+        Ok(Self {
+            id: id.into(),
+            trader_id: trader_id.into(),
+            symbol_id: 0,
             side,
             price,
             quantity,
             timestamp,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
         })
     }
+
+    /// An `expiry_nanos` of 0 means no expiry.
+    pub fn with_expiry(mut self, expiry_nanos: u64) -> Self {
+        self.expiry = NonZeroU64::new(expiry_nanos);
+        self
+    }
+
+    pub fn with_symbol(mut self, symbol_id: u32) -> Self {
+        self.symbol_id = symbol_id;
+        self
+    }
+
+    pub fn with_reduce_only(mut self, reduce_only: bool) -> Self {
+        self.reduce_only = reduce_only;
+        self
+    }
+
+    pub fn with_post_only(mut self, post_only: bool) -> Self {
+        self.post_only = post_only;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -43,7 +216,7 @@ mod tests {
 
     #[test]
     fn create_bid_order() {
-        let order = Order::new(1, 1, Side::Bid, 15005, 100, 1_000_000).unwrap();
+        let order = Order::try_new(1, 1, Side::Bid, 15005, 100, 1_000_000).unwrap();
         assert_eq!(order.id, 1);
         assert_eq!(order.trader_id, 1);
         assert_eq!(order.side, Side::Bid);
@@ -54,19 +227,59 @@ mod tests {
 
     #[test]
     fn create_ask_order() {
-        let order = Order::new(2, 1, Side::Ask, 15010, 50, 2_000_000).unwrap();
+        let order = Order::try_new(2, 1, Side::Ask, 15010, 50, 2_000_000).unwrap();
         assert_eq!(order.side, Side::Ask);
         assert_eq!(order.quantity, 50);
     }
 
     #[test]
     fn reject_zero_quantity() {
-        assert!(Order::new(1, 1, Side::Bid, 15005, 0, 1_000_000).is_none());
+        assert_eq!(
+            Order::try_new(1, 1, Side::Bid, 15005, 0, 1_000_000),
+            Err(OrderError::ZeroQuantity)
+        );
+    }
+
+    #[test]
+    fn reject_min_price() {
+        assert_eq!(
+            Order::try_new(1, 1, Side::Bid, i64::MIN, 10, 0),
+            Err(OrderError::InvalidPrice(i64::MIN))
+        );
+        assert!(Order::try_new(1, 1, Side::Bid, i64::MIN + 1, 10, 0).is_ok());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_new_wraps_try_new() {
+        assert!(Order::new(1, 1, Side::Bid, 100, 0, 0).is_none());
+        assert_eq!(
+            Order::new(1, 1, Side::Bid, 100, 10, 0),
+            Order::try_new(1, 1, Side::Bid, 100, 10, 0).ok()
+        );
+    }
+
+    #[test]
+    fn new_order_has_no_expiry() {
+        let order = Order::try_new(1, 1, Side::Bid, 100, 10, 0).unwrap();
+        assert_eq!(order.expiry, None);
+        assert_eq!(order.clone().with_expiry(0).expiry, None);
+        assert_eq!(order.with_expiry(5_000).expiry, NonZeroU64::new(5_000));
+    }
+
+    #[test]
+    fn newtypes_convert_and_compare_with_integers() {
+        let order = Order::try_new(OrderId(7), TraderId(3), Side::Ask, Price(-5), 10, 0).unwrap();
+        assert_eq!(order.id, 7);
+        assert_eq!(u64::from(order.trader_id), 3);
+        assert!(order.price < 0);
+        assert_eq!(order.quantity - Quantity(4), Quantity::from(6));
+        assert_eq!(order.price.to_string(), "-5");
     }
 
     #[test]
     fn negative_price_allowed() {
-        let order = Order::new(1, 1, Side::Bid, -100, 10, 0);
-        assert!(order.is_some());
+        let order = Order::try_new(1, 1, Side::Bid, -100, 10, 0);
+        assert!(order.is_ok());
     }
 }
@@ -1,17 +1,151 @@
-use crate::order::{Order, Side};
+use std::num::NonZeroU64;
+
+use crate::book::BookError;
+use crate::matching::{HaltPolicy, MatchingError, OrderStatus};
+use crate::order::{Order, OrderError, Side};
 
 pub const MSG_NEW_ORDER: u8 = 0x01;
 pub const MSG_CANCEL_ORDER: u8 = 0x02;
 pub const MSG_EXECUTION_REPORT: u8 = 0x03;
+pub const MSG_CANCEL_REPLACE: u8 = 0x04;
+/// `MSG_NEW_ORDER` followed by an expiry; sent for orders with `expiry` set.
+pub const MSG_NEW_ORDER_GTD: u8 = 0x05;
+/// `MSG_CANCEL_REPLACE` followed by an expiry for the replacement.
+pub const MSG_CANCEL_REPLACE_GTD: u8 = 0x06;
+/// A count followed by that many `MSG_NEW_ORDER` messages. Decoding is
+/// all-or-nothing: one malformed order rejects the whole batch.
+pub const MSG_BATCH: u8 = 0x07;
+/// Outbound best bid/ask, sent when either price or the size at it changes.
+pub const MSG_BOOK_UPDATE: u8 = 0x08;
+/// Sent back to the TCP client when the gateway drops one of its commands.
+pub const MSG_REJECT: u8 = 0x09;
+/// Outbound notice that the engine rejected a new order or cancel-replace.
+pub const MSG_ORDER_REJECT: u8 = 0x0A;
+/// Outbound confirmation of an accepted new order or cancel-replace.
+pub const MSG_ORDER_ACK: u8 = 0x0B;
+/// Outbound summary of consecutive fills at one price from a single order.
+pub const MSG_AGG_TRADE: u8 = 0x0C;
+/// Cancels every resting order of one trader.
+pub const MSG_CANCEL_ALL: u8 = 0x0D;
+/// Outbound notice that a resting order was removed by a cancel-all.
+pub const MSG_CANCEL_REPORT: u8 = 0x0E;
+/// Halts trading; byte 1 selects the `HaltPolicy`.
+pub const MSG_HALT: u8 = 0x0F;
+pub const MSG_RESUME: u8 = 0x10;
+/// Aggregated depth of the whole book, for bootstrapping a subscriber.
+/// Variable length: a header with level counts, then the levels.
+pub const MSG_BOOK_SNAPSHOT: u8 = 0x11;
+/// Asks for a `MSG_BOOK_SNAPSHOT` back on the same TCP connection.
+pub const MSG_REQUEST_SNAPSHOT: u8 = 0x12;
+/// Outbound new aggregate size of one price level, for keeping a full book
+/// from a `MSG_BOOK_SNAPSHOT` onward.
+pub const MSG_LEVEL_DELTA: u8 = 0x13;
+/// Asks the matching thread to write a full snapshot now, answered with a
+/// `MSG_ADMIN_SNAPSHOT_REPLY` on the same TCP connection.
+pub const MSG_ADMIN_SNAPSHOT: u8 = 0x14;
+pub const MSG_ADMIN_SNAPSHOT_REPLY: u8 = 0x15;
+/// Takes quantity off a resting order without losing its queue position.
+pub const MSG_REDUCE_ORDER: u8 = 0x16;
+
+/// Reject reason codes, shared by `MSG_REJECT` and `MSG_ORDER_REJECT`. Values
+/// are part of the wire format and never reused.
+/// The ring stayed full past `RingFullPolicy::Reject`'s timeout.
+pub const REJECT_RING_FULL: u8 = 1;
+pub const REJECT_DUPLICATE_ORDER_ID: u8 = 2;
+/// The order a cancel-replace targeted isn't resting.
+pub const REJECT_ORDER_NOT_FOUND: u8 = 3;
+pub const REJECT_ARENA_FULL: u8 = 4;
+pub const REJECT_ZERO_QUANTITY: u8 = 5;
+pub const REJECT_INVALID_TICK: u8 = 6;
+pub const REJECT_PRICE_BAND: u8 = 7;
+pub const REJECT_QUANTITY_LIMIT: u8 = 8;
+pub const REJECT_NOTIONAL_LIMIT: u8 = 9;
+/// A book invariant broke mid-match; should never be seen.
+pub const REJECT_INTERNAL: u8 = 10;
+pub const REJECT_TRADER_ORDER_LIMIT: u8 = 11;
+pub const REJECT_HALTED: u8 = 12;
+/// Resting the order would overflow a quantity or exposure total.
+pub const REJECT_QUANTITY_OVERFLOW: u8 = 13;
+/// A reduce asked for more than the order had left.
+pub const REJECT_REDUCE_EXCEEDS_QUANTITY: u8 = 14;
+/// The order named an instrument this engine doesn't trade.
+pub const REJECT_WRONG_SYMBOL: u8 = 15;
+
+/// `AdminSnapshotReply::status` codes, part of the wire format.
+pub const SNAPSHOT_SAVED: u8 = 0;
+/// The gateway runs without a data directory.
+pub const SNAPSHOT_DISABLED: u8 = 1;
+/// Writing the snapshot failed; the previous ones are untouched.
+pub const SNAPSHOT_FAILED: u8 = 2;
+
+/// Bits of the flags byte at offset 2 of new order and cancel-replace messages.
+pub const ORDER_FLAG_REDUCE_ONLY: u8 = 0x01;
+pub const ORDER_FLAG_POST_ONLY: u8 = 0x02;
+
+/// Multicast feed layout version, carried in the byte after the message type.
+/// Bumped whenever an outbound message layout changes.
+pub const PROTOCOL_VERSION: u8 = 2;
 
 pub const NEW_ORDER_SIZE: usize = 40;
 pub const CANCEL_ORDER_SIZE: usize = 16;
-pub const EXECUTION_REPORT_SIZE: usize = 48;
+pub const EXECUTION_REPORT_SIZE: usize = 64;
+pub const BOOK_UPDATE_SIZE: usize = 48;
+pub const REJECT_SIZE: usize = 16;
+pub const ORDER_REJECT_SIZE: usize = 24;
+pub const ORDER_ACK_SIZE: usize = 24;
+pub const AGG_TRADE_SIZE: usize = 48;
+pub const CANCEL_ALL_SIZE: usize = 16;
+pub const CANCEL_REPORT_SIZE: usize = 32;
+pub const HALT_SIZE: usize = 8;
+pub const RESUME_SIZE: usize = 8;
+pub const REQUEST_SNAPSHOT_SIZE: usize = 8;
+pub const LEVEL_DELTA_SIZE: usize = 40;
+pub const ADMIN_SNAPSHOT_SIZE: usize = 8;
+pub const ADMIN_SNAPSHOT_REPLY_SIZE: usize = 16;
+pub const REDUCE_ORDER_SIZE: usize = 24;
+pub const CANCEL_REPLACE_SIZE: usize = 48;
+pub const NEW_ORDER_GTD_SIZE: usize = NEW_ORDER_SIZE + 8;
+pub const CANCEL_REPLACE_GTD_SIZE: usize = CANCEL_REPLACE_SIZE + 8;
+pub const BATCH_HEADER_SIZE: usize = 8;
+pub const MAX_BATCH_ORDERS: usize = 64;
+pub const MAX_BATCH_SIZE: usize = BATCH_HEADER_SIZE + MAX_BATCH_ORDERS * NEW_ORDER_SIZE;
+pub const BOOK_SNAPSHOT_HEADER_SIZE: usize = 24;
+pub const BOOK_SNAPSHOT_LEVEL_SIZE: usize = 16;
+
+/// Length prefix of a message under length-prefixed TCP framing.
+pub const FRAME_HEADER_SIZE: usize = 4;
+/// Largest frame payload accepted, the largest inbound message.
+pub const MAX_FRAME_SIZE: usize = MAX_BATCH_SIZE;
+
+/// Largest inbound command on the wire; sizes read and WAL encode buffers.
+pub const MAX_COMMAND_SIZE: usize = CANCEL_REPLACE_GTD_SIZE;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum EngineCommand {
     NewOrder(Order),
-    CancelOrder { order_id: u64 },
+    CancelOrder {
+        order_id: u64,
+    },
+    CancelReplace {
+        old_id: u64,
+        new_order: Order,
+    },
+    CancelAll {
+        trader_id: u64,
+    },
+    ReduceOrder {
+        order_id: u64,
+        reduce_by: u64,
+    },
+    Halt {
+        policy: HaltPolicy,
+    },
+    Resume,
+    /// Read-only: answered to the requesting client and never logged.
+    RequestSnapshot,
+    /// Writes a full snapshot at this point in the command stream. Answered
+    /// to the requesting client and never logged.
+    AdminSnapshot,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -22,6 +156,110 @@ pub struct ExecutionReport {
     pub price: i64,
     pub quantity: u64,
     pub timestamp: u64,
+    /// Engine sequence numbers, unique where client order ids may not be.
+    pub taker_seq: u64,
+    pub maker_seq: u64,
+}
+
+/// Best price and the total quantity resting at it, per side. An empty side
+/// is sent as price 0, quantity 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookUpdate {
+    pub seq_num: u32,
+    pub best_bid: Option<(i64, u64)>,
+    pub best_ask: Option<(i64, u64)>,
+    pub timestamp: u64,
+}
+
+/// Gateway reply for a command that never reached the engine. `order_id` is
+/// the new order's id, or the cancelled id for a cancel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reject {
+    pub order_id: u64,
+    pub reason: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderReject {
+    pub seq_num: u32,
+    pub order_id: u64,
+    pub reason: u8,
+    pub timestamp: u64,
+}
+
+/// `timestamp` is the one the gateway assigned, so a client can match the
+/// ack to its submission and measure the round trip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderAck {
+    pub seq_num: u32,
+    pub order_id: u64,
+    pub status: OrderStatus,
+    /// `AddOrderResult::became_best`: the order now rests at the top.
+    pub became_best: bool,
+    pub timestamp: u64,
+}
+
+/// One price level swept by `taker_order_id`: the summed quantity of the
+/// `fill_count` execution reports at `price`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggTrade {
+    pub seq_num: u32,
+    pub taker_order_id: u64,
+    pub price: i64,
+    pub quantity: u64,
+    pub fill_count: u32,
+    pub timestamp: u64,
+}
+
+/// `quantity` is what was still resting when the order was cancelled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CancelReport {
+    pub seq_num: u32,
+    pub order_id: u64,
+    pub quantity: u64,
+    pub timestamp: u64,
+}
+
+/// Answer to `MSG_ADMIN_SNAPSHOT`. `record_count` is the number of WAL
+/// records the snapshot covers, 0 unless `status` is `SNAPSHOT_SAVED`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminSnapshotReply {
+    pub status: u8,
+    pub record_count: u64,
+}
+
+/// A level's total resting quantity and order count after a command changed
+/// it. Quantity 0 means the level is gone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelDelta {
+    pub seq_num: u32,
+    pub side: Side,
+    pub price: i64,
+    pub quantity: u64,
+    pub order_count: u32,
+    pub timestamp: u64,
+}
+
+/// `(price, quantity)` per level, each side best price first. `seq_num` is
+/// the last feed message the depth reflects, so a subscriber applies the
+/// feed from `seq_num + 1`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BookSnapshot {
+    pub seq_num: u32,
+    pub bids: Vec<(i64, u64)>,
+    pub asks: Vec<(i64, u64)>,
+    pub timestamp: u64,
+}
+
+impl BookSnapshot {
+    /// Encoded size of this snapshot.
+    pub fn encoded_size(&self) -> usize {
+        book_snapshot_size(self.bids.len(), self.asks.len())
+    }
+}
+
+pub fn book_snapshot_size(bid_levels: usize, ask_levels: usize) -> usize {
+    BOOK_SNAPSHOT_HEADER_SIZE + (bid_levels + ask_levels) * BOOK_SNAPSHOT_LEVEL_SIZE
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -30,6 +268,30 @@ pub enum ProtocolError {
     UnknownMessageType(u8),
     InvalidSide(u8),
     ZeroQuantity,
+    /// `i64::MIN`, which `Order::try_new` refuses.
+    InvalidPrice(i64),
+    VersionMismatch {
+        expected: u8,
+        got: u8,
+    },
+    InvalidBatchCount(u16),
+    /// A GTD order passed to `encode_batch`; batches carry plain new orders.
+    GtdInBatch,
+    InvalidStatus(u8),
+    InvalidHaltPolicy(u8),
+    /// A length prefix of 0.
+    InvalidFrameLength(u32),
+    /// A message or frame longer than the gateway's `max_message_size`.
+    MessageTooLarge {
+        size: usize,
+        max: usize,
+    },
+    /// The client went away `received` bytes into a message or frame of
+    /// `expected`.
+    TruncatedMessage {
+        received: usize,
+        expected: usize,
+    },
 }
 
 impl std::fmt::Display for ProtocolError {
@@ -39,12 +301,52 @@ impl std::fmt::Display for ProtocolError {
             Self::UnknownMessageType(t) => write!(f, "unknown message type: 0x{t:02x}"),
             Self::InvalidSide(s) => write!(f, "invalid side: {s}"),
             Self::ZeroQuantity => write!(f, "zero quantity"),
+            Self::InvalidPrice(p) => write!(f, "invalid price: {p}"),
+            Self::VersionMismatch { expected, got } => {
+                write!(
+                    f,
+                    "protocol version mismatch: expected {expected}, got {got}"
+                )
+            }
+            Self::InvalidStatus(s) => write!(f, "invalid order status: {s}"),
+            Self::InvalidHaltPolicy(p) => write!(f, "invalid halt policy: {p}"),
+            Self::InvalidFrameLength(n) => write!(f, "invalid frame length {n}"),
+            Self::MessageTooLarge { size, max } => {
+                write!(f, "message of {size} bytes exceeds limit of {max}")
+            }
+            Self::TruncatedMessage { received, expected } => {
+                write!(
+                    f,
+                    "connection closed {received} bytes into a {expected}-byte message"
+                )
+            }
+            Self::InvalidBatchCount(n) => {
+                write!(
+                    f,
+                    "invalid batch count {n}, expected 1..={MAX_BATCH_ORDERS}"
+                )
+            }
+            Self::GtdInBatch => write!(f, "GTD orders can't be sent in a batch"),
         }
     }
 }
 
 impl std::error::Error for ProtocolError {}
 
+/// `Order::try_new` for a decoded order, with its error as a protocol one.
+fn try_order(
+    id: u64,
+    trader_id: u64,
+    side: Side,
+    price: i64,
+    quantity: u64,
+) -> Result<Order, ProtocolError> {
+    Order::try_new(id, trader_id, side, price, quantity, 0).map_err(|e| match e {
+        OrderError::ZeroQuantity => ProtocolError::ZeroQuantity,
+        OrderError::InvalidPrice(p) => ProtocolError::InvalidPrice(p),
+    })
+}
+
 fn read_u8(buf: &[u8], offset: usize) -> Result<u8, ProtocolError> {
     buf.get(offset)
         .copied()
@@ -122,46 +424,80 @@ fn encode_side(side: Side) -> u8 {
     }
 }
 
+fn encode_flags(order: &Order) -> u8 {
+    let mut flags = 0;
+    if order.reduce_only {
+        flags |= ORDER_FLAG_REDUCE_ONLY;
+    }
+    if order.post_only {
+        flags |= ORDER_FLAG_POST_ONLY;
+    }
+    flags
+}
+
+/// Decodes `MSG_NEW_ORDER`, or `MSG_NEW_ORDER_GTD` when the type byte says so.
 pub fn decode_new_order(buf: &[u8]) -> Result<Order, ProtocolError> {
-    if buf.len() < NEW_ORDER_SIZE {
+    let gtd = buf.first() == Some(&MSG_NEW_ORDER_GTD);
+    let size = if gtd {
+        NEW_ORDER_GTD_SIZE
+    } else {
+        NEW_ORDER_SIZE
+    };
+    if buf.len() < size {
         return Err(ProtocolError::BufferTooShort);
     }
 
     let side = decode_side(read_u8(buf, 1)?)?;
+    let flags = read_u8(buf, 2)?;
+    let symbol_id = read_u32(buf, 4)?;
     let order_id = read_u64(buf, 8)?;
     let trader_id = read_u64(buf, 16)?;
     let price = read_i64(buf, 24)?;
     let quantity = read_u64(buf, 32)?;
-
-    if quantity == 0 {
-        return Err(ProtocolError::ZeroQuantity);
-    }
+    let expiry = if gtd {
+        NonZeroU64::new(read_u64(buf, 40)?)
+    } else {
+        None
+    };
 
     Ok(Order {
-        id: order_id,
-        side,
-        trader_id,
-        price,
-        quantity,
-        timestamp: 0,
+        symbol_id,
+        expiry,
+        reduce_only: flags & ORDER_FLAG_REDUCE_ONLY != 0,
+        post_only: flags & ORDER_FLAG_POST_ONLY != 0,
+        ..try_order(order_id, trader_id, side, price, quantity)?
     })
 }
 
+/// Writes `MSG_NEW_ORDER_GTD` if the order has an expiry, else `MSG_NEW_ORDER`.
 pub fn encode_new_order(buf: &mut [u8], order: &Order) -> Result<usize, ProtocolError> {
-    if buf.len() < NEW_ORDER_SIZE {
+    let size = if order.expiry.is_some() {
+        NEW_ORDER_GTD_SIZE
+    } else {
+        NEW_ORDER_SIZE
+    };
+    if buf.len() < size {
         return Err(ProtocolError::BufferTooShort);
     }
 
-    buf[..NEW_ORDER_SIZE].fill(0);
+    buf[..size].fill(0);
 
-    write_u8(buf, 0, MSG_NEW_ORDER)?;
     write_u8(buf, 1, encode_side(order.side))?;
-    write_u64(buf, 8, order.id)?;
-    write_u64(buf, 16, order.trader_id)?;
-    write_i64(buf, 24, order.price)?;
-    write_u64(buf, 32, order.quantity)?;
+    write_u8(buf, 2, encode_flags(order))?;
+    write_u32(buf, 4, order.symbol_id)?;
+    write_u64(buf, 8, order.id.0)?;
+    write_u64(buf, 16, order.trader_id.0)?;
+    write_i64(buf, 24, order.price.0)?;
+    write_u64(buf, 32, order.quantity.0)?;
+    match order.expiry {
+        Some(expiry) => {
+            write_u8(buf, 0, MSG_NEW_ORDER_GTD)?;
+            write_u64(buf, 40, expiry.get())?;
+        }
+        None => write_u8(buf, 0, MSG_NEW_ORDER)?,
+    }
 
-    Ok(NEW_ORDER_SIZE)
+    Ok(size)
 }
 
 pub fn decode_cancel_order(buf: &[u8]) -> Result<u64, ProtocolError> {
@@ -185,21 +521,302 @@ pub fn encode_cancel_order(buf: &mut [u8], order_id: u64) -> Result<usize, Proto
     Ok(CANCEL_ORDER_SIZE)
 }
 
+pub fn decode_cancel_all(buf: &[u8]) -> Result<u64, ProtocolError> {
+    if buf.len() < CANCEL_ALL_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    read_u64(buf, 8)
+}
+
+pub fn encode_cancel_all(buf: &mut [u8], trader_id: u64) -> Result<usize, ProtocolError> {
+    if buf.len() < CANCEL_ALL_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..CANCEL_ALL_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_CANCEL_ALL)?;
+    write_u64(buf, 8, trader_id)?;
+
+    Ok(CANCEL_ALL_SIZE)
+}
+
+pub fn decode_reduce_order(buf: &[u8]) -> Result<(u64, u64), ProtocolError> {
+    if buf.len() < REDUCE_ORDER_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    Ok((read_u64(buf, 8)?, read_u64(buf, 16)?))
+}
+
+pub fn encode_reduce_order(
+    buf: &mut [u8],
+    order_id: u64,
+    reduce_by: u64,
+) -> Result<usize, ProtocolError> {
+    if buf.len() < REDUCE_ORDER_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..REDUCE_ORDER_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_REDUCE_ORDER)?;
+    write_u64(buf, 8, order_id)?;
+    write_u64(buf, 16, reduce_by)?;
+
+    Ok(REDUCE_ORDER_SIZE)
+}
+
+pub fn decode_halt(buf: &[u8]) -> Result<HaltPolicy, ProtocolError> {
+    if buf.len() < HALT_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    match read_u8(buf, 1)? {
+        0 => Ok(HaltPolicy::RejectMarketable),
+        1 => Ok(HaltPolicy::RejectAll),
+        other => Err(ProtocolError::InvalidHaltPolicy(other)),
+    }
+}
+
+pub fn encode_halt(buf: &mut [u8], policy: HaltPolicy) -> Result<usize, ProtocolError> {
+    if buf.len() < HALT_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..HALT_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_HALT)?;
+    let policy = match policy {
+        HaltPolicy::RejectMarketable => 0,
+        HaltPolicy::RejectAll => 1,
+    };
+    write_u8(buf, 1, policy)?;
+
+    Ok(HALT_SIZE)
+}
+
+pub fn encode_resume(buf: &mut [u8]) -> Result<usize, ProtocolError> {
+    if buf.len() < RESUME_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..RESUME_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_RESUME)?;
+
+    Ok(RESUME_SIZE)
+}
+
+pub fn encode_request_snapshot(buf: &mut [u8]) -> Result<usize, ProtocolError> {
+    if buf.len() < REQUEST_SNAPSHOT_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..REQUEST_SNAPSHOT_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_REQUEST_SNAPSHOT)?;
+
+    Ok(REQUEST_SNAPSHOT_SIZE)
+}
+
+pub fn encode_admin_snapshot(buf: &mut [u8]) -> Result<usize, ProtocolError> {
+    if buf.len() < ADMIN_SNAPSHOT_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..ADMIN_SNAPSHOT_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_ADMIN_SNAPSHOT)?;
+
+    Ok(ADMIN_SNAPSHOT_SIZE)
+}
+
+/// Decodes `MSG_CANCEL_REPLACE`, or `MSG_CANCEL_REPLACE_GTD` when the type byte says so.
+pub fn decode_cancel_replace(buf: &[u8]) -> Result<(u64, Order), ProtocolError> {
+    let gtd = buf.first() == Some(&MSG_CANCEL_REPLACE_GTD);
+    let size = if gtd {
+        CANCEL_REPLACE_GTD_SIZE
+    } else {
+        CANCEL_REPLACE_SIZE
+    };
+    if buf.len() < size {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    let side = decode_side(read_u8(buf, 1)?)?;
+    let flags = read_u8(buf, 2)?;
+    let symbol_id = read_u32(buf, 4)?;
+    let old_id = read_u64(buf, 8)?;
+    let order_id = read_u64(buf, 16)?;
+    let trader_id = read_u64(buf, 24)?;
+    let price = read_i64(buf, 32)?;
+    let quantity = read_u64(buf, 40)?;
+    let expiry = if gtd {
+        NonZeroU64::new(read_u64(buf, 48)?)
+    } else {
+        None
+    };
+
+    Ok((
+        old_id,
+        Order {
+            symbol_id,
+            expiry,
+            reduce_only: flags & ORDER_FLAG_REDUCE_ONLY != 0,
+            post_only: flags & ORDER_FLAG_POST_ONLY != 0,
+            ..try_order(order_id, trader_id, side, price, quantity)?
+        },
+    ))
+}
+
+pub fn encode_cancel_replace(
+    buf: &mut [u8],
+    old_id: u64,
+    new_order: &Order,
+) -> Result<usize, ProtocolError> {
+    let size = if new_order.expiry.is_some() {
+        CANCEL_REPLACE_GTD_SIZE
+    } else {
+        CANCEL_REPLACE_SIZE
+    };
+    if buf.len() < size {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..size].fill(0);
+
+    write_u8(buf, 1, encode_side(new_order.side))?;
+    write_u8(buf, 2, encode_flags(new_order))?;
+    write_u32(buf, 4, new_order.symbol_id)?;
+    write_u64(buf, 8, old_id)?;
+    write_u64(buf, 16, new_order.id.0)?;
+    write_u64(buf, 24, new_order.trader_id.0)?;
+    write_i64(buf, 32, new_order.price.0)?;
+    write_u64(buf, 40, new_order.quantity.0)?;
+    match new_order.expiry {
+        Some(expiry) => {
+            write_u8(buf, 0, MSG_CANCEL_REPLACE_GTD)?;
+            write_u64(buf, 48, expiry.get())?;
+        }
+        None => write_u8(buf, 0, MSG_CANCEL_REPLACE)?,
+    }
+
+    Ok(size)
+}
+
 pub fn decode_message(buf: &[u8]) -> Result<EngineCommand, ProtocolError> {
     let msg_type = read_u8(buf, 0)?;
     match msg_type {
-        MSG_NEW_ORDER => Ok(EngineCommand::NewOrder(decode_new_order(buf)?)),
+        MSG_NEW_ORDER | MSG_NEW_ORDER_GTD => Ok(EngineCommand::NewOrder(decode_new_order(buf)?)),
         MSG_CANCEL_ORDER => Ok(EngineCommand::CancelOrder {
             order_id: decode_cancel_order(buf)?,
         }),
+        MSG_CANCEL_REPLACE | MSG_CANCEL_REPLACE_GTD => {
+            let (old_id, new_order) = decode_cancel_replace(buf)?;
+            Ok(EngineCommand::CancelReplace { old_id, new_order })
+        }
+        MSG_CANCEL_ALL => Ok(EngineCommand::CancelAll {
+            trader_id: decode_cancel_all(buf)?,
+        }),
+        MSG_REDUCE_ORDER => {
+            let (order_id, reduce_by) = decode_reduce_order(buf)?;
+            Ok(EngineCommand::ReduceOrder {
+                order_id,
+                reduce_by,
+            })
+        }
+        MSG_HALT => Ok(EngineCommand::Halt {
+            policy: decode_halt(buf)?,
+        }),
+        MSG_RESUME if buf.len() >= RESUME_SIZE => Ok(EngineCommand::Resume),
+        MSG_RESUME => Err(ProtocolError::BufferTooShort),
+        MSG_REQUEST_SNAPSHOT if buf.len() >= REQUEST_SNAPSHOT_SIZE => {
+            Ok(EngineCommand::RequestSnapshot)
+        }
+        MSG_REQUEST_SNAPSHOT => Err(ProtocolError::BufferTooShort),
+        MSG_ADMIN_SNAPSHOT if buf.len() >= ADMIN_SNAPSHOT_SIZE => Ok(EngineCommand::AdminSnapshot),
+        MSG_ADMIN_SNAPSHOT => Err(ProtocolError::BufferTooShort),
         other => Err(ProtocolError::UnknownMessageType(other)),
     }
 }
 
+/// Encodes `orders` as one `MSG_BATCH`. GTD orders can't be batched and are
+/// rejected with `GtdInBatch`.
+pub fn encode_batch(buf: &mut [u8], orders: &[Order]) -> Result<usize, ProtocolError> {
+    let count = u16::try_from(orders.len()).unwrap_or(u16::MAX);
+    if orders.is_empty() || orders.len() > MAX_BATCH_ORDERS {
+        return Err(ProtocolError::InvalidBatchCount(count));
+    }
+    if orders.iter().any(|o| o.expiry.is_some()) {
+        return Err(ProtocolError::GtdInBatch);
+    }
+    let size = BATCH_HEADER_SIZE + orders.len() * NEW_ORDER_SIZE;
+    if buf.len() < size {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..BATCH_HEADER_SIZE].fill(0);
+    write_u8(buf, 0, MSG_BATCH)?;
+    buf[2..4].copy_from_slice(&count.to_le_bytes());
+    for (i, order) in orders.iter().enumerate() {
+        let start = BATCH_HEADER_SIZE + i * NEW_ORDER_SIZE;
+        encode_new_order(&mut buf[start..], order)?;
+    }
+
+    Ok(size)
+}
+
+/// Total size of a `MSG_BATCH` given its header.
+pub fn batch_size(header: &[u8]) -> Result<usize, ProtocolError> {
+    let count: [u8; 2] = header
+        .get(2..4)
+        .ok_or(ProtocolError::BufferTooShort)?
+        .try_into()
+        .map_err(|_| ProtocolError::BufferTooShort)?;
+    let count = u16::from_le_bytes(count);
+    if count == 0 || count as usize > MAX_BATCH_ORDERS {
+        return Err(ProtocolError::InvalidBatchCount(count));
+    }
+    Ok(BATCH_HEADER_SIZE + count as usize * NEW_ORDER_SIZE)
+}
+
+pub fn decode_batch(buf: &[u8]) -> Result<Vec<EngineCommand>, ProtocolError> {
+    let msg_type = read_u8(buf, 0)?;
+    if msg_type != MSG_BATCH {
+        return Err(ProtocolError::UnknownMessageType(msg_type));
+    }
+    let size = batch_size(buf)?;
+    if buf.len() < size {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[BATCH_HEADER_SIZE..size]
+        .chunks_exact(NEW_ORDER_SIZE)
+        .map(|msg| match msg[0] {
+            MSG_NEW_ORDER => decode_new_order(msg).map(EngineCommand::NewOrder),
+            other => Err(ProtocolError::UnknownMessageType(other)),
+        })
+        .collect()
+}
+
+/// Size of a message from its type byte. For `MSG_BATCH` this is only the
+/// header; `batch_size` gives the rest.
 pub fn message_size(msg_type: u8) -> Result<usize, ProtocolError> {
     match msg_type {
         MSG_NEW_ORDER => Ok(NEW_ORDER_SIZE),
         MSG_CANCEL_ORDER => Ok(CANCEL_ORDER_SIZE),
+        MSG_CANCEL_REPLACE => Ok(CANCEL_REPLACE_SIZE),
+        MSG_NEW_ORDER_GTD => Ok(NEW_ORDER_GTD_SIZE),
+        MSG_CANCEL_REPLACE_GTD => Ok(CANCEL_REPLACE_GTD_SIZE),
+        MSG_BATCH => Ok(BATCH_HEADER_SIZE),
+        MSG_CANCEL_ALL => Ok(CANCEL_ALL_SIZE),
+        MSG_REDUCE_ORDER => Ok(REDUCE_ORDER_SIZE),
+        MSG_HALT => Ok(HALT_SIZE),
+        MSG_RESUME => Ok(RESUME_SIZE),
+        MSG_REQUEST_SNAPSHOT => Ok(REQUEST_SNAPSHOT_SIZE),
+        MSG_ADMIN_SNAPSHOT => Ok(ADMIN_SNAPSHOT_SIZE),
         _ => Err(ProtocolError::UnknownMessageType(msg_type)),
     }
 }
@@ -217,12 +834,15 @@ pub fn encode_execution_report(
     buf[..EXECUTION_REPORT_SIZE].fill(0);
 
     write_u8(buf, 0, MSG_EXECUTION_REPORT)?;
+    write_u8(buf, 1, PROTOCOL_VERSION)?;
     write_u32(buf, 4, seq_num)?;
     write_u64(buf, 8, fill.taker_order_id)?;
     write_u64(buf, 16, fill.maker_order_id)?;
     write_i64(buf, 24, fill.price)?;
     write_u64(buf, 32, fill.quantity)?;
     write_u64(buf, 40, timestamp)?;
+    write_u64(buf, 48, fill.taker_seq)?;
+    write_u64(buf, 56, fill.maker_seq)?;
 
     Ok(EXECUTION_REPORT_SIZE)
 }
@@ -231,15 +851,443 @@ pub fn decode_execution_report(buf: &[u8]) -> Result<ExecutionReport, ProtocolEr
     if buf.len() < EXECUTION_REPORT_SIZE {
         return Err(ProtocolError::BufferTooShort);
     }
+    check_feed_header(buf, MSG_EXECUTION_REPORT)?;
+
+    Ok(ExecutionReport {
+        seq_num: read_u32(buf, 4)?,
+        taker_order_id: read_u64(buf, 8)?,
+        maker_order_id: read_u64(buf, 16)?,
+        price: read_i64(buf, 24)?,
+        quantity: read_u64(buf, 32)?,
+        timestamp: read_u64(buf, 40)?,
+        taker_seq: read_u64(buf, 48)?,
+        maker_seq: read_u64(buf, 56)?,
+    })
+}
+
+pub fn encode_book_update(buf: &mut [u8], update: &BookUpdate) -> Result<usize, ProtocolError> {
+    if buf.len() < BOOK_UPDATE_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..BOOK_UPDATE_SIZE].fill(0);
+
+    let (bid_price, bid_qty) = update.best_bid.unwrap_or_default();
+    let (ask_price, ask_qty) = update.best_ask.unwrap_or_default();
+    write_u8(buf, 0, MSG_BOOK_UPDATE)?;
+    write_u8(buf, 1, PROTOCOL_VERSION)?;
+    write_u32(buf, 4, update.seq_num)?;
+    write_i64(buf, 8, bid_price)?;
+    write_u64(buf, 16, bid_qty)?;
+    write_i64(buf, 24, ask_price)?;
+    write_u64(buf, 32, ask_qty)?;
+    write_u64(buf, 40, update.timestamp)?;
+
+    Ok(BOOK_UPDATE_SIZE)
+}
+
+pub fn decode_book_update(buf: &[u8]) -> Result<BookUpdate, ProtocolError> {
+    if buf.len() < BOOK_UPDATE_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+    check_feed_header(buf, MSG_BOOK_UPDATE)?;
+
+    let level = |price, qty| (qty != 0).then_some((price, qty));
+    Ok(BookUpdate {
+        seq_num: read_u32(buf, 4)?,
+        best_bid: level(read_i64(buf, 8)?, read_u64(buf, 16)?),
+        best_ask: level(read_i64(buf, 24)?, read_u64(buf, 32)?),
+        timestamp: read_u64(buf, 40)?,
+    })
+}
+
+pub fn encode_reject(buf: &mut [u8], reject: &Reject) -> Result<usize, ProtocolError> {
+    if buf.len() < REJECT_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..REJECT_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_REJECT)?;
+    write_u8(buf, 1, PROTOCOL_VERSION)?;
+    write_u8(buf, 2, reject.reason)?;
+    write_u64(buf, 8, reject.order_id)?;
+
+    Ok(REJECT_SIZE)
+}
+
+pub fn decode_reject(buf: &[u8]) -> Result<Reject, ProtocolError> {
+    if buf.len() < REJECT_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+    check_feed_header(buf, MSG_REJECT)?;
+
+    Ok(Reject {
+        order_id: read_u64(buf, 8)?,
+        reason: read_u8(buf, 2)?,
+    })
+}
+
+pub fn encode_admin_snapshot_reply(
+    buf: &mut [u8],
+    reply: &AdminSnapshotReply,
+) -> Result<usize, ProtocolError> {
+    if buf.len() < ADMIN_SNAPSHOT_REPLY_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..ADMIN_SNAPSHOT_REPLY_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_ADMIN_SNAPSHOT_REPLY)?;
+    write_u8(buf, 1, PROTOCOL_VERSION)?;
+    write_u8(buf, 2, reply.status)?;
+    write_u64(buf, 8, reply.record_count)?;
+
+    Ok(ADMIN_SNAPSHOT_REPLY_SIZE)
+}
+
+pub fn decode_admin_snapshot_reply(buf: &[u8]) -> Result<AdminSnapshotReply, ProtocolError> {
+    if buf.len() < ADMIN_SNAPSHOT_REPLY_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+    check_feed_header(buf, MSG_ADMIN_SNAPSHOT_REPLY)?;
+
+    Ok(AdminSnapshotReply {
+        status: read_u8(buf, 2)?,
+        record_count: read_u64(buf, 8)?,
+    })
+}
+
+/// Stable reject reason code for an engine error.
+pub fn reject_reason(err: &MatchingError) -> u8 {
+    match err {
+        MatchingError::Book(BookError::DuplicateOrderId(_))
+        | MatchingError::DuplicateOrderId(_) => REJECT_DUPLICATE_ORDER_ID,
+        MatchingError::Book(BookError::OrderNotFound(_)) => REJECT_ORDER_NOT_FOUND,
+        MatchingError::Book(BookError::ArenaFull) => REJECT_ARENA_FULL,
+        MatchingError::Book(BookError::QuantityOverflow) => REJECT_QUANTITY_OVERFLOW,
+        MatchingError::Book(
+            BookError::PriceLevelNotFound(_)
+            | BookError::FillExceedsQuantity { .. }
+            | BookError::LevelMismatch { .. }
+            | BookError::Crossed { .. },
+        ) => REJECT_INTERNAL,
+        MatchingError::ZeroQuantity => REJECT_ZERO_QUANTITY,
+        MatchingError::InvalidTick { .. } => REJECT_INVALID_TICK,
+        MatchingError::PriceBandViolation { .. } => REJECT_PRICE_BAND,
+        MatchingError::QuantityLimitExceeded { .. } => REJECT_QUANTITY_LIMIT,
+        MatchingError::NotionalLimitExceeded { .. } => REJECT_NOTIONAL_LIMIT,
+        MatchingError::TraderOrderLimitExceeded { .. } => REJECT_TRADER_ORDER_LIMIT,
+        MatchingError::Halted => REJECT_HALTED,
+        MatchingError::ReduceExceedsQuantity { .. } => REJECT_REDUCE_EXCEEDS_QUANTITY,
+        MatchingError::WrongSymbol { .. } => REJECT_WRONG_SYMBOL,
+    }
+}
+
+pub fn encode_order_reject(buf: &mut [u8], reject: &OrderReject) -> Result<usize, ProtocolError> {
+    if buf.len() < ORDER_REJECT_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..ORDER_REJECT_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_ORDER_REJECT)?;
+    write_u8(buf, 1, PROTOCOL_VERSION)?;
+    write_u8(buf, 2, reject.reason)?;
+    write_u32(buf, 4, reject.seq_num)?;
+    write_u64(buf, 8, reject.order_id)?;
+    write_u64(buf, 16, reject.timestamp)?;
+
+    Ok(ORDER_REJECT_SIZE)
+}
+
+pub fn decode_order_reject(buf: &[u8]) -> Result<OrderReject, ProtocolError> {
+    if buf.len() < ORDER_REJECT_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+    check_feed_header(buf, MSG_ORDER_REJECT)?;
+
+    Ok(OrderReject {
+        seq_num: read_u32(buf, 4)?,
+        order_id: read_u64(buf, 8)?,
+        reason: read_u8(buf, 2)?,
+        timestamp: read_u64(buf, 16)?,
+    })
+}
+
+fn encode_status(status: OrderStatus) -> u8 {
+    match status {
+        OrderStatus::FullyFilled => 1,
+        OrderStatus::PartiallyFilled => 2,
+        OrderStatus::Resting => 3,
+        OrderStatus::CancelledSelfTrade => 4,
+        OrderStatus::ReduceOnlyClamped => 5,
+        OrderStatus::RejectedPostOnly => 6,
+    }
+}
+
+fn decode_status(val: u8) -> Result<OrderStatus, ProtocolError> {
+    match val {
+        1 => Ok(OrderStatus::FullyFilled),
+        2 => Ok(OrderStatus::PartiallyFilled),
+        3 => Ok(OrderStatus::Resting),
+        4 => Ok(OrderStatus::CancelledSelfTrade),
+        5 => Ok(OrderStatus::ReduceOnlyClamped),
+        6 => Ok(OrderStatus::RejectedPostOnly),
+        _ => Err(ProtocolError::InvalidStatus(val)),
+    }
+}
+
+pub fn encode_order_ack(buf: &mut [u8], ack: &OrderAck) -> Result<usize, ProtocolError> {
+    if buf.len() < ORDER_ACK_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..ORDER_ACK_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_ORDER_ACK)?;
+    write_u8(buf, 1, PROTOCOL_VERSION)?;
+    write_u8(buf, 2, encode_status(ack.status))?;
+    write_u8(buf, 3, ack.became_best as u8)?;
+    write_u32(buf, 4, ack.seq_num)?;
+    write_u64(buf, 8, ack.order_id)?;
+    write_u64(buf, 16, ack.timestamp)?;
+
+    Ok(ORDER_ACK_SIZE)
+}
+
+pub fn decode_order_ack(buf: &[u8]) -> Result<OrderAck, ProtocolError> {
+    if buf.len() < ORDER_ACK_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+    check_feed_header(buf, MSG_ORDER_ACK)?;
+
+    Ok(OrderAck {
+        seq_num: read_u32(buf, 4)?,
+        order_id: read_u64(buf, 8)?,
+        status: decode_status(read_u8(buf, 2)?)?,
+        became_best: read_u8(buf, 3)? != 0,
+        timestamp: read_u64(buf, 16)?,
+    })
+}
+
+pub fn encode_agg_trade(buf: &mut [u8], trade: &AggTrade) -> Result<usize, ProtocolError> {
+    if buf.len() < AGG_TRADE_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..AGG_TRADE_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_AGG_TRADE)?;
+    write_u8(buf, 1, PROTOCOL_VERSION)?;
+    write_u32(buf, 4, trade.seq_num)?;
+    write_u64(buf, 8, trade.taker_order_id)?;
+    write_i64(buf, 16, trade.price)?;
+    write_u64(buf, 24, trade.quantity)?;
+    write_u32(buf, 32, trade.fill_count)?;
+    write_u64(buf, 40, trade.timestamp)?;
+
+    Ok(AGG_TRADE_SIZE)
+}
+
+pub fn decode_agg_trade(buf: &[u8]) -> Result<AggTrade, ProtocolError> {
+    if buf.len() < AGG_TRADE_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+    check_feed_header(buf, MSG_AGG_TRADE)?;
+
+    Ok(AggTrade {
+        seq_num: read_u32(buf, 4)?,
+        taker_order_id: read_u64(buf, 8)?,
+        price: read_i64(buf, 16)?,
+        quantity: read_u64(buf, 24)?,
+        fill_count: read_u32(buf, 32)?,
+        timestamp: read_u64(buf, 40)?,
+    })
+}
+
+pub fn encode_cancel_report(buf: &mut [u8], report: &CancelReport) -> Result<usize, ProtocolError> {
+    if buf.len() < CANCEL_REPORT_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..CANCEL_REPORT_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_CANCEL_REPORT)?;
+    write_u8(buf, 1, PROTOCOL_VERSION)?;
+    write_u32(buf, 4, report.seq_num)?;
+    write_u64(buf, 8, report.order_id)?;
+    write_u64(buf, 16, report.quantity)?;
+    write_u64(buf, 24, report.timestamp)?;
+
+    Ok(CANCEL_REPORT_SIZE)
+}
+
+pub fn decode_cancel_report(buf: &[u8]) -> Result<CancelReport, ProtocolError> {
+    if buf.len() < CANCEL_REPORT_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+    check_feed_header(buf, MSG_CANCEL_REPORT)?;
+
+    Ok(CancelReport {
+        seq_num: read_u32(buf, 4)?,
+        order_id: read_u64(buf, 8)?,
+        quantity: read_u64(buf, 16)?,
+        timestamp: read_u64(buf, 24)?,
+    })
+}
+
+pub fn encode_level_delta(buf: &mut [u8], delta: &LevelDelta) -> Result<usize, ProtocolError> {
+    if buf.len() < LEVEL_DELTA_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..LEVEL_DELTA_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_LEVEL_DELTA)?;
+    write_u8(buf, 1, PROTOCOL_VERSION)?;
+    write_u8(buf, 2, encode_side(delta.side))?;
+    write_u32(buf, 4, delta.seq_num)?;
+    write_i64(buf, 8, delta.price)?;
+    write_u64(buf, 16, delta.quantity)?;
+    write_u32(buf, 24, delta.order_count)?;
+    write_u64(buf, 32, delta.timestamp)?;
+
+    Ok(LEVEL_DELTA_SIZE)
+}
+
+pub fn decode_level_delta(buf: &[u8]) -> Result<LevelDelta, ProtocolError> {
+    if buf.len() < LEVEL_DELTA_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+    check_feed_header(buf, MSG_LEVEL_DELTA)?;
+
+    Ok(LevelDelta {
+        seq_num: read_u32(buf, 4)?,
+        side: decode_side(read_u8(buf, 2)?)?,
+        price: read_i64(buf, 8)?,
+        quantity: read_u64(buf, 16)?,
+        order_count: read_u32(buf, 24)?,
+        timestamp: read_u64(buf, 32)?,
+    })
+}
+
+/// Layout: header with seq_num at 4, bid and ask level counts (u32) at 8 and
+/// 12 and timestamp at 16, then the bid levels and the ask levels.
+pub fn encode_book_snapshot(
+    buf: &mut [u8],
+    snapshot: &BookSnapshot,
+) -> Result<usize, ProtocolError> {
+    let size = snapshot.encoded_size();
+    if buf.len() < size {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    buf[..BOOK_SNAPSHOT_HEADER_SIZE].fill(0);
+
+    write_u8(buf, 0, MSG_BOOK_SNAPSHOT)?;
+    write_u8(buf, 1, PROTOCOL_VERSION)?;
+    write_u32(buf, 4, snapshot.seq_num)?;
+    write_u32(buf, 8, snapshot.bids.len() as u32)?;
+    write_u32(buf, 12, snapshot.asks.len() as u32)?;
+    write_u64(buf, 16, snapshot.timestamp)?;
+    let levels = snapshot.bids.iter().chain(&snapshot.asks);
+    for (i, &(price, quantity)) in levels.enumerate() {
+        let offset = BOOK_SNAPSHOT_HEADER_SIZE + i * BOOK_SNAPSHOT_LEVEL_SIZE;
+        write_i64(buf, offset, price)?;
+        write_u64(buf, offset + 8, quantity)?;
+    }
+
+    Ok(size)
+}
+
+pub fn decode_book_snapshot(buf: &[u8]) -> Result<BookSnapshot, ProtocolError> {
+    if buf.len() < BOOK_SNAPSHOT_HEADER_SIZE {
+        return Err(ProtocolError::BufferTooShort);
+    }
+    check_feed_header(buf, MSG_BOOK_SNAPSHOT)?;
+
+    let bid_levels = read_u32(buf, 8)? as usize;
+    let ask_levels = read_u32(buf, 12)? as usize;
+    let size = bid_levels
+        .checked_add(ask_levels)
+        .and_then(|n| n.checked_mul(BOOK_SNAPSHOT_LEVEL_SIZE))
+        .and_then(|n| n.checked_add(BOOK_SNAPSHOT_HEADER_SIZE))
+        .ok_or(ProtocolError::BufferTooShort)?;
+    if buf.len() < size {
+        return Err(ProtocolError::BufferTooShort);
+    }
+
+    let mut levels = buf[BOOK_SNAPSHOT_HEADER_SIZE..size]
+        .chunks_exact(BOOK_SNAPSHOT_LEVEL_SIZE)
+        .map(|level| Ok((read_i64(level, 0)?, read_u64(level, 8)?)));
+    let bids = levels.by_ref().take(bid_levels).collect::<Result<_, _>>()?;
+    let asks = levels.collect::<Result<_, _>>()?;
+    Ok(BookSnapshot {
+        seq_num: read_u32(buf, 4)?,
+        bids,
+        asks,
+        timestamp: read_u64(buf, 16)?,
+    })
+}
+
+/// Runs every decoder over `data`, for fuzz targets. Decoders only return
+/// errors on bad input, so this panics exactly when one of them would read
+/// out of bounds or otherwise misbehave. A command that decodes must also
+/// decode the same from its own re-encoding.
+#[cfg(any(test, feature = "fuzzing"))]
+pub fn fuzz_decode(data: &[u8]) {
+    if let Ok(cmd) = decode_message(data) {
+        let mut buf = [0u8; MAX_COMMAND_SIZE];
+        let n = match &cmd {
+            EngineCommand::NewOrder(order) => encode_new_order(&mut buf, order),
+            EngineCommand::CancelOrder { order_id } => encode_cancel_order(&mut buf, *order_id),
+            EngineCommand::CancelReplace { old_id, new_order } => {
+                encode_cancel_replace(&mut buf, *old_id, new_order)
+            }
+            EngineCommand::CancelAll { trader_id } => encode_cancel_all(&mut buf, *trader_id),
+            EngineCommand::ReduceOrder {
+                order_id,
+                reduce_by,
+            } => encode_reduce_order(&mut buf, *order_id, *reduce_by),
+            EngineCommand::Halt { policy } => encode_halt(&mut buf, *policy),
+            EngineCommand::Resume => encode_resume(&mut buf),
+            EngineCommand::RequestSnapshot => encode_request_snapshot(&mut buf),
+            EngineCommand::AdminSnapshot => encode_admin_snapshot(&mut buf),
+        }
+        .expect("a decoded command re-encodes");
+        assert_eq!(decode_message(&buf[..n]).ok(), Some(cmd));
+    }
+    if let Some(&msg_type) = data.first() {
+        let _ = message_size(msg_type);
+    }
+    let _ = batch_size(data);
+    let _ = decode_batch(data);
+    let _ = decode_execution_report(data);
+    let _ = decode_book_update(data);
+    let _ = decode_reject(data);
+    let _ = decode_order_reject(data);
+    let _ = decode_order_ack(data);
+    let _ = decode_agg_trade(data);
+    let _ = decode_cancel_report(data);
+    let _ = decode_book_snapshot(data);
+    let _ = decode_level_delta(data);
+    let _ = decode_admin_snapshot_reply(data);
+}
 
-    Ok(ExecutionReport {
-        seq_num: read_u32(buf, 4)?,
-        taker_order_id: read_u64(buf, 8)?,
-        maker_order_id: read_u64(buf, 16)?,
-        price: read_i64(buf, 24)?,
-        quantity: read_u64(buf, 32)?,
-        timestamp: read_u64(buf, 40)?,
-    })
+fn check_feed_header(buf: &[u8], msg_type: u8) -> Result<(), ProtocolError> {
+    let got_type = read_u8(buf, 0)?;
+    if got_type != msg_type {
+        return Err(ProtocolError::UnknownMessageType(got_type));
+    }
+    let version = read_u8(buf, 1)?;
+    if version != PROTOCOL_VERSION {
+        return Err(ProtocolError::VersionMismatch {
+            expected: PROTOCOL_VERSION,
+            got: version,
+        });
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -250,12 +1298,16 @@ mod tests {
     #[test]
     fn roundtrip_new_order_bid() {
         let order = Order {
-            id: 42,
-            trader_id: 7,
+            id: 42.into(),
+            trader_id: 7.into(),
+            symbol_id: 12,
             side: Side::Bid,
-            price: 15005,
-            quantity: 100,
+            price: 15005.into(),
+            quantity: 100.into(),
             timestamp: 0,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
         };
 
         let mut buf = [0u8; NEW_ORDER_SIZE];
@@ -264,6 +1316,8 @@ mod tests {
         let decoded = decode_new_order(&buf).unwrap();
         assert_eq!(decoded.id, 42);
         assert_eq!(decoded.trader_id, 7);
+        assert_eq!(decoded.symbol_id, 12);
+        assert_eq!(buf[4..8], 12u32.to_le_bytes());
         assert_eq!(decoded.side, Side::Bid);
         assert_eq!(decoded.price, 15005);
         assert_eq!(decoded.quantity, 100);
@@ -273,12 +1327,16 @@ mod tests {
     #[test]
     fn roundtrip_new_order_ask() {
         let order = Order {
-            id: 99,
-            trader_id: 3,
+            id: 99.into(),
+            trader_id: 3.into(),
+            symbol_id: 0,
             side: Side::Ask,
-            price: -500,
-            quantity: 1,
+            price: (-500).into(),
+            quantity: 1.into(),
             timestamp: 0,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
         };
 
         let mut buf = [0u8; NEW_ORDER_SIZE];
@@ -299,6 +1357,97 @@ mod tests {
         assert_eq!(order_id, 12345);
     }
 
+    #[test]
+    fn roundtrip_cancel_all() {
+        let mut buf = [0u8; CANCEL_ALL_SIZE];
+        assert_eq!(encode_cancel_all(&mut buf, 77).unwrap(), CANCEL_ALL_SIZE);
+        assert_eq!(message_size(buf[0]), Ok(CANCEL_ALL_SIZE));
+        assert_eq!(
+            decode_message(&buf),
+            Ok(EngineCommand::CancelAll { trader_id: 77 })
+        );
+    }
+
+    #[test]
+    fn roundtrip_reduce_order() {
+        let mut buf = [0u8; REDUCE_ORDER_SIZE];
+        assert_eq!(
+            encode_reduce_order(&mut buf, 42, 7).unwrap(),
+            REDUCE_ORDER_SIZE
+        );
+        assert_eq!(message_size(buf[0]), Ok(REDUCE_ORDER_SIZE));
+        assert_eq!(
+            decode_message(&buf),
+            Ok(EngineCommand::ReduceOrder {
+                order_id: 42,
+                reduce_by: 7,
+            })
+        );
+        assert_eq!(
+            decode_message(&buf[..REDUCE_ORDER_SIZE - 1]),
+            Err(ProtocolError::BufferTooShort)
+        );
+    }
+
+    #[test]
+    fn roundtrip_halt_and_resume() {
+        let mut buf = [0u8; HALT_SIZE];
+        for policy in [HaltPolicy::RejectMarketable, HaltPolicy::RejectAll] {
+            assert_eq!(encode_halt(&mut buf, policy).unwrap(), HALT_SIZE);
+            assert_eq!(decode_message(&buf), Ok(EngineCommand::Halt { policy }));
+        }
+        buf[1] = 9;
+        assert_eq!(
+            decode_message(&buf),
+            Err(ProtocolError::InvalidHaltPolicy(9))
+        );
+
+        assert_eq!(encode_resume(&mut buf).unwrap(), RESUME_SIZE);
+        assert_eq!(message_size(buf[0]), Ok(RESUME_SIZE));
+        assert_eq!(decode_message(&buf), Ok(EngineCommand::Resume));
+        assert_eq!(
+            decode_message(&buf[..1]),
+            Err(ProtocolError::BufferTooShort)
+        );
+    }
+
+    #[test]
+    fn roundtrip_request_snapshot() {
+        let mut buf = [0u8; REQUEST_SNAPSHOT_SIZE];
+        assert_eq!(
+            encode_request_snapshot(&mut buf).unwrap(),
+            REQUEST_SNAPSHOT_SIZE
+        );
+        assert_eq!(message_size(buf[0]), Ok(REQUEST_SNAPSHOT_SIZE));
+        assert_eq!(decode_message(&buf), Ok(EngineCommand::RequestSnapshot));
+        assert_eq!(
+            decode_message(&buf[..4]),
+            Err(ProtocolError::BufferTooShort)
+        );
+    }
+
+    #[test]
+    fn roundtrip_admin_snapshot() {
+        let mut buf = [0u8; ADMIN_SNAPSHOT_SIZE];
+        assert_eq!(
+            encode_admin_snapshot(&mut buf).unwrap(),
+            ADMIN_SNAPSHOT_SIZE
+        );
+        assert_eq!(message_size(buf[0]), Ok(ADMIN_SNAPSHOT_SIZE));
+        assert_eq!(decode_message(&buf), Ok(EngineCommand::AdminSnapshot));
+
+        let reply = AdminSnapshotReply {
+            status: SNAPSHOT_SAVED,
+            record_count: 1_234,
+        };
+        let mut buf = [0u8; ADMIN_SNAPSHOT_REPLY_SIZE];
+        assert_eq!(
+            encode_admin_snapshot_reply(&mut buf, &reply).unwrap(),
+            ADMIN_SNAPSHOT_REPLY_SIZE
+        );
+        assert_eq!(decode_admin_snapshot_reply(&buf).unwrap(), reply);
+    }
+
     #[test]
     fn roundtrip_execution_report() {
         let fill = Fill {
@@ -307,6 +1456,8 @@ mod tests {
             price: 9999,
             quantity: 50,
             maker_fully_filled: true,
+            taker_seq: 7,
+            maker_seq: 3,
         };
 
         let mut buf = [0u8; EXECUTION_REPORT_SIZE];
@@ -319,6 +1470,293 @@ mod tests {
         assert_eq!(report.price, 9999);
         assert_eq!(report.quantity, 50);
         assert_eq!(report.timestamp, 123_456_789);
+        assert_eq!((report.taker_seq, report.maker_seq), (7, 3));
+    }
+
+    #[test]
+    fn execution_report_version_mismatch_rejected() {
+        let fill = Fill {
+            taker_order_id: 10,
+            maker_order_id: 20,
+            price: 9999,
+            quantity: 50,
+            maker_fully_filled: true,
+            taker_seq: 0,
+            maker_seq: 0,
+        };
+
+        let mut buf = [0u8; EXECUTION_REPORT_SIZE];
+        encode_execution_report(&mut buf, 1, &fill, 0).unwrap();
+        assert_eq!(buf[1], PROTOCOL_VERSION);
+
+        buf[1] = PROTOCOL_VERSION + 1;
+        assert_eq!(
+            decode_execution_report(&buf),
+            Err(ProtocolError::VersionMismatch {
+                expected: PROTOCOL_VERSION,
+                got: PROTOCOL_VERSION + 1,
+            })
+        );
+    }
+
+    #[test]
+    fn roundtrip_book_update() {
+        let update = BookUpdate {
+            seq_num: 9,
+            best_bid: Some((-5, 30)),
+            best_ask: None,
+            timestamp: 77,
+        };
+        // Room for an execution report too, so the type check is what fails
+        let mut buf = [0u8; EXECUTION_REPORT_SIZE];
+        assert_eq!(
+            encode_book_update(&mut buf, &update).unwrap(),
+            BOOK_UPDATE_SIZE
+        );
+        assert_eq!(decode_book_update(&buf).unwrap(), update);
+        assert_eq!(
+            decode_execution_report(&buf),
+            Err(ProtocolError::UnknownMessageType(MSG_BOOK_UPDATE))
+        );
+    }
+
+    #[test]
+    fn roundtrip_reject() {
+        let reject = Reject {
+            order_id: 31,
+            reason: REJECT_RING_FULL,
+        };
+        let mut buf = [0xFFu8; REJECT_SIZE];
+        assert_eq!(encode_reject(&mut buf, &reject).unwrap(), REJECT_SIZE);
+        assert_eq!(
+            buf[..4],
+            [MSG_REJECT, PROTOCOL_VERSION, REJECT_RING_FULL, 0]
+        );
+        assert_eq!(decode_reject(&buf).unwrap(), reject);
+        assert_eq!(
+            decode_reject(&buf[..REJECT_SIZE - 1]),
+            Err(ProtocolError::BufferTooShort)
+        );
+    }
+
+    #[test]
+    fn roundtrip_order_reject() {
+        let reject = OrderReject {
+            seq_num: 4,
+            order_id: 12,
+            reason: REJECT_PRICE_BAND,
+            timestamp: 99,
+        };
+        let mut buf = [0u8; ORDER_REJECT_SIZE];
+        assert_eq!(
+            encode_order_reject(&mut buf, &reject).unwrap(),
+            ORDER_REJECT_SIZE
+        );
+        assert_eq!(decode_order_reject(&buf).unwrap(), reject);
+        assert_eq!(
+            decode_reject(&buf),
+            Err(ProtocolError::UnknownMessageType(MSG_ORDER_REJECT))
+        );
+    }
+
+    #[test]
+    fn roundtrip_agg_trade() {
+        let trade = AggTrade {
+            seq_num: 12,
+            taker_order_id: 7,
+            price: -250,
+            quantity: 900,
+            fill_count: 31,
+            timestamp: 1_234,
+        };
+        let mut buf = [0u8; AGG_TRADE_SIZE];
+        assert_eq!(encode_agg_trade(&mut buf, &trade).unwrap(), AGG_TRADE_SIZE);
+        assert_eq!(decode_agg_trade(&buf).unwrap(), trade);
+        assert_eq!(
+            decode_agg_trade(&buf[..AGG_TRADE_SIZE - 1]),
+            Err(ProtocolError::BufferTooShort)
+        );
+    }
+
+    #[test]
+    fn roundtrip_cancel_report() {
+        let report = CancelReport {
+            seq_num: 4,
+            order_id: 99,
+            quantity: 250,
+            timestamp: 5_000,
+        };
+        let mut buf = [0u8; CANCEL_REPORT_SIZE];
+        assert_eq!(
+            encode_cancel_report(&mut buf, &report).unwrap(),
+            CANCEL_REPORT_SIZE
+        );
+        assert_eq!(decode_cancel_report(&buf).unwrap(), report);
+    }
+
+    #[test]
+    fn roundtrip_level_delta() {
+        let delta = LevelDelta {
+            seq_num: 12,
+            side: Side::Ask,
+            price: -15,
+            quantity: 0,
+            order_count: 0,
+            timestamp: 6_000,
+        };
+        let mut buf = [0u8; LEVEL_DELTA_SIZE];
+        assert_eq!(
+            encode_level_delta(&mut buf, &delta).unwrap(),
+            LEVEL_DELTA_SIZE
+        );
+        assert_eq!(decode_level_delta(&buf).unwrap(), delta);
+
+        buf[2] = 2;
+        assert_eq!(decode_level_delta(&buf), Err(ProtocolError::InvalidSide(2)));
+    }
+
+    #[test]
+    fn roundtrip_book_snapshot() {
+        let snapshot = BookSnapshot {
+            seq_num: 41,
+            bids: vec![(100, 30), (99, 5), (-2, 1)],
+            asks: vec![(101, 12)],
+            timestamp: 8_000,
+        };
+        let mut buf = vec![0xFF; snapshot.encoded_size() + 8];
+        let n = encode_book_snapshot(&mut buf, &snapshot).unwrap();
+        assert_eq!(n, BOOK_SNAPSHOT_HEADER_SIZE + 4 * BOOK_SNAPSHOT_LEVEL_SIZE);
+        assert_eq!(decode_book_snapshot(&buf[..n]).unwrap(), snapshot);
+
+        let empty = BookSnapshot::default();
+        let n = encode_book_snapshot(&mut buf, &empty).unwrap();
+        assert_eq!(n, BOOK_SNAPSHOT_HEADER_SIZE);
+        assert_eq!(decode_book_snapshot(&buf[..n]).unwrap(), empty);
+    }
+
+    #[test]
+    fn book_snapshot_buffer_too_short() {
+        let snapshot = BookSnapshot {
+            seq_num: 1,
+            bids: vec![(100, 30)],
+            asks: vec![(101, 12), (102, 4)],
+            timestamp: 0,
+        };
+        let mut buf = vec![0u8; snapshot.encoded_size()];
+        assert_eq!(
+            encode_book_snapshot(&mut buf[..snapshot.encoded_size() - 1], &snapshot),
+            Err(ProtocolError::BufferTooShort)
+        );
+
+        // A truncated tail level, or counts claiming more than was sent
+        let n = encode_book_snapshot(&mut buf, &snapshot).unwrap();
+        assert_eq!(
+            decode_book_snapshot(&buf[..n - 1]),
+            Err(ProtocolError::BufferTooShort)
+        );
+        buf[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            decode_book_snapshot(&buf),
+            Err(ProtocolError::BufferTooShort)
+        );
+        assert_eq!(
+            decode_book_snapshot(&buf[..BOOK_SNAPSHOT_HEADER_SIZE - 1]),
+            Err(ProtocolError::BufferTooShort)
+        );
+    }
+
+    #[test]
+    fn roundtrip_order_ack() {
+        let statuses = [
+            OrderStatus::FullyFilled,
+            OrderStatus::PartiallyFilled,
+            OrderStatus::Resting,
+            OrderStatus::CancelledSelfTrade,
+            OrderStatus::ReduceOnlyClamped,
+            OrderStatus::RejectedPostOnly,
+        ];
+        let mut buf = [0u8; ORDER_ACK_SIZE];
+        for (i, status) in statuses.into_iter().enumerate() {
+            let ack = OrderAck {
+                seq_num: i as u32,
+                order_id: 40 + i as u64,
+                status,
+                became_best: i % 2 == 0,
+                timestamp: 1_000 + i as u64,
+            };
+            assert_eq!(encode_order_ack(&mut buf, &ack).unwrap(), ORDER_ACK_SIZE);
+            assert_eq!(buf[2], i as u8 + 1);
+            assert_eq!(decode_order_ack(&buf).unwrap(), ack);
+        }
+
+        buf[2] = 0;
+        assert_eq!(decode_order_ack(&buf), Err(ProtocolError::InvalidStatus(0)));
+    }
+
+    #[test]
+    fn reject_reasons_are_stable() {
+        let cases = [
+            (MatchingError::Book(BookError::DuplicateOrderId(1)), 2),
+            (MatchingError::DuplicateOrderId(1), 2),
+            (MatchingError::Book(BookError::OrderNotFound(1)), 3),
+            (MatchingError::Book(BookError::ArenaFull), 4),
+            (MatchingError::ZeroQuantity, 5),
+            (
+                MatchingError::InvalidTick {
+                    price: 3,
+                    tick_size: 5,
+                },
+                6,
+            ),
+            (
+                MatchingError::PriceBandViolation {
+                    price: 3,
+                    reference: 100,
+                },
+                7,
+            ),
+            (
+                MatchingError::QuantityLimitExceeded {
+                    quantity: 10,
+                    limit: 5,
+                },
+                8,
+            ),
+            (
+                MatchingError::NotionalLimitExceeded {
+                    notional: 10,
+                    limit: 5,
+                },
+                9,
+            ),
+            (MatchingError::Book(BookError::PriceLevelNotFound(1)), 10),
+            (
+                MatchingError::TraderOrderLimitExceeded {
+                    trader_id: 1,
+                    limit: 5,
+                },
+                11,
+            ),
+            (MatchingError::Halted, 12),
+            (MatchingError::Book(BookError::QuantityOverflow), 13),
+            (
+                MatchingError::ReduceExceedsQuantity {
+                    order_id: 1,
+                    remaining: 5,
+                },
+                14,
+            ),
+            (
+                MatchingError::WrongSymbol {
+                    symbol_id: 2,
+                    expected: 1,
+                },
+                15,
+            ),
+        ];
+        for (err, code) in cases {
+            assert_eq!(reject_reason(&err), code, "{err:?}");
+        }
     }
 
     #[test]
@@ -382,12 +1820,16 @@ mod tests {
     #[test]
     fn encode_new_order_buffer_too_short() {
         let order = Order {
-            id: 1,
-            trader_id: 1,
+            id: 1.into(),
+            trader_id: 1.into(),
+            symbol_id: 0,
             side: Side::Bid,
-            price: 100,
-            quantity: 10,
+            price: 100.into(),
+            quantity: 10.into(),
             timestamp: 0,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
         };
         let mut buf = [0u8; NEW_ORDER_SIZE - 1];
         assert_eq!(
@@ -404,6 +1846,8 @@ mod tests {
             price: 100,
             quantity: 10,
             maker_fully_filled: true,
+            taker_seq: 0,
+            maker_seq: 0,
         };
         let mut buf = [0u8; EXECUTION_REPORT_SIZE - 1];
         assert_eq!(
@@ -415,12 +1859,16 @@ mod tests {
     #[test]
     fn decode_message_dispatches_new_order() {
         let order = Order {
-            id: 5,
-            trader_id: 3,
+            id: 5.into(),
+            trader_id: 3.into(),
+            symbol_id: 0,
             side: Side::Ask,
-            price: 200,
-            quantity: 50,
+            price: 200.into(),
+            quantity: 50.into(),
             timestamp: 0,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
         };
 
         let mut buf = [0u8; NEW_ORDER_SIZE];
@@ -446,31 +1894,85 @@ mod tests {
     }
 
     #[test]
-    fn negative_price_roundtrips() {
+    fn roundtrip_cancel_replace() {
         let order = Order {
-            id: 1,
-            trader_id: 1,
-            side: Side::Bid,
-            price: i64::MIN,
-            quantity: 1,
+            id: 77.into(),
+            trader_id: 3.into(),
+            symbol_id: 0xA1B2_C3D4,
+            side: Side::Ask,
+            price: (-42).into(),
+            quantity: 500.into(),
             timestamp: 0,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
         };
 
-        let mut buf = [0u8; NEW_ORDER_SIZE];
+        let mut buf = [0u8; CANCEL_REPLACE_SIZE];
+        encode_cancel_replace(&mut buf, 12, &order).unwrap();
+        assert_eq!(buf[0], MSG_CANCEL_REPLACE);
+        assert_eq!(buf[4..8], 0xA1B2_C3D4u32.to_le_bytes());
+
+        let cmd = decode_message(&buf).unwrap();
+        assert_eq!(
+            cmd,
+            EngineCommand::CancelReplace {
+                old_id: 12,
+                new_order: order
+            }
+        );
+    }
+
+    #[test]
+    fn cancel_replace_buffer_too_short_and_zero_quantity() {
+        let buf = [0u8; CANCEL_REPLACE_SIZE - 1];
+        assert_eq!(
+            decode_cancel_replace(&buf),
+            Err(ProtocolError::BufferTooShort)
+        );
+
+        let mut buf = [0u8; CANCEL_REPLACE_SIZE];
+        buf[0] = MSG_CANCEL_REPLACE;
+        assert_eq!(
+            decode_cancel_replace(&buf),
+            Err(ProtocolError::ZeroQuantity)
+        );
+    }
+
+    #[test]
+    fn negative_price_roundtrips_down_to_min_plus_one() {
+        let mut order = Order::try_new(1, 1, Side::Bid, i64::MIN + 1, 1, 0).unwrap();
+        let mut buf = [0u8; CANCEL_REPLACE_SIZE];
         encode_new_order(&mut buf, &order).unwrap();
-        let decoded = decode_new_order(&buf).unwrap();
-        assert_eq!(decoded.price, i64::MIN);
+        assert_eq!(decode_new_order(&buf).unwrap().price, i64::MIN + 1);
+
+        // Encoding doesn't check, but neither decoder accepts i64::MIN.
+        order.price = i64::MIN.into();
+        encode_new_order(&mut buf, &order).unwrap();
+        assert_eq!(
+            decode_new_order(&buf),
+            Err(ProtocolError::InvalidPrice(i64::MIN))
+        );
+        encode_cancel_replace(&mut buf, 7, &order).unwrap();
+        assert_eq!(
+            decode_cancel_replace(&buf),
+            Err(ProtocolError::InvalidPrice(i64::MIN))
+        );
     }
 
     #[test]
     fn max_values_roundtrip() {
         let order = Order {
-            id: u64::MAX,
-            trader_id: u64::MAX,
+            id: u64::MAX.into(),
+            trader_id: u64::MAX.into(),
+            symbol_id: 0,
             side: Side::Ask,
-            price: i64::MAX,
-            quantity: u64::MAX,
+            price: i64::MAX.into(),
+            quantity: u64::MAX.into(),
             timestamp: 0,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
         };
 
         let mut buf = [0u8; NEW_ORDER_SIZE];
@@ -482,31 +1984,141 @@ mod tests {
         assert_eq!(decoded.quantity, u64::MAX);
     }
 
+    #[test]
+    fn reduce_only_flag_roundtrips() {
+        let order = Order::try_new(5, 2, Side::Ask, 100, 10, 0)
+            .unwrap()
+            .with_reduce_only(true);
+
+        let mut buf = [0u8; NEW_ORDER_SIZE];
+        encode_new_order(&mut buf, &order).unwrap();
+        assert_eq!(buf[2], ORDER_FLAG_REDUCE_ONLY);
+        assert_eq!(decode_new_order(&buf).unwrap(), order);
+
+        let order = order.with_post_only(true);
+        encode_new_order(&mut buf, &order).unwrap();
+        assert_eq!(buf[2], ORDER_FLAG_REDUCE_ONLY | ORDER_FLAG_POST_ONLY);
+        assert_eq!(decode_new_order(&buf).unwrap(), order);
+
+        let mut buf = [0u8; CANCEL_REPLACE_SIZE];
+        encode_cancel_replace(&mut buf, 4, &order).unwrap();
+        assert_eq!(decode_cancel_replace(&buf).unwrap(), (4, order));
+    }
+
     #[test]
     fn reserved_bytes_ignored() {
         let order = Order {
-            id: 1,
-            trader_id: 1,
+            id: 1.into(),
+            trader_id: 1.into(),
+            symbol_id: 0,
             side: Side::Bid,
-            price: 100,
-            quantity: 10,
+            price: 100.into(),
+            quantity: 10.into(),
             timestamp: 0,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
         };
 
         let mut buf = [0u8; NEW_ORDER_SIZE];
         encode_new_order(&mut buf, &order).unwrap();
 
-        buf[2..8].fill(0xFF);
+        buf[3..8].fill(0xFF);
 
         let decoded = decode_new_order(&buf).unwrap();
         assert_eq!(decoded.id, 1);
         assert_eq!(decoded.quantity, 10);
     }
 
+    fn batch_order(id: u64, quantity: u64) -> Order {
+        Order {
+            id: id.into(),
+            trader_id: 3.into(),
+            symbol_id: 0,
+            side: Side::Ask,
+            price: (100 + id as i64).into(),
+            quantity: quantity.into(),
+            timestamp: 0,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
+        }
+    }
+
+    #[test]
+    fn roundtrip_batch() {
+        let orders: Vec<Order> = (1..=3).map(|id| batch_order(id, 10)).collect();
+        let mut buf = [0u8; MAX_BATCH_SIZE];
+        let n = encode_batch(&mut buf, &orders).unwrap();
+        assert_eq!(n, BATCH_HEADER_SIZE + 3 * NEW_ORDER_SIZE);
+        assert_eq!(message_size(buf[0]).unwrap(), BATCH_HEADER_SIZE);
+        assert_eq!(batch_size(&buf[..BATCH_HEADER_SIZE]).unwrap(), n);
+
+        let cmds = decode_batch(&buf[..n]).unwrap();
+        let expected: Vec<_> = orders.into_iter().map(EngineCommand::NewOrder).collect();
+        assert_eq!(cmds, expected);
+    }
+
+    #[test]
+    fn batch_rejected_as_a_whole() {
+        let orders: Vec<Order> = (1..=3).map(|id| batch_order(id, 10)).collect();
+        let mut buf = [0u8; MAX_BATCH_SIZE];
+        let n = encode_batch(&mut buf, &orders).unwrap();
+
+        // Zero the second order's quantity
+        let qty = BATCH_HEADER_SIZE + NEW_ORDER_SIZE + 32;
+        buf[qty..qty + 8].fill(0);
+        assert_eq!(decode_batch(&buf[..n]), Err(ProtocolError::ZeroQuantity));
+
+        buf[BATCH_HEADER_SIZE + NEW_ORDER_SIZE] = MSG_CANCEL_ORDER;
+        assert_eq!(
+            decode_batch(&buf[..n]),
+            Err(ProtocolError::UnknownMessageType(MSG_CANCEL_ORDER))
+        );
+    }
+
+    #[test]
+    fn batch_count_and_gtd_validated() {
+        let mut buf = [0u8; MAX_BATCH_SIZE + NEW_ORDER_SIZE];
+        assert_eq!(
+            encode_batch(&mut buf, &[]),
+            Err(ProtocolError::InvalidBatchCount(0))
+        );
+        let too_many: Vec<Order> = (1..=MAX_BATCH_ORDERS as u64 + 1)
+            .map(|id| batch_order(id, 1))
+            .collect();
+        assert_eq!(
+            encode_batch(&mut buf, &too_many),
+            Err(ProtocolError::InvalidBatchCount(
+                MAX_BATCH_ORDERS as u16 + 1
+            ))
+        );
+        assert_eq!(
+            encode_batch(&mut buf, &[batch_order(1, 1).with_expiry(5)]),
+            Err(ProtocolError::GtdInBatch)
+        );
+        assert_eq!(
+            ProtocolError::GtdInBatch.to_string(),
+            "GTD orders can't be sent in a batch"
+        );
+
+        let mut header = [0u8; BATCH_HEADER_SIZE];
+        header[0] = MSG_BATCH;
+        header[2..4].copy_from_slice(&200u16.to_le_bytes());
+        assert_eq!(
+            batch_size(&header),
+            Err(ProtocolError::InvalidBatchCount(200))
+        );
+    }
+
     #[test]
     fn message_size_lookup() {
         assert_eq!(message_size(MSG_NEW_ORDER).unwrap(), NEW_ORDER_SIZE);
         assert_eq!(message_size(MSG_CANCEL_ORDER).unwrap(), CANCEL_ORDER_SIZE);
+        assert_eq!(
+            message_size(MSG_CANCEL_REPLACE).unwrap(),
+            CANCEL_REPLACE_SIZE
+        );
         assert!(message_size(0xFF).is_err());
     }
 
@@ -515,4 +2127,123 @@ mod tests {
         let buf: &[u8] = &[];
         assert_eq!(decode_message(buf), Err(ProtocolError::BufferTooShort));
     }
+
+    #[test]
+    fn roundtrip_new_order_gtd() {
+        let order = Order::try_new(9, 4, Side::Ask, 105, 10, 0)
+            .unwrap()
+            .with_expiry(1_700_000_000_000_000_000);
+
+        let mut buf = [0u8; MAX_COMMAND_SIZE];
+        let n = encode_new_order(&mut buf, &order).unwrap();
+        assert_eq!(n, NEW_ORDER_GTD_SIZE);
+        assert_eq!(buf[0], MSG_NEW_ORDER_GTD);
+        assert_eq!(message_size(buf[0]).unwrap(), n);
+
+        assert_eq!(
+            decode_message(&buf[..n]).unwrap(),
+            EngineCommand::NewOrder(order.clone())
+        );
+        assert_eq!(
+            encode_new_order(&mut [0u8; NEW_ORDER_SIZE], &order),
+            Err(ProtocolError::BufferTooShort)
+        );
+        assert_eq!(
+            decode_new_order(&buf[..NEW_ORDER_SIZE]),
+            Err(ProtocolError::BufferTooShort)
+        );
+    }
+
+    #[test]
+    fn roundtrip_cancel_replace_gtd() {
+        let order = Order::try_new(8, 2, Side::Bid, 99, 5, 0)
+            .unwrap()
+            .with_expiry(42);
+
+        let mut buf = [0u8; MAX_COMMAND_SIZE];
+        let n = encode_cancel_replace(&mut buf, 7, &order).unwrap();
+        assert_eq!(n, CANCEL_REPLACE_GTD_SIZE);
+        assert_eq!(message_size(buf[0]).unwrap(), n);
+        assert_eq!(
+            decode_message(&buf[..n]).unwrap(),
+            EngineCommand::CancelReplace {
+                old_id: 7,
+                new_order: order,
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Every type byte the decoders know, so most inputs get past the
+    /// dispatch and into field parsing.
+    const TYPES: [u8; 22] = [
+        MSG_NEW_ORDER,
+        MSG_CANCEL_ORDER,
+        MSG_EXECUTION_REPORT,
+        MSG_CANCEL_REPLACE,
+        MSG_NEW_ORDER_GTD,
+        MSG_CANCEL_REPLACE_GTD,
+        MSG_BATCH,
+        MSG_BOOK_UPDATE,
+        MSG_REJECT,
+        MSG_ORDER_REJECT,
+        MSG_ORDER_ACK,
+        MSG_AGG_TRADE,
+        MSG_CANCEL_ALL,
+        MSG_CANCEL_REPORT,
+        MSG_HALT,
+        MSG_RESUME,
+        MSG_BOOK_SNAPSHOT,
+        MSG_REQUEST_SNAPSHOT,
+        MSG_LEVEL_DELTA,
+        MSG_ADMIN_SNAPSHOT,
+        MSG_ADMIN_SNAPSHOT_REPLY,
+        MSG_REDUCE_ORDER,
+    ];
+
+    fn arb_message() -> impl Strategy<Value = Vec<u8>> {
+        let msg_type = prop_oneof![prop::sample::select(&TYPES[..]), any::<u8>()];
+        let version = prop_oneof![Just(PROTOCOL_VERSION), any::<u8>()];
+        (
+            msg_type,
+            version,
+            prop::collection::vec(any::<u8>(), 0..BATCH_HEADER_SIZE + 3 * NEW_ORDER_SIZE),
+        )
+            .prop_map(|(msg_type, version, mut rest)| {
+                rest.insert(0, version);
+                rest.insert(0, msg_type);
+                rest
+            })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(4096))]
+
+        #[test]
+        fn decoders_never_panic_on_arbitrary_bytes(data in arb_message()) {
+            for len in 0..=data.len() {
+                fuzz_decode(&data[..len]);
+            }
+        }
+
+        #[test]
+        fn small_batches_decode_without_panicking(
+            count in 0u16..=4,
+            body in prop::collection::vec(any::<u8>(), 4 * NEW_ORDER_SIZE),
+        ) {
+            let mut data = vec![MSG_BATCH, 0];
+            data.extend_from_slice(&count.to_le_bytes());
+            data.extend_from_slice(&[0; BATCH_HEADER_SIZE - 4]);
+            data.extend_from_slice(&body);
+            for chunk in data[BATCH_HEADER_SIZE..].chunks_mut(NEW_ORDER_SIZE) {
+                chunk[0] = MSG_NEW_ORDER;
+            }
+            fuzz_decode(&data);
+        }
+    }
 }
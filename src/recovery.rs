@@ -1,15 +1,34 @@
 use std::fs;
 use std::path::Path;
 
-use crate::matching::MatchingEngine;
+use crate::matching::{AddOrderResult, Fill, MatchingEngine, MatchingError};
 use crate::protocol::EngineCommand;
-use crate::snapshot::{Snapshot, SnapshotError};
-use crate::wal::{Wal, WalError};
+use crate::snapshot::{DeltaSnapshot, Snapshot, SnapshotError};
+use crate::wal::{Outcome, Wal, WalError};
+
+/// How much checking WAL replay does during recovery.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplayMode {
+    /// Replay commands without checking their results.
+    #[default]
+    Fast,
+    /// Fail recovery if a replayed command's outcome differs from the one
+    /// recorded in the WAL. Records without an outcome aren't checked.
+    Strict,
+}
 
 #[derive(Debug)]
 pub(crate) enum RecoveryError {
     Wal(WalError),
     Snapshot(SnapshotError),
+    /// The book rebuilt from a snapshot disagrees with what the snapshot recorded.
+    SnapshotInconsistent(String),
+    /// Strict replay produced a different outcome than the original run.
+    ReplayDivergence {
+        record: u64,
+        recorded: Outcome,
+        replayed: Outcome,
+    },
 }
 
 impl std::fmt::Display for RecoveryError {
@@ -17,11 +36,28 @@ impl std::fmt::Display for RecoveryError {
         match self {
             Self::Wal(e) => write!(f, "recovery wal error: {e}"),
             Self::Snapshot(e) => write!(f, "recovery snapshot error: {e}"),
+            Self::SnapshotInconsistent(e) => write!(f, "recovery snapshot inconsistent: {e}"),
+            Self::ReplayDivergence {
+                record,
+                recorded,
+                replayed,
+            } => write!(
+                f,
+                "replay diverged at wal record {record}: recorded {recorded:?}, replayed {replayed:?}"
+            ),
         }
     }
 }
 
-impl std::error::Error for RecoveryError {}
+impl std::error::Error for RecoveryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Wal(e) => Some(e),
+            Self::Snapshot(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl From<WalError> for RecoveryError {
     fn from(e: WalError) -> Self {
@@ -38,6 +74,18 @@ impl From<SnapshotError> for RecoveryError {
 pub(crate) fn recover(
     data_dir: &Path,
     arena_capacity: u32,
+    mode: ReplayMode,
+) -> Result<(MatchingEngine, Wal), RecoveryError> {
+    recover_with(data_dir, arena_capacity, mode, |_, _| {})
+}
+
+/// `recover`, calling `on_replay` with the 1-based WAL record number and command of
+/// every record replayed past the snapshots, just before the engine applies it.
+pub(crate) fn recover_with(
+    data_dir: &Path,
+    arena_capacity: u32,
+    mode: ReplayMode,
+    mut on_replay: impl FnMut(u64, &EngineCommand),
 ) -> Result<(MatchingEngine, Wal), RecoveryError> {
     fs::create_dir_all(data_dir).map_err(WalError::Io)?;
 
@@ -47,20 +95,38 @@ pub(crate) fn recover(
         Some(snap) => {
             let record_count = snap.wal_record_count;
             let engine = snap.restore(arena_capacity)?;
+            verify_restored(&snap, &engine)?;
             (engine, record_count)
         }
         None => (MatchingEngine::with_capacity(arena_capacity), 0),
     };
 
+    let mut start_record = start_record;
+    for delta in DeltaSnapshot::load_chain(&snapshot_dir, start_record)? {
+        delta.apply(&mut engine)?;
+        start_record = delta.wal_record_count;
+    }
+
     let wal_path = data_dir.join("wal.bin");
     let mut wal = Wal::open(&wal_path)?;
 
     let mut record_count_at_replay = start_record;
 
-    for result in wal.iter_from(start_record) {
+    for result in wal.iter_from(start_record).with_outcomes() {
         match result {
-            Ok((_record_num, cmd)) => {
-                replay_command(&mut engine, cmd);
+            Ok((record, cmd, recorded)) => {
+                on_replay(record, &cmd);
+                let replayed = replay_command(&mut engine, cmd);
+                if mode == ReplayMode::Strict
+                    && let Some(recorded) = recorded
+                    && recorded != replayed
+                {
+                    return Err(RecoveryError::ReplayDivergence {
+                        record,
+                        recorded,
+                        replayed,
+                    });
+                }
                 record_count_at_replay += 1;
             }
             Err(WalError::Corruption { offset } | WalError::TruncatedRecord { offset }) => {
@@ -75,29 +141,120 @@ pub(crate) fn recover(
     Ok((engine, wal))
 }
 
-fn replay_command(engine: &mut MatchingEngine, cmd: EngineCommand) {
-    match cmd {
-        EngineCommand::NewOrder(order) => {
-            let _ = engine.add_order(order);
+fn verify_restored(snap: &Snapshot, engine: &MatchingEngine) -> Result<(), RecoveryError> {
+    let book = engine.book();
+    let inconsistent = |what: &str, stored: String, rebuilt: String| {
+        Err(RecoveryError::SnapshotInconsistent(format!(
+            "{what}: snapshot has {stored}, rebuilt book has {rebuilt}"
+        )))
+    };
+
+    if book.best_bid() != snap.best_bid {
+        return inconsistent(
+            "best bid",
+            format!("{:?}", snap.best_bid),
+            format!("{:?}", book.best_bid()),
+        );
+    }
+    if book.best_ask() != snap.best_ask {
+        return inconsistent(
+            "best ask",
+            format!("{:?}", snap.best_ask),
+            format!("{:?}", book.best_ask()),
+        );
+    }
+    if book.order_count() != snap.order_count() {
+        return inconsistent(
+            "order count",
+            snap.order_count().to_string(),
+            book.order_count().to_string(),
+        );
+    }
+    if engine.last_trade_price() != snap.last_trade_price {
+        return inconsistent(
+            "last trade price",
+            format!("{:?}", snap.last_trade_price),
+            format!("{:?}", engine.last_trade_price()),
+        );
+    }
+    if engine.in_auction() != snap.in_auction {
+        return inconsistent(
+            "auction",
+            snap.in_auction.to_string(),
+            engine.in_auction().to_string(),
+        );
+    }
+    let hash = Snapshot::book_hash(book, snap.checksum_algorithm);
+    if hash != snap.book_hash {
+        return inconsistent(
+            "book hash",
+            format!("{:#010x}", snap.book_hash),
+            format!("{hash:#010x}"),
+        );
+    }
+    Ok(())
+}
+
+fn replay_command(engine: &mut MatchingEngine, cmd: EngineCommand) -> Outcome {
+    apply_command(engine, cmd, |_| {})
+}
+
+/// Applies `cmd` the way replay does, passing the fills of any order it adds
+/// to `on_fills`.
+pub(crate) fn apply_command(
+    engine: &mut MatchingEngine,
+    cmd: EngineCommand,
+    mut on_fills: impl FnMut(&[Fill]),
+) -> Outcome {
+    let mut added = |result: Result<AddOrderResult, MatchingError>| {
+        if let Ok(r) = &result {
+            on_fills(&r.fills);
         }
+        Outcome::of_add(&result)
+    };
+    match cmd {
+        EngineCommand::NewOrder(order) => added(engine.add_order(order)),
         EngineCommand::CancelOrder { order_id } => {
-            let _ = engine.cancel_order(order_id);
+            Outcome::of_cancel(&engine.cancel_order(order_id))
+        }
+        EngineCommand::CancelReplace { old_id, new_order } => {
+            added(engine.cancel_replace(old_id, new_order))
+        }
+        EngineCommand::CancelAll { trader_id } => {
+            Outcome::of_cancel_all(&engine.cancel_all_for_trader(trader_id))
         }
+        EngineCommand::ReduceOrder {
+            order_id,
+            reduce_by,
+        } => Outcome::of_reduce(&engine.reduce_order(order_id, reduce_by)),
+        EngineCommand::Halt { policy } => {
+            engine.halt(policy);
+            Outcome::Applied
+        }
+        EngineCommand::Resume => {
+            engine.resume();
+            Outcome::Applied
+        }
+        // The gateway never logs them; replaying one changes nothing.
+        EngineCommand::RequestSnapshot | EngineCommand::AdminSnapshot => Outcome::Applied,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::matching::{AmendPolicy, CrossPolicy, FillPricing, HaltPolicy, RiskConfig};
     use crate::order::{Order, Side};
-    use crate::snapshot::Snapshot;
+    use crate::snapshot::{DeltaSnapshot, Snapshot, SnapshotCompression};
+    use crate::wal::{FILE_HEADER_SIZE, WalRetention};
+    use std::time::Duration;
 
     fn bid(id: u64, price: i64, qty: u64) -> Order {
-        Order::new(id, id, Side::Bid, price, qty, id).unwrap()
+        Order::try_new(id, id, Side::Bid, price, qty, id).unwrap()
     }
 
     fn ask(id: u64, price: i64, qty: u64) -> Order {
-        Order::new(id, id, Side::Ask, price, qty, id).unwrap()
+        Order::try_new(id, id, Side::Ask, price, qty, id).unwrap()
     }
 
     #[test]
@@ -105,11 +262,29 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         let data_dir = dir.path().join("data");
 
-        let (engine, wal) = recover(&data_dir, 1024).unwrap();
+        let (engine, wal) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
         assert_eq!(engine.book().order_count(), 0);
         assert_eq!(wal.record_count(), 0);
     }
 
+    #[test]
+    fn recovery_error_unwinds_to_the_io_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        fs::write(&data_dir, b"not a directory").unwrap();
+
+        let Err(err) = recover(&data_dir, 1024, ReplayMode::Fast) else {
+            panic!("recovery into a file should fail");
+        };
+        assert!(matches!(err, RecoveryError::Wal(WalError::Io(_))));
+        let mut root: &dyn std::error::Error = &err;
+        while let Some(source) = root.source() {
+            root = source;
+        }
+        let io = root.downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(io.kind(), std::io::ErrorKind::AlreadyExists);
+    }
+
     #[test]
     fn wal_only_recovery() {
         let dir = tempfile::tempdir().unwrap();
@@ -126,7 +301,7 @@ mod tests {
                 .unwrap();
         }
 
-        let (engine, wal) = recover(&data_dir, 1024).unwrap();
+        let (engine, wal) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
         assert_eq!(engine.book().order_count(), 3);
         assert_eq!(engine.book().best_bid(), Some(100));
         assert_eq!(engine.book().best_ask(), Some(110));
@@ -142,9 +317,11 @@ mod tests {
         let mut engine = MatchingEngine::with_capacity(1024);
         engine.add_order(bid(1, 100, 10)).unwrap();
         engine.add_order(ask(2, 110, 20)).unwrap();
-        Snapshot::capture(&engine, 2).save(&snap_dir).unwrap();
+        Snapshot::capture(&engine, 2)
+            .save_with(&snap_dir, SnapshotCompression::None)
+            .unwrap();
 
-        let (recovered, wal) = recover(&data_dir, 1024).unwrap();
+        let (recovered, wal) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
         assert_eq!(recovered.book().order_count(), 2);
         assert_eq!(recovered.book().best_bid(), Some(100));
         assert_eq!(recovered.book().best_ask(), Some(110));
@@ -162,7 +339,9 @@ mod tests {
         engine.add_order(bid(1, 100, 10)).unwrap();
         engine.add_order(ask(2, 110, 20)).unwrap();
 
-        Snapshot::capture(&engine, 2).save(&snap_dir).unwrap();
+        Snapshot::capture(&engine, 2)
+            .save_with(&snap_dir, SnapshotCompression::None)
+            .unwrap();
 
         // WAL records 1, 2, 3 — only 3 is after snapshot
         {
@@ -175,12 +354,98 @@ mod tests {
                 .unwrap();
         }
 
-        let (recovered, wal) = recover(&data_dir, 1024).unwrap();
+        let (recovered, wal) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
         assert_eq!(recovered.book().order_count(), 3);
         assert_eq!(recovered.book().best_bid(), Some(100));
         assert_eq!(wal.record_count(), 3);
     }
 
+    #[test]
+    fn replay_hook_sees_each_replayed_record_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let snap_dir = data_dir.join("snapshots");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let mut engine = MatchingEngine::with_capacity(1024);
+        engine.add_order(bid(1, 100, 10)).unwrap();
+        Snapshot::capture(&engine, 1)
+            .save_with(&snap_dir, SnapshotCompression::None)
+            .unwrap();
+
+        let cmds = [
+            EngineCommand::NewOrder(bid(1, 100, 10)),
+            EngineCommand::NewOrder(ask(2, 110, 20)),
+            EngineCommand::CancelOrder { order_id: 1 },
+        ];
+        {
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            for cmd in &cmds {
+                wal.append(cmd).unwrap();
+            }
+        }
+
+        let mut seen = Vec::new();
+        let (recovered, _) = recover_with(&data_dir, 1024, ReplayMode::Fast, |record, cmd| {
+            seen.push((record, cmd.clone()))
+        })
+        .unwrap();
+        // Records are numbered from 1; the snapshot covers record 1
+        assert_eq!(seen, vec![(2, cmds[1].clone()), (3, cmds[2].clone())]);
+        assert_eq!(recovered.book().order_count(), 1);
+    }
+
+    #[test]
+    fn recovers_after_retention_drops_snapshotted_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let snap_dir = data_dir.join("snapshots");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let mut engine = MatchingEngine::with_capacity(1024);
+        {
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            // Four records per segment.
+            wal.set_segment_size(Some(FILE_HEADER_SIZE as u64 + 4 * 56));
+            let mut log = |engine: &mut MatchingEngine, order: Order| {
+                wal.append(&EngineCommand::NewOrder(order.clone())).unwrap();
+                engine.add_order(order).unwrap();
+            };
+            for id in 1..=10 {
+                let price = if id % 2 == 0 { 100 } else { 101 };
+                log(&mut engine, bid(id, price, id));
+            }
+            Snapshot::capture(&engine, 10)
+                .save_with(&snap_dir, SnapshotCompression::None)
+                .unwrap();
+            for id in 11..=14 {
+                log(&mut engine, ask(id, 100, 3));
+            }
+
+            let age_out = WalRetention {
+                max_age: Some(Duration::ZERO),
+                ..WalRetention::default()
+            };
+            // Records 9..=12 straddle the snapshot, so their segment stays.
+            assert_eq!(wal.apply_retention(&age_out, 10).unwrap(), 2);
+            assert_eq!(wal.segment_count(), 2);
+        }
+
+        let (recovered, wal) = recover(&data_dir, 1024, ReplayMode::Strict).unwrap();
+        assert_eq!(wal.record_count(), 14);
+        assert_eq!(recovered.book().state_hash(), engine.book().state_hash());
+
+        // Without the snapshot the deleted records are needed, and missed.
+        fs::remove_dir_all(&snap_dir).unwrap();
+        assert!(matches!(
+            recover(&data_dir, 1024, ReplayMode::Fast),
+            Err(RecoveryError::Wal(WalError::MissingRecords {
+                first: 1,
+                last: 8
+            }))
+        ));
+    }
+
     #[test]
     fn recovery_matches_full_replay() {
         let dir = tempfile::tempdir().unwrap();
@@ -204,7 +469,9 @@ mod tests {
             let mut partial = MatchingEngine::with_capacity(1024);
             partial.add_order(orders[0].clone()).unwrap();
             partial.add_order(orders[1].clone()).unwrap();
-            Snapshot::capture(&partial, 2).save(&snap_dir).unwrap();
+            Snapshot::capture(&partial, 2)
+                .save_with(&snap_dir, SnapshotCompression::None)
+                .unwrap();
         }
         {
             let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
@@ -213,7 +480,7 @@ mod tests {
             }
         }
 
-        let (recovered, _) = recover(&data_dir, 1024).unwrap();
+        let (recovered, _) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
 
         let full_orders = full_engine.book().all_resting_orders();
         let recovered_orders = recovered.book().all_resting_orders();
@@ -244,7 +511,7 @@ mod tests {
                 .unwrap();
         }
 
-        let (engine, wal) = recover(&data_dir, 1024).unwrap();
+        let (engine, wal) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
         assert_eq!(engine.book().order_count(), 3);
         assert_eq!(wal.record_count(), 3);
     }
@@ -272,8 +539,8 @@ mod tests {
             }
         }
 
-        let (engine1, _) = recover(&data1, 1024).unwrap();
-        let (engine2, _) = recover(&data2, 1024).unwrap();
+        let (engine1, _) = recover(&data1, 1024, ReplayMode::Fast).unwrap();
+        let (engine2, _) = recover(&data2, 1024, ReplayMode::Fast).unwrap();
 
         let orders1 = engine1.book().all_resting_orders();
         let orders2 = engine2.book().all_resting_orders();
@@ -286,6 +553,83 @@ mod tests {
         }
     }
 
+    #[test]
+    fn trader_stats_survive_snapshot_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let snap_dir = data_dir.join("snapshots");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let orders = vec![
+            ask(1, 100, 20),
+            bid(2, 100, 5),
+            bid(3, 100, 5),
+            bid(4, 95, 10),
+        ];
+
+        let mut full_engine = MatchingEngine::with_capacity(1024);
+        for o in &orders {
+            full_engine.add_order(o.clone()).unwrap();
+        }
+
+        {
+            let mut partial = MatchingEngine::with_capacity(1024);
+            partial.add_order(orders[0].clone()).unwrap();
+            partial.add_order(orders[1].clone()).unwrap();
+            Snapshot::capture(&partial, 2)
+                .save_with(&snap_dir, SnapshotCompression::None)
+                .unwrap();
+
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            for o in &orders {
+                wal.append(&EngineCommand::NewOrder(o.clone())).unwrap();
+            }
+        }
+
+        let (recovered, _) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
+        for trader in 1..=4 {
+            assert_eq!(
+                recovered.trader_stats(trader),
+                full_engine.trader_stats(trader),
+                "trader {trader}"
+            );
+        }
+        assert_eq!(recovered.trader_position(1), -10);
+        assert_eq!(recovered.trader_exposure(1), 1_000);
+    }
+
+    #[test]
+    fn recovery_with_cancel_replace() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        {
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            wal.append(&EngineCommand::NewOrder(bid(1, 100, 10)))
+                .unwrap();
+            wal.append(&EngineCommand::NewOrder(bid(2, 99, 10)))
+                .unwrap();
+            wal.append(&EngineCommand::CancelReplace {
+                old_id: 1,
+                new_order: bid(3, 101, 5),
+            })
+            .unwrap();
+            // Rejected (duplicate id) — order 2 must survive replay too
+            wal.append(&EngineCommand::CancelReplace {
+                old_id: 2,
+                new_order: bid(3, 90, 5),
+            })
+            .unwrap();
+        }
+
+        let (engine, _) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
+        assert_eq!(engine.book().order_count(), 2);
+        assert!(engine.book().contains_order(2));
+        assert!(engine.book().contains_order(3));
+        assert_eq!(engine.book().best_bid(), Some(101));
+    }
+
     #[test]
     fn recovery_with_cancels() {
         let dir = tempfile::tempdir().unwrap();
@@ -302,10 +646,499 @@ mod tests {
                 .unwrap();
         }
 
-        let (engine, wal) = recover(&data_dir, 1024).unwrap();
+        let (engine, wal) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
         assert_eq!(engine.book().order_count(), 1);
         assert_eq!(engine.book().best_bid(), None);
         assert_eq!(engine.book().best_ask(), Some(110));
         assert_eq!(wal.record_count(), 3);
     }
+
+    #[test]
+    fn recovery_replays_cancel_all() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        {
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            for (id, trader) in [(1, 7), (2, 8), (3, 7)] {
+                let order = Order::try_new(id, trader, Side::Bid, 100 - id as i64, 10, id).unwrap();
+                wal.append(&EngineCommand::NewOrder(order)).unwrap();
+            }
+            wal.append(&EngineCommand::CancelAll { trader_id: 7 })
+                .unwrap();
+        }
+
+        let (engine, wal) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
+        assert_eq!(engine.book().order_count(), 1);
+        assert!(engine.book().contains_order(2));
+        assert_eq!(wal.record_count(), 4);
+    }
+
+    #[test]
+    fn recovery_replays_reduce_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        {
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            wal.append(&EngineCommand::NewOrder(bid(1, 100, 10)))
+                .unwrap();
+            wal.append(&EngineCommand::NewOrder(bid(2, 100, 10)))
+                .unwrap();
+            wal.append(&EngineCommand::ReduceOrder {
+                order_id: 1,
+                reduce_by: 4,
+            })
+            .unwrap();
+            wal.record_outcome(Outcome::Reduced);
+        }
+
+        let (engine, _) = recover(&data_dir, 1024, ReplayMode::Strict).unwrap();
+        let resting = engine.book().all_resting_orders();
+        let queue: Vec<_> = resting.iter().map(|o| (o.id.0, o.quantity.0)).collect();
+        assert_eq!(queue, vec![(1, 6), (2, 10)]);
+    }
+
+    #[test]
+    fn recovery_replays_under_snapshotted_fill_pricing() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let snap_dir = data_dir.join("snapshots");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        // The gateway pins a pricing change with a snapshot at startup,
+        // before the WAL holds anything priced by it.
+        let mut engine = MatchingEngine::with_capacity(1024);
+        engine.set_fill_pricing(FillPricing::Midpoint);
+        Snapshot::capture(&engine, 0)
+            .save_with(&snap_dir, SnapshotCompression::None)
+            .unwrap();
+        {
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            for order in [ask(1, 100, 10), bid(2, 90, 1), bid(3, 110, 10)] {
+                wal.append(&EngineCommand::NewOrder(order)).unwrap();
+            }
+        }
+
+        let (engine, _) = recover(&data_dir, 1024, ReplayMode::Strict).unwrap();
+        assert_eq!(engine.fill_pricing(), FillPricing::Midpoint);
+        assert_eq!(engine.last_trade_price(), Some(95));
+    }
+
+    #[test]
+    fn price_band_reference_survives_snapshot_and_delta() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let snap_dir = data_dir.join("snapshots");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        // Each trade empties the book, leaving the last trade price as the
+        // only band reference: 100 in the snapshot, 103 in the delta.
+        let risk = RiskConfig {
+            price_band_ticks: Some(5),
+            ..RiskConfig::default()
+        };
+        let mut live = MatchingEngine::with_capacity(1024);
+        live.set_risk_config(risk.clone());
+        live.set_change_tracking(true);
+        {
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            let cmds = [
+                ask(1, 100, 10),
+                bid(2, 100, 10),
+                ask(3, 103, 5),
+                bid(4, 103, 5),
+            ];
+            for (i, order) in cmds.into_iter().enumerate() {
+                let cmd = EngineCommand::NewOrder(order);
+                wal.append(&cmd).unwrap();
+                replay_command(&mut live, cmd);
+                if i == 1 {
+                    Snapshot::capture(&live, 2)
+                        .save_with(&snap_dir, SnapshotCompression::None)
+                        .unwrap();
+                    assert_eq!(live.last_trade_price(), Some(100));
+                    live.clear_changes();
+                }
+            }
+            DeltaSnapshot::capture(&mut live, 2, 4)
+                .save_with(&snap_dir, SnapshotCompression::None)
+                .unwrap();
+        }
+
+        let (mut recovered, _) = recover(&data_dir, 1024, ReplayMode::Strict).unwrap();
+        recovered.set_risk_config(risk);
+        assert_eq!(recovered.last_trade_price(), Some(103));
+        // 96 is within 5 of 100 but not of 103, and 107 the other way round.
+        for (id, price, accepted) in [(5, 96, false), (6, 107, true), (7, 109, false)] {
+            assert_eq!(live.add_order(bid(id, price, 1)).is_ok(), accepted);
+            assert_eq!(
+                recovered.add_order(bid(id, price, 1)).is_ok(),
+                accepted,
+                "bid at {price}"
+            );
+        }
+    }
+
+    #[test]
+    fn recovery_replays_amends_under_snapshotted_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let mut engine = MatchingEngine::with_capacity(1024);
+        engine.set_amend_policy(AmendPolicy::KeepOnSamePrice);
+        Snapshot::capture(&engine, 0)
+            .save_with(&data_dir.join("snapshots"), SnapshotCompression::None)
+            .unwrap();
+        {
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            wal.append(&EngineCommand::NewOrder(bid(1, 100, 10)))
+                .unwrap();
+            wal.append(&EngineCommand::NewOrder(bid(2, 100, 10)))
+                .unwrap();
+            wal.append(&EngineCommand::CancelReplace {
+                old_id: 1,
+                new_order: bid(1, 100, 15),
+            })
+            .unwrap();
+        }
+
+        let (engine, _) = recover(&data_dir, 1024, ReplayMode::Strict).unwrap();
+        assert_eq!(engine.amend_policy(), AmendPolicy::KeepOnSamePrice);
+        assert_eq!(engine.book().queue_position(1), Some(0));
+        assert_eq!(engine.book().get_order(1).unwrap().quantity, 15);
+    }
+
+    #[test]
+    fn recovery_mid_auction_resumes_the_call() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let snap_dir = data_dir.join("snapshots");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        // Crossing orders collected across a snapshot, a delta and the WAL.
+        let mut live = MatchingEngine::with_capacity(1024);
+        live.set_change_tracking(true);
+        live.start_auction();
+        {
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            let orders = [
+                ask(1, 100, 10),
+                bid(2, 105, 4),
+                bid(3, 103, 8),
+                ask(4, 101, 3),
+            ];
+            for (i, order) in orders.into_iter().enumerate() {
+                let cmd = EngineCommand::NewOrder(order);
+                wal.append(&cmd).unwrap();
+                replay_command(&mut live, cmd);
+                match i + 1 {
+                    2 => {
+                        Snapshot::capture(&live, 2)
+                            .save_with(&snap_dir, SnapshotCompression::None)
+                            .unwrap();
+                        live.clear_changes();
+                    }
+                    3 => {
+                        DeltaSnapshot::capture(&mut live, 2, 3)
+                            .save_with(&snap_dir, SnapshotCompression::None)
+                            .unwrap();
+                    }
+                    _ => {}
+                }
+            }
+        }
+        assert!(live.book().best_bid() > live.book().best_ask());
+
+        let (mut recovered, _) = recover(&data_dir, 1024, ReplayMode::Strict).unwrap();
+        assert!(recovered.in_auction());
+        assert_eq!(recovered.book().order_count(), 4);
+        let uncrossed = recovered.uncross().unwrap();
+        assert_eq!(uncrossed, live.uncross().unwrap());
+        assert_eq!(uncrossed.fills.iter().map(|f| f.quantity).sum::<u64>(), 12);
+        assert!(!recovered.in_auction());
+    }
+
+    #[test]
+    fn recovery_replays_under_snapshotted_cross_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let mut engine = MatchingEngine::with_capacity(1024);
+        engine.set_cross_policy(CrossPolicy::StrictlyThrough);
+        Snapshot::capture(&engine, 0)
+            .save_with(&data_dir.join("snapshots"), SnapshotCompression::None)
+            .unwrap();
+        {
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            wal.append(&EngineCommand::NewOrder(ask(1, 100, 10)))
+                .unwrap();
+            wal.append(&EngineCommand::NewOrder(bid(2, 100, 10)))
+                .unwrap();
+        }
+
+        // The default policy would have traded the two orders.
+        let (engine, _) = recover(&data_dir, 1024, ReplayMode::Strict).unwrap();
+        assert_eq!(engine.cross_policy(), CrossPolicy::StrictlyThrough);
+        assert_eq!(engine.book().order_count(), 2);
+        assert_eq!(engine.metrics().fills, 0);
+    }
+
+    #[test]
+    fn recovery_replays_halt() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        {
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            wal.append(&EngineCommand::NewOrder(ask(1, 100, 10)))
+                .unwrap();
+            wal.append(&EngineCommand::Halt {
+                policy: HaltPolicy::RejectMarketable,
+            })
+            .unwrap();
+            wal.append(&EngineCommand::NewOrder(bid(2, 100, 10)))
+                .unwrap();
+        }
+
+        let (engine, _) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
+        assert_eq!(engine.halt_policy(), Some(HaltPolicy::RejectMarketable));
+        assert_eq!(engine.book().order_count(), 1);
+        assert_eq!(engine.book().best_ask(), Some(100));
+    }
+
+    /// Deterministic mix of crossing orders, cancels, id reuse and replaces.
+    /// Timestamps come from a logical clock, one tick per command.
+    fn churn_commands(count: u64) -> Vec<EngineCommand> {
+        let mut state = 0x2545_f491_u64;
+        let mut next = move || {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1);
+            state >> 33
+        };
+        (1..=count)
+            .map(|tick| {
+                let id = 1 + next() % 60;
+                let side = if next().is_multiple_of(2) {
+                    Side::Bid
+                } else {
+                    Side::Ask
+                };
+                let order = Order::try_new(
+                    id,
+                    next() % 5,
+                    side,
+                    95 + (next() % 10) as i64,
+                    1 + next() % 20,
+                    tick,
+                )
+                .unwrap();
+                match next() % 6 {
+                    0 => EngineCommand::CancelOrder { order_id: id },
+                    1 => EngineCommand::CancelReplace {
+                        old_id: 1 + next() % 60,
+                        new_order: order,
+                    },
+                    _ => EngineCommand::NewOrder(order),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn delta_recovery_matches_full_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let snap_dir = data_dir.join("snapshots");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let cmds = churn_commands(400);
+
+        {
+            let mut live = MatchingEngine::with_capacity(1024);
+            live.set_change_tracking(true);
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            let mut last_capture = 0;
+            for (i, cmd) in cmds.iter().enumerate() {
+                wal.append(cmd).unwrap();
+                replay_command(&mut live, cmd.clone());
+                let count = (i + 1) as u64;
+                if count == 100 {
+                    Snapshot::capture(&live, count)
+                        .save_with(&snap_dir, SnapshotCompression::None)
+                        .unwrap();
+                    live.clear_changes();
+                    last_capture = count;
+                } else if count > 100 && count.is_multiple_of(70) {
+                    DeltaSnapshot::capture(&mut live, last_capture, count)
+                        .save_with(&snap_dir, SnapshotCompression::None)
+                        .unwrap();
+                    last_capture = count;
+                }
+            }
+            assert_eq!(last_capture, 350);
+        }
+        assert_eq!(DeltaSnapshot::load_chain(&snap_dir, 100).unwrap().len(), 4);
+
+        let replay_dir = dir.path().join("replay");
+        fs::create_dir_all(&replay_dir).unwrap();
+        fs::copy(data_dir.join("wal.bin"), replay_dir.join("wal.bin")).unwrap();
+        let (full_replay, _) = recover(&replay_dir, 1024, ReplayMode::Fast).unwrap();
+
+        let (recovered, wal) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
+        assert_eq!(wal.record_count(), 400);
+        assert_eq!(
+            recovered.book().all_resting_orders(),
+            full_replay.book().all_resting_orders()
+        );
+        for trader in 0..5 {
+            assert_eq!(
+                recovered.trader_stats(trader),
+                full_replay.trader_stats(trader),
+                "trader {trader}"
+            );
+        }
+    }
+
+    /// Logs `cmds` with the outcomes a live engine produced for them.
+    fn write_with_outcomes(data_dir: &Path, cmds: &[EngineCommand]) {
+        let mut live = MatchingEngine::with_capacity(1024);
+        let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+        for cmd in cmds {
+            wal.append(cmd).unwrap();
+            wal.record_outcome(replay_command(&mut live, cmd.clone()));
+        }
+    }
+
+    #[test]
+    fn strict_replay_accepts_matching_outcomes() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+        write_with_outcomes(&data_dir, &churn_commands(300));
+
+        let (engine, wal) = recover(&data_dir, 1024, ReplayMode::Strict).unwrap();
+        assert_eq!(wal.record_count(), 300);
+        assert!(engine.book().order_count() > 0);
+    }
+
+    #[test]
+    fn strict_replay_detects_divergence() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+        write_with_outcomes(
+            &data_dir,
+            &[
+                EngineCommand::NewOrder(ask(1, 100, 10)),
+                EngineCommand::NewOrder(bid(2, 100, 10)),
+            ],
+        );
+        {
+            // Pretend the original run rested the crossing bid
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            wal.truncate_to(FILE_HEADER_SIZE as u64 + 56, 1).unwrap();
+            wal.append(&EngineCommand::NewOrder(bid(2, 100, 10)))
+                .unwrap();
+            wal.record_outcome(Outcome::Resting);
+        }
+
+        let Err(RecoveryError::ReplayDivergence {
+            record,
+            recorded,
+            replayed,
+        }) = recover(&data_dir, 1024, ReplayMode::Strict)
+        else {
+            panic!("expected replay divergence");
+        };
+        assert_eq!(record, 2);
+        assert_eq!(recorded, Outcome::Resting);
+        assert_eq!(replayed, Outcome::FullyFilled);
+
+        assert!(recover(&data_dir, 1024, ReplayMode::Fast).is_ok());
+    }
+
+    #[test]
+    fn replay_is_byte_identical() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let mut live = MatchingEngine::with_capacity(1024);
+        {
+            let mut wal = Wal::open(data_dir.join("wal.bin")).unwrap();
+            for cmd in churn_commands(300) {
+                wal.append(&cmd).unwrap();
+                replay_command(&mut live, cmd);
+            }
+        }
+
+        let (first, _) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
+        let (second, _) = recover(&data_dir, 1024, ReplayMode::Fast).unwrap();
+        let encode = |engine: &MatchingEngine| {
+            let path = Snapshot::capture(engine, 300)
+                .save_with(&dir.path().join("encoded"), SnapshotCompression::None)
+                .unwrap();
+            fs::read(path).unwrap()
+        };
+
+        let resting = first.book().all_resting_orders();
+        assert!(!resting.is_empty());
+        assert!(resting.iter().all(|o| o.timestamp > 0));
+        assert_eq!(encode(&first), encode(&second));
+        assert_eq!(encode(&first), encode(&live));
+    }
+
+    #[test]
+    fn tampered_best_bid_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let snap_dir = data_dir.join("snapshots");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let mut engine = MatchingEngine::with_capacity(1024);
+        engine.add_order(bid(1, 100, 10)).unwrap();
+        engine.add_order(ask(2, 110, 20)).unwrap();
+        let mut snap = Snapshot::capture(&engine, 2);
+        snap.best_bid = Some(101);
+        // The orders checksum still passes: only the cross-check catches this.
+        snap.verify_checksum().unwrap();
+        snap.save_with(&snap_dir, SnapshotCompression::None)
+            .unwrap();
+
+        let Err(RecoveryError::SnapshotInconsistent(e)) =
+            recover(&data_dir, 1024, ReplayMode::Fast)
+        else {
+            panic!("expected SnapshotInconsistent");
+        };
+        assert!(e.contains("best bid"), "{e}");
+    }
+
+    #[test]
+    fn tampered_book_hash_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let snap_dir = data_dir.join("snapshots");
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let mut engine = MatchingEngine::with_capacity(1024);
+        engine.add_order(bid(1, 100, 10)).unwrap();
+        let mut snap = Snapshot::capture(&engine, 1);
+        snap.book_hash ^= 1;
+        snap.save_with(&snap_dir, SnapshotCompression::None)
+            .unwrap();
+
+        let Err(RecoveryError::SnapshotInconsistent(e)) =
+            recover(&data_dir, 1024, ReplayMode::Fast)
+        else {
+            panic!("expected SnapshotInconsistent");
+        };
+        assert!(e.contains("book hash"), "{e}");
+    }
 }
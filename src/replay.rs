@@ -0,0 +1,162 @@
+use std::path::Path;
+
+use crate::matching::{Fill, MatchingEngine};
+use crate::recovery::apply_command;
+use crate::wal::{WalError, WalReader, list_segments};
+
+/// What `replay_wal` rebuilt.
+#[derive(Debug)]
+pub struct WalReplay {
+    /// The engine after every record replayed.
+    pub engine: MatchingEngine,
+    /// Every fill replay produced, in order.
+    pub fills: Vec<Fill>,
+    /// Records replayed, counted from the first.
+    pub records: u64,
+    /// Why replay stopped short of the end of the log: a corrupt or truncated
+    /// record, or a gap between segments. `None` if it reached the end.
+    pub stopped_by: Option<WalError>,
+}
+
+/// Replays the WAL at `path`, with any rotated segments beside it, into a
+/// fresh engine, the way recovery does but with no snapshots, and collects
+/// the fills. For backtesting against recorded flow. The files are only
+/// read, so this is safe to point at a live engine's log.
+///
+/// Fails only if the first segment can't be opened. Damage later in the log
+/// ends replay there, with the records before it applied. The engine runs
+/// with default settings, so a log recorded under another fill pricing or
+/// amend policy replays differently.
+pub fn replay_wal(path: impl AsRef<Path>, arena_capacity: u32) -> Result<WalReplay, WalError> {
+    let path = path.as_ref();
+    let segments = list_segments(path)?;
+    if segments.is_empty() {
+        // Reports the missing file.
+        WalReader::open(path)?;
+    }
+
+    let mut replay = WalReplay {
+        engine: MatchingEngine::with_capacity(arena_capacity),
+        fills: Vec::new(),
+        records: 0,
+        stopped_by: None,
+    };
+    for (i, (first_record, segment)) in segments.into_iter().enumerate() {
+        if first_record != replay.records + 1 {
+            replay.stopped_by = Some(WalError::MissingRecords {
+                first: replay.records + 1,
+                last: first_record - 1,
+            });
+            break;
+        }
+        let reader = match WalReader::open(&segment) {
+            Ok(reader) => reader,
+            Err(e) if i == 0 => return Err(e),
+            Err(e) => {
+                replay.stopped_by = Some(e);
+                break;
+            }
+        };
+        for result in reader.iter() {
+            match result {
+                Ok((_, cmd)) => {
+                    apply_command(&mut replay.engine, cmd, |f| {
+                        replay.fills.extend_from_slice(f)
+                    });
+                    replay.records += 1;
+                }
+                Err(e) => {
+                    replay.stopped_by = Some(e);
+                    return Ok(replay);
+                }
+            }
+        }
+    }
+    Ok(replay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::run_sequence;
+    use crate::order::{Order, Side};
+    use crate::protocol::EngineCommand;
+    use crate::wal::{FILE_HEADER_SIZE, Wal};
+    use std::fs;
+
+    fn order(id: u64, side: Side, price: i64, qty: u64) -> Order {
+        Order::try_new(id, id, side, price, qty, id).unwrap()
+    }
+
+    fn commands() -> Vec<EngineCommand> {
+        vec![
+            EngineCommand::NewOrder(order(1, Side::Ask, 101, 10)),
+            EngineCommand::NewOrder(order(2, Side::Ask, 102, 10)),
+            EngineCommand::NewOrder(order(3, Side::Bid, 102, 15)),
+            EngineCommand::CancelOrder { order_id: 2 },
+            EngineCommand::NewOrder(order(4, Side::Bid, 99, 5)),
+            EngineCommand::NewOrder(order(5, Side::Ask, 99, 2)),
+        ]
+    }
+
+    fn write_wal(path: &Path, cmds: &[EngineCommand], segment_size: Option<u64>) {
+        let mut wal = Wal::open_with_size(path, 4096).unwrap();
+        wal.set_segment_size(segment_size);
+        for cmd in cmds {
+            wal.append(cmd).unwrap();
+        }
+        wal.flush_async().unwrap();
+    }
+
+    #[test]
+    fn replay_matches_a_live_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+        let cmds = commands();
+        // Two records per segment, so replay crosses segments.
+        write_wal(&path, &cmds, Some(FILE_HEADER_SIZE as u64 + 2 * 56));
+
+        let replay = replay_wal(&path, 64).unwrap();
+        assert!(replay.stopped_by.is_none());
+        assert_eq!(replay.records, cmds.len() as u64);
+        let (fills, hash) = run_sequence(&cmds);
+        assert_eq!(replay.fills, fills);
+        assert_eq!(replay.engine.book().state_hash(), hash);
+        assert_eq!(replay.fills.len(), 3);
+        // No snapshots or anything else are written beside the log.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn replay_stops_at_a_corrupt_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+        let cmds = commands();
+        write_wal(&path, &cmds, None);
+
+        // Damage the third record's payload.
+        let mut data = fs::read(&path).unwrap();
+        data[FILE_HEADER_SIZE + 2 * 56 + 20] ^= 0xFF;
+        fs::write(&path, &data).unwrap();
+
+        let replay = replay_wal(&path, 64).unwrap();
+        assert_eq!(replay.records, 2);
+        assert!(matches!(
+            replay.stopped_by,
+            Some(WalError::Corruption { .. })
+        ));
+        assert!(replay.fills.is_empty());
+        assert_eq!(replay.engine.book().order_count(), 2);
+        // The file is left as it was.
+        assert_eq!(fs::read(&path).unwrap(), data);
+    }
+
+    #[test]
+    fn missing_wal_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            replay_wal(dir.path().join("wal.bin"), 64),
+            Err(WalError::Io(_))
+        ));
+    }
+}
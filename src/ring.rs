@@ -2,6 +2,7 @@ use std::cell::UnsafeCell;
 use std::fmt;
 use std::mem::MaybeUninit;
 use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
@@ -40,8 +41,55 @@ impl<T> fmt::Debug for Full<T> {
 #[derive(Debug)]
 pub struct Empty;
 
+/// A capacity the ring can't be built with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingError {
+    ZeroCapacity,
+    NotPowerOfTwo(usize),
+    /// No power of two at or above the requested capacity fits in `usize`.
+    CapacityOverflow(usize),
+}
+
+impl fmt::Display for RingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ZeroCapacity => write!(f, "ring buffer capacity must be greater than zero"),
+            Self::NotPowerOfTwo(n) => {
+                write!(f, "ring buffer capacity must be a power of two, got {n}")
+            }
+            Self::CapacityOverflow(n) => {
+                write!(f, "ring buffer capacity {n} has no power of two above it")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RingError {}
+
+/// One ring slot, as laid out in storage passed to `ring_buffer_in`.
+pub type Slot<T> = UnsafeCell<MaybeUninit<T>>;
+
+enum Storage<T> {
+    Owned(Box<[Slot<T>]>),
+    /// From a `&'static mut` slice, which already needs `T: 'static`. Kept
+    /// as a pointer so the enum doesn't carry that bound to owned storage.
+    Borrowed(NonNull<[Slot<T>]>),
+}
+
+impl<T> Deref for Storage<T> {
+    type Target = [Slot<T>];
+    fn deref(&self) -> &[Slot<T>] {
+        match self {
+            Self::Owned(b) => b,
+            // SAFETY: Built from a `&'static mut` slice, so the storage is
+            // valid forever and nothing else can access it.
+            Self::Borrowed(s) => unsafe { s.as_ref() },
+        }
+    }
+}
+
 struct RingBufferInner<T> {
-    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    buffer: Storage<T>,
     capacity: usize,
     mask: usize,
     head: CachePadded<AtomicUsize>,
@@ -115,6 +163,23 @@ impl<T> Producer<T> {
     pub fn capacity(&self) -> usize {
         self.inner.capacity
     }
+
+    /// Items currently queued. Approximate: the consumer may pop right after
+    /// the read, so this is an upper bound by the time it's returned.
+    pub fn len(&self) -> usize {
+        let tail = self.inner.tail.load(Ordering::Acquire);
+        self.cached_head.wrapping_sub(tail)
+    }
+
+    /// Approximate, like `len`.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Approximate, like `len`; a `push` may still succeed afterwards.
+    pub fn is_full(&self) -> bool {
+        self.len() == self.inner.capacity
+    }
 }
 
 pub struct Consumer<T> {
@@ -128,53 +193,156 @@ pub struct Consumer<T> {
 unsafe impl<T: Send> Send for Consumer<T> {}
 
 impl<T> Consumer<T> {
+    /// Returns the front element without removing it. The slot stays owned
+    /// by the consumer until `advance` is called.
+    pub fn peek(&mut self) -> Option<&T> {
+        let tail = self.front()?;
+
+        // SAFETY: Same as `pop` — the slot at `tail` was written by the
+        // producer and can't be reused until `tail` is advanced, which needs
+        // `&mut self` and so ends this borrow first.
+        Some(unsafe { (*self.inner.buffer[tail & self.inner.mask].get()).assume_init_ref() })
+    }
+
+    /// Drops the front element and releases its slot to the producer.
+    pub fn advance(&mut self) -> Result<(), Empty> {
+        let tail = self.front().ok_or(Empty)?;
+
+        // SAFETY: As in `peek`; the value is dropped in place exactly once
+        // before the slot is released.
+        unsafe { (*self.inner.buffer[tail & self.inner.mask].get()).assume_init_drop() };
+        self.release(tail);
+
+        Ok(())
+    }
+
     pub fn pop(&mut self) -> Result<T, Empty> {
+        let tail = self.front().ok_or(Empty)?;
+
+        // SAFETY: Consumer has exclusive read access to buffer[tail & mask].
+        // The slot was written by the producer (head has advanced past it).
+        // The Acquire load of `head` in `front` ensures the producer's write
+        // is visible.
+        let value =
+            unsafe { (*self.inner.buffer[tail & self.inner.mask].get()).assume_init_read() };
+        self.release(tail);
+
+        Ok(value)
+    }
+
+    /// Index of the front slot, or `None` if the ring is empty.
+    fn front(&mut self) -> Option<usize> {
         let tail = self.cached_tail;
 
         if tail == self.cached_head {
             self.cached_head = self.inner.head.load(Ordering::Acquire);
             if tail == self.cached_head {
-                return Err(Empty);
+                return None;
             }
         }
 
-        // SAFETY: Consumer has exclusive read access to buffer[tail & mask].
-        // The slot was written by the producer (head has advanced past it).
-        // The Acquire load of `head` above ensures the producer's write is
-        // visible.
-        let value =
-            unsafe { (*self.inner.buffer[tail & self.inner.mask].get()).assume_init_read() };
+        Some(tail)
+    }
 
+    fn release(&mut self, tail: usize) {
         self.inner
             .tail
             .store(tail.wrapping_add(1), Ordering::Release);
         self.cached_tail = tail.wrapping_add(1);
-
-        Ok(value)
     }
 
     pub fn capacity(&self) -> usize {
         self.inner.capacity
     }
+
+    /// Items currently queued. Approximate: the producer may push right after
+    /// the read, so this is a lower bound by the time it's returned.
+    pub fn len(&self) -> usize {
+        let head = self.inner.head.load(Ordering::Acquire);
+        head.wrapping_sub(self.cached_tail)
+    }
+
+    /// Approximate, like `len`; a `pop` may still succeed afterwards.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Approximate, like `len`.
+    pub fn is_full(&self) -> bool {
+        self.len() == self.inner.capacity
+    }
 }
 
+/// Panics unless `capacity` is a nonzero power of two; see
+/// `ring_buffer_checked` for capacities read from config.
 pub fn ring_buffer<T: Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
-    assert!(
-        capacity > 0,
-        "ring buffer capacity must be greater than zero"
-    );
-    assert!(
-        capacity.is_power_of_two(),
-        "ring buffer capacity must be a power of two, got {capacity}"
-    );
+    check_capacity(capacity);
+    build(capacity)
+}
 
+/// Like `ring_buffer`, but returns an error instead of panicking.
+pub fn ring_buffer_checked<T: Send>(
+    capacity: usize,
+) -> Result<(Producer<T>, Consumer<T>), RingError> {
+    validate_capacity(capacity)?;
+    Ok(build(capacity))
+}
+
+/// Rounds `capacity` up to the next power of two, so the ring holds at least
+/// that many items. Check `Producer::capacity` for the size actually used.
+pub fn ring_buffer_rounded<T: Send>(
+    capacity: usize,
+) -> Result<(Producer<T>, Consumer<T>), RingError> {
+    if capacity == 0 {
+        return Err(RingError::ZeroCapacity);
+    }
+    let rounded = capacity
+        .checked_next_power_of_two()
+        .ok_or(RingError::CapacityOverflow(capacity))?;
+    Ok(build(rounded))
+}
+
+fn build<T: Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
     let mut buffer = Vec::with_capacity(capacity);
     for _ in 0..capacity {
         buffer.push(UnsafeCell::new(MaybeUninit::uninit()));
     }
 
+    split(Storage::Owned(buffer.into_boxed_slice()))
+}
+
+/// Builds the ring over caller-provided storage, e.g. a leaked hugepage or
+/// NUMA-local allocation. The capacity is `storage.len()` and must be a power
+/// of two. Existing slot contents are treated as uninitialized; items still
+/// queued when both endpoints drop are dropped in place, and the storage is
+/// never freed by the ring.
+pub fn ring_buffer_in<T: Send + 'static>(
+    storage: &'static mut [Slot<T>],
+) -> (Producer<T>, Consumer<T>) {
+    check_capacity(storage.len());
+    split(Storage::Borrowed(NonNull::from(storage)))
+}
+
+fn validate_capacity(capacity: usize) -> Result<(), RingError> {
+    if capacity == 0 {
+        return Err(RingError::ZeroCapacity);
+    }
+    if !capacity.is_power_of_two() {
+        return Err(RingError::NotPowerOfTwo(capacity));
+    }
+    Ok(())
+}
+
+fn check_capacity(capacity: usize) {
+    if let Err(e) = validate_capacity(capacity) {
+        panic!("{e}");
+    }
+}
+
+fn split<T: Send>(buffer: Storage<T>) -> (Producer<T>, Consumer<T>) {
+    let capacity = buffer.len();
     let inner = Arc::new(RingBufferInner {
-        buffer: buffer.into_boxed_slice(),
+        buffer,
         capacity,
         mask: capacity - 1,
         head: CachePadded::new(AtomicUsize::new(0)),
@@ -196,6 +364,196 @@ pub fn ring_buffer<T: Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
     (producer, consumer)
 }
 
+struct MpscSlot<T> {
+    /// Vyukov sequence: equals the claiming position when the slot is free
+    /// for a producer, and position + 1 once the value is published.
+    seq: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+struct MpscInner<T> {
+    buffer: Box<[MpscSlot<T>]>,
+    capacity: usize,
+    mask: usize,
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+}
+
+// SAFETY: Producers only touch a slot after winning the CAS on `head` for its
+// position and hand it over by publishing `seq`; the single consumer only reads
+// a slot whose `seq` says it was published, and hands it back the same way.
+unsafe impl<T: Send> Sync for MpscInner<T> {}
+
+impl<T> Drop for MpscInner<T> {
+    fn drop(&mut self) {
+        let head = *self.head.get_mut();
+        let mut tail = *self.tail.get_mut();
+
+        // SAFETY: Exclusive `&mut self` access; every slot in `tail..head`
+        // was claimed, and with all producers gone each claim was published.
+        while tail != head {
+            unsafe { (*self.buffer[tail & self.mask].value.get()).assume_init_drop() };
+            tail = tail.wrapping_add(1);
+        }
+    }
+}
+
+/// Cloneable producer of an MPSC ring. Each push claims a slot with a CAS on
+/// the shared head, so it costs more than `Producer::push`.
+pub struct MpscProducer<T> {
+    inner: Arc<MpscInner<T>>,
+}
+
+// SAFETY: Producers coordinate through the `head` CAS and per-slot sequence,
+// so any number of them may run on different threads as long as T: Send.
+unsafe impl<T: Send> Send for MpscProducer<T> {}
+
+impl<T> Clone for MpscProducer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T> MpscProducer<T> {
+    pub fn push(&self, value: T) -> Result<(), Full<T>> {
+        let mut pos = self.inner.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.inner.buffer[pos & self.inner.mask];
+            let seq = slot.seq.load(Ordering::Acquire);
+            let lag = seq.wrapping_sub(pos) as isize;
+
+            if lag == 0 {
+                match self.inner.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: Winning the CAS gives this producer sole
+                        // ownership of the slot until `seq` is published; the
+                        // Acquire load of `seq` saw the consumer release it.
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if lag < 0 {
+                // The consumer hasn't released this slot from the last lap.
+                return Err(Full(value));
+            } else {
+                pos = self.inner.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity
+    }
+
+    /// Slots claimed but not yet popped. Approximate: other producers and the
+    /// consumer keep moving, and claimed slots may not be published yet.
+    pub fn len(&self) -> usize {
+        let tail = self.inner.tail.load(Ordering::Acquire);
+        self.inner.head.load(Ordering::Acquire).wrapping_sub(tail)
+    }
+
+    /// Approximate, like `len`.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The single consumer of an MPSC ring. Items from one producer come out in
+/// the order it pushed them; items from different producers interleave in
+/// the order their slots were claimed.
+pub struct MpscConsumer<T> {
+    inner: Arc<MpscInner<T>>,
+    tail: usize,
+}
+
+// SAFETY: MpscConsumer is the sole reader and can't be cloned.
+unsafe impl<T: Send> Send for MpscConsumer<T> {}
+
+impl<T> MpscConsumer<T> {
+    /// Returns `Empty` if the next slot is claimed but not yet published, even
+    /// when later slots are ready, so per-position order is kept.
+    pub fn pop(&mut self) -> Result<T, Empty> {
+        let pos = self.tail;
+        let slot = &self.inner.buffer[pos & self.inner.mask];
+        if slot.seq.load(Ordering::Acquire) != pos.wrapping_add(1) {
+            return Err(Empty);
+        }
+
+        // SAFETY: `seq == pos + 1` means the producer that claimed `pos`
+        // published its write, and the Acquire load makes it visible.
+        let value = unsafe { (*slot.value.get()).assume_init_read() };
+        slot.seq
+            .store(pos.wrapping_add(self.inner.capacity), Ordering::Release);
+        self.tail = pos.wrapping_add(1);
+        self.inner.tail.store(self.tail, Ordering::Release);
+
+        Ok(value)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity
+    }
+
+    /// Slots claimed by producers and not yet popped. Approximate, like
+    /// `MpscProducer::len`.
+    pub fn len(&self) -> usize {
+        self.inner
+            .head
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.tail)
+    }
+
+    /// Approximate, like `len`.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Multi-producer single-consumer ring for several ingest threads feeding one
+/// matching thread. Separate from `ring_buffer`, which stays the lower-latency
+/// choice when there is only one producer.
+///
+/// Panics unless `capacity` is a power of two of at least 2: with one slot,
+/// a full slot's sequence number reads as free to the next push.
+pub fn mpsc_ring_buffer<T: Send>(capacity: usize) -> (MpscProducer<T>, MpscConsumer<T>) {
+    check_capacity(capacity);
+    assert!(
+        capacity >= 2,
+        "mpsc ring buffer capacity must be at least 2, got {capacity}"
+    );
+
+    let buffer = (0..capacity)
+        .map(|i| MpscSlot {
+            seq: AtomicUsize::new(i),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        })
+        .collect();
+
+    let inner = Arc::new(MpscInner {
+        buffer,
+        capacity,
+        mask: capacity - 1,
+        head: CachePadded::new(AtomicUsize::new(0)),
+        tail: CachePadded::new(AtomicUsize::new(0)),
+    });
+
+    (
+        MpscProducer {
+            inner: Arc::clone(&inner),
+        },
+        MpscConsumer { inner, tail: 0 },
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -274,6 +632,42 @@ mod tests {
         assert_eq!(c.capacity(), 16);
     }
 
+    #[test]
+    fn len_tracks_occupancy() {
+        let (mut p, mut c) = ring_buffer::<u64>(4);
+        assert!(p.is_empty() && c.is_empty());
+
+        p.push(1).unwrap();
+        p.push(2).unwrap();
+        assert_eq!(p.len(), 2);
+        assert_eq!(c.len(), 2);
+
+        p.push(3).unwrap();
+        p.push(4).unwrap();
+        assert!(p.is_full() && c.is_full());
+
+        c.pop().unwrap();
+        assert_eq!(p.len(), 3);
+        assert_eq!(c.len(), 3);
+        assert!(!p.is_full());
+
+        while c.pop().is_ok() {}
+        assert!(p.is_empty() && c.is_empty());
+    }
+
+    #[test]
+    fn len_across_wraparound() {
+        let (mut p, mut c) = ring_buffer::<u64>(4);
+        for i in 0..10 {
+            p.push(i).unwrap();
+            p.push(i).unwrap();
+            assert_eq!(c.len(), 2);
+            c.pop().unwrap();
+            c.pop().unwrap();
+            assert_eq!(p.len(), 0);
+        }
+    }
+
     #[test]
     #[should_panic(expected = "greater than zero")]
     fn zero_capacity_panics() {
@@ -286,6 +680,144 @@ mod tests {
         ring_buffer::<u64>(3);
     }
 
+    #[test]
+    fn checked_rejects_bad_capacities() {
+        assert_eq!(
+            ring_buffer_checked::<u64>(0).err(),
+            Some(RingError::ZeroCapacity)
+        );
+        assert_eq!(
+            ring_buffer_checked::<u64>(100).err(),
+            Some(RingError::NotPowerOfTwo(100))
+        );
+        let (mut p, mut c) = ring_buffer_checked::<u64>(8).unwrap();
+        assert_eq!(p.capacity(), 8);
+        p.push(5).unwrap();
+        assert_eq!(c.pop().unwrap(), 5);
+    }
+
+    #[test]
+    fn rounded_picks_next_power_of_two() {
+        assert_eq!(ring_buffer_rounded::<u64>(100).unwrap().0.capacity(), 128);
+        assert_eq!(ring_buffer_rounded::<u64>(64).unwrap().0.capacity(), 64);
+        assert_eq!(ring_buffer_rounded::<u64>(1).unwrap().0.capacity(), 1);
+        assert_eq!(
+            ring_buffer_rounded::<u64>(0).err(),
+            Some(RingError::ZeroCapacity)
+        );
+        assert_eq!(
+            ring_buffer_rounded::<u64>(usize::MAX).err(),
+            Some(RingError::CapacityOverflow(usize::MAX))
+        );
+    }
+
+    fn leaked_slots<T>(n: usize) -> &'static mut [Slot<T>] {
+        let slots: Vec<Slot<T>> = (0..n)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect();
+        Box::leak(slots.into_boxed_slice())
+    }
+
+    #[test]
+    fn ring_in_external_storage() {
+        let storage = leaked_slots::<u64>(4);
+        let base = storage.as_ptr();
+        let (mut p, mut c) = ring_buffer_in(storage);
+        assert_eq!(p.capacity(), 4);
+
+        for round in 0..3 {
+            for i in 0..4 {
+                p.push(round * 4 + i).unwrap();
+            }
+            assert!(p.push(99).is_err());
+            for i in 0..4 {
+                assert_eq!(c.pop().unwrap(), round * 4 + i);
+            }
+        }
+        assert_eq!(c.inner.buffer.as_ptr(), base);
+    }
+
+    #[test]
+    fn ring_in_drops_remaining_items() {
+        let drop_count = Arc::new(StdAtomicUsize::new(0));
+
+        struct DropCounter(Arc<StdAtomicUsize>);
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        {
+            let (mut p, mut c) = ring_buffer_in(leaked_slots::<DropCounter>(2));
+            p.push(DropCounter(Arc::clone(&drop_count))).unwrap();
+            p.push(DropCounter(Arc::clone(&drop_count))).unwrap();
+            c.advance().unwrap();
+        }
+
+        assert_eq!(drop_count.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "power of two")]
+    fn ring_in_non_power_of_two_panics() {
+        ring_buffer_in(leaked_slots::<u64>(6));
+    }
+
+    #[test]
+    fn peek_does_not_consume() {
+        let (mut p, mut c) = ring_buffer::<u64>(4);
+        assert!(c.peek().is_none());
+
+        p.push(1).unwrap();
+        p.push(2).unwrap();
+        assert_eq!(c.peek(), Some(&1));
+        assert_eq!(c.peek(), Some(&1));
+
+        c.advance().unwrap();
+        assert_eq!(c.peek(), Some(&2));
+        assert_eq!(c.pop().unwrap(), 2);
+        assert!(c.advance().is_err());
+    }
+
+    #[test]
+    fn advance_frees_slot_for_producer() {
+        let (mut p, mut c) = ring_buffer::<u64>(2);
+        p.push(1).unwrap();
+        p.push(2).unwrap();
+
+        assert_eq!(c.peek(), Some(&1));
+        assert!(p.push(3).is_err());
+
+        c.advance().unwrap();
+        p.push(3).unwrap();
+        assert_eq!(c.pop().unwrap(), 2);
+        assert_eq!(c.pop().unwrap(), 3);
+    }
+
+    #[test]
+    fn advance_drops_value() {
+        let drop_count = Arc::new(StdAtomicUsize::new(0));
+
+        struct DropCounter(Arc<StdAtomicUsize>);
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let (mut p, mut c) = ring_buffer::<DropCounter>(4);
+        p.push(DropCounter(Arc::clone(&drop_count))).unwrap();
+        p.push(DropCounter(Arc::clone(&drop_count))).unwrap();
+
+        assert!(c.peek().is_some());
+        c.advance().unwrap();
+        assert_eq!(drop_count.load(Ordering::Relaxed), 1);
+
+        drop((p, c));
+        assert_eq!(drop_count.load(Ordering::Relaxed), 2);
+    }
+
     #[test]
     fn drop_remaining_items() {
         let drop_count = Arc::new(StdAtomicUsize::new(0));
@@ -307,6 +839,95 @@ mod tests {
         assert_eq!(drop_count.load(Ordering::Relaxed), 3);
     }
 
+    #[test]
+    fn mpsc_push_pop_and_full() {
+        let (p, mut c) = mpsc_ring_buffer::<u64>(4);
+        let p2 = p.clone();
+        assert!(c.pop().is_err());
+
+        for round in 0..3 {
+            p.push(round * 10).unwrap();
+            p2.push(round * 10 + 1).unwrap();
+            p.push(round * 10 + 2).unwrap();
+            p2.push(round * 10 + 3).unwrap();
+            assert!(p.push(99).is_err());
+            assert_eq!(c.len(), 4);
+
+            for i in 0..4 {
+                assert_eq!(c.pop().unwrap(), round * 10 + i);
+            }
+            assert!(c.is_empty());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 2")]
+    fn mpsc_capacity_one_panics() {
+        mpsc_ring_buffer::<u64>(1);
+    }
+
+    #[test]
+    fn mpsc_drop_remaining_items() {
+        let drop_count = Arc::new(StdAtomicUsize::new(0));
+
+        struct DropCounter(Arc<StdAtomicUsize>);
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        {
+            let (p, mut c) = mpsc_ring_buffer::<DropCounter>(4);
+            for _ in 0..3 {
+                p.push(DropCounter(Arc::clone(&drop_count))).unwrap();
+            }
+            drop(c.pop().unwrap());
+        }
+
+        assert_eq!(drop_count.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn mpsc_concurrent_producers() {
+        const PRODUCERS: u64 = 4;
+        const PER_PRODUCER: u64 = 50_000;
+
+        let (p, mut c) = mpsc_ring_buffer::<(u64, u64)>(64);
+        let handles: Vec<_> = (0..PRODUCERS)
+            .map(|id| {
+                let p = p.clone();
+                thread::spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        while p.push((id, i)).is_err() {
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(p);
+
+        let mut next = [0u64; PRODUCERS as usize];
+        for _ in 0..PRODUCERS * PER_PRODUCER {
+            let (id, i) = loop {
+                match c.pop() {
+                    Ok(v) => break v,
+                    Err(_) => thread::yield_now(),
+                }
+            };
+            // Per-producer FIFO rules out both loss and duplication
+            assert_eq!(i, next[id as usize]);
+            next[id as usize] += 1;
+        }
+
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert!(c.pop().is_err());
+        assert_eq!(next, [PER_PRODUCER; PRODUCERS as usize]);
+    }
+
     #[test]
     fn concurrent_push_pop() {
         let (mut p, mut c) = ring_buffer::<u64>(1024);
@@ -380,10 +1001,14 @@ mod tests {
 
         let producer = thread::spawn(move || {
             for i in 0..count {
-                let order = Order::new(
+                let order = Order::try_new(
                     i,
                     i % 100,
-                    if i % 2 == 0 { Side::Bid } else { Side::Ask },
+                    if i.is_multiple_of(2) {
+                        Side::Bid
+                    } else {
+                        Side::Ask
+                    },
                     10_000 + (i as i64 % 500),
                     (i % 1000) + 1,
                     i * 1000,
@@ -414,7 +1039,14 @@ mod tests {
             let i = i as u64;
             assert_eq!(order.id, i);
             assert_eq!(order.trader_id, i % 100);
-            assert_eq!(order.side, if i % 2 == 0 { Side::Bid } else { Side::Ask });
+            assert_eq!(
+                order.side,
+                if i.is_multiple_of(2) {
+                    Side::Bid
+                } else {
+                    Side::Ask
+                }
+            );
             assert_eq!(order.price, 10_000 + (i as i64 % 500));
             assert_eq!(order.quantity, (i % 1000) + 1);
             assert_eq!(order.timestamp, i * 1000);
@@ -1,37 +1,66 @@
-use std::fs;
-use std::io;
+use std::fs::{self, File};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
+use std::num::NonZeroU64;
+
 use serde::{Deserialize, Serialize};
 
-use crate::matching::MatchingEngine;
-use crate::order::Order;
+use crate::book::{LevelQueue, OrderBook};
+use crate::checksum::Algorithm;
+use crate::matching::{
+    AmendPolicy, BookDelta, CrossPolicy, FillPricing, HaltPolicy, MatchingEngine, MatchingError,
+};
+use crate::order::{Order, Side};
 
 #[derive(Debug)]
 pub(crate) enum SnapshotError {
     Io(io::Error),
-    Serialize(String),
     Deserialize(String),
-    ChecksumMismatch { expected: u32, actual: u32 },
-    Restore(String),
+    ChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// The file header names a format version this build can't read.
+    /// `supported` is the newest it can.
+    UnsupportedVersion {
+        found: u32,
+        supported: u32,
+    },
+    /// The file header names a checksum this build doesn't know.
+    UnsupportedChecksum(u32),
+    /// The engine refused to rebuild the book from the file's orders.
+    Restore(MatchingError),
 }
 
 impl std::fmt::Display for SnapshotError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Io(e) => write!(f, "snapshot io error: {e}"),
-            Self::Serialize(e) => write!(f, "snapshot serialize error: {e}"),
             Self::Deserialize(e) => write!(f, "snapshot deserialize error: {e}"),
             Self::ChecksumMismatch { expected, actual } => write!(
                 f,
                 "snapshot checksum mismatch: expected {expected:#010x}, got {actual:#010x}"
             ),
+            Self::UnsupportedVersion { found, supported } => write!(
+                f,
+                "snapshot format version {found} is not supported, this build reads {OLDEST_HEADER_VERSION} to {supported}"
+            ),
+            Self::UnsupportedChecksum(id) => write!(f, "unsupported snapshot checksum id {id}"),
             Self::Restore(e) => write!(f, "snapshot restore error: {e}"),
         }
     }
 }
 
-impl std::error::Error for SnapshotError {}
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Restore(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl From<io::Error> for SnapshotError {
     fn from(e: io::Error) -> Self {
@@ -39,71 +68,121 @@ impl From<io::Error> for SnapshotError {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Format written in the file header. Version 1 files have no header: bare
+/// bincode of `LegacySnapshotV1`, optionally behind `ZSTD_MAGIC`. Full
+/// snapshots are still read, through `SnapshotFile::migrate_v1`. Version 3 added the amend
+/// policy, version 4 the cross policy, version 5 the last trade price and
+/// version 6 the auction flag and the header's checksum id.
+const FORMAT_VERSION: u32 = 6;
+
+/// The first version with a header.
+const OLDEST_HEADER_VERSION: u32 = 2;
+
+/// Magic, version, compression and checksum id, each field LE. Headers
+/// before version 6 stop after compression and use CRC-32.
+const HEADER_SIZE: usize = 20;
+
+const COMPRESSION_NONE: u32 = 0;
+const COMPRESSION_ZSTD: u32 = 1;
+
+/// Prefix of a zstd-compressed version 1 file.
+const ZSTD_MAGIC: &[u8; 4] = b"FXZS";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SnapshotCompression {
+    #[default]
+    None,
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+}
+
+#[derive(Debug, Clone)]
 pub(crate) struct Snapshot {
     pub(crate) wal_record_count: u64,
-    pub(crate) orders: Vec<Order>,
+    /// Asks ascending then bids descending, each queue head first.
+    pub(crate) levels: Vec<LevelQueue>,
     pub(crate) best_bid: Option<i64>,
     pub(crate) best_ask: Option<i64>,
-    /// CRC32 of bincode-serialized `orders`.
+    /// Net filled position per trader, sorted by trader id.
+    pub(crate) positions: Vec<(u64, i128)>,
+    /// Halt in force at capture, so it survives a restart past the `Halt`.
+    pub(crate) halt: Option<HaltPolicy>,
+    /// Fill pricing in force at capture, applied to the WAL replayed after it.
+    pub(crate) fill_pricing: FillPricing,
+    /// Amend policy in force at capture, like `fill_pricing`. Not in version
+    /// 1 files, which read it as the default.
+    pub(crate) amend_policy: AmendPolicy,
+    /// Cross policy in force at capture, like `amend_policy`.
+    pub(crate) cross_policy: CrossPolicy,
+    /// Price of the last fill, the price-band reference while a side is
+    /// empty. Older files read it as no trade yet.
+    pub(crate) last_trade_price: Option<i64>,
+    /// Whether the engine was collecting orders for an auction uncross,
+    /// which are the crossing ones in `levels`. Older files read it as not.
+    pub(crate) in_auction: bool,
+    /// Engine sequence number for the next order.
+    pub(crate) next_seq: u64,
+    /// `book_hash` of the live book at capture, recomputed after restore.
+    pub(crate) book_hash: u32,
+    /// Checksum of `levels` as encoded in the file.
     pub(crate) checksum: u32,
+    /// What `checksum` and `book_hash` are computed with, named in the file
+    /// header.
+    pub(crate) checksum_algorithm: Algorithm,
 }
 
 impl Snapshot {
+    /// Levels are taken in `all_resting_orders` order and positions sorted
+    /// by trader, so two captures of equal engines encode to the same bytes.
     pub(crate) fn capture(engine: &MatchingEngine, wal_record_count: u64) -> Self {
-        let orders = engine.book().all_resting_orders();
+        let levels = engine.book().level_queues();
         let best_bid = engine.book().best_bid();
         let best_ask = engine.book().best_ask();
-        let checksum = Self::compute_checksum(&orders);
+        let positions = engine.trader_positions();
+        let checksum_algorithm = Algorithm::DEFAULT;
+        let book_hash = Self::book_hash(engine.book(), checksum_algorithm);
+        let checksum = Self::compute_checksum(checksum_algorithm, &levels);
 
         Self {
             wal_record_count,
-            orders,
+            levels,
             best_bid,
             best_ask,
+            positions,
+            halt: engine.halt_policy(),
+            fill_pricing: engine.fill_pricing(),
+            amend_policy: engine.amend_policy(),
+            cross_policy: engine.cross_policy(),
+            last_trade_price: engine.last_trade_price(),
+            in_auction: engine.in_auction(),
+            next_seq: engine.next_seq(),
+            book_hash,
             checksum,
+            checksum_algorithm,
         }
     }
 
-    /// Atomic save: write to temp file, then rename.
-    pub(crate) fn save(&self, dir: &Path) -> Result<PathBuf, SnapshotError> {
-        fs::create_dir_all(dir)?;
-
+    pub(crate) fn save_with(
+        &self,
+        dir: &Path,
+        compression: SnapshotCompression,
+    ) -> Result<PathBuf, SnapshotError> {
         let filename = format!("snapshot_{:010}.bin", self.wal_record_count);
-        let final_path = dir.join(&filename);
-        let tmp_path = dir.join(format!("{filename}.tmp"));
-
-        let data = bincode::serialize(self).map_err(|e| SnapshotError::Serialize(e.to_string()))?;
-
-        fs::write(&tmp_path, &data)?;
-        fs::rename(&tmp_path, &final_path)?;
-
-        Ok(final_path)
+        write_file(dir, &filename, self, compression)
     }
 
     /// Returns `Ok(None)` if the directory is empty or doesn't exist.
     pub(crate) fn load_latest(dir: &Path) -> Result<Option<Self>, SnapshotError> {
-        let entries = match fs::read_dir(dir) {
-            Ok(e) => e,
-            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
-            Err(e) => return Err(SnapshotError::Io(e)),
-        };
-
-        let mut snapshot_files: Vec<PathBuf> = entries
-            .filter_map(|e| e.ok())
-            .map(|e| e.path())
-            .filter(|p| {
-                p.file_name()
-                    .and_then(|n| n.to_str())
-                    .is_some_and(|n| n.starts_with("snapshot_") && n.ends_with(".bin"))
-            })
+        let mut snapshot_files: Vec<PathBuf> = list_files(dir, "snapshot_")?
+            .into_iter()
+            .map(|(path, _)| path)
             .collect();
 
         // Sort lexicographically — highest (most recent) last.
         snapshot_files.sort();
 
         while let Some(path) = snapshot_files.pop() {
-            match Self::load_from_file(&path) {
+            match read_file::<Self>(&path) {
                 Ok(snap) => {
                     if snap.verify_checksum().is_ok() {
                         return Ok(Some(snap));
@@ -119,13 +198,56 @@ impl Snapshot {
         Ok(None)
     }
 
+    /// Keeps the newest `keep` full snapshots (at least one) and deletes the
+    /// rest, along with deltas based before the oldest one kept. Only call
+    /// after the newest snapshot has been saved. Returns the number of files
+    /// removed.
+    pub(crate) fn prune(dir: &Path, keep: usize) -> Result<usize, SnapshotError> {
+        let mut snapshots: Vec<(u64, PathBuf)> = list_files(dir, "snapshot_")?
+            .into_iter()
+            .filter_map(|(path, stem)| Some((stem.parse().ok()?, path)))
+            .collect();
+        snapshots.sort();
+
+        let stale = snapshots.len().saturating_sub(keep.max(1));
+        let Some(&(oldest_kept, _)) = snapshots.get(stale) else {
+            return Ok(0);
+        };
+
+        let mut removed = 0;
+        for (_, path) in &snapshots[..stale] {
+            fs::remove_file(path)?;
+            removed += 1;
+        }
+        for (path, stem) in list_files(dir, "delta_")? {
+            let base = stem
+                .split_once('_')
+                .and_then(|(base, _)| base.parse::<u64>().ok());
+            if base.is_some_and(|base| base < oldest_kept) {
+                fs::remove_file(path)?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
     pub(crate) fn restore(&self, arena_capacity: u32) -> Result<MatchingEngine, SnapshotError> {
-        MatchingEngine::restore_from_orders(&self.orders, arena_capacity)
-            .map_err(|e| SnapshotError::Restore(format!("{e:?}")))
+        let mut engine = MatchingEngine::restore_exact(&self.levels, arena_capacity)
+            .map_err(SnapshotError::Restore)?;
+        engine.restore_auction(self.in_auction);
+        engine.restore_positions(&self.positions);
+        restore_halt(&mut engine, self.halt);
+        engine.set_fill_pricing(self.fill_pricing);
+        engine.set_amend_policy(self.amend_policy);
+        engine.set_cross_policy(self.cross_policy);
+        engine.restore_last_trade_price(self.last_trade_price);
+        engine.restore_next_seq(self.next_seq);
+        Ok(engine)
     }
 
     pub(crate) fn verify_checksum(&self) -> Result<(), SnapshotError> {
-        let actual = Self::compute_checksum(&self.orders);
+        let actual = Self::compute_checksum(self.checksum_algorithm, &self.levels);
         if self.checksum == actual {
             Ok(())
         } else {
@@ -136,30 +258,813 @@ impl Snapshot {
         }
     }
 
-    fn compute_checksum(orders: &[Order]) -> u32 {
-        let bytes =
-            bincode::serialize(orders).expect("serializing orders for checksum should not fail");
-        crc32fast::hash(&bytes)
+    pub(crate) fn order_count(&self) -> usize {
+        self.levels.iter().map(|l| l.orders.len()).sum()
+    }
+
+    fn compute_checksum(algorithm: Algorithm, levels: &[LevelQueue]) -> u32 {
+        let mut e = Encoder::default();
+        e.levels(levels);
+        algorithm.checksum(&[&e.0])
+    }
+
+    /// Checksum over the book as the book itself reports it: resting orders
+    /// in queue order, best prices and order count.
+    pub(crate) fn book_hash(book: &OrderBook, algorithm: Algorithm) -> u32 {
+        let mut e = Encoder::default();
+        let orders = book.all_resting_orders();
+        e.len(orders.len());
+        for order in &orders {
+            e.order(order);
+        }
+        e.opt_i64(book.best_bid());
+        e.opt_i64(book.best_ask());
+        e.u64(book.order_count() as u64);
+        algorithm.checksum(&[&e.0])
+    }
+}
+
+/// A version 1 snapshot: the resting orders in `all_resting_orders` order
+/// and the best prices, with no header and nothing else of the engine.
+#[derive(Serialize, Deserialize)]
+struct LegacySnapshotV1 {
+    wal_record_count: u64,
+    orders: Vec<LegacyOrderV1>,
+    best_bid: Option<i64>,
+    best_ask: Option<i64>,
+    /// CRC-32 of `orders` as bincode.
+    checksum: u32,
+}
+
+/// `Order` as version 1 wrote it, before symbols, expiry and order flags.
+#[derive(Serialize, Deserialize)]
+struct LegacyOrderV1 {
+    id: u64,
+    trader_id: u64,
+    side: Side,
+    price: i64,
+    quantity: u64,
+    timestamp: u64,
+}
+
+impl SnapshotFile for Snapshot {
+    const MAGIC: &'static [u8; 8] = b"FRXSNP01";
+
+    fn encode(&self, e: &mut Encoder) {
+        e.u64(self.wal_record_count);
+        e.levels(&self.levels);
+        e.opt_i64(self.best_bid);
+        e.opt_i64(self.best_ask);
+        e.positions(&self.positions);
+        e.halt(self.halt);
+        e.u8(match self.fill_pricing {
+            FillPricing::MakerPrice => 0,
+            FillPricing::Midpoint => 1,
+        });
+        e.u8(match self.amend_policy {
+            AmendPolicy::ResetPriority => 0,
+            AmendPolicy::KeepOnDecrease => 1,
+            AmendPolicy::KeepOnSamePrice => 2,
+        });
+        e.u8(match self.cross_policy {
+            CrossPolicy::AtEqualPrice => 0,
+            CrossPolicy::StrictlyThrough => 1,
+        });
+        e.opt_i64(self.last_trade_price);
+        e.bool(self.in_auction);
+        e.u64(self.next_seq);
+        e.u32(self.book_hash);
+        e.u32(self.checksum);
+    }
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, SnapshotError> {
+        Ok(Self {
+            wal_record_count: d.u64()?,
+            levels: d.levels()?,
+            best_bid: d.opt_i64()?,
+            best_ask: d.opt_i64()?,
+            positions: d.positions()?,
+            halt: d.halt()?,
+            fill_pricing: match d.u8()? {
+                0 => FillPricing::MakerPrice,
+                1 => FillPricing::Midpoint,
+                n => return Err(d.invalid("fill pricing", n)),
+            },
+            amend_policy: match d.version {
+                ..3 => AmendPolicy::default(),
+                _ => match d.u8()? {
+                    0 => AmendPolicy::ResetPriority,
+                    1 => AmendPolicy::KeepOnDecrease,
+                    2 => AmendPolicy::KeepOnSamePrice,
+                    n => return Err(d.invalid("amend policy", n)),
+                },
+            },
+            cross_policy: match d.version {
+                ..4 => CrossPolicy::default(),
+                _ => match d.u8()? {
+                    0 => CrossPolicy::AtEqualPrice,
+                    1 => CrossPolicy::StrictlyThrough,
+                    n => return Err(d.invalid("cross policy", n)),
+                },
+            },
+            last_trade_price: match d.version {
+                ..5 => None,
+                _ => d.opt_i64()?,
+            },
+            in_auction: match d.version {
+                ..6 => false,
+                _ => d.bool("auction flag")?,
+            },
+            next_seq: d.u64()?,
+            book_hash: d.u32()?,
+            checksum: d.u32()?,
+            checksum_algorithm: d.checksum,
+        })
+    }
+
+    /// Checks the bincode checksum, then queues the orders in file order
+    /// with fresh seqs. The rest of the engine state takes its defaults, as
+    /// version 1 had none of it.
+    fn checksum_algorithm(&self) -> Algorithm {
+        self.checksum_algorithm
+    }
+
+    fn migrate_v1(raw: &[u8]) -> Result<Self, SnapshotError> {
+        let legacy: LegacySnapshotV1 =
+            bincode::deserialize(raw).map_err(|e| SnapshotError::Deserialize(e.to_string()))?;
+        let checksum =
+            bincode::serialize(&legacy.orders).map_or(0, |b| Algorithm::Crc32.checksum(&[&b]));
+        if checksum != legacy.checksum {
+            return Err(SnapshotError::ChecksumMismatch {
+                expected: legacy.checksum,
+                actual: checksum,
+            });
+        }
+
+        let mut levels: Vec<LevelQueue> = Vec::new();
+        for (seq, o) in (1..).zip(&legacy.orders) {
+            let order = Order::try_new(o.id, o.trader_id, o.side, o.price, o.quantity, o.timestamp)
+                .map_err(|e| SnapshotError::Deserialize(format!("order {}: {e}", o.id)))?;
+            match levels.last_mut() {
+                Some(level) if level.side == o.side && level.price == o.price => {
+                    level.orders.push((order, seq));
+                }
+                _ => levels.push(LevelQueue {
+                    side: o.side,
+                    price: o.price,
+                    orders: vec![(order, seq)],
+                }),
+            }
+        }
+
+        let mut snap = Self {
+            wal_record_count: legacy.wal_record_count,
+            best_bid: legacy.best_bid,
+            best_ask: legacy.best_ask,
+            positions: Vec::new(),
+            halt: None,
+            fill_pricing: FillPricing::default(),
+            amend_policy: AmendPolicy::default(),
+            cross_policy: CrossPolicy::default(),
+            last_trade_price: None,
+            in_auction: false,
+            next_seq: legacy.orders.len() as u64 + 1,
+            book_hash: 0,
+            checksum: Self::compute_checksum(Algorithm::DEFAULT, &levels),
+            checksum_algorithm: Algorithm::DEFAULT,
+            levels,
+        };
+        let capacity = u32::try_from(snap.order_count()).unwrap_or(u32::MAX - 1);
+        snap.book_hash = Self::book_hash(snap.restore(capacity.max(1))?.book(), Algorithm::DEFAULT);
+        Ok(snap)
+    }
+}
+
+/// The changes between two captures. Named `delta_<base>_<count>.bin`: it
+/// turns the state at WAL record `base_record_count` (a full snapshot or the
+/// previous delta) into the state at `wal_record_count`.
+#[derive(Debug, Clone)]
+pub(crate) struct DeltaSnapshot {
+    pub(crate) base_record_count: u64,
+    pub(crate) wal_record_count: u64,
+    pub(crate) delta: BookDelta,
+    /// Net filled position per trader, sorted by trader id. Stored in full.
+    pub(crate) positions: Vec<(u64, i128)>,
+    pub(crate) halt: Option<HaltPolicy>,
+    /// As on `Snapshot`.
+    pub(crate) last_trade_price: Option<i64>,
+    pub(crate) in_auction: bool,
+    pub(crate) next_seq: u64,
+    /// Checksum of `delta` as encoded in the file.
+    pub(crate) checksum: u32,
+    pub(crate) checksum_algorithm: Algorithm,
+}
+
+impl DeltaSnapshot {
+    /// Drains the engine's changeset; the engine must have change tracking on.
+    pub(crate) fn capture(
+        engine: &mut MatchingEngine,
+        base_record_count: u64,
+        wal_record_count: u64,
+    ) -> Self {
+        let delta = engine.take_delta();
+        let positions = engine.trader_positions();
+        let checksum_algorithm = Algorithm::DEFAULT;
+        let checksum = Self::compute_checksum(checksum_algorithm, &delta);
+
+        Self {
+            base_record_count,
+            wal_record_count,
+            delta,
+            positions,
+            halt: engine.halt_policy(),
+            last_trade_price: engine.last_trade_price(),
+            in_auction: engine.in_auction(),
+            next_seq: engine.next_seq(),
+            checksum,
+            checksum_algorithm,
+        }
+    }
+
+    pub(crate) fn save_with(
+        &self,
+        dir: &Path,
+        compression: SnapshotCompression,
+    ) -> Result<PathBuf, SnapshotError> {
+        let filename = format!(
+            "delta_{:010}_{:010}.bin",
+            self.base_record_count, self.wal_record_count
+        );
+        write_file(dir, &filename, self, compression)
+    }
+
+    /// Deltas chaining forward from `base_record_count`, oldest first. The
+    /// chain stops at the first gap or unreadable file; the WAL covers the rest.
+    pub(crate) fn load_chain(
+        dir: &Path,
+        base_record_count: u64,
+    ) -> Result<Vec<Self>, SnapshotError> {
+        let mut deltas: Vec<(u64, u64, PathBuf)> = list_files(dir, "delta_")?
+            .into_iter()
+            .filter_map(|(path, stem)| {
+                let (base, count) = stem.split_once('_')?;
+                Some((base.parse().ok()?, count.parse().ok()?, path))
+            })
+            .filter(|&(base, count, _)| count > base)
+            .collect();
+        deltas.sort();
+
+        let mut chain = Vec::new();
+        let mut next = base_record_count;
+        for (base, _, path) in deltas {
+            if base != next {
+                continue;
+            }
+            match read_file::<Self>(&path) {
+                Ok(delta) if delta.base_record_count == base && delta.verify_checksum().is_ok() => {
+                    next = delta.wal_record_count;
+                    chain.push(delta);
+                }
+                _ => break,
+            }
+        }
+
+        Ok(chain)
+    }
+
+    pub(crate) fn apply(&self, engine: &mut MatchingEngine) -> Result<(), SnapshotError> {
+        engine
+            .apply_delta(&self.delta)
+            .map_err(SnapshotError::Restore)?;
+        engine.restore_auction(self.in_auction);
+        engine.restore_positions(&self.positions);
+        restore_halt(engine, self.halt);
+        engine.restore_last_trade_price(self.last_trade_price);
+        engine.restore_next_seq(self.next_seq);
+        Ok(())
+    }
+
+    pub(crate) fn verify_checksum(&self) -> Result<(), SnapshotError> {
+        let actual = Self::compute_checksum(self.checksum_algorithm, &self.delta);
+        if self.checksum == actual {
+            Ok(())
+        } else {
+            Err(SnapshotError::ChecksumMismatch {
+                expected: self.checksum,
+                actual,
+            })
+        }
+    }
+
+    fn compute_checksum(algorithm: Algorithm, delta: &BookDelta) -> u32 {
+        let mut e = Encoder::default();
+        e.delta(delta);
+        algorithm.checksum(&[&e.0])
+    }
+}
+
+impl SnapshotFile for DeltaSnapshot {
+    const MAGIC: &'static [u8; 8] = b"FRXDLT01";
+
+    fn encode(&self, e: &mut Encoder) {
+        e.u64(self.base_record_count);
+        e.u64(self.wal_record_count);
+        e.delta(&self.delta);
+        e.positions(&self.positions);
+        e.halt(self.halt);
+        e.opt_i64(self.last_trade_price);
+        e.bool(self.in_auction);
+        e.u64(self.next_seq);
+        e.u32(self.checksum);
+    }
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, SnapshotError> {
+        Ok(Self {
+            base_record_count: d.u64()?,
+            wal_record_count: d.u64()?,
+            delta: d.delta()?,
+            positions: d.positions()?,
+            halt: d.halt()?,
+            last_trade_price: match d.version {
+                ..5 => None,
+                _ => d.opt_i64()?,
+            },
+            in_auction: match d.version {
+                ..6 => false,
+                _ => d.bool("auction flag")?,
+            },
+            next_seq: d.u64()?,
+            checksum: d.u32()?,
+            checksum_algorithm: d.checksum,
+        })
+    }
+
+    fn checksum_algorithm(&self) -> Algorithm {
+        self.checksum_algorithm
+    }
+
+    /// Refused: a version 1 delta's seqs and order layout don't carry over
+    /// to a migrated base snapshot, so the chain stops and the WAL after the
+    /// base covers the rest.
+    fn migrate_v1(_raw: &[u8]) -> Result<Self, SnapshotError> {
+        Err(SnapshotError::UnsupportedVersion {
+            found: 1,
+            supported: FORMAT_VERSION,
+        })
+    }
+}
+
+/// A file kind in the snapshot directory. Headered files are
+/// `MAGIC | version | compression | checksum id | body`, the header fields
+/// u32 LE and the body as `encode` writes it, compressed or not. Bodies are
+/// fixed-width LE fields in the order `encode` lists them, with no padding.
+/// A `Vec` is a u32 count then its items, an `Option` a 0/1 byte then the
+/// value if present, and an order its fields in declaration order, with the
+/// side as 0 bid / 1 ask, no expiry as 0, and reduce-only and post-only as
+/// bits 0 and 1 of one flags byte. A field added in a later version is
+/// skipped by `decode` for files older than it.
+trait SnapshotFile: Sized {
+    const MAGIC: &'static [u8; 8];
+
+    fn encode(&self, e: &mut Encoder);
+
+    fn decode(d: &mut Decoder<'_>) -> Result<Self, SnapshotError>;
+
+    /// Written to the header as its `Algorithm::id`.
+    fn checksum_algorithm(&self) -> Algorithm;
+
+    /// Reads a headerless version 1 file, already decompressed, or refuses
+    /// it.
+    fn migrate_v1(raw: &[u8]) -> Result<Self, SnapshotError>;
+}
+
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn i64(&mut self, v: i64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn len(&mut self, n: usize) {
+        self.u32(u32::try_from(n).expect("snapshot collection exceeds u32::MAX items"));
+    }
+
+    fn opt_i64(&mut self, v: Option<i64>) {
+        match v {
+            Some(v) => {
+                self.u8(1);
+                self.i64(v);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn bool(&mut self, v: bool) {
+        self.u8(v.into());
+    }
+
+    fn halt(&mut self, halt: Option<HaltPolicy>) {
+        self.u8(match halt {
+            None => 0,
+            Some(HaltPolicy::RejectMarketable) => 1,
+            Some(HaltPolicy::RejectAll) => 2,
+        });
+    }
+
+    fn side(&mut self, side: Side) {
+        self.u8(match side {
+            Side::Bid => 0,
+            Side::Ask => 1,
+        });
+    }
+
+    fn order(&mut self, o: &Order) {
+        self.u64(o.id.0);
+        self.u64(o.trader_id.0);
+        self.u32(o.symbol_id);
+        self.side(o.side);
+        self.i64(o.price.0);
+        self.u64(o.quantity.0);
+        self.u64(o.timestamp);
+        self.u64(o.expiry.map_or(0, NonZeroU64::get));
+        self.u8(u8::from(o.reduce_only) | u8::from(o.post_only) << 1);
+    }
+
+    fn sequenced(&mut self, orders: &[(Order, u64)]) {
+        self.len(orders.len());
+        for (order, seq) in orders {
+            self.order(order);
+            self.u64(*seq);
+        }
+    }
+
+    fn levels(&mut self, levels: &[LevelQueue]) {
+        self.len(levels.len());
+        for level in levels {
+            self.side(level.side);
+            self.i64(level.price);
+            self.sequenced(&level.orders);
+        }
+    }
+
+    fn positions(&mut self, positions: &[(u64, i128)]) {
+        self.len(positions.len());
+        for &(trader, position) in positions {
+            self.u64(trader);
+            self.0.extend_from_slice(&position.to_le_bytes());
+        }
+    }
+
+    fn delta(&mut self, delta: &BookDelta) {
+        self.len(delta.removed.len());
+        for &id in &delta.removed {
+            self.u64(id);
+        }
+        self.len(delta.modified.len());
+        for &(id, quantity) in &delta.modified {
+            self.u64(id);
+            self.u64(quantity);
+        }
+        self.sequenced(&delta.added);
+    }
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// Format version of the file being read.
+    version: u32,
+    /// Checksum named in its header.
+    checksum: Algorithm,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(data: &'a [u8], version: u32, checksum: Algorithm) -> Self {
+        Self {
+            data,
+            pos: 0,
+            version,
+            checksum,
+        }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + N)
+            .ok_or_else(|| SnapshotError::Deserialize(format!("truncated at byte {}", self.pos)))?;
+        self.pos += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn invalid(&self, what: &str, value: u8) -> SnapshotError {
+        SnapshotError::Deserialize(format!("invalid {what} {value} before byte {}", self.pos))
     }
 
-    fn load_from_file(path: &Path) -> Result<Self, SnapshotError> {
-        let data = fs::read(path)?;
-        bincode::deserialize(&data).map_err(|e| SnapshotError::Deserialize(e.to_string()))
+    fn finish(&self) -> Result<(), SnapshotError> {
+        if self.pos == self.data.len() {
+            Ok(())
+        } else {
+            Err(SnapshotError::Deserialize(format!(
+                "{} trailing bytes",
+                self.data.len() - self.pos
+            )))
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, SnapshotError> {
+        Ok(i64::from_le_bytes(self.take()?))
+    }
+
+    /// A count, checked against the bytes left so a corrupt one can't
+    /// allocate more than the file could hold.
+    fn len(&mut self, min_item_size: usize) -> Result<usize, SnapshotError> {
+        let n = self.u32()? as usize;
+        if n.saturating_mul(min_item_size) > self.data.len() - self.pos {
+            return Err(SnapshotError::Deserialize(format!(
+                "count {n} before byte {} overruns the file",
+                self.pos
+            )));
+        }
+        Ok(n)
+    }
+
+    fn opt_i64(&mut self) -> Result<Option<i64>, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.i64()?)),
+            n => Err(self.invalid("option tag", n)),
+        }
+    }
+
+    fn bool(&mut self, what: &str) -> Result<bool, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            n => Err(self.invalid(what, n)),
+        }
+    }
+
+    fn halt(&mut self) -> Result<Option<HaltPolicy>, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(HaltPolicy::RejectMarketable)),
+            2 => Ok(Some(HaltPolicy::RejectAll)),
+            n => Err(self.invalid("halt policy", n)),
+        }
+    }
+
+    fn side(&mut self) -> Result<Side, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(Side::Bid),
+            1 => Ok(Side::Ask),
+            n => Err(self.invalid("side", n)),
+        }
+    }
+
+    fn order(&mut self) -> Result<Order, SnapshotError> {
+        let mut order = Order {
+            id: self.u64()?.into(),
+            trader_id: self.u64()?.into(),
+            symbol_id: self.u32()?,
+            side: self.side()?,
+            price: self.i64()?.into(),
+            quantity: self.u64()?.into(),
+            timestamp: self.u64()?,
+            expiry: NonZeroU64::new(self.u64()?),
+            reduce_only: false,
+            post_only: false,
+        };
+        match self.u8()? {
+            flags @ 0..=3 => {
+                order.reduce_only = flags & 1 != 0;
+                order.post_only = flags & 2 != 0;
+                Ok(order)
+            }
+            n => Err(self.invalid("order flags", n)),
+        }
+    }
+
+    fn sequenced(&mut self) -> Result<Vec<(Order, u64)>, SnapshotError> {
+        (0..self.len(ORDER_SIZE + 8)?)
+            .map(|_| Ok((self.order()?, self.u64()?)))
+            .collect()
+    }
+
+    fn levels(&mut self) -> Result<Vec<LevelQueue>, SnapshotError> {
+        (0..self.len(13)?)
+            .map(|_| {
+                Ok(LevelQueue {
+                    side: self.side()?,
+                    price: self.i64()?,
+                    orders: self.sequenced()?,
+                })
+            })
+            .collect()
+    }
+
+    fn positions(&mut self) -> Result<Vec<(u64, i128)>, SnapshotError> {
+        (0..self.len(24)?)
+            .map(|_| Ok((self.u64()?, i128::from_le_bytes(self.take()?))))
+            .collect()
+    }
+
+    fn delta(&mut self) -> Result<BookDelta, SnapshotError> {
+        let removed = (0..self.len(8)?)
+            .map(|_| self.u64())
+            .collect::<Result<_, _>>()?;
+        let modified = (0..self.len(16)?)
+            .map(|_| Ok((self.u64()?, self.u64()?)))
+            .collect::<Result<_, SnapshotError>>()?;
+        Ok(BookDelta {
+            removed,
+            modified,
+            added: self.sequenced()?,
+        })
+    }
+}
+
+/// Encoded size of one `Order`.
+const ORDER_SIZE: usize = 54;
+
+fn restore_halt(engine: &mut MatchingEngine, halt: Option<HaltPolicy>) {
+    match halt {
+        Some(policy) => engine.halt(policy),
+        None => engine.resume(),
+    }
+}
+
+/// Atomic save: write and fsync a temp file, rename it into place, then
+/// fsync the directory so the rename itself is durable.
+fn write_file<T: SnapshotFile>(
+    dir: &Path,
+    filename: &str,
+    value: &T,
+    compression: SnapshotCompression,
+) -> Result<PathBuf, SnapshotError> {
+    fs::create_dir_all(dir)?;
+
+    let final_path = dir.join(filename);
+    let tmp_path = dir.join(format!("{filename}.tmp"));
+
+    let mut body = Encoder::default();
+    value.encode(&mut body);
+    let mut data = Vec::with_capacity(HEADER_SIZE + body.0.len());
+    data.extend_from_slice(T::MAGIC);
+    data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    let compression_id = match compression {
+        SnapshotCompression::None => COMPRESSION_NONE,
+        #[cfg(feature = "zstd")]
+        SnapshotCompression::Zstd { .. } => COMPRESSION_ZSTD,
+    };
+    data.extend_from_slice(&compression_id.to_le_bytes());
+    data.extend_from_slice(&value.checksum_algorithm().id().to_le_bytes());
+    match compression {
+        SnapshotCompression::None => data.extend_from_slice(&body.0),
+        #[cfg(feature = "zstd")]
+        SnapshotCompression::Zstd { level } => {
+            zstd::stream::copy_encode(body.0.as_slice(), &mut data, level)?;
+        }
+    }
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(&data)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp_path, &final_path)?;
+    sync_dir(dir)?;
+
+    Ok(final_path)
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Reads a file of either version. A header naming any other version is
+/// refused rather than guessed at.
+fn read_file<T: SnapshotFile>(path: &Path) -> Result<T, SnapshotError> {
+    let data = fs::read(path)?;
+    let Some(rest) = data.strip_prefix(T::MAGIC) else {
+        let raw = match data.strip_prefix(ZSTD_MAGIC) {
+            Some(compressed) => decompress(compressed)?,
+            None => data,
+        };
+        return T::migrate_v1(&raw);
+    };
+
+    let mut header = Decoder::new(rest, FORMAT_VERSION, Algorithm::Crc32);
+    let version = header.u32()?;
+    if !(OLDEST_HEADER_VERSION..=FORMAT_VERSION).contains(&version) {
+        return Err(SnapshotError::UnsupportedVersion {
+            found: version,
+            supported: FORMAT_VERSION,
+        });
     }
+    let compression = header.u32()?;
+    let checksum = match version {
+        ..6 => Algorithm::Crc32,
+        _ => {
+            let id = header.u32()?;
+            Algorithm::from_id(id).ok_or(SnapshotError::UnsupportedChecksum(id))?
+        }
+    };
+    let stored = &rest[header.pos..];
+    let body = match compression {
+        COMPRESSION_NONE => stored.to_vec(),
+        COMPRESSION_ZSTD => decompress(stored)?,
+        n => {
+            return Err(SnapshotError::Deserialize(format!(
+                "unknown compression {n}"
+            )));
+        }
+    };
+    let mut d = Decoder::new(&body, version, checksum);
+    let value = T::decode(&mut d)?;
+    d.finish()?;
+    Ok(value)
+}
+
+/// `(path, stem)` for every `<prefix><stem>.bin` in `dir`; empty if `dir`
+/// doesn't exist.
+fn list_files(dir: &Path, prefix: &str) -> Result<Vec<(PathBuf, String)>, SnapshotError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(SnapshotError::Io(e)),
+    };
+
+    Ok(entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter_map(|path| {
+            let stem = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix(prefix)?
+                .strip_suffix(".bin")?
+                .to_string();
+            Some((path, stem))
+        })
+        .collect())
+}
+
+#[cfg(feature = "zstd")]
+fn decompress(compressed: &[u8]) -> Result<Vec<u8>, SnapshotError> {
+    zstd::stream::decode_all(compressed).map_err(|e| SnapshotError::Deserialize(e.to_string()))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress(_compressed: &[u8]) -> Result<Vec<u8>, SnapshotError> {
+    Err(SnapshotError::Deserialize(
+        "snapshot is zstd-compressed but the `zstd` feature is disabled".to_string(),
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::book::BookError;
     use crate::matching::MatchingEngine;
     use crate::order::{Order, Side};
 
     fn bid(id: u64, price: i64, qty: u64) -> Order {
-        Order::new(id, 1, Side::Bid, price, qty, id).unwrap()
+        Order::try_new(id, 1, Side::Bid, price, qty, id).unwrap()
     }
 
     fn ask(id: u64, price: i64, qty: u64) -> Order {
-        Order::new(id, 1, Side::Ask, price, qty, id).unwrap()
+        Order::try_new(id, 1, Side::Ask, price, qty, id).unwrap()
     }
 
     fn engine_with_orders(orders: &[Order]) -> MatchingEngine {
@@ -176,7 +1081,7 @@ mod tests {
         let snap = Snapshot::capture(&engine, 0);
 
         assert_eq!(snap.wal_record_count, 0);
-        assert!(snap.orders.is_empty());
+        assert!(snap.levels.is_empty());
         assert_eq!(snap.best_bid, None);
         assert_eq!(snap.best_ask, None);
         snap.verify_checksum().unwrap();
@@ -188,7 +1093,7 @@ mod tests {
         let snap = Snapshot::capture(&engine, 5);
 
         assert_eq!(snap.wal_record_count, 5);
-        assert_eq!(snap.orders.len(), 2);
+        assert_eq!(snap.order_count(), 2);
         assert_eq!(snap.best_bid, Some(100));
         assert_eq!(snap.best_ask, Some(110));
         snap.verify_checksum().unwrap();
@@ -200,7 +1105,9 @@ mod tests {
         let engine = engine_with_orders(&[bid(1, 100, 10), ask(2, 110, 20), bid(3, 98, 30)]);
         let snap = Snapshot::capture(&engine, 42);
 
-        let path = snap.save(dir.path()).unwrap();
+        let path = snap
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
         assert!(path.exists());
         assert!(
             path.file_name()
@@ -212,7 +1119,7 @@ mod tests {
 
         let loaded = Snapshot::load_latest(dir.path()).unwrap().unwrap();
         assert_eq!(loaded.wal_record_count, 42);
-        assert_eq!(loaded.orders.len(), 3);
+        assert_eq!(loaded.order_count(), 3);
         assert_eq!(loaded.best_bid, Some(100));
         assert_eq!(loaded.best_ask, Some(110));
         loaded.verify_checksum().unwrap();
@@ -225,7 +1132,7 @@ mod tests {
 
         snap.verify_checksum().unwrap();
 
-        snap.orders[0].quantity = 999;
+        snap.levels[0].orders[0].0.quantity = 999.into();
         assert!(snap.verify_checksum().is_err());
     }
 
@@ -241,8 +1148,12 @@ mod tests {
         assert_eq!(restored.book().best_ask(), Some(110));
 
         let restored_orders = restored.book().all_resting_orders();
-        assert_eq!(restored_orders.len(), snap.orders.len());
-        for (orig, rest) in snap.orders.iter().zip(restored_orders.iter()) {
+        assert_eq!(restored_orders.len(), snap.order_count());
+        let snap_orders = snap
+            .levels
+            .iter()
+            .flat_map(|l| l.orders.iter().map(|(o, _)| o));
+        for (orig, rest) in snap_orders.zip(restored_orders.iter()) {
             assert_eq!(orig.id, rest.id);
             assert_eq!(orig.price, rest.price);
             assert_eq!(orig.quantity, rest.quantity);
@@ -250,6 +1161,63 @@ mod tests {
         }
     }
 
+    #[test]
+    fn restore_keeps_queue_positions() {
+        let engine = engine_with_orders(&[
+            bid(1, 100, 10),
+            bid(2, 100, 20),
+            ask(3, 110, 5),
+            bid(4, 100, 30),
+            ask(5, 110, 15),
+        ]);
+        let snap = Snapshot::capture(&engine, 5);
+        assert_eq!(snap.levels.len(), 2);
+        assert_eq!(
+            snap.levels[1]
+                .orders
+                .iter()
+                .map(|(o, seq)| (o.id.0, *seq))
+                .collect::<Vec<_>>(),
+            [(1, 1), (2, 2), (4, 4)]
+        );
+
+        let restored = snap.restore(1024).unwrap();
+        for id in 1..=5 {
+            assert_eq!(
+                restored.book().queue_ahead(id),
+                engine.book().queue_ahead(id)
+            );
+            assert_eq!(restored.book().order_seq(id), engine.book().order_seq(id));
+        }
+        assert_eq!(restored.next_seq(), 6);
+        assert_eq!(restored.book().queue_position(4), Some(2));
+        assert_eq!(
+            restored.book().all_resting_orders_ordered(),
+            engine.book().all_resting_orders_ordered()
+        );
+    }
+
+    #[test]
+    fn restore_rejects_order_under_wrong_level() {
+        let engine = engine_with_orders(&[bid(1, 100, 10), bid(2, 99, 10)]);
+        let mut snap = Snapshot::capture(&engine, 2);
+        let moved = snap.levels[1].orders.pop().unwrap();
+        snap.levels[0].orders.push(moved);
+
+        let err = snap.restore(1024).unwrap_err();
+        assert!(matches!(
+            err,
+            SnapshotError::Restore(MatchingError::Book(BookError::LevelMismatch {
+                order_id: 2
+            }))
+        ));
+        let root = std::error::Error::source(&err).and_then(|e| e.source());
+        assert_eq!(
+            root.and_then(|e| e.downcast_ref::<BookError>()),
+            Some(&BookError::LevelMismatch { order_id: 2 })
+        );
+    }
+
     #[test]
     fn restore_then_match() {
         let orders = vec![ask(1, 100, 10)];
@@ -258,25 +1226,97 @@ mod tests {
 
         let mut restored = snap.restore(1024).unwrap();
         let result = restored
-            .add_order(Order::new(2, 2, Side::Bid, 100, 10, 2).unwrap())
+            .add_order(Order::try_new(2, 2, Side::Bid, 100, 10, 2).unwrap())
             .unwrap();
         assert_eq!(result.fills.len(), 1);
         assert_eq!(result.fills[0].maker_order_id, 1);
     }
 
+    #[test]
+    fn restore_keeps_fill_pricing() {
+        let mut engine = engine_with_orders(&[ask(1, 100, 10), bid(3, 90, 1)]);
+        engine.set_fill_pricing(FillPricing::Midpoint);
+
+        let mut restored = Snapshot::capture(&engine, 1).restore(1024).unwrap();
+        assert_eq!(restored.fill_pricing(), FillPricing::Midpoint);
+        let result = restored
+            .add_order(Order::try_new(2, 2, Side::Bid, 110, 10, 2).unwrap())
+            .unwrap();
+        assert_eq!(result.fills[0].price, 95);
+    }
+
+    #[test]
+    fn policies_round_trip_and_older_versions_read_the_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut engine = engine_with_orders(&[bid(1, 100, 10)]);
+        engine.set_amend_policy(AmendPolicy::KeepOnSamePrice);
+        engine.set_cross_policy(CrossPolicy::StrictlyThrough);
+        let path = Snapshot::capture(&engine, 1)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+        let restored = Snapshot::load_latest(dir.path())
+            .unwrap()
+            .unwrap()
+            .restore(1024)
+            .unwrap();
+        assert_eq!(restored.amend_policy(), AmendPolicy::KeepOnSamePrice);
+        assert_eq!(restored.cross_policy(), CrossPolicy::StrictlyThrough);
+
+        // Each older body is the newer one less its last field before
+        // next_seq, book_hash and checksum: the auction flag, the last trade
+        // price (here the none tag), then each policy byte.
+        // They also have no checksum id, and CRC-32 is what they're read with.
+        let mut data = fs::read(&path).unwrap();
+        data.drain(16..20);
+        for (version, byte) in [(5u32, 0), (4, 0), (3, 1), (2, 2)] {
+            let at = data.len() - 17;
+            assert_eq!(data.remove(at), byte);
+            data[8..12].copy_from_slice(&version.to_le_bytes());
+            fs::write(&path, &data).unwrap();
+            let loaded = read_file::<Snapshot>(&path).unwrap();
+            assert_eq!(loaded.checksum_algorithm, Algorithm::Crc32);
+            assert_eq!(loaded.last_trade_price, None);
+            assert_eq!(
+                loaded.cross_policy == CrossPolicy::StrictlyThrough,
+                version >= 4
+            );
+            assert_eq!(loaded.levels, Snapshot::capture(&engine, 1).levels);
+        }
+        let loaded = read_file::<Snapshot>(&path).unwrap();
+        assert_eq!(loaded.amend_policy, AmendPolicy::ResetPriority);
+    }
+
+    #[test]
+    fn restore_keeps_halt() {
+        let mut engine = engine_with_orders(&[ask(1, 100, 10)]);
+        engine.halt(HaltPolicy::RejectAll);
+
+        let mut restored = Snapshot::capture(&engine, 1).restore(1024).unwrap();
+        assert_eq!(restored.halt_policy(), Some(HaltPolicy::RejectAll));
+        assert!(
+            restored
+                .add_order(Order::try_new(2, 2, Side::Bid, 100, 10, 2).unwrap())
+                .is_err()
+        );
+    }
+
     #[test]
     fn load_latest_picks_newest() {
         let dir = tempfile::tempdir().unwrap();
 
         let engine1 = engine_with_orders(&[bid(1, 100, 10)]);
-        Snapshot::capture(&engine1, 10).save(dir.path()).unwrap();
+        Snapshot::capture(&engine1, 10)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
 
         let engine2 = engine_with_orders(&[bid(1, 100, 10), ask(2, 110, 20)]);
-        Snapshot::capture(&engine2, 20).save(dir.path()).unwrap();
+        Snapshot::capture(&engine2, 20)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
 
         let loaded = Snapshot::load_latest(dir.path()).unwrap().unwrap();
         assert_eq!(loaded.wal_record_count, 20);
-        assert_eq!(loaded.orders.len(), 2);
+        assert_eq!(loaded.order_count(), 2);
     }
 
     #[test]
@@ -292,12 +1332,245 @@ mod tests {
         assert!(loaded.is_none());
     }
 
+    fn header(data: &[u8]) -> (&[u8], u32, u32, u32) {
+        let field = |i: usize| u32::from_le_bytes(data[i..i + 4].try_into().unwrap());
+        (&data[..8], field(8), field(12), field(16))
+    }
+
+    #[test]
+    fn uncompressed_snapshot_has_versioned_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_orders(&[bid(1, 100, 10)]);
+        let path = Snapshot::capture(&engine, 1)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+
+        let data = fs::read(&path).unwrap();
+        assert_eq!(
+            header(&data),
+            (
+                &b"FRXSNP01"[..],
+                FORMAT_VERSION,
+                COMPRESSION_NONE,
+                Algorithm::DEFAULT.id()
+            )
+        );
+        // The body starts with wal_record_count.
+        assert_eq!(data[HEADER_SIZE..HEADER_SIZE + 8], 1u64.to_le_bytes());
+    }
+
+    #[test]
+    fn unknown_version_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_orders(&[bid(1, 100, 10)]);
+        let path = Snapshot::capture(&engine, 1)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+
+        let mut data = fs::read(&path).unwrap();
+        for version in [1u32, FORMAT_VERSION + 1] {
+            data[8..12].copy_from_slice(&version.to_le_bytes());
+            fs::write(&path, &data).unwrap();
+            let err = read_file::<Snapshot>(&path).unwrap_err();
+            assert!(matches!(
+                err,
+                SnapshotError::UnsupportedVersion { found, supported: FORMAT_VERSION }
+                    if found == version
+            ));
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "snapshot format version {version} is not supported, this build reads 2 to 6"
+                )
+            );
+        }
+        assert!(Snapshot::load_latest(dir.path()).unwrap().is_none());
+    }
+
+    #[test]
+    fn snapshot_keeps_the_checksum_named_in_its_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_orders(&[bid(1, 100, 10), ask(2, 110, 5)]);
+        let other = match Algorithm::DEFAULT {
+            Algorithm::Crc32 => Algorithm::Crc32c,
+            Algorithm::Crc32c => Algorithm::Crc32,
+        };
+        let mut snap = Snapshot::capture(&engine, 2);
+        snap.checksum_algorithm = other;
+        snap.checksum = Snapshot::compute_checksum(other, &snap.levels);
+        snap.book_hash = Snapshot::book_hash(engine.book(), other);
+        let path = snap
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+
+        let mut data = fs::read(&path).unwrap();
+        assert_eq!(header(&data).3, other.id());
+        let loaded = Snapshot::load_latest(dir.path()).unwrap().unwrap();
+        assert_eq!(loaded.checksum_algorithm, other);
+        assert_eq!(
+            loaded.book_hash,
+            Snapshot::book_hash(engine.book(), loaded.checksum_algorithm)
+        );
+
+        data[16..20].copy_from_slice(&2u32.to_le_bytes());
+        fs::write(&path, &data).unwrap();
+        assert!(matches!(
+            read_file::<Snapshot>(&path),
+            Err(SnapshotError::UnsupportedChecksum(2))
+        ));
+        assert!(Snapshot::load_latest(dir.path()).unwrap().is_none());
+    }
+
+    /// A version 1 file as the original build wrote it: bincode of
+    /// `{ wal_record_count, orders, best_bid, best_ask, checksum }`, each
+    /// order `{ id, trader_id, side, price, quantity, timestamp }`.
+    fn version_1_file(wal_record_count: u64, book: &OrderBook, corrupt: bool) -> Vec<u8> {
+        let opt = |out: &mut Vec<u8>, v: Option<i64>| match v {
+            None => out.push(0),
+            Some(p) => {
+                out.push(1);
+                out.extend_from_slice(&p.to_le_bytes());
+            }
+        };
+        let orders = book.all_resting_orders();
+        let mut encoded = (orders.len() as u64).to_le_bytes().to_vec();
+        for o in &orders {
+            encoded.extend_from_slice(&o.id.0.to_le_bytes());
+            encoded.extend_from_slice(&o.trader_id.0.to_le_bytes());
+            encoded.extend_from_slice(&(o.side as u32).to_le_bytes());
+            encoded.extend_from_slice(&o.price.0.to_le_bytes());
+            encoded.extend_from_slice(&o.quantity.0.to_le_bytes());
+            encoded.extend_from_slice(&o.timestamp.to_le_bytes());
+        }
+        let mut file = wal_record_count.to_le_bytes().to_vec();
+        file.extend_from_slice(&encoded);
+        opt(&mut file, book.best_bid());
+        opt(&mut file, book.best_ask());
+        let checksum = Algorithm::Crc32.checksum(&[&encoded]) ^ u32::from(corrupt);
+        file.extend_from_slice(&checksum.to_le_bytes());
+        file
+    }
+
+    #[test]
+    fn headerless_version_1_snapshot_is_migrated() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_orders(&[
+            bid(1, 100, 10),
+            ask(2, 110, 20),
+            bid(3, 100, 5),
+            ask(4, 111, 7),
+            bid(5, 99, 1),
+        ]);
+        let path = dir.path().join("snapshot_0000000009.bin");
+        fs::write(&path, version_1_file(9, engine.book(), false)).unwrap();
+
+        let loaded = Snapshot::load_latest(dir.path()).unwrap().unwrap();
+        loaded.verify_checksum().unwrap();
+        assert_eq!(loaded.wal_record_count, 9);
+        assert_eq!((loaded.best_bid, loaded.best_ask), (Some(100), Some(110)));
+        assert_eq!(
+            loaded.book_hash,
+            Snapshot::book_hash(engine.book(), loaded.checksum_algorithm)
+        );
+        // Version 1 kept no seqs, so only the queue order carries over.
+        let restored = loaded.restore(1024).unwrap();
+        assert_eq!(
+            restored.book().all_resting_orders(),
+            engine.book().all_resting_orders()
+        );
+        assert_eq!(restored.halt_policy(), None);
+        assert_eq!(restored.next_seq(), 6);
+
+        // Saving again writes the current version, which reads back the same.
+        let path = loaded
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+        assert_eq!(header(&fs::read(&path).unwrap()).1, FORMAT_VERSION);
+        let reread = read_file::<Snapshot>(&path).unwrap();
+        assert_eq!(reread.levels, loaded.levels);
+        assert_eq!(reread.book_hash, loaded.book_hash);
+
+        // A legacy file whose contents don't match its checksum is refused.
+        fs::write(&path, version_1_file(9, engine.book(), true)).unwrap();
+        assert!(matches!(
+            read_file::<Snapshot>(&path),
+            Err(SnapshotError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn compressed_snapshot_skipped_without_feature() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_orders(&[bid(1, 100, 10)]);
+        Snapshot::capture(&engine, 10)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+
+        let mut data = ZSTD_MAGIC.to_vec();
+        data.extend_from_slice(b"not really zstd");
+        fs::write(dir.path().join("snapshot_0000000020.bin"), data).unwrap();
+
+        let loaded = Snapshot::load_latest(dir.path()).unwrap().unwrap();
+        assert_eq!(loaded.wal_record_count, 10);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_roundtrip_and_smaller() {
+        let dir = tempfile::tempdir().unwrap();
+        let orders: Vec<Order> = (1..=500)
+            .map(|i| bid(i, 100 - (i as i64 % 10), 10))
+            .collect();
+        let engine = engine_with_orders(&orders);
+        let snap = Snapshot::capture(&engine, 7);
+
+        let raw_path = snap
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+        let raw_len = fs::metadata(&raw_path).unwrap().len();
+        fs::remove_file(&raw_path).unwrap();
+
+        let path = snap
+            .save_with(dir.path(), SnapshotCompression::Zstd { level: 3 })
+            .unwrap();
+        let data = fs::read(&path).unwrap();
+        assert_eq!(header(&data).2, COMPRESSION_ZSTD);
+        assert!((data.len() as u64) < raw_len);
+
+        let loaded = Snapshot::load_latest(dir.path()).unwrap().unwrap();
+        assert_eq!(loaded.wal_record_count, 7);
+        assert_eq!(loaded.levels, snap.levels);
+        loaded.verify_checksum().unwrap();
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compressed_and_raw_snapshots_coexist() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_orders(&[bid(1, 100, 10)]);
+        Snapshot::capture(&engine, 10)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+
+        let engine2 = engine_with_orders(&[bid(1, 100, 10), ask(2, 110, 20)]);
+        Snapshot::capture(&engine2, 20)
+            .save_with(dir.path(), SnapshotCompression::Zstd { level: 1 })
+            .unwrap();
+
+        let loaded = Snapshot::load_latest(dir.path()).unwrap().unwrap();
+        assert_eq!(loaded.wal_record_count, 20);
+        assert_eq!(loaded.order_count(), 2);
+    }
+
     #[test]
     fn load_latest_skips_corrupt_file() {
         let dir = tempfile::tempdir().unwrap();
 
         let engine = engine_with_orders(&[bid(1, 100, 10)]);
-        Snapshot::capture(&engine, 10).save(dir.path()).unwrap();
+        Snapshot::capture(&engine, 10)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
 
         let corrupt_path = dir.path().join("snapshot_0000000020.bin");
         fs::write(&corrupt_path, b"garbage data").unwrap();
@@ -305,4 +1578,243 @@ mod tests {
         let loaded = Snapshot::load_latest(dir.path()).unwrap().unwrap();
         assert_eq!(loaded.wal_record_count, 10);
     }
+
+    fn tracked_engine(orders: &[Order]) -> MatchingEngine {
+        let mut engine = engine_with_orders(orders);
+        engine.set_change_tracking(true);
+        engine
+    }
+
+    #[test]
+    fn delta_save_load_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut engine = tracked_engine(&[bid(1, 100, 10)]);
+        Snapshot::capture(&engine, 1)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+
+        engine.add_order(ask(2, 110, 20)).unwrap();
+        let path = DeltaSnapshot::capture(&mut engine, 1, 2)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+        assert!(
+            path.file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .contains("delta_0000000001_0000000002")
+        );
+
+        engine.cancel_order(1).unwrap();
+        DeltaSnapshot::capture(&mut engine, 2, 3)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+
+        // Full snapshots are unaffected by delta files in the same directory.
+        let snap = Snapshot::load_latest(dir.path()).unwrap().unwrap();
+        assert_eq!(snap.wal_record_count, 1);
+
+        let chain = DeltaSnapshot::load_chain(dir.path(), 1).unwrap();
+        let counts: Vec<u64> = chain.iter().map(|d| d.wal_record_count).collect();
+        assert_eq!(counts, vec![2, 3]);
+
+        let mut restored = snap.restore(1024).unwrap();
+        for delta in &chain {
+            delta.apply(&mut restored).unwrap();
+        }
+        assert_eq!(
+            restored.book().all_resting_orders(),
+            engine.book().all_resting_orders()
+        );
+    }
+
+    #[test]
+    fn delta_chain_ignores_other_bases() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut engine = tracked_engine(&[]);
+        engine.add_order(bid(1, 100, 10)).unwrap();
+        DeltaSnapshot::capture(&mut engine, 5, 6)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+
+        assert!(DeltaSnapshot::load_chain(dir.path(), 1).unwrap().is_empty());
+        assert_eq!(DeltaSnapshot::load_chain(dir.path(), 5).unwrap().len(), 1);
+        assert!(
+            DeltaSnapshot::load_chain(Path::new("/nonexistent/snapshot/dir"), 0)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn delta_chain_stops_at_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut engine = tracked_engine(&[]);
+        engine.add_order(bid(1, 100, 10)).unwrap();
+        DeltaSnapshot::capture(&mut engine, 0, 1)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+        fs::write(
+            dir.path().join("delta_0000000001_0000000002.bin"),
+            b"garbage",
+        )
+        .unwrap();
+        engine.add_order(bid(3, 99, 10)).unwrap();
+        DeltaSnapshot::capture(&mut engine, 2, 3)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+
+        let chain = DeltaSnapshot::load_chain(dir.path(), 0).unwrap();
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain[0].wal_record_count, 1);
+    }
+
+    #[test]
+    fn headerless_delta_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("delta_0000000000_0000000001.bin");
+        let mut data = 0u64.to_le_bytes().to_vec();
+        data.extend_from_slice(&1u64.to_le_bytes());
+        data.extend_from_slice(&[0; 32]);
+        fs::write(&path, &data).unwrap();
+
+        let err = read_file::<DeltaSnapshot>(&path).unwrap_err();
+        assert!(matches!(
+            err,
+            SnapshotError::UnsupportedVersion {
+                found: 1,
+                supported: FORMAT_VERSION
+            }
+        ));
+        assert!(DeltaSnapshot::load_chain(dir.path(), 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn delta_checksum_detects_corruption() {
+        let mut engine = tracked_engine(&[]);
+        engine.add_order(bid(1, 100, 10)).unwrap();
+        let mut delta = DeltaSnapshot::capture(&mut engine, 0, 1);
+        delta.verify_checksum().unwrap();
+
+        delta.delta.added[0].0.quantity = 999.into();
+        assert!(delta.verify_checksum().is_err());
+    }
+
+    #[test]
+    fn prune_keeps_newest_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut engine = tracked_engine(&[]);
+        for count in 1..=5 {
+            engine.add_order(bid(count, 100, 10)).unwrap();
+            Snapshot::capture(&engine, count * 10)
+                .save_with(dir.path(), SnapshotCompression::None)
+                .unwrap();
+        }
+        engine.add_order(bid(6, 100, 10)).unwrap();
+        DeltaSnapshot::capture(&mut engine, 20, 25)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+        DeltaSnapshot::capture(&mut engine, 40, 45)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+
+        assert_eq!(Snapshot::prune(dir.path(), 3).unwrap(), 3);
+
+        let mut files: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                "delta_0000000040_0000000045.bin",
+                "snapshot_0000000030.bin",
+                "snapshot_0000000040.bin",
+                "snapshot_0000000050.bin",
+            ]
+        );
+        assert_eq!(
+            Snapshot::load_latest(dir.path())
+                .unwrap()
+                .unwrap()
+                .wal_record_count,
+            50
+        );
+    }
+
+    #[test]
+    fn prune_never_removes_newest() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = engine_with_orders(&[bid(1, 100, 10)]);
+        Snapshot::capture(&engine, 1)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+        Snapshot::capture(&engine, 2)
+            .save_with(dir.path(), SnapshotCompression::None)
+            .unwrap();
+
+        assert_eq!(Snapshot::prune(dir.path(), 0).unwrap(), 1);
+        assert_eq!(
+            Snapshot::load_latest(dir.path())
+                .unwrap()
+                .unwrap()
+                .wal_record_count,
+            2
+        );
+        assert_eq!(
+            Snapshot::prune(Path::new("/nonexistent/snapshot/dir"), 3).unwrap(),
+            0
+        );
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use crate::matching::MatchingEngine;
+    use crate::order::{Order, Side};
+    use proptest::prelude::*;
+
+    fn encoded(engine: &MatchingEngine) -> Vec<u8> {
+        let mut e = Encoder::default();
+        Snapshot::capture(engine, 7).encode(&mut e);
+        e.0
+    }
+
+    /// Adds, trades and cancels from `ops`, so the arena's free list and
+    /// slot order end up far from the order the book lists.
+    fn build(ops: &[(bool, u8, i64, u64)], capacity: u32) -> MatchingEngine {
+        let mut engine = MatchingEngine::with_capacity(capacity);
+        for (i, &(ask, trader, price, qty)) in ops.iter().enumerate() {
+            let id = i as u64 + 1;
+            let side = if ask { Side::Ask } else { Side::Bid };
+            let order = Order::try_new(id, u64::from(trader), side, price, qty, id).unwrap();
+            let _ = engine.add_order(order);
+            if id.is_multiple_of(3) {
+                let _ = engine.cancel_order(id / 2);
+            }
+        }
+        engine
+    }
+
+    proptest! {
+        #[test]
+        fn captures_of_the_same_book_are_byte_identical(
+            ops in proptest::collection::vec(
+                (any::<bool>(), 1_u8..=5, 95_i64..=105, 1_u64..=20),
+                1..60,
+            )
+        ) {
+            let engine = build(&ops, 256);
+            let bytes = encoded(&engine);
+            prop_assert_eq!(&encoded(&engine), &bytes);
+
+            // The same commands on another engine, and the book restored
+            // into fresh arena slots, capture the same.
+            prop_assert_eq!(&encoded(&build(&ops, 1024)), &bytes);
+            let restored = Snapshot::capture(&engine, 7).restore(512).unwrap();
+            prop_assert_eq!(&encoded(&restored), &bytes);
+        }
+    }
 }
@@ -0,0 +1,216 @@
+use std::hint;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering, fence};
+
+use crate::book::{LevelView, OrderBook};
+use crate::order::Side;
+
+/// Levels per side a `TopOfBookReader` sees.
+pub const TOP_DEPTH: usize = 5;
+
+/// Price, quantity and order count for each level, bids then asks.
+const WORDS: usize = 2 * TOP_DEPTH * 3;
+
+/// Best levels of both sides as of one `TopOfBookWriter::publish`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BookTop {
+    /// Publishes so far, counting this one; 0 before the first.
+    pub updates: u64,
+    /// Best first. A side with fewer levels is padded with `None`.
+    pub bids: [Option<LevelView>; TOP_DEPTH],
+    pub asks: [Option<LevelView>; TOP_DEPTH],
+}
+
+impl BookTop {
+    pub fn best_bid(&self) -> Option<i64> {
+        self.bids[0].map(|l| l.price)
+    }
+
+    pub fn best_ask(&self) -> Option<i64> {
+        self.asks[0].map(|l| l.price)
+    }
+}
+
+/// Seqlock: `seq` is odd while a write is in progress, and a reader keeps a
+/// copy only if `seq` was even and unchanged across it. The fields are
+/// atomics so a read racing a write is a retry, never a data race.
+struct Shared {
+    seq: AtomicU64,
+    words: [AtomicU64; WORDS],
+}
+
+/// A writer and a reader over the same top of book, empty until the first
+/// publish. Clone the reader for each thread that wants one.
+pub fn top_of_book() -> (TopOfBookWriter, TopOfBookReader) {
+    let shared = Arc::new(Shared {
+        seq: AtomicU64::new(0),
+        words: std::array::from_fn(|_| AtomicU64::new(0)),
+    });
+    (
+        TopOfBookWriter {
+            shared: Arc::clone(&shared),
+        },
+        TopOfBookReader { shared },
+    )
+}
+
+/// Owned by the matching thread, the only writer. Publishing never waits
+/// for readers.
+pub struct TopOfBookWriter {
+    shared: Arc<Shared>,
+}
+
+impl TopOfBookWriter {
+    /// Copies the best `TOP_DEPTH` levels of each side of `book`.
+    pub fn publish(&mut self, book: &OrderBook) {
+        let seq = self.shared.seq.load(Ordering::Relaxed);
+        self.shared.seq.store(seq + 1, Ordering::Relaxed);
+        // Orders the odd `seq` before the field writes below.
+        fence(Ordering::Release);
+
+        for (i, side) in [Side::Bid, Side::Ask].into_iter().enumerate() {
+            let mut levels = book.iter_levels(side);
+            let words = &self.shared.words[i * TOP_DEPTH * 3..(i + 1) * TOP_DEPTH * 3];
+            for slot in words.chunks_exact(3) {
+                let (price, quantity, count) = levels
+                    .next()
+                    .map_or((0, 0, 0), |l| (l.price, l.quantity, l.order_count));
+                slot[0].store(price as u64, Ordering::Relaxed);
+                slot[1].store(quantity, Ordering::Relaxed);
+                slot[2].store(u64::from(count), Ordering::Relaxed);
+            }
+        }
+
+        self.shared.seq.store(seq + 2, Ordering::Release);
+    }
+}
+
+/// Read side, safe to share across threads and to clone.
+#[derive(Clone)]
+pub struct TopOfBookReader {
+    shared: Arc<Shared>,
+}
+
+impl TopOfBookReader {
+    /// The latest published top of book, retrying while a publish is in
+    /// progress. Never blocks the writer.
+    pub fn read(&self) -> BookTop {
+        loop {
+            let before = self.shared.seq.load(Ordering::Acquire);
+            if before & 1 == 1 {
+                hint::spin_loop();
+                continue;
+            }
+            let words: [u64; WORDS] =
+                std::array::from_fn(|i| self.shared.words[i].load(Ordering::Relaxed));
+            // Orders the field reads before the second look at `seq`.
+            fence(Ordering::Acquire);
+            if self.shared.seq.load(Ordering::Relaxed) == before {
+                return decode(before / 2, &words);
+            }
+        }
+    }
+}
+
+fn decode(updates: u64, words: &[u64; WORDS]) -> BookTop {
+    let side = |offset: usize| -> [Option<LevelView>; TOP_DEPTH] {
+        std::array::from_fn(|level| {
+            let slot = &words[offset + level * 3..offset + level * 3 + 3];
+            // A level always holds some quantity, so 0 marks an unused slot.
+            (slot[1] > 0).then(|| LevelView {
+                price: slot[0] as i64,
+                quantity: slot[1],
+                order_count: slot[2] as u32,
+            })
+        })
+    };
+    BookTop {
+        updates,
+        bids: side(0),
+        asks: side(TOP_DEPTH * 3),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::Order;
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+
+    fn order(id: u64, side: Side, price: i64, qty: u64) -> Order {
+        Order::try_new(id, id, side, price, qty, id).unwrap()
+    }
+
+    #[test]
+    fn publishes_best_levels_first() {
+        let (mut writer, reader) = top_of_book();
+        assert_eq!(reader.read(), BookTop::default());
+
+        let mut book = OrderBook::with_capacity(16);
+        for (id, price) in [(1, 99), (2, 101), (3, 100), (4, 100)] {
+            book.insert_order(order(id, Side::Bid, price, 10), id)
+                .unwrap();
+        }
+        for id in 5..=11 {
+            book.insert_order(order(id, Side::Ask, 100 + id as i64, 1), id)
+                .unwrap();
+        }
+        writer.publish(&book);
+
+        let top = reader.read();
+        assert_eq!(top.updates, 1);
+        assert_eq!((top.best_bid(), top.best_ask()), (Some(101), Some(105)));
+        let bids: Vec<_> = top.bids.iter().flatten().map(|l| l.price).collect();
+        assert_eq!(bids, vec![101, 100, 99]);
+        assert_eq!(top.bids[1].unwrap().order_count, 2);
+        // Asks beyond TOP_DEPTH are not published.
+        assert_eq!(top.asks[TOP_DEPTH - 1].unwrap().price, 109);
+    }
+
+    #[test]
+    fn concurrent_readers_never_see_a_torn_write() {
+        const PUBLISHES: u64 = 20_000;
+        let (mut writer, reader) = top_of_book();
+        let done = Arc::new(AtomicBool::new(false));
+
+        // Publish `n` rests one bid with price and quantity both `n`, so any
+        // mix of two publishes shows up as a mismatch.
+        let readers: Vec<_> = (0..3)
+            .map(|_| {
+                let reader = reader.clone();
+                let done = Arc::clone(&done);
+                thread::spawn(move || {
+                    let mut last = 0;
+                    while !done.load(Ordering::Acquire) {
+                        let top = reader.read();
+                        if top.updates == 0 {
+                            continue;
+                        }
+                        let bid = top.bids[0].unwrap();
+                        assert_eq!(bid.price as u64, top.updates);
+                        assert_eq!(bid.quantity, top.updates);
+                        assert!(top.bids[1].is_none() && top.asks[0].is_none());
+                        assert!(top.updates >= last);
+                        last = top.updates;
+                    }
+                })
+            })
+            .collect();
+
+        let mut book = OrderBook::with_capacity(4);
+        for n in 1..=PUBLISHES {
+            book.insert_order(order(n, Side::Bid, n as i64, n), n)
+                .unwrap();
+            if n > 1 {
+                book.cancel_order(n - 1).unwrap();
+            }
+            writer.publish(&book);
+        }
+        done.store(true, Ordering::Release);
+        for r in readers {
+            r.join().unwrap();
+        }
+        assert_eq!(reader.read().updates, PUBLISHES);
+    }
+}
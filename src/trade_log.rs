@@ -0,0 +1,369 @@
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::path::Path;
+
+use memmap2::{Mmap, MmapMut};
+
+use crate::checksum::Algorithm;
+use crate::matching::Fill;
+use crate::wal::{
+    FILE_HEADER_SIZE, WalError, check_file_header, file_header, read_frame, scan_frames,
+    write_frame,
+};
+
+const MAGIC: &[u8; 8] = b"FRXTRD01";
+
+const FORMAT_VERSION: u32 = 1;
+
+/// Payload: seq_num, wal_record, taker, maker, price, quantity, timestamp,
+/// each 8 bytes LE.
+const PAYLOAD_SIZE: usize = 56;
+
+/// Framed size: 8-byte record header plus the payload, already 8-aligned.
+const RECORD_SIZE: u64 = 8 + PAYLOAD_SIZE as u64;
+
+const DEFAULT_INITIAL_SIZE: u64 = 16 * 1024 * 1024;
+
+/// One executed fill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TradeRecord {
+    /// Position in the trade log, 1-based and gapless.
+    pub seq_num: u64,
+    /// Number of the WAL record whose command produced the fill, or 0 when
+    /// running without a WAL.
+    pub wal_record: u64,
+    pub taker_order_id: u64,
+    pub maker_order_id: u64,
+    pub price: i64,
+    pub quantity: u64,
+    pub timestamp: u64,
+}
+
+impl TradeRecord {
+    fn encode(&self) -> [u8; PAYLOAD_SIZE] {
+        let mut buf = [0u8; PAYLOAD_SIZE];
+        let fields = [
+            self.seq_num,
+            self.wal_record,
+            self.taker_order_id,
+            self.maker_order_id,
+            self.price as u64,
+            self.quantity,
+            self.timestamp,
+        ];
+        for (chunk, field) in buf.chunks_exact_mut(8).zip(fields) {
+            chunk.copy_from_slice(&field.to_le_bytes());
+        }
+        buf
+    }
+
+    fn decode(payload: &[u8]) -> Option<Self> {
+        if payload.len() != PAYLOAD_SIZE {
+            return None;
+        }
+        let field = |i: usize| u64::from_le_bytes(payload[i * 8..i * 8 + 8].try_into().unwrap());
+        Some(Self {
+            seq_num: field(0),
+            wal_record: field(1),
+            taker_order_id: field(2),
+            maker_order_id: field(3),
+            price: field(4) as i64,
+            quantity: field(5),
+            timestamp: field(6),
+        })
+    }
+}
+
+/// Append-only log of fills, kept apart from the WAL: the WAL records what
+/// was asked for, this records what traded. Uses the WAL's file header and
+/// record framing under its own magic.
+///
+/// Fills are logged as they are matched, not on replay, so fills from
+/// commands the WAL holds but the trade log missed in a crash are not
+/// written again after recovery. `wal_record` makes such a gap visible.
+pub(crate) struct TradeLog {
+    mmap: MmapMut,
+    file: File,
+    checksum: Algorithm,
+    write_pos: u64,
+    mapped_size: u64,
+    record_count: u64,
+}
+
+impl TradeLog {
+    /// Open or create a trade log. On reopen, checks the header and scans to
+    /// the last intact record; anything after it is overwritten.
+    pub(crate) fn open(path: impl AsRef<Path>) -> Result<Self, WalError> {
+        Self::open_with_size(path, DEFAULT_INITIAL_SIZE)
+    }
+
+    pub(crate) fn open_with_size(
+        path: impl AsRef<Path>,
+        initial_size: u64,
+    ) -> Result<Self, WalError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path.as_ref())?;
+
+        let mut header = Vec::with_capacity(FILE_HEADER_SIZE);
+        (&file)
+            .take(FILE_HEADER_SIZE as u64)
+            .read_to_end(&mut header)?;
+        let is_new = header.iter().all(|&b| b == 0);
+        let checksum = if is_new {
+            Algorithm::DEFAULT
+        } else {
+            check_file_header(&header, MAGIC, FORMAT_VERSION)?
+        };
+
+        let file_len = file.metadata()?.len();
+        let mapped_size = if file_len < initial_size {
+            file.set_len(initial_size)?;
+            initial_size
+        } else {
+            file_len
+        };
+
+        // SAFETY: Only the matching thread writes this file.
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+        if is_new {
+            mmap[..FILE_HEADER_SIZE].copy_from_slice(&file_header(MAGIC, FORMAT_VERSION));
+        }
+
+        let (write_pos, record_count) = scan_frames(&mmap, checksum);
+        Ok(Self {
+            mmap,
+            file,
+            checksum,
+            write_pos,
+            mapped_size,
+            record_count,
+        })
+    }
+
+    /// Logs one fill and returns its sequence number.
+    pub(crate) fn append(
+        &mut self,
+        wal_record: u64,
+        fill: &Fill,
+        timestamp: u64,
+    ) -> Result<u64, WalError> {
+        self.ensure_capacity()?;
+        let record = TradeRecord {
+            seq_num: self.record_count + 1,
+            wal_record,
+            taker_order_id: fill.taker_order_id,
+            maker_order_id: fill.maker_order_id,
+            price: fill.price,
+            quantity: fill.quantity,
+            timestamp,
+        };
+        let pos = self.write_pos as usize;
+        self.write_pos += write_frame(&mut self.mmap, pos, &record.encode(), self.checksum) as u64;
+        self.record_count += 1;
+        Ok(self.record_count)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn record_count(&self) -> u64 {
+        self.record_count
+    }
+
+    #[cfg(test)]
+    pub(crate) fn iter(&self) -> TradeLogIterator<'_> {
+        TradeLogIterator {
+            data: &self.mmap,
+            read_pos: FILE_HEADER_SIZE as u64,
+            end_pos: self.write_pos,
+            checksum: self.checksum,
+        }
+    }
+
+    pub(crate) fn flush_async(&self) -> Result<(), WalError> {
+        self.mmap.flush_async().map_err(WalError::Io)
+    }
+
+    fn ensure_capacity(&mut self) -> Result<(), WalError> {
+        if self.write_pos + RECORD_SIZE <= self.mapped_size {
+            return Ok(());
+        }
+
+        let new_size = (self.mapped_size * 2).max(self.write_pos + RECORD_SIZE);
+        self.file.set_len(new_size)?;
+
+        // SAFETY: Same single-writer invariant as open.
+        self.mmap = unsafe { MmapMut::map_mut(&self.file)? };
+        self.mapped_size = new_size;
+        Ok(())
+    }
+}
+
+/// Read-only view of a trade log for reconciliation. Safe to point at a
+/// live engine's log.
+pub struct TradeLogReader {
+    mmap: Mmap,
+    checksum: Algorithm,
+}
+
+impl TradeLogReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, WalError> {
+        let file = File::open(path)?;
+        // SAFETY: The map is only read. A live writer may still append, which
+        // at worst shows up as a truncated or corrupt tail record.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let checksum = check_file_header(&mmap, MAGIC, FORMAT_VERSION)?;
+        Ok(Self { mmap, checksum })
+    }
+
+    /// Iterates trades from the start of the file, with the same stopping
+    /// rules as `WalReader::iter`.
+    pub fn iter(&self) -> TradeLogIterator<'_> {
+        TradeLogIterator {
+            data: &self.mmap,
+            read_pos: FILE_HEADER_SIZE as u64,
+            end_pos: self.mmap.len() as u64,
+            checksum: self.checksum,
+        }
+    }
+}
+
+/// Yields trades in log order. Stops after the first error.
+pub struct TradeLogIterator<'a> {
+    data: &'a [u8],
+    read_pos: u64,
+    end_pos: u64,
+    checksum: Algorithm,
+}
+
+impl Iterator for TradeLogIterator<'_> {
+    type Item = Result<TradeRecord, WalError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.read_pos;
+        let result = match read_frame(self.data, offset, self.end_pos, self.checksum) {
+            Ok(Some((payload, record_size))) => {
+                self.read_pos += record_size as u64;
+                TradeRecord::decode(payload).ok_or(WalError::Corruption { offset })
+            }
+            Ok(None) => return None,
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            self.end_pos = self.read_pos;
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(maker: u64, price: i64, quantity: u64) -> Fill {
+        Fill {
+            taker_order_id: 100,
+            maker_order_id: maker,
+            price,
+            quantity,
+            maker_fully_filled: true,
+            taker_seq: 0,
+            maker_seq: 0,
+        }
+    }
+
+    #[test]
+    fn append_and_iterate() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = TradeLog::open_with_size(dir.path().join("trades.bin"), 4096).unwrap();
+
+        assert_eq!(log.append(7, &fill(1, 15000, 10), 500).unwrap(), 1);
+        assert_eq!(log.append(7, &fill(2, 15001, 5), 500).unwrap(), 2);
+
+        let trades: Vec<_> = log.iter().map(Result::unwrap).collect();
+        assert_eq!(
+            trades[1],
+            TradeRecord {
+                seq_num: 2,
+                wal_record: 7,
+                taker_order_id: 100,
+                maker_order_id: 2,
+                price: 15001,
+                quantity: 5,
+                timestamp: 500,
+            }
+        );
+        assert_eq!(trades.len(), 2);
+    }
+
+    #[test]
+    fn reopen_continues_sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trades.bin");
+        {
+            let mut log = TradeLog::open_with_size(&path, 4096).unwrap();
+            log.append(1, &fill(1, 15000, 10), 0).unwrap();
+            log.flush_async().unwrap();
+        }
+
+        let mut log = TradeLog::open_with_size(&path, 4096).unwrap();
+        assert_eq!(log.record_count(), 1);
+        assert_eq!(log.append(2, &fill(2, 15000, 10), 0).unwrap(), 2);
+
+        let reader = TradeLogReader::open(&path).unwrap();
+        let seqs: Vec<_> = reader.iter().map(|t| t.unwrap().seq_num).collect();
+        assert_eq!(seqs, vec![1, 2]);
+    }
+
+    #[test]
+    fn grows_past_initial_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = TradeLog::open_with_size(dir.path().join("trades.bin"), 128).unwrap();
+        for i in 0..20 {
+            log.append(i, &fill(i, 15000, 1), 0).unwrap();
+        }
+        assert_eq!(log.iter().count(), 20);
+    }
+
+    #[test]
+    fn corrupt_record_ends_iteration_with_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trades.bin");
+        {
+            let mut log = TradeLog::open_with_size(&path, 4096).unwrap();
+            log.append(1, &fill(1, 15000, 10), 0).unwrap();
+            log.append(2, &fill(2, 15000, 10), 0).unwrap();
+        }
+        let mut data = std::fs::read(&path).unwrap();
+        let second = FILE_HEADER_SIZE + RECORD_SIZE as usize;
+        data[second + 8 + 24] ^= 0xFF;
+        std::fs::write(&path, &data).unwrap();
+
+        let reader = TradeLogReader::open(&path).unwrap();
+        let results: Vec<_> = reader.iter().collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(WalError::Corruption { offset }) if offset == second as u64
+        ));
+
+        // The writer drops the damaged tail and reuses its slot.
+        let mut log = TradeLog::open_with_size(&path, 4096).unwrap();
+        assert_eq!(log.record_count(), 1);
+        assert_eq!(log.append(3, &fill(3, 15000, 10), 0).unwrap(), 2);
+    }
+
+    #[test]
+    fn rejects_wal_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+        crate::wal::Wal::open_with_size(&path, 4096).unwrap();
+        assert!(matches!(
+            TradeLog::open_with_size(&path, 4096),
+            Err(WalError::BadMagic)
+        ));
+    }
+}
@@ -1,28 +1,160 @@
-use std::fs::{File, OpenOptions};
-use std::io;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
-use memmap2::MmapMut;
+use memmap2::{Mmap, MmapMut};
 
-use crate::protocol::{self, EngineCommand, NEW_ORDER_SIZE};
+use crate::checksum::Algorithm;
+use crate::matching::{AddOrderResult, MatchingError, OrderStatus};
+use crate::order::Order;
+use crate::protocol::{self, EngineCommand, MAX_COMMAND_SIZE};
 
-/// WAL record header size: 4 bytes payload_len + 4 bytes CRC32.
+/// WAL record header size: 4 bytes payload_len + 4 bytes CRC.
 const HEADER_SIZE: usize = 8;
 
+/// File header: `[magic: 8 bytes][format_version: u32 LE][checksum: u32 LE]`,
+/// before the first record. `checksum` is the `Algorithm::id` of the
+/// record CRCs; it was reserved and zero before, which reads as CRC-32.
+pub(crate) const FILE_HEADER_SIZE: usize = 16;
+
+const MAGIC: &[u8; 8] = b"FRXWAL01";
+
+/// Version 2 CRCs cover the payload length as well as the payload. Version 1
+/// files had no file header and a payload-only CRC.
+const FORMAT_VERSION: u32 = 2;
+
 const ALIGNMENT: usize = 8;
 
+/// The low 24 bits of the length word hold the payload length; the high
+/// byte holds the command's `Outcome`, written after matching.
+const LEN_MASK: u32 = 0x00FF_FFFF;
+const OUTCOME_OFFSET: usize = 3;
+
+/// Orders carry their timestamp after the protocol message, so replay sees
+/// the time they were stamped with rather than re-stamping.
+const TIMESTAMP_SIZE: usize = 8;
+
+const MAX_PAYLOAD_SIZE: usize = MAX_COMMAND_SIZE + TIMESTAMP_SIZE;
+
+/// Smallest common page size; larger pages just get touched more than once.
+const PREFAULT_STRIDE: usize = 4096;
+
 const DEFAULT_INITIAL_SIZE: u64 = 64 * 1024 * 1024;
 
 fn align_up(n: usize) -> usize {
     (n + ALIGNMENT - 1) & !(ALIGNMENT - 1)
 }
 
+/// CRC over the 24-bit payload length and the payload. The outcome byte is
+/// left out so it can be patched in place.
+fn record_crc(checksum: Algorithm, len_word: &[u8], payload: &[u8]) -> u32 {
+    checksum.checksum(&[&len_word[..OUTCOME_OFFSET], payload])
+}
+
+/// Header for a new file, whose records use `Algorithm::DEFAULT`.
+pub(crate) fn file_header(magic: &[u8; 8], version: u32) -> [u8; FILE_HEADER_SIZE] {
+    let mut header = [0u8; FILE_HEADER_SIZE];
+    header[..8].copy_from_slice(magic);
+    header[8..12].copy_from_slice(&version.to_le_bytes());
+    header[12..16].copy_from_slice(&Algorithm::DEFAULT.id().to_le_bytes());
+    header
+}
+
+/// Checks magic and version and returns the checksum the records use.
+pub(crate) fn check_file_header(
+    data: &[u8],
+    magic: &[u8; 8],
+    version: u32,
+) -> Result<Algorithm, WalError> {
+    if data.len() < FILE_HEADER_SIZE || data[..8] != *magic {
+        return Err(WalError::BadMagic);
+    }
+    let found = u32::from_le_bytes(data[8..12].try_into().unwrap());
+    if found != version {
+        return Err(WalError::UnsupportedVersion {
+            found,
+            expected: version,
+        });
+    }
+    let id = u32::from_le_bytes(data[12..16].try_into().unwrap());
+    Algorithm::from_id(id).ok_or(WalError::UnsupportedChecksum(id))
+}
+
+/// Writes one framed record at `pos` and returns its size including padding.
+/// The caller makes sure `buf` has room.
+pub(crate) fn write_frame(
+    buf: &mut [u8],
+    pos: usize,
+    payload: &[u8],
+    checksum: Algorithm,
+) -> usize {
+    let record_size = align_up(HEADER_SIZE + payload.len());
+    let len_word = (payload.len() as u32).to_le_bytes();
+    let crc = record_crc(checksum, &len_word, payload);
+    buf[pos..pos + 4].copy_from_slice(&len_word);
+    buf[pos + 4..pos + 8].copy_from_slice(&crc.to_le_bytes());
+    buf[pos + HEADER_SIZE..pos + HEADER_SIZE + payload.len()].copy_from_slice(payload);
+    buf[pos + HEADER_SIZE + payload.len()..pos + record_size].fill(0);
+    record_size
+}
+
+/// Reads the framed record at `pos`, looking no further than `end`. Returns
+/// the payload and the record size, or `None` at the first unwritten header.
+pub(crate) fn read_frame(
+    data: &[u8],
+    pos: u64,
+    end: u64,
+    checksum: Algorithm,
+) -> Result<Option<(&[u8], usize)>, WalError> {
+    if pos + HEADER_SIZE as u64 > end {
+        return Ok(None);
+    }
+
+    let p = pos as usize;
+    let payload_len = (u32::from_le_bytes(data[p..p + 4].try_into().unwrap()) & LEN_MASK) as usize;
+
+    // A zero payload_len means we've hit unwritten space.
+    if payload_len == 0 {
+        return Ok(None);
+    }
+
+    let record_size = align_up(HEADER_SIZE + payload_len);
+    if pos + record_size as u64 > end {
+        return Err(WalError::TruncatedRecord { offset: pos });
+    }
+
+    let stored_crc = u32::from_le_bytes(data[p + 4..p + 8].try_into().unwrap());
+    let payload = &data[p + HEADER_SIZE..p + HEADER_SIZE + payload_len];
+    if stored_crc != record_crc(checksum, &data[p..p + 4], payload) {
+        return Err(WalError::Corruption { offset: pos });
+    }
+    Ok(Some((payload, record_size)))
+}
+
 #[derive(Debug)]
-pub(crate) enum WalError {
+pub enum WalError {
     Io(io::Error),
     Protocol(protocol::ProtocolError),
-    Corruption { offset: u64 },
-    TruncatedRecord { offset: u64 },
+    Corruption {
+        offset: u64,
+    },
+    TruncatedRecord {
+        offset: u64,
+    },
+    BadMagic,
+    UnsupportedVersion {
+        found: u32,
+        expected: u32,
+    },
+    /// The file header names a checksum this build doesn't know.
+    UnsupportedChecksum(u32),
+    /// Records `first..=last` are not in any segment, e.g. deleted by
+    /// retention, so replay can't get past them.
+    MissingRecords {
+        first: u64,
+        last: u64,
+    },
 }
 
 impl std::fmt::Display for WalError {
@@ -34,11 +166,28 @@ impl std::fmt::Display for WalError {
             Self::TruncatedRecord { offset } => {
                 write!(f, "wal truncated record at offset {offset}")
             }
+            Self::BadMagic => write!(f, "not a wal file (bad magic)"),
+            Self::UnsupportedVersion { found, expected } => write!(
+                f,
+                "unsupported wal format version {found} (expected {expected})"
+            ),
+            Self::UnsupportedChecksum(id) => write!(f, "unsupported wal checksum id {id}"),
+            Self::MissingRecords { first, last } => {
+                write!(f, "wal records {first}..={last} are missing")
+            }
         }
     }
 }
 
-impl std::error::Error for WalError {}
+impl std::error::Error for WalError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Protocol(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl From<io::Error> for WalError {
     fn from(e: io::Error) -> Self {
@@ -52,25 +201,233 @@ impl From<protocol::ProtocolError> for WalError {
     }
 }
 
+/// What the engine did with a logged command, kept so replay can be checked
+/// against the original run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Rejected = 1,
+    FullyFilled = 2,
+    PartiallyFilled = 3,
+    Resting = 4,
+    CancelledSelfTrade = 5,
+    Cancelled = 6,
+    ReduceOnlyClamped = 7,
+    RejectedPostOnly = 8,
+    /// A halt or resume, which always applies.
+    Applied = 9,
+    /// A reduce that left part of the order resting.
+    Reduced = 10,
+}
+
+impl Outcome {
+    pub(crate) fn of_add(result: &Result<AddOrderResult, MatchingError>) -> Self {
+        match result {
+            Ok(r) => match r.status {
+                OrderStatus::FullyFilled => Self::FullyFilled,
+                OrderStatus::PartiallyFilled => Self::PartiallyFilled,
+                OrderStatus::Resting => Self::Resting,
+                OrderStatus::CancelledSelfTrade => Self::CancelledSelfTrade,
+                OrderStatus::ReduceOnlyClamped => Self::ReduceOnlyClamped,
+                OrderStatus::RejectedPostOnly => Self::RejectedPostOnly,
+            },
+            Err(_) => Self::Rejected,
+        }
+    }
+
+    pub(crate) fn of_cancel(result: &Result<Order, MatchingError>) -> Self {
+        match result {
+            Ok(_) => Self::Cancelled,
+            Err(_) => Self::Rejected,
+        }
+    }
+
+    /// A reduce to zero counts as a cancel.
+    pub(crate) fn of_reduce(result: &Result<Order, MatchingError>) -> Self {
+        match result {
+            Ok(order) if order.quantity == 0 => Self::Cancelled,
+            Ok(_) => Self::Reduced,
+            Err(_) => Self::Rejected,
+        }
+    }
+
+    /// A cancel-all that found nothing to cancel counts as rejected.
+    pub(crate) fn of_cancel_all(cancelled: &[Order]) -> Self {
+        if cancelled.is_empty() {
+            Self::Rejected
+        } else {
+            Self::Cancelled
+        }
+    }
+
+    fn from_byte(b: u8) -> Option<Self> {
+        match b {
+            1 => Some(Self::Rejected),
+            2 => Some(Self::FullyFilled),
+            3 => Some(Self::PartiallyFilled),
+            4 => Some(Self::Resting),
+            5 => Some(Self::CancelledSelfTrade),
+            6 => Some(Self::Cancelled),
+            7 => Some(Self::ReduceOnlyClamped),
+            8 => Some(Self::RejectedPostOnly),
+            9 => Some(Self::Applied),
+            10 => Some(Self::Reduced),
+            _ => None,
+        }
+    }
+}
+
+/// When to delete sealed WAL segments, checked by `Wal::apply_retention`.
+/// Either limit deletes a segment, oldest first, but only once a snapshot
+/// covers every record in it. The default keeps everything.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WalRetention {
+    /// Delete segments last written at least this long ago.
+    pub max_age: Option<Duration>,
+    /// Delete the oldest segments while all segments, the active one
+    /// included, take up more than this many bytes.
+    pub max_total_size: Option<u64>,
+}
+
+/// A full segment, no longer written to.
+struct Segment {
+    first_record: u64,
+    path: PathBuf,
+    mmap: Mmap,
+    checksum: Algorithm,
+}
+
+/// Path of the segment whose first record is `first_record`: the WAL path
+/// itself for the first segment, so an unrotated WAL is a single file, and
+/// `<path>.<first_record>` zero-padded to 20 digits after that.
+fn segment_path(path: &Path, first_record: u64) -> PathBuf {
+    if first_record == 1 {
+        return path.to_path_buf();
+    }
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{first_record:020}"));
+    path.with_file_name(name)
+}
+
+/// First record number of a segment from its file name.
+fn segment_first_record(path: &Path) -> u64 {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .filter(|ext| ext.len() == 20)
+        .and_then(|ext| ext.parse().ok())
+        .unwrap_or(1)
+}
+
+/// Existing segments of the WAL at `path`, oldest first.
+pub(crate) fn list_segments(path: &Path) -> Result<Vec<(u64, PathBuf)>, WalError> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().unwrap_or_default();
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry_path = entry?.path();
+        let first_record = segment_first_record(&entry_path);
+        if entry_path.file_name() == Some(name)
+            || (first_record > 1 && entry_path == segment_path(path, first_record))
+        {
+            segments.push((first_record, entry_path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+/// Opens or creates a segment for writing, at least `initial_size` long.
+/// Appends keep to the checksum an existing segment was started with.
+fn open_writable(
+    path: &Path,
+    initial_size: u64,
+) -> Result<(File, MmapMut, u64, Algorithm), WalError> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+
+    // Check an existing header before growing the file, so a file that
+    // isn't ours is rejected untouched.
+    let mut header = Vec::with_capacity(FILE_HEADER_SIZE);
+    (&file)
+        .take(FILE_HEADER_SIZE as u64)
+        .read_to_end(&mut header)?;
+    let is_new = header.iter().all(|&b| b == 0);
+    let checksum = if is_new {
+        Algorithm::DEFAULT
+    } else {
+        check_file_header(&header, MAGIC, FORMAT_VERSION)?
+    };
+
+    let file_len = file.metadata()?.len();
+    let mapped_size = if file_len < initial_size {
+        file.set_len(initial_size)?;
+        initial_size
+    } else {
+        file_len
+    };
+
+    // SAFETY: Single-writer invariant — only the matching thread accesses
+    // this file. No other process reads/writes it concurrently.
+    let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+
+    if is_new {
+        mmap[..FILE_HEADER_SIZE].copy_from_slice(&file_header(MAGIC, FORMAT_VERSION));
+    }
+    Ok((file, mmap, mapped_size, checksum))
+}
+
 /// Append-only write-ahead log backed by a memory-mapped file.
 ///
 /// Record format on disk:
 /// ```text
-/// [payload_len: u32 LE][crc32: u32 LE][payload: N bytes][padding to 8-byte align]
+/// [payload_len: u24 LE][outcome: u8][crc: u32 LE][payload: N bytes][padding to 8-byte align]
 /// ```
+///
+/// Records start after a 16-byte file header holding a magic number, the
+/// format version and which checksum the segment's CRCs use.
+/// The CRC covers `payload_len` and the payload, so a damaged length is
+/// caught before it is trusted as a record boundary. The outcome byte is 0
+/// until `record_outcome` fills it in, and isn't covered by the CRC so it can
+/// be set without rewriting the record.
+///
+/// The payload is the command's protocol encoding; for `NewOrder` and
+/// `CancelReplace` it is followed by the order timestamp (u64 LE). Records
+/// without the trailing timestamp replay with timestamp 0.
+///
+/// With a segment size set, the log rotates: once the active file would
+/// grow past it, the file is trimmed and sealed and appends continue in a
+/// new segment named after its first record (see `segment_path`). Record
+/// numbers run on across segments.
 pub(crate) struct Wal {
     mmap: MmapMut,
     file: File,
-    #[allow(dead_code)]
     path: PathBuf,
+    /// Checksum of the active segment's records.
+    checksum: Algorithm,
+    /// Record number of the active segment's first record.
+    first_record: u64,
+    sealed: Vec<Segment>,
+    segment_size: Option<u64>,
+    initial_size: u64,
     write_pos: u64,
     mapped_size: u64,
-    encode_buf: [u8; NEW_ORDER_SIZE], // pre-allocated, max payload size
+    encode_buf: [u8; MAX_PAYLOAD_SIZE], // pre-allocated, max payload size
     record_count: u64,
+    last_record_pos: Option<u64>,
+    /// Records and framed bytes appended since the last flush.
+    unflushed_records: u64,
+    unflushed_bytes: u64,
 }
 
 impl Wal {
-    /// Open or create a WAL file. On reopen, scans existing records to restore
+    /// Open or create a WAL file. On reopen, checks the magic and version,
+    /// maps any sealed segments and scans the newest one's records to restore
     /// `write_pos` and `record_count`.
     pub(crate) fn open(path: impl AsRef<Path>) -> Result<Self, WalError> {
         Self::open_with_size(path, DEFAULT_INITIAL_SIZE)
@@ -82,34 +439,42 @@ impl Wal {
         initial_size: u64,
     ) -> Result<Self, WalError> {
         let path = path.as_ref().to_path_buf();
-
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(false)
-            .open(&path)?;
-
-        let file_len = file.metadata()?.len();
-        let mapped_size = if file_len < initial_size {
-            file.set_len(initial_size)?;
-            initial_size
-        } else {
-            file_len
-        };
-
-        // SAFETY: Single-writer invariant — only the matching thread accesses
-        // this file. No other process reads/writes it concurrently.
-        let mmap = unsafe { MmapMut::map_mut(&file)? };
+        let mut segments = list_segments(&path)?;
+        let (first_record, active_path) = segments.pop().unwrap_or((1, path.clone()));
+
+        let sealed = segments
+            .into_iter()
+            .map(|(first_record, path)| {
+                let file = File::open(&path)?;
+                // SAFETY: Sealed segments are never written again.
+                let mmap = unsafe { Mmap::map(&file)? };
+                let checksum = check_file_header(&mmap, MAGIC, FORMAT_VERSION)?;
+                Ok(Segment {
+                    first_record,
+                    path,
+                    mmap,
+                    checksum,
+                })
+            })
+            .collect::<Result<Vec<_>, WalError>>()?;
+        let (file, mmap, mapped_size, checksum) = open_writable(&active_path, initial_size)?;
 
         let mut wal = Self {
             mmap,
             file,
             path,
-            write_pos: 0,
+            checksum,
+            first_record,
+            sealed,
+            segment_size: None,
+            initial_size,
+            write_pos: FILE_HEADER_SIZE as u64,
             mapped_size,
-            encode_buf: [0u8; NEW_ORDER_SIZE],
+            encode_buf: [0u8; MAX_PAYLOAD_SIZE],
             record_count: 0,
+            last_record_pos: None,
+            unflushed_records: 0,
+            unflushed_bytes: 0,
         };
 
         wal.scan_to_end()?;
@@ -117,63 +482,160 @@ impl Wal {
         Ok(wal)
     }
 
+    /// Rotate to a new segment once the active one would grow past `bytes`.
+    /// `None` keeps appending to one file.
+    pub(crate) fn set_segment_size(&mut self, bytes: Option<u64>) {
+        self.segment_size = bytes;
+    }
+
     /// Append an `EngineCommand` to the WAL. Returns the record number (1-based).
     pub(crate) fn append(&mut self, cmd: &EngineCommand) -> Result<u64, WalError> {
-        let payload_len = match cmd {
-            EngineCommand::NewOrder(order) => {
-                protocol::encode_new_order(&mut self.encode_buf, order)?
+        let (msg_len, timestamp) = match cmd {
+            EngineCommand::NewOrder(order) => (
+                protocol::encode_new_order(&mut self.encode_buf, order)?,
+                Some(order.timestamp),
+            ),
+            EngineCommand::CancelOrder { order_id } => (
+                protocol::encode_cancel_order(&mut self.encode_buf, *order_id)?,
+                None,
+            ),
+            EngineCommand::CancelReplace { old_id, new_order } => (
+                protocol::encode_cancel_replace(&mut self.encode_buf, *old_id, new_order)?,
+                Some(new_order.timestamp),
+            ),
+            EngineCommand::CancelAll { trader_id } => (
+                protocol::encode_cancel_all(&mut self.encode_buf, *trader_id)?,
+                None,
+            ),
+            EngineCommand::ReduceOrder {
+                order_id,
+                reduce_by,
+            } => (
+                protocol::encode_reduce_order(&mut self.encode_buf, *order_id, *reduce_by)?,
+                None,
+            ),
+            EngineCommand::Halt { policy } => {
+                (protocol::encode_halt(&mut self.encode_buf, *policy)?, None)
+            }
+            EngineCommand::Resume => (protocol::encode_resume(&mut self.encode_buf)?, None),
+            EngineCommand::RequestSnapshot => (
+                protocol::encode_request_snapshot(&mut self.encode_buf)?,
+                None,
+            ),
+            EngineCommand::AdminSnapshot => {
+                (protocol::encode_admin_snapshot(&mut self.encode_buf)?, None)
             }
-            EngineCommand::CancelOrder { order_id } => {
-                protocol::encode_cancel_order(&mut self.encode_buf, *order_id)?
+        };
+        let payload_len = match timestamp {
+            Some(ts) => {
+                self.encode_buf[msg_len..msg_len + TIMESTAMP_SIZE]
+                    .copy_from_slice(&ts.to_le_bytes());
+                msg_len + TIMESTAMP_SIZE
             }
+            None => msg_len,
         };
 
         let record_size = align_up(HEADER_SIZE + payload_len);
+        if let Some(max) = self.segment_size
+            && self.write_pos + record_size as u64 > max
+            && self.write_pos > FILE_HEADER_SIZE as u64
+        {
+            self.rotate()?;
+        }
         self.ensure_capacity(record_size as u64)?;
 
-        let pos = self.write_pos as usize;
-
-        let crc = crc32fast::hash(&self.encode_buf[..payload_len]);
-        self.mmap[pos..pos + 4].copy_from_slice(&(payload_len as u32).to_le_bytes());
-        self.mmap[pos + 4..pos + 8].copy_from_slice(&crc.to_le_bytes());
-
-        self.mmap[pos + HEADER_SIZE..pos + HEADER_SIZE + payload_len]
-            .copy_from_slice(&self.encode_buf[..payload_len]);
-
-        let pad_start = pos + HEADER_SIZE + payload_len;
-        let pad_end = pos + record_size;
-        if pad_end > pad_start {
-            self.mmap[pad_start..pad_end].fill(0);
-        }
+        write_frame(
+            &mut self.mmap,
+            self.write_pos as usize,
+            &self.encode_buf[..payload_len],
+            self.checksum,
+        );
 
+        self.last_record_pos = Some(self.write_pos);
         self.write_pos += record_size as u64;
         self.record_count += 1;
+        self.unflushed_records += 1;
+        self.unflushed_bytes += record_size as u64;
 
         Ok(self.record_count)
     }
 
+    /// Stores the outcome of the most recently appended command. A no-op if
+    /// nothing has been appended since open or truncation.
+    pub(crate) fn record_outcome(&mut self, outcome: Outcome) {
+        if let Some(pos) = self.last_record_pos {
+            self.mmap[pos as usize + OUTCOME_OFFSET] = outcome as u8;
+        }
+    }
+
     pub(crate) fn record_count(&self) -> u64 {
         self.record_count
     }
 
+    /// Records appended since the last `flush_async` or rotation, the most a
+    /// machine crash can lose right now. Counts from 0 at open.
+    pub(crate) fn records_since_flush(&self) -> u64 {
+        self.unflushed_records
+    }
+
+    /// Framed size of the records counted by `records_since_flush`.
+    pub(crate) fn bytes_since_flush(&self) -> u64 {
+        self.unflushed_bytes
+    }
+
     #[cfg(test)]
     pub(crate) fn write_pos(&self) -> u64 {
         self.write_pos
     }
 
     /// Iterate all valid records starting from record number `start_record` (1-based).
-    /// Pass 0 to iterate from the very beginning.
+    /// Pass 0 to iterate from the very beginning. If records after
+    /// `start_record` have been deleted, yields `WalError::MissingRecords`.
     pub(crate) fn iter_from(&self, start_record: u64) -> WalIterator<'_> {
-        WalIterator {
-            mmap: &self.mmap,
-            read_pos: 0,
-            end_pos: self.write_pos,
-            current_record: 0,
-            start_record,
-        }
+        let sealed = self
+            .sealed
+            .iter()
+            .map(|s| (s.first_record, &s.mmap[..], s.mmap.len() as u64, s.checksum));
+        let active = (
+            self.first_record,
+            &self.mmap[..],
+            self.write_pos,
+            self.checksum,
+        );
+        WalIterator::new(sealed.chain([active]).collect(), start_record)
     }
 
+    /// Drops every record after `record_count`, the first of which starts at
+    /// `offset` in its segment. Segments after that one are deleted and it
+    /// becomes the active segment again.
     pub(crate) fn truncate_to(&mut self, offset: u64, record_count: u64) -> Result<(), WalError> {
+        debug_assert!(offset >= FILE_HEADER_SIZE as u64);
+        if let Some(i) = self
+            .sealed
+            .iter()
+            .rposition(|s| s.first_record <= record_count + 1)
+            .filter(|_| record_count + 1 < self.first_record)
+        {
+            let later = self.sealed.split_off(i + 1);
+            let reopened = self.sealed.pop().expect("segment at rposition");
+            let records_end = reopened.mmap.len() as u64;
+            drop(reopened.mmap);
+            let (file, mmap, mapped_size, checksum) =
+                open_writable(&reopened.path, self.initial_size)?;
+            let active = segment_path(&self.path, self.first_record);
+            self.mmap = mmap;
+            self.file = file;
+            self.checksum = checksum;
+            self.mapped_size = mapped_size;
+            self.write_pos = records_end;
+            self.first_record = reopened.first_record;
+            for segment in later {
+                drop(segment.mmap);
+                fs::remove_file(&segment.path)?;
+            }
+            fs::remove_file(active)?;
+        }
+
         let start = offset as usize;
         let end = self.write_pos as usize;
         if end > start {
@@ -181,11 +643,77 @@ impl Wal {
         }
         self.write_pos = offset;
         self.record_count = record_count;
+        self.last_record_pos = None;
+        Ok(())
+    }
+
+    /// Deletes sealed segments, oldest first, that `retention` lets go and
+    /// whose records are all at or below `covered`, the WAL record count of
+    /// the latest snapshot. The active segment is never deleted. Returns
+    /// the number of segments removed.
+    pub(crate) fn apply_retention(
+        &mut self,
+        retention: &WalRetention,
+        covered: u64,
+    ) -> Result<usize, WalError> {
+        let now = SystemTime::now();
+        let mut total_size =
+            self.mapped_size + self.sealed.iter().map(|s| s.mmap.len() as u64).sum::<u64>();
+
+        let mut removed = 0;
+        while let Some(oldest) = self.sealed.first() {
+            let next_first = self
+                .sealed
+                .get(1)
+                .map_or(self.first_record, |s| s.first_record);
+            if next_first - 1 > covered {
+                break;
+            }
+            let expired = retention.max_age.is_some_and(|max_age| {
+                fs::metadata(&oldest.path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|modified| now.duration_since(modified).ok())
+                    .is_some_and(|age| age >= max_age)
+            });
+            let oversized = retention.max_total_size.is_some_and(|max| total_size > max);
+            if !expired && !oversized {
+                break;
+            }
+
+            let segment = self.sealed.remove(0);
+            total_size -= segment.mmap.len() as u64;
+            drop(segment.mmap);
+            fs::remove_file(&segment.path)?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn segment_count(&self) -> usize {
+        self.sealed.len() + 1
+    }
+
+    /// Starts writeback of the active segment and restarts the
+    /// `records_since_flush` count. The write to disk may still be in
+    /// progress when this returns.
+    pub(crate) fn flush_async(&mut self) -> Result<(), WalError> {
+        self.mmap.flush_async()?;
+        self.unflushed_records = 0;
+        self.unflushed_bytes = 0;
         Ok(())
     }
 
-    pub(crate) fn flush_async(&self) -> Result<(), WalError> {
-        self.mmap.flush_async().map_err(WalError::Io)
+    /// Writes a zero into every page of the active segment past the last
+    /// record, so appends don't take a page fault each time they reach a new
+    /// page. The space past the records already reads as zeros, so the file
+    /// is unchanged. Segments mapped later by growth or rotation aren't
+    /// covered.
+    pub(crate) fn prefault(&mut self) {
+        for pos in (self.write_pos as usize..self.mmap.len()).step_by(PREFAULT_STRIDE) {
+            self.mmap[pos] = 0;
+        }
     }
 
     fn ensure_capacity(&mut self, needed: u64) -> Result<(), WalError> {
@@ -203,88 +731,175 @@ impl Wal {
         Ok(())
     }
 
-    fn scan_to_end(&mut self) -> Result<(), WalError> {
-        let mut pos: u64 = 0;
-        let mut count: u64 = 0;
-        let file_len = self.mapped_size;
-
-        loop {
-            if pos + HEADER_SIZE as u64 > file_len {
-                break;
-            }
-
-            let p = pos as usize;
-            let payload_len = u32::from_le_bytes(self.mmap[p..p + 4].try_into().unwrap()) as usize;
-
-            // A zero payload_len means we've hit unwritten space.
-            if payload_len == 0 {
-                break;
-            }
+    /// Seals the active segment, trimmed to its records, and starts the next.
+    fn rotate(&mut self) -> Result<(), WalError> {
+        self.mmap.flush()?;
+        self.unflushed_records = 0;
+        self.unflushed_bytes = 0;
+        let first_record = self.record_count + 1;
+        let initial_size = self
+            .segment_size
+            .map_or(self.initial_size, |max| max.min(self.initial_size));
+        let (file, mmap, mapped_size, checksum) =
+            open_writable(&segment_path(&self.path, first_record), initial_size)?;
+
+        drop(std::mem::replace(&mut self.mmap, mmap));
+        let sealed_file = std::mem::replace(&mut self.file, file);
+        sealed_file.set_len(self.write_pos)?;
+        // SAFETY: Sealed segments are never written again.
+        let sealed_map = unsafe { Mmap::map(&sealed_file)? };
+        self.sealed.push(Segment {
+            first_record: self.first_record,
+            path: segment_path(&self.path, self.first_record),
+            mmap: sealed_map,
+            checksum: self.checksum,
+        });
+
+        self.first_record = first_record;
+        self.checksum = checksum;
+        self.mapped_size = mapped_size;
+        self.write_pos = FILE_HEADER_SIZE as u64;
+        self.last_record_pos = None;
+        Ok(())
+    }
 
-            let record_size = align_up(HEADER_SIZE + payload_len);
-            if pos + record_size as u64 > file_len {
-                // Truncated record — discard it.
-                break;
-            }
+    fn scan_to_end(&mut self) -> Result<(), WalError> {
+        let (write_pos, record_count) = scan_frames(&self.mmap, self.checksum);
+        self.write_pos = write_pos;
+        self.record_count = self.first_record - 1 + record_count;
+        Ok(())
+    }
+}
 
-            let stored_crc = u32::from_le_bytes(self.mmap[p + 4..p + 8].try_into().unwrap());
-            let computed_crc =
-                crc32fast::hash(&self.mmap[p + HEADER_SIZE..p + HEADER_SIZE + payload_len]);
+/// End of the last intact record and the number of records before it. A
+/// truncated or corrupt record ends the scan and is overwritten by the next
+/// append.
+pub(crate) fn scan_frames(data: &[u8], checksum: Algorithm) -> (u64, u64) {
+    let mut pos = FILE_HEADER_SIZE as u64;
+    let mut count = 0;
+    while let Ok(Some((_, record_size))) = read_frame(data, pos, data.len() as u64, checksum) {
+        pos += record_size as u64;
+        count += 1;
+    }
+    (pos, count)
+}
 
-            if stored_crc != computed_crc {
-                break;
-            }
+/// Read-only view of a WAL file for offline inspection. Never resizes or
+/// writes the file, so it is safe to point at a live engine's log.
+pub struct WalReader {
+    mmap: Mmap,
+    first_record: u64,
+    checksum: Algorithm,
+}
 
-            pos += record_size as u64;
-            count += 1;
-        }
+impl WalReader {
+    /// Opens one segment. A rotated segment (`wal.bin.<n>`) numbers its
+    /// records from `n`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, WalError> {
+        let first_record = segment_first_record(path.as_ref());
+        let file = File::open(path)?;
+        // SAFETY: The map is only read. A live writer may still append, which
+        // at worst shows up as a truncated or corrupt tail record.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let checksum = check_file_header(&mmap, MAGIC, FORMAT_VERSION)?;
+        Ok(Self {
+            mmap,
+            first_record,
+            checksum,
+        })
+    }
 
-        self.write_pos = pos;
-        self.record_count = count;
-        Ok(())
+    /// Iterates records from the start of the file. Ends at the first unwritten
+    /// header; a truncated or corrupt record yields an error carrying its byte
+    /// offset and ends the iteration.
+    pub fn iter(&self) -> WalIterator<'_> {
+        let segment = (
+            self.first_record,
+            &self.mmap[..],
+            self.mmap.len() as u64,
+            self.checksum,
+        );
+        WalIterator::new(vec![segment], self.first_record - 1)
     }
 }
 
-pub(crate) struct WalIterator<'a> {
+/// Record number, command and recorded outcome.
+pub(crate) type OutcomeRecord = (u64, EngineCommand, Option<Outcome>);
+
+/// A segment to iterate: first record number, data, end of its records and
+/// their checksum.
+type SegmentView<'a> = (u64, &'a [u8], u64, Algorithm);
+
+/// Yields `(record number, command)` pairs. Stops after the first error.
+pub struct WalIterator<'a> {
     mmap: &'a [u8],
     read_pos: u64,
     end_pos: u64,
+    checksum: Algorithm,
     current_record: u64,
     start_record: u64,
+    /// Segments after the current one.
+    next_segments: std::vec::IntoIter<SegmentView<'a>>,
+    /// Set when the records after `start_record` are gone.
+    missing: Option<(u64, u64)>,
 }
 
-impl<'a> Iterator for WalIterator<'a> {
-    type Item = Result<(u64, EngineCommand), WalError>;
+impl<'a> WalIterator<'a> {
+    /// Starts in the last segment that begins at or before the record after
+    /// `start_record`, skipping earlier ones unread.
+    fn new(mut segments: Vec<SegmentView<'a>>, start_record: u64) -> Self {
+        let skip = segments
+            .iter()
+            .rposition(|&(first, ..)| first <= start_record + 1)
+            .unwrap_or(0);
+        let mut next_segments = segments.split_off(skip).into_iter();
+        let (first, mmap, end_pos, checksum) =
+            next_segments
+                .next()
+                .unwrap_or((1, &[], 0, Algorithm::DEFAULT));
+        let missing = (first > start_record + 1).then_some((start_record + 1, first - 1));
+        Self {
+            mmap,
+            read_pos: FILE_HEADER_SIZE as u64,
+            end_pos,
+            checksum,
+            current_record: first - 1,
+            start_record,
+            next_segments,
+            missing,
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            if self.read_pos + HEADER_SIZE as u64 > self.end_pos {
-                return None;
-            }
+    /// Like the plain iterator, but also yields each record's outcome, or
+    /// `None` for records logged before outcomes were kept.
+    pub(crate) fn with_outcomes(self) -> WithOutcomes<'a> {
+        WithOutcomes(self)
+    }
 
+    fn next_record(&mut self) -> Option<Result<OutcomeRecord, WalError>> {
+        if let Some((first, last)) = self.missing.take() {
+            return Some(Err(self.fail(WalError::MissingRecords { first, last })));
+        }
+        loop {
             let p = self.read_pos as usize;
-            let payload_len = u32::from_le_bytes(self.mmap[p..p + 4].try_into().unwrap()) as usize;
-
-            if payload_len == 0 {
-                return None;
-            }
-
-            let record_size = align_up(HEADER_SIZE + payload_len);
-            if self.read_pos + record_size as u64 > self.end_pos {
-                return Some(Err(WalError::TruncatedRecord {
-                    offset: self.read_pos,
-                }));
-            }
-
-            let stored_crc = u32::from_le_bytes(self.mmap[p + 4..p + 8].try_into().unwrap());
-            let payload = &self.mmap[p + HEADER_SIZE..p + HEADER_SIZE + payload_len];
-            let computed_crc = crc32fast::hash(payload);
-
-            if stored_crc != computed_crc {
-                return Some(Err(WalError::Corruption {
-                    offset: self.read_pos,
-                }));
-            }
+            let frame = read_frame(self.mmap, self.read_pos, self.end_pos, self.checksum);
+            let (payload, record_size) = match frame {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    let (first, mmap, end_pos, checksum) = self.next_segments.next()?;
+                    if first != self.current_record + 1 {
+                        let last = first - 1;
+                        let first = self.current_record + 1;
+                        return Some(Err(self.fail(WalError::MissingRecords { first, last })));
+                    }
+                    self.mmap = mmap;
+                    self.read_pos = FILE_HEADER_SIZE as u64;
+                    self.end_pos = end_pos;
+                    self.checksum = checksum;
+                    continue;
+                }
+                Err(e) => return Some(Err(self.fail(e))),
+            };
 
             self.read_pos += record_size as u64;
             self.current_record += 1;
@@ -294,27 +909,84 @@ impl<'a> Iterator for WalIterator<'a> {
                 continue;
             }
 
-            match protocol::decode_message(payload) {
-                Ok(cmd) => return Some(Ok((self.current_record, cmd))),
-                Err(e) => return Some(Err(WalError::Protocol(e))),
-            }
+            let outcome = Outcome::from_byte(self.mmap[p + OUTCOME_OFFSET]);
+            return Some(match decode_payload(payload) {
+                Ok(cmd) => Ok((self.current_record, cmd, outcome)),
+                Err(e) => Err(self.fail(e)),
+            });
         }
     }
+
+    fn fail(&mut self, e: WalError) -> WalError {
+        self.end_pos = self.read_pos;
+        self.next_segments = Vec::new().into_iter();
+        e
+    }
+}
+
+pub(crate) struct WithOutcomes<'a>(WalIterator<'a>);
+
+impl Iterator for WithOutcomes<'_> {
+    type Item = Result<OutcomeRecord, WalError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_record()
+    }
+}
+
+impl Iterator for WalIterator<'_> {
+    type Item = Result<(u64, EngineCommand), WalError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record()
+            .map(|r| r.map(|(record, cmd, _)| (record, cmd)))
+    }
+}
+
+fn decode_payload(payload: &[u8]) -> Result<EngineCommand, WalError> {
+    let mut cmd = protocol::decode_message(payload)?;
+    let msg_len = protocol::message_size(payload[0])?;
+    let timestamp = payload
+        .get(msg_len..msg_len + TIMESTAMP_SIZE)
+        .map_or(0, |b| u64::from_le_bytes(b.try_into().unwrap()));
+
+    match &mut cmd {
+        EngineCommand::NewOrder(order)
+        | EngineCommand::CancelReplace {
+            new_order: order, ..
+        } => order.timestamp = timestamp,
+        EngineCommand::CancelOrder { .. }
+        | EngineCommand::CancelAll { .. }
+        | EngineCommand::ReduceOrder { .. }
+        | EngineCommand::Halt { .. }
+        | EngineCommand::Resume
+        | EngineCommand::RequestSnapshot
+        | EngineCommand::AdminSnapshot => {}
+    }
+    Ok(cmd)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::order::{Order, Side};
+    use crate::protocol::NEW_ORDER_SIZE;
+
+    /// Offset of the first record.
+    const START: u64 = FILE_HEADER_SIZE as u64;
 
     fn make_order(id: u64) -> Order {
         Order {
-            id,
-            trader_id: 1,
+            id: id.into(),
+            trader_id: 1.into(),
+            symbol_id: 0,
             side: Side::Bid,
-            price: 15005,
-            quantity: 100,
+            price: 15005.into(),
+            quantity: 100.into(),
             timestamp: 1_000_000,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
         }
     }
 
@@ -326,6 +998,132 @@ mod tests {
         EngineCommand::CancelOrder { order_id: id }
     }
 
+    /// A WAL rotating every 4 `NewOrder` records, holding orders 1..=count.
+    fn rotating_wal(path: &Path, count: u64) -> Wal {
+        let mut wal = Wal::open_with_size(path, 4096).unwrap();
+        wal.set_segment_size(Some(START + 4 * 56));
+        for id in 1..=count {
+            wal.append(&new_order_cmd(id)).unwrap();
+        }
+        wal
+    }
+
+    fn record_numbers(wal: &Wal, start_record: u64) -> Vec<u64> {
+        wal.iter_from(start_record).map(|r| r.unwrap().0).collect()
+    }
+
+    #[test]
+    fn rotates_into_numbered_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+        let wal = rotating_wal(&path, 10);
+        assert_eq!(wal.segment_count(), 3);
+        assert_eq!(record_numbers(&wal, 0), (1..=10).collect::<Vec<_>>());
+        assert_eq!(record_numbers(&wal, 6), vec![7, 8, 9, 10]);
+        drop(wal);
+
+        // Sealed segments are trimmed to their records.
+        let second = dir.path().join("wal.bin.00000000000000000005");
+        assert_eq!(fs::metadata(&second).unwrap().len(), START + 4 * 56);
+        let reader = WalReader::open(&second).unwrap();
+        let numbers: Vec<_> = reader.iter().map(|r| r.unwrap().0).collect();
+        assert_eq!(numbers, vec![5, 6, 7, 8]);
+
+        let mut wal = Wal::open_with_size(&path, 4096).unwrap();
+        assert_eq!(wal.segment_count(), 3);
+        assert_eq!(wal.record_count(), 10);
+        assert_eq!(wal.append(&cancel_cmd(1)).unwrap(), 11);
+        assert_eq!(record_numbers(&wal, 0), (1..=11).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn truncate_into_sealed_segment_drops_later_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+        let mut wal = rotating_wal(&path, 10);
+
+        // Record 7 is the third in the segment starting at 5.
+        wal.truncate_to(START + 2 * 56, 6).unwrap();
+        assert_eq!(wal.segment_count(), 2);
+        assert!(!dir.path().join("wal.bin.00000000000000000009").exists());
+        assert_eq!(wal.append(&cancel_cmd(1)).unwrap(), 7);
+        drop(wal);
+
+        let wal = Wal::open_with_size(&path, 4096).unwrap();
+        assert_eq!(record_numbers(&wal, 0), (1..=7).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn retention_only_deletes_segments_a_snapshot_covers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+        let mut wal = rotating_wal(&path, 10);
+        let retention = WalRetention {
+            max_total_size: Some(0),
+            ..WalRetention::default()
+        };
+
+        // Records 5..=8 aren't all covered, so only the first segment goes.
+        assert_eq!(wal.apply_retention(&retention, 7).unwrap(), 1);
+        assert!(!path.exists());
+        assert_eq!(record_numbers(&wal, 7), vec![8, 9, 10]);
+        assert!(matches!(
+            wal.iter_from(0).next(),
+            Some(Err(WalError::MissingRecords { first: 1, last: 4 }))
+        ));
+
+        // The active segment stays whatever is covered.
+        assert_eq!(wal.apply_retention(&retention, 10).unwrap(), 1);
+        assert_eq!(wal.segment_count(), 1);
+        drop(wal);
+
+        let mut wal = Wal::open_with_size(&path, 4096).unwrap();
+        assert_eq!(wal.record_count(), 10);
+        assert_eq!(wal.append(&cancel_cmd(1)).unwrap(), 11);
+    }
+
+    #[test]
+    fn retention_by_age_keeps_recent_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+        let mut wal = rotating_wal(&path, 10);
+        let hour = Duration::from_secs(3600);
+        let two_hours_ago = SystemTime::now() - 2 * hour;
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(two_hours_ago)
+            .unwrap();
+
+        let retention = WalRetention {
+            max_age: Some(hour),
+            ..WalRetention::default()
+        };
+        assert_eq!(wal.apply_retention(&retention, 10).unwrap(), 1);
+        assert_eq!(wal.segment_count(), 2);
+        assert_eq!(
+            wal.apply_retention(&WalRetention::default(), 10).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn missing_middle_segment_ends_iteration() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+        drop(rotating_wal(&path, 10));
+        fs::remove_file(dir.path().join("wal.bin.00000000000000000005")).unwrap();
+
+        let wal = Wal::open_with_size(&path, 4096).unwrap();
+        let results: Vec<_> = wal.iter_from(0).collect();
+        assert_eq!(results.len(), 5);
+        assert!(matches!(
+            results[4],
+            Err(WalError::MissingRecords { first: 5, last: 8 })
+        ));
+    }
+
     #[test]
     fn create_new_wal() {
         let dir = tempfile::tempdir().unwrap();
@@ -333,7 +1131,7 @@ mod tests {
 
         let wal = Wal::open(&path).unwrap();
         assert_eq!(wal.record_count(), 0);
-        assert_eq!(wal.write_pos(), 0);
+        assert_eq!(wal.write_pos(), START);
         assert!(path.exists());
     }
 
@@ -347,8 +1145,8 @@ mod tests {
 
         assert_eq!(seq, 1);
         assert_eq!(wal.record_count(), 1);
-        // NewOrder payload = 40 bytes, record = align_up(8 + 40) = 48 bytes
-        assert_eq!(wal.write_pos(), 48);
+        // NewOrder payload = 40 + 8 timestamp bytes, record = align_up(8 + 48) = 56 bytes
+        assert_eq!(wal.write_pos(), START + 56);
     }
 
     #[test]
@@ -361,7 +1159,7 @@ mod tests {
 
         assert_eq!(seq, 1);
         // CancelOrder payload = 16 bytes, record = align_up(8 + 16) = 24 bytes
-        assert_eq!(wal.write_pos(), 24);
+        assert_eq!(wal.write_pos(), START + 24);
     }
 
     #[test]
@@ -376,7 +1174,7 @@ mod tests {
         }
 
         assert_eq!(wal.record_count(), 100);
-        assert_eq!(wal.write_pos(), 100 * 48);
+        assert_eq!(wal.write_pos(), START + 100 * 56);
     }
 
     #[test]
@@ -449,7 +1247,7 @@ mod tests {
 
         let wal = Wal::open(&path).unwrap();
         assert_eq!(wal.record_count(), 3);
-        assert_eq!(wal.write_pos(), 48 + 48 + 24); // two NewOrders + one Cancel
+        assert_eq!(wal.write_pos(), START + 56 + 56 + 24); // two NewOrders + one Cancel
 
         let records: Vec<_> = wal.iter_from(0).collect::<Result<Vec<_>, _>>().unwrap();
         assert_eq!(records.len(), 3);
@@ -463,15 +1261,76 @@ mod tests {
         let mut wal = Wal::open(&path).unwrap();
         wal.append(&new_order_cmd(42)).unwrap();
 
-        let payload_len = u32::from_le_bytes(wal.mmap[0..4].try_into().unwrap());
-        assert_eq!(payload_len, NEW_ORDER_SIZE as u32);
-
-        let stored_crc = u32::from_le_bytes(wal.mmap[4..8].try_into().unwrap());
-        let computed_crc = crc32fast::hash(&wal.mmap[8..8 + NEW_ORDER_SIZE]);
+        assert_eq!(
+            wal.mmap[..FILE_HEADER_SIZE],
+            file_header(MAGIC, FORMAT_VERSION)
+        );
+        assert_eq!(&wal.mmap[..8], b"FRXWAL01");
+
+        let payload_len = u32::from_le_bytes(
+            wal.mmap[FILE_HEADER_SIZE..FILE_HEADER_SIZE + 4]
+                .try_into()
+                .unwrap(),
+        );
+        assert_eq!(payload_len, (NEW_ORDER_SIZE + TIMESTAMP_SIZE) as u32);
+
+        let stored_crc = u32::from_le_bytes(
+            wal.mmap[FILE_HEADER_SIZE + 4..FILE_HEADER_SIZE + 8]
+                .try_into()
+                .unwrap(),
+        );
+        let computed_crc = record_crc(
+            Algorithm::DEFAULT,
+            &wal.mmap[FILE_HEADER_SIZE..FILE_HEADER_SIZE + 4],
+            &wal.mmap[FILE_HEADER_SIZE + 8..FILE_HEADER_SIZE + 8 + NEW_ORDER_SIZE + TIMESTAMP_SIZE],
+        );
         assert_eq!(stored_crc, computed_crc);
 
         // First byte of payload is the message type
-        assert_eq!(wal.mmap[8], protocol::MSG_NEW_ORDER);
+        assert_eq!(wal.mmap[FILE_HEADER_SIZE + 8], protocol::MSG_NEW_ORDER);
+    }
+
+    #[test]
+    fn segment_keeps_the_checksum_named_in_its_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+        let other = match Algorithm::DEFAULT {
+            Algorithm::Crc32 => Algorithm::Crc32c,
+            Algorithm::Crc32c => Algorithm::Crc32,
+        };
+
+        // A segment written by a build with the other default.
+        let mut data = vec![0u8; 4096];
+        data[..FILE_HEADER_SIZE].copy_from_slice(&file_header(MAGIC, FORMAT_VERSION));
+        data[12..16].copy_from_slice(&other.id().to_le_bytes());
+        let mut msg = [0u8; protocol::CANCEL_ORDER_SIZE];
+        let n = protocol::encode_cancel_order(&mut msg, 7).unwrap();
+        write_frame(&mut data, FILE_HEADER_SIZE, &msg[..n], other);
+        std::fs::write(&path, &data).unwrap();
+
+        let mut wal = Wal::open_with_size(&path, 4096).unwrap();
+        assert_eq!(wal.checksum, other);
+        assert_eq!(wal.record_count(), 1);
+        wal.append(&EngineCommand::CancelOrder { order_id: 8 })
+            .unwrap();
+        drop(wal);
+
+        let reader = WalReader::open(&path).unwrap();
+        let ids: Vec<_> = reader
+            .iter()
+            .map(|r| match r.unwrap().1 {
+                EngineCommand::CancelOrder { order_id } => order_id,
+                cmd => panic!("unexpected {cmd:?}"),
+            })
+            .collect();
+        assert_eq!(ids, [7, 8]);
+
+        data[12..16].copy_from_slice(&9u32.to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+        assert!(matches!(
+            WalReader::open(&path),
+            Err(WalError::UnsupportedChecksum(9))
+        ));
     }
 
     #[test]
@@ -483,14 +1342,14 @@ mod tests {
         wal.append(&new_order_cmd(1)).unwrap();
         wal.append(&new_order_cmd(2)).unwrap();
 
-        // Corrupt the CRC of the second record (at offset 48)
-        wal.mmap[48 + 4] ^= 0xFF;
+        // Corrupt the CRC of the second record (56 bytes after the first)
+        wal.mmap[FILE_HEADER_SIZE + 56 + 4] ^= 0xFF;
 
         // Iterator should yield first record, then error on second
         let mut iter = wal.iter_from(0);
         assert!(iter.next().unwrap().is_ok());
         let err = iter.next().unwrap().unwrap_err();
-        matches!(err, WalError::Corruption { offset: 48 });
+        assert!(matches!(err, WalError::Corruption { offset } if offset == START + 56));
     }
 
     #[test]
@@ -502,11 +1361,76 @@ mod tests {
         wal.append(&new_order_cmd(1)).unwrap();
 
         // Corrupt a payload byte
-        wal.mmap[HEADER_SIZE + 5] ^= 0xFF;
+        wal.mmap[FILE_HEADER_SIZE + HEADER_SIZE + 5] ^= 0xFF;
 
         let mut iter = wal.iter_from(0);
         let err = iter.next().unwrap().unwrap_err();
-        matches!(err, WalError::Corruption { offset: 0 });
+        assert!(matches!(err, WalError::Corruption { offset } if offset == START));
+    }
+
+    #[test]
+    fn reader_iterates_without_resizing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+
+        let mut wal = Wal::open_with_size(&path, 4096).unwrap();
+        wal.append(&new_order_cmd(1)).unwrap();
+        wal.append(&cancel_cmd(1)).unwrap();
+        wal.flush_async().unwrap();
+        drop(wal);
+
+        let reader = WalReader::open(&path).unwrap();
+        let records: Vec<_> = reader.iter().map(|r| r.unwrap()).collect();
+        assert_eq!(records, vec![(1, new_order_cmd(1)), (2, cancel_cmd(1))]);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 4096);
+    }
+
+    #[test]
+    fn reader_reports_corrupt_record_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+
+        let mut wal = Wal::open_with_size(&path, 4096).unwrap();
+        for i in 1..=3 {
+            wal.append(&new_order_cmd(i)).unwrap();
+        }
+        wal.mmap[FILE_HEADER_SIZE + 56 + HEADER_SIZE + 1] ^= 0xFF;
+        drop(wal);
+
+        let reader = WalReader::open(&path).unwrap();
+        let mut iter = reader.iter();
+        assert!(iter.next().unwrap().is_ok());
+        assert!(matches!(
+            iter.next().unwrap(),
+            Err(WalError::Corruption { offset }) if offset == START + 56
+        ));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn reader_reports_truncated_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+
+        let mut wal = Wal::open_with_size(&path, 4096).unwrap();
+        wal.append(&new_order_cmd(1)).unwrap();
+        wal.append(&new_order_cmd(2)).unwrap();
+        drop(wal);
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(START + 100)
+            .unwrap();
+
+        let reader = WalReader::open(&path).unwrap();
+        let mut iter = reader.iter();
+        assert!(iter.next().unwrap().is_ok());
+        assert!(matches!(
+            iter.next().unwrap(),
+            Err(WalError::TruncatedRecord { offset }) if offset == START + 56
+        ));
+        assert!(iter.next().is_none());
     }
 
     #[test]
@@ -542,13 +1466,66 @@ mod tests {
             wal.append(&new_order_cmd(3)).unwrap();
 
             // Corrupt record 2's CRC
-            wal.mmap[48 + 4] ^= 0xFF;
+            wal.mmap[FILE_HEADER_SIZE + 56 + 4] ^= 0xFF;
         }
 
         // Reopen should find only 1 valid record (stops at corruption)
         let wal = Wal::open(&path).unwrap();
         assert_eq!(wal.record_count(), 1);
-        assert_eq!(wal.write_pos(), 48);
+        assert_eq!(wal.write_pos(), START + 56);
+    }
+
+    #[test]
+    fn corrupt_length_detected_at_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+
+        {
+            let mut wal = Wal::open(&path).unwrap();
+            wal.append(&new_order_cmd(1)).unwrap();
+            wal.append(&cancel_cmd(1)).unwrap();
+            wal.append(&new_order_cmd(2)).unwrap();
+
+            // Shrink record 2's length from 16 to 8: the new boundary still
+            // lands inside the file, so only the CRC can catch it
+            wal.mmap[FILE_HEADER_SIZE + 56] = 8;
+        }
+
+        let wal = Wal::open(&path).unwrap();
+        assert_eq!(wal.record_count(), 1);
+        assert_eq!(wal.write_pos(), START + 56);
+    }
+
+    #[test]
+    fn headerless_v1_file_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+
+        // A v1 file starts with the first record's length word
+        let mut data = vec![0u8; 4096];
+        data[..4].copy_from_slice(&48u32.to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+
+        assert!(matches!(Wal::open(&path), Err(WalError::BadMagic)));
+        assert!(matches!(WalReader::open(&path), Err(WalError::BadMagic)));
+        // Rejected files are left as they were
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+    }
+
+    #[test]
+    fn unknown_version_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+
+        drop(Wal::open_with_size(&path, 4096).unwrap());
+        let mut data = std::fs::read(&path).unwrap();
+        data[8..12].copy_from_slice(&7u32.to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+
+        assert!(matches!(
+            Wal::open(&path),
+            Err(WalError::UnsupportedVersion { found: 7, .. })
+        ));
     }
 
     #[test]
@@ -562,9 +1539,9 @@ mod tests {
         wal.append(&new_order_cmd(3)).unwrap();
 
         // Truncate to after the first record
-        wal.truncate_to(48, 1).unwrap();
+        wal.truncate_to(START + 56, 1).unwrap();
         assert_eq!(wal.record_count(), 1);
-        assert_eq!(wal.write_pos(), 48);
+        assert_eq!(wal.write_pos(), START + 56);
 
         let records: Vec<_> = wal.iter_from(0).collect::<Result<Vec<_>, _>>().unwrap();
         assert_eq!(records.len(), 1);
@@ -575,7 +1552,7 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         let path = dir.path().join("wal.bin");
 
-        // Start with a tiny mmap (256 bytes — room for 5 NewOrder records)
+        // Start with a tiny mmap (256 bytes — room for 4 NewOrder records)
         let mut wal = Wal::open_with_size(&path, 256).unwrap();
         assert_eq!(wal.mapped_size, 256);
 
@@ -591,6 +1568,55 @@ mod tests {
         assert_eq!(records.len(), 10);
     }
 
+    #[test]
+    fn prefault_leaves_records_readable() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+        {
+            let mut wal = Wal::open_with_size(&path, 64 * 1024).unwrap();
+            wal.append(&new_order_cmd(1)).unwrap();
+            wal.prefault();
+            wal.append(&new_order_cmd(2)).unwrap();
+            wal.flush_async().unwrap();
+        }
+
+        let wal = Wal::open_with_size(&path, 64 * 1024).unwrap();
+        assert_eq!(wal.record_count(), 2);
+        assert_eq!(wal.iter_from(0).count(), 2);
+    }
+
+    #[test]
+    fn flush_resets_the_unflushed_counts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+        let mut wal = Wal::open_with_size(&path, 4096).unwrap();
+        assert_eq!((wal.records_since_flush(), wal.bytes_since_flush()), (0, 0));
+
+        wal.append(&new_order_cmd(1)).unwrap();
+        wal.append(&cancel_cmd(1)).unwrap();
+        assert_eq!(wal.records_since_flush(), 2);
+        assert_eq!(wal.bytes_since_flush(), wal.write_pos() - START);
+
+        wal.flush_async().unwrap();
+        assert_eq!((wal.records_since_flush(), wal.bytes_since_flush()), (0, 0));
+        let before = wal.write_pos();
+        wal.append(&new_order_cmd(2)).unwrap();
+        assert_eq!(wal.records_since_flush(), 1);
+        assert_eq!(wal.bytes_since_flush(), wal.write_pos() - before);
+
+        // Rotation flushes the sealed segment, leaving only the record that
+        // opened the new one.
+        wal.set_segment_size(Some(wal.write_pos()));
+        wal.append(&new_order_cmd(3)).unwrap();
+        assert_eq!(wal.segment_count(), 2);
+        assert_eq!(wal.records_since_flush(), 1);
+
+        // Reopening counts nothing: what is in the file survived.
+        drop(wal);
+        let wal = Wal::open_with_size(&path, 4096).unwrap();
+        assert_eq!(wal.records_since_flush(), 0);
+    }
+
     #[test]
     fn mixed_new_order_and_cancel() {
         let dir = tempfile::tempdir().unwrap();
@@ -610,8 +1636,59 @@ mod tests {
         assert!(matches!(records[2].1, EngineCommand::CancelOrder { .. }));
         assert!(matches!(records[3].1, EngineCommand::NewOrder(_)));
 
-        // Verify write positions: 48 + 48 + 24 + 48 = 168
-        assert_eq!(wal.write_pos(), 168);
+        // Verify write positions: 56 + 56 + 24 + 56 = 192
+        assert_eq!(wal.write_pos(), START + 192);
+    }
+
+    #[test]
+    fn cancel_replace_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+
+        let cmd = EngineCommand::CancelReplace {
+            old_id: 1,
+            new_order: make_order(2),
+        };
+        let mut wal = Wal::open(&path).unwrap();
+        wal.append(&cmd).unwrap();
+        // CancelReplace payload = 48 + 8 timestamp bytes, record = align_up(8 + 56) = 64 bytes
+        assert_eq!(wal.write_pos(), START + 64);
+
+        let records: Vec<_> = wal.iter_from(0).collect::<Result<Vec<_>, _>>().unwrap();
+        match &records[0].1 {
+            EngineCommand::CancelReplace { old_id, new_order } => {
+                assert_eq!(*old_id, 1);
+                assert_eq!(new_order.id, 2);
+                assert_eq!(new_order.price, 15005);
+            }
+            _ => panic!("expected CancelReplace"),
+        }
+    }
+
+    #[test]
+    fn gtd_orders_keep_expiry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+
+        let new_order = EngineCommand::NewOrder(make_order(1).with_expiry(9_000));
+        let replace = EngineCommand::CancelReplace {
+            old_id: 1,
+            new_order: make_order(2).with_expiry(9_500),
+        };
+        let mut wal = Wal::open(&path).unwrap();
+        wal.append(&new_order).unwrap();
+        wal.append(&replace).unwrap();
+        // 8 + 48 + 8 = 64 and 8 + 56 + 8 = 72 byte records, both already aligned
+        assert_eq!(wal.write_pos(), START + 136);
+
+        let records: Vec<_> = wal.iter_from(0).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(records[0].1, new_order);
+        match &records[1].1 {
+            EngineCommand::CancelReplace { new_order, .. } => {
+                assert_eq!(new_order.expiry, std::num::NonZeroU64::new(9_500));
+            }
+            _ => panic!("expected CancelReplace"),
+        }
     }
 
     #[test]
@@ -630,12 +1707,16 @@ mod tests {
         let path = dir.path().join("wal.bin");
 
         let order = Order {
-            id: 999,
-            trader_id: 42,
+            id: 999.into(),
+            trader_id: 42.into(),
+            symbol_id: 0,
             side: Side::Ask,
-            price: -12345,
-            quantity: u64::MAX,
-            timestamp: 0, // timestamp not encoded in protocol
+            price: (-12345).into(),
+            quantity: u64::MAX.into(),
+            timestamp: 7_777,
+            expiry: None,
+            reduce_only: false,
+            post_only: false,
         };
 
         let mut wal = Wal::open(&path).unwrap();
@@ -649,8 +1730,78 @@ mod tests {
                 assert_eq!(o.side, Side::Ask);
                 assert_eq!(o.price, -12345);
                 assert_eq!(o.quantity, u64::MAX);
+                assert_eq!(o.timestamp, 7_777);
+            }
+            _ => panic!("expected NewOrder"),
+        }
+    }
+
+    #[test]
+    fn record_without_timestamp_replays_as_zero() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+
+        let mut wal = Wal::open(&path).unwrap();
+        let mut msg = [0u8; NEW_ORDER_SIZE];
+        protocol::encode_new_order(&mut msg, &make_order(1)).unwrap();
+        let len_word = (NEW_ORDER_SIZE as u32).to_le_bytes();
+        let crc = record_crc(wal.checksum, &len_word, &msg);
+        wal.mmap[FILE_HEADER_SIZE..FILE_HEADER_SIZE + 4].copy_from_slice(&len_word);
+        wal.mmap[FILE_HEADER_SIZE + 4..FILE_HEADER_SIZE + 8].copy_from_slice(&crc.to_le_bytes());
+        wal.mmap[FILE_HEADER_SIZE + 8..FILE_HEADER_SIZE + 8 + NEW_ORDER_SIZE].copy_from_slice(&msg);
+        drop(wal);
+
+        let wal = Wal::open(&path).unwrap();
+        let records: Vec<_> = wal.iter_from(0).collect::<Result<Vec<_>, _>>().unwrap();
+        match &records[0].1 {
+            EngineCommand::NewOrder(o) => {
+                assert_eq!(o.id, 1);
+                assert_eq!(o.timestamp, 0);
             }
             _ => panic!("expected NewOrder"),
         }
     }
+
+    #[test]
+    fn outcome_stored_outside_crc() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+
+        {
+            let mut wal = Wal::open(&path).unwrap();
+            wal.append(&new_order_cmd(1)).unwrap();
+            wal.record_outcome(Outcome::Resting);
+            wal.append(&EngineCommand::CancelOrder { order_id: 9 })
+                .unwrap();
+            wal.record_outcome(Outcome::Rejected);
+            wal.append(&new_order_cmd(2)).unwrap();
+        }
+
+        let wal = Wal::open(&path).unwrap();
+        assert_eq!(wal.record_count(), 3);
+        let outcomes: Vec<_> = wal
+            .iter_from(0)
+            .with_outcomes()
+            .map(|r| r.unwrap().2)
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![Some(Outcome::Resting), Some(Outcome::Rejected), None]
+        );
+    }
+
+    #[test]
+    fn record_outcome_after_truncate_is_noop() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal.bin");
+
+        let mut wal = Wal::open(&path).unwrap();
+        wal.append(&new_order_cmd(1)).unwrap();
+        wal.truncate_to(START, 0).unwrap();
+        wal.record_outcome(Outcome::Resting);
+        assert_eq!(
+            wal.mmap[FILE_HEADER_SIZE..FILE_HEADER_SIZE + HEADER_SIZE],
+            [0u8; HEADER_SIZE]
+        );
+    }
 }